@@ -1,14 +1,29 @@
 use anyhow::{Context, Result};
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::sign::CertifiedKey;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::fs;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+use x509_parser::prelude::*;
 
-use crate::ca::csr::generate_csr;
-use crate::common::{write_file_bytes, PqSecureError};
-use crate::config::CaConfig;
+use crate::ca::csr::{describe_csr, generate_csr};
+use crate::ca::issuance_log::{IssuanceLog, IssuanceRecord};
+use crate::ca::notifier::{CertificateEvent, EventNotifier};
+use crate::ca::storage::{CachingIdentityStorage, FileIdentityStorage, IdentityStorage, MemoryIdentityStorage};
+use crate::common::{certificate_serial, write_file_bytes, PqSecureError};
+use crate::config::{CaConfig, CaRetryConfig, IdentityStorageType, Pkcs12OutputConfig};
+use crate::crypto::{crypto_provider, to_pkcs12};
+
+/// Upper bound for the delay between startup provisioning attempts
+const MAX_PROVISION_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How long an identity read from the certificate and key files is reused
+/// before they are read again
+const STORED_IDENTITY_CACHE_TTL: Duration = Duration::from_secs(60);
 
 /// Client for interacting with Smallstep CA
 #[derive(Debug, Clone)]
@@ -19,12 +34,54 @@ pub struct SmallstepClient {
     base_url: String,
     /// Authorization token for API requests
     token: String,
-    /// Path to store certificate
-    cert_path: String,
-    /// Path to store private key
-    key_path: String,
+    /// File to read the authorization token from when `token` is empty
+    token_file: Option<PathBuf>,
+    /// Where issued certificates and keys are kept
+    storage: Arc<dyn IdentityStorage>,
     /// SPIFFE ID to use in CSR
     spiffe_id: String,
+    /// Retry policy for CA requests
+    retry: CaRetryConfig,
+    /// Remaining-lifetime percentage below which a stored certificate is replaced
+    renew_threshold_percent: u8,
+    /// Remaining lifetime in seconds below which a stored certificate is replaced
+    renew_before_seconds: Option<u64>,
+    /// Requested certificate validity in hours
+    cert_duration_hours: Option<u64>,
+    /// Audit log of issued certificates
+    issuance_log: Option<IssuanceLog>,
+    /// Webhook notified of issuance events
+    notifier: Option<Arc<EventNotifier>>,
+    /// PKCS#12 copy written alongside the PEM files
+    pkcs12: Option<Pkcs12OutputConfig>,
+}
+
+/// State of a certificate's validity period at a point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CertLifetime {
+    /// Valid with enough lifetime remaining
+    Valid,
+    /// Valid, but the remaining lifetime is below the percentage or absolute
+    /// renewal threshold
+    NearExpiry,
+    /// Expired or not yet valid
+    Invalid,
+}
+
+/// What a certificate request would contain, reported by a dry run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DryRunReport {
+    /// CA the request would be sent to
+    pub ca_url: String,
+
+    /// Subject alternative names of the generated CSR
+    pub sans: Vec<String>,
+
+    /// Signature algorithm of the generated CSR
+    pub signature_algorithm: String,
+
+    /// Requested validity, or none for the CA provisioner's default
+    pub requested_validity: Option<String>,
 }
 
 /// Request payload for certificate signing
@@ -32,6 +89,9 @@ pub struct SmallstepClient {
 struct SignRequest {
     csr: String,
     ott: String,
+    /// Requested validity as a duration such as "24h"
+    #[serde(rename = "notAfter", skip_serializing_if = "Option::is_none")]
+    not_after: Option<String>,
 }
 
 /// Response from certificate signing request
@@ -44,8 +104,10 @@ struct SignResponse {
 impl SmallstepClient {
     /// Create a new Smallstep CA client
     pub fn new(config: &CaConfig) -> Result<Self> {
-        // Create HTTP client with default settings
+        // Create HTTP client with the configured timeouts
         let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout_seconds))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_seconds))
             .build()
             .context("Failed to create HTTP client")?;
 
@@ -53,47 +115,266 @@ impl SmallstepClient {
             client,
             base_url: config.api_url.clone(),
             token: config.token.clone(),
-            cert_path: config.cert_path.display().to_string(),
-            key_path: config.key_path.display().to_string(),
+            token_file: config.token_file.clone(),
+            storage: match config.storage {
+                IdentityStorageType::File => Arc::new(CachingIdentityStorage::new(
+                    Arc::new(FileIdentityStorage::new(config.cert_path.clone(), config.key_path.clone())),
+                    STORED_IDENTITY_CACHE_TTL,
+                )),
+                IdentityStorageType::Memory => Arc::new(MemoryIdentityStorage::new()),
+            },
             spiffe_id: config.spiffe_id.clone(),
+            retry: config.retry.clone(),
+            renew_threshold_percent: config.renew_threshold_percent,
+            renew_before_seconds: config.renew_before_seconds,
+            cert_duration_hours: config.cert_duration_hours,
+            issuance_log: config.issuance_log.as_ref().map(IssuanceLog::new),
+            notifier: config
+                .webhook
+                .as_ref()
+                .map(|webhook| EventNotifier::new(webhook, Duration::from_secs(config.request_timeout_seconds)))
+                .transpose()?
+                .map(Arc::new),
+            pkcs12: config.pkcs12.clone(),
+        })
+    }
+
+    /// Check that the CA is reachable and healthy
+    pub async fn check_health(&self) -> Result<()> {
+        let url = format!("{}/health", self.base_url);
+        let response = self
+            .send_with_retry("CA health check", true, || self.client.get(&url))
+            .await
+            .context("Failed to reach CA health endpoint")?;
+
+        if !response.status().is_success() {
+            return Err(PqSecureError::CaClientError(format!(
+                "CA health check returned {}",
+                response.status()
+            ))
+            .into());
+        }
+
+        debug!("CA health check succeeded");
+        Ok(())
+    }
+
+    /// Check CA reachability, the token and CSR generation without requesting a certificate
+    ///
+    /// Nothing is sent to the sign endpoint and no file is written. step-ca
+    /// one-time tokens are consumed when used, so the token is only checked
+    /// to be readable and non-empty.
+    pub async fn dry_run(&self) -> Result<DryRunReport> {
+        self.check_health().await?;
+        self.current_token().await?;
+
+        let (csr_pem, _) = generate_csr(&self.spiffe_id).context("Failed to generate CSR")?;
+        let (sans, signature_algorithm) = describe_csr(&csr_pem)?;
+
+        Ok(DryRunReport {
+            ca_url: self.base_url.clone(),
+            sans,
+            signature_algorithm,
+            requested_validity: self.requested_validity(),
         })
     }
 
+    /// Validity sent with sign requests, as a duration such as "24h"
+    fn requested_validity(&self) -> Option<String> {
+        self.cert_duration_hours.map(|hours| format!("{}h", hours))
+    }
+
+    /// Send a request, retrying failures that are safe to repeat
+    ///
+    /// Connection failures are retried with exponential backoff. 5xx
+    /// responses, timeouts and other transport errors are only retried for
+    /// idempotent requests, since the CA may already have acted on them.
+    async fn send_with_retry<F>(
+        &self,
+        description: &str,
+        idempotent: bool,
+        build_request: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut backoff = Duration::from_millis(self.retry.backoff_ms);
+
+        for attempt in 1..=max_attempts {
+            let last_attempt = attempt == max_attempts;
+
+            match build_request().send().await {
+                Ok(response) if response.status().is_server_error() && idempotent && !last_attempt => {
+                    warn!(
+                        "{} failed with {} (attempt {}/{}), retrying in {:?}",
+                        description,
+                        response.status(),
+                        attempt,
+                        max_attempts,
+                        backoff
+                    );
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if !last_attempt && (e.is_connect() || idempotent) => {
+                    warn!(
+                        "{} failed: {} (attempt {}/{}), retrying in {:?}",
+                        description, e, attempt, max_attempts, backoff
+                    );
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+
+        unreachable!("the final attempt always returns")
+    }
+
     /// Load existing certificate and key or request new ones
     pub async fn load_or_request_cert(
         &self,
     ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
-        // Check if certificate and key files exist
-        if Path::new(&self.cert_path).exists() && Path::new(&self.key_path).exists() {
-            debug!("Loading existing certificate and key");
-            return self.load_cert_and_key().await;
+        match self.load_stored_identity().await {
+            // Only serve with the stored certificate if it will not fail handshakes soon
+            Ok(Some((certs, key))) => match self.cert_lifetime(&certs, SystemTime::now()) {
+                Ok(CertLifetime::Valid) => return Ok((certs, key)),
+                Ok(CertLifetime::NearExpiry) => {
+                    warn!("Stored certificate is close to expiry, requesting a new one")
+                }
+                Ok(CertLifetime::Invalid) => {
+                    warn!("Stored certificate is expired or not yet valid, requesting a new one")
+                }
+                Err(e) => warn!("Stored certificate is unusable ({}), requesting a new one", e),
+            },
+            Ok(None) => debug!("No stored identity"),
+            Err(e) => warn!("Stored identity is unreadable ({:#}), requesting a new one", e),
         }
 
         // Request new certificate
         info!("Requesting new certificate from CA");
-        self.request_cert().await?;
-        self.load_cert_and_key().await
+        if let Err(e) = self.request_cert().await {
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(CertificateEvent::issue_failed(&self.spiffe_id, format!("{:#}", e), SystemTime::now()));
+            }
+            return Err(e);
+        }
+        let (certs, key) = self
+            .load_cert_and_key()
+            .await?
+            .context("Issued identity was not stored")?;
+
+        // Refuse to start with a certificate that cannot complete a handshake
+        match self.cert_lifetime(&certs, SystemTime::now())? {
+            CertLifetime::Valid => {}
+            CertLifetime::NearExpiry => {
+                warn!("Certificate issued by the CA is already close to expiry")
+            }
+            CertLifetime::Invalid => {
+                return Err(PqSecureError::CertificateError(
+                    "Certificate issued by the CA is expired or not yet valid".to_string(),
+                )
+                .into());
+            }
+        }
+
+        Ok((certs, key))
     }
 
-    /// Load certificate and key from files
-    async fn load_cert_and_key(&self) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
-        // Load certificate from file
-        let cert_pem = fs::read_to_string(&self.cert_path)
-            .await
-            .context("Failed to read certificate file")?;
+    /// Provision the identity before serving, retrying until `wait` has elapsed
+    ///
+    /// Startup calls this before binding any listener, so a CA that is briefly
+    /// unavailable delays startup instead of failing it or the first clients.
+    pub async fn provision_identity(
+        &self,
+        wait: Duration,
+    ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let deadline = tokio::time::Instant::now() + wait;
+        let mut backoff = Duration::from_millis(self.retry.backoff_ms.max(1));
+
+        for attempt in 1.. {
+            match self.load_or_request_cert().await {
+                Ok(identity) => {
+                    info!("Identity {} provisioned (attempt {})", self.spiffe_id, attempt);
+                    return Ok(identity);
+                }
+                Err(e) if tokio::time::Instant::now() + backoff < deadline => {
+                    warn!(
+                        "Identity provisioning failed (attempt {}): {}, retrying in {:?}",
+                        attempt, e, backoff
+                    );
+                }
+                Err(e) => {
+                    return Err(e.context(format!(
+                        "Identity provisioning did not succeed within {:?} ({} attempts)",
+                        wait, attempt
+                    )));
+                }
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(MAX_PROVISION_BACKOFF);
+        }
+
+        unreachable!("the loop only exits by returning")
+    }
+
+    /// Classify the leaf certificate's validity period at the given time
+    fn cert_lifetime(&self, certs: &[CertificateDer<'_>], now: SystemTime) -> Result<CertLifetime> {
+        let leaf = certs
+            .first()
+            .ok_or_else(|| PqSecureError::CertificateError("Certificate file is empty".to_string()))?;
+        let (_, cert) = X509Certificate::from_der(leaf.as_ref())
+            .context("Failed to parse X.509 certificate")?;
+
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let not_before = cert.validity().not_before.timestamp();
+        let not_after = cert.validity().not_after.timestamp();
+
+        if now < not_before || now >= not_after {
+            return Ok(CertLifetime::Invalid);
+        }
+
+        // Compare in integers to avoid rounding at the threshold boundary
+        let lifetime = (not_after - not_before).max(1) as i128;
+        let remaining = (not_after - now) as i128;
+        if remaining * 100 < lifetime * self.renew_threshold_percent as i128 {
+            return Ok(CertLifetime::NearExpiry);
+        }
+        if self.renew_before_seconds.is_some_and(|before| remaining < before as i128) {
+            return Ok(CertLifetime::NearExpiry);
+        }
+
+        Ok(CertLifetime::Valid)
+    }
+
+    /// Load the stored certificate and key, checking that they belong together
+    ///
+    /// Files left behind by an older version or edited by hand may still be
+    /// truncated or from different identities.
+    async fn load_stored_identity(&self) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>> {
+        let Some((certs, key)) = self.load_cert_and_key().await? else {
+            return Ok(None);
+        };
+        CertifiedKey::from_der(certs.clone(), key.clone_key(), &crypto_provider())
+            .context("Stored certificate does not match the private key")?;
+        Ok(Some((certs, key)))
+    }
+
+    /// Load certificate and key from storage
+    async fn load_cert_and_key(&self) -> Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>> {
+        let Some((cert_pem, key_bytes)) = self.storage.load_identity().await? else {
+            return Ok(None);
+        };
 
         // Parse PEM certificate chain
         let mut cert_reader = cert_pem.as_bytes();
         let certs = rustls_pemfile::certs(&mut cert_reader)
-            .collect::<std::io::Result<Vec<_>>>()?
-            .into_iter()
-            .map(CertificateDer::from)
-            .collect();
-
-        // Load private key from file
-        let key_bytes = fs::read(&self.key_path)
-            .await
-            .context("Failed to read private key file")?;
+            .collect::<std::io::Result<Vec<_>>>()?;
 
         // Parse private key
         let key = if key_bytes.starts_with(b"-----BEGIN") {
@@ -103,7 +384,7 @@ impl SmallstepClient {
                 .collect::<std::io::Result<Vec<_>>>()?;
 
             if let Some(key) = keys.into_iter().next() {
-                PrivateKeyDer::Pkcs8(key.into())
+                PrivateKeyDer::Pkcs8(key)
             } else {
                 // Try RSA key if no PKCS8 key found
                 let mut key_reader = key_bytes.as_slice();
@@ -111,7 +392,7 @@ impl SmallstepClient {
                     .collect::<std::io::Result<Vec<_>>>()?;
 
                 if let Some(key) = keys.into_iter().next() {
-                    PrivateKeyDer::Pkcs1(key.into())
+                    PrivateKeyDer::Pkcs1(key)
                 } else {
                     return Err(anyhow::anyhow!("No private key found in file"));
                 }
@@ -121,7 +402,44 @@ impl SmallstepClient {
             PrivateKeyDer::Pkcs8(key_bytes.into())
         };
 
-        Ok((certs, key))
+        if let Some(leaf) = certs.first() {
+            match certificate_serial(leaf) {
+                Ok(serial) => info!("Loaded certificate with serial {}", serial),
+                Err(e) => warn!("Failed to read certificate serial: {}", e),
+            }
+        }
+
+        Ok(Some((certs, key)))
+    }
+
+    /// Resolve the token to use for the next CA request
+    ///
+    /// An explicit token takes precedence; otherwise the token file is re-read
+    /// so that rotated tokens are picked up without a restart.
+    async fn current_token(&self) -> Result<String> {
+        if !self.token.is_empty() {
+            return Ok(self.token.clone());
+        }
+
+        let token_file = self.token_file.as_ref().ok_or_else(|| {
+            PqSecureError::CaClientError("No CA token or token file configured".to_string())
+        })?;
+
+        let token = fs::read_to_string(token_file)
+            .await
+            .context(format!("Failed to read CA token file: {}", token_file.display()))?
+            .trim()
+            .to_string();
+
+        if token.is_empty() {
+            return Err(PqSecureError::CaClientError(format!(
+                "CA token file is empty: {}",
+                token_file.display()
+            ))
+            .into());
+        }
+
+        Ok(token)
     }
 
     /// Request a new certificate from the CA
@@ -129,27 +447,30 @@ impl SmallstepClient {
         // Generate CSR and private key
         let (csr_pem, key_der) = generate_csr(&self.spiffe_id).context("Failed to generate CSR")?;
 
+        let token = self.current_token().await?;
+
         // Set up headers for API request
         let mut headers = HeaderMap::new();
         headers.insert(
             AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", self.token)).context("Invalid token")?,
+            HeaderValue::from_str(&format!("Bearer {}", token)).context("Invalid token")?,
         );
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
         // Create request payload
         let sign_request = SignRequest {
             csr: csr_pem,
-            ott: self.token.clone(),
+            ott: token,
+            not_after: self.requested_validity(),
         };
 
-        // Make API request
+        // Make API request; signing is not idempotent, so only failures where
+        // the CA cannot have issued a certificate are retried
+        let url = format!("{}/1.0/sign", self.base_url);
         let response = self
-            .client
-            .post(&format!("{}/1.0/sign", self.base_url))
-            .headers(headers)
-            .json(&sign_request)
-            .send()
+            .send_with_retry("CA sign request", false, || {
+                self.client.post(&url).headers(headers.clone()).json(&sign_request)
+            })
             .await
             .context("Failed to send CSR to CA")?;
 
@@ -173,13 +494,46 @@ impl SmallstepClient {
         // Combine certificate with CA certificate
         let cert_chain = format!("{}\n{}", sign_response.crt, sign_response.ca);
 
-        // Save certificate and key to files
-        write_file_bytes(&self.cert_path, cert_chain.as_bytes())
-            .context("Failed to write certificate file")?;
-
-        write_file_bytes(&self.key_path, &key_der).context("Failed to write private key file")?;
+        // Save certificate and key
+        self.storage.save_identity(&cert_chain, &key_der).await?;
+        // The certificate is already issued and stored; a failed export must not
+        // send the caller back to the CA for another one
+        if let Err(e) = self.write_pkcs12(cert_chain.as_bytes(), &key_der) {
+            warn!("Failed to export PKCS#12 bundle: {:#}", e);
+        }
 
         info!("Certificate and key saved successfully");
+
+        if self.issuance_log.is_none() && self.notifier.is_none() {
+            return Ok(());
+        }
+
+        let leaf = rustls_pemfile::certs(&mut sign_response.crt.as_bytes())
+            .next()
+            .ok_or_else(|| PqSecureError::CertificateError("CA response contains no certificate".to_string()))?
+            .context("Failed to parse certificate from CA response")?;
+
+        if let Some(issuance_log) = &self.issuance_log {
+            let record = IssuanceRecord::from_certificate(&leaf, &self.spiffe_id, "smallstep", SystemTime::now())?;
+            issuance_log.append(&record).context("Failed to record certificate issuance")?;
+        }
+
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(CertificateEvent::issued(&self.spiffe_id, certificate_serial(&leaf)?, SystemTime::now()));
+        }
+
+        Ok(())
+    }
+
+    /// Write the issued identity as a PKCS#12 bundle too, if configured
+    fn write_pkcs12(&self, cert_chain: &[u8], key_der: &[u8]) -> Result<()> {
+        let Some(pkcs12) = &self.pkcs12 else {
+            return Ok(());
+        };
+
+        let bundle = to_pkcs12(cert_chain, key_der, None, &pkcs12.password)?;
+        write_file_bytes(&pkcs12.path, &bundle).context("Failed to write PKCS#12 bundle")?;
+        debug!("PKCS#12 bundle saved to {}", pkcs12.path.display());
         Ok(())
     }
 }
@@ -187,8 +541,280 @@ impl SmallstepClient {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::CaConfig;
+    use std::path::Path;
+    use crate::config::{CaConfig, IssuanceLogConfig, Pkcs12OutputConfig, WebhookConfig};
+    use crate::test_support::self_signed_cert;
+    use rcgen::{date_time_ymd, CertificateParams, KeyPair};
+    use std::sync::{Arc, Mutex};
     use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A request received by the mock CA
+    #[derive(Debug, Clone)]
+    struct RecordedRequest {
+        path: String,
+        authorization: Option<String>,
+        body: String,
+    }
+
+    /// Start a mock CA that answers each request with the next canned response
+    async fn spawn_mock_ca(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<RecordedRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded_clone = recorded.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                // Read until the end of the headers, then the declared body
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let header_end = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                };
+                let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+                let content_length = head
+                    .lines()
+                    .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .unwrap_or(0);
+                while buf.len() < header_end + content_length {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+
+                recorded_clone.lock().unwrap().push(RecordedRequest {
+                    path: head.split_whitespace().nth(1).unwrap_or_default().to_string(),
+                    authorization: head.lines().find_map(|l| {
+                        l.strip_prefix("authorization: ")
+                            .or_else(|| l.strip_prefix("Authorization: "))
+                            .map(str::to_string)
+                    }),
+                    body: String::from_utf8_lossy(&buf[header_end..]).to_string(),
+                });
+
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (base_url, recorded)
+    }
+
+    fn sign_response() -> String {
+        r#"{"crt":"-----BEGIN CERTIFICATE-----\nleaf\n-----END CERTIFICATE-----","ca":"-----BEGIN CERTIFICATE-----\nroot\n-----END CERTIFICATE-----"}"#.to_string()
+    }
+
+    fn test_config(dir: &Path, api_url: &str) -> CaConfig {
+        CaConfig {
+            api_url: api_url.to_string(),
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+            storage: IdentityStorageType::File,
+            token: String::new(),
+            token_file: None,
+            spiffe_id: "spiffe://example.org/service/test".to_string(),
+            request_timeout_seconds: 5,
+            connect_timeout_seconds: 5,
+            retry: CaRetryConfig {
+                max_attempts: 3,
+                backoff_ms: 10,
+            },
+            renew_threshold_percent: 10,
+            renew_before_seconds: None,
+            cert_duration_hours: None,
+            issuance_log: None,
+            webhook: None,
+            pkcs12: None,
+            startup_wait_seconds: 60,
+        }
+    }
+
+    /// Generate a self-signed certificate and key PEM valid between the given years
+    fn generate_cert_pem(not_before_year: i32, not_after_year: i32) -> (String, String) {
+        let mut params = CertificateParams::default();
+        params.not_before = date_time_ymd(not_before_year, 1, 1);
+        params.not_after = date_time_ymd(not_after_year, 1, 1);
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+        (cert.pem(), key_pair.serialize_pem())
+    }
+
+    /// Sign response carrying the given certificate PEM
+    fn sign_response_with(cert_pem: &str) -> String {
+        let escaped = cert_pem.trim().replace('\n', "\\n");
+        format!(r#"{{"crt":"{}","ca":"{}"}}"#, escaped, escaped)
+    }
+
+    #[tokio::test]
+    async fn test_issuance_events_sent_to_webhook() {
+        let dir = tempdir().unwrap();
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (200, sign_response_with(&cert_pem)),
+            (200, String::new()),
+            (500, String::new()),
+            (200, String::new()),
+        ])
+        .await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.retry.max_attempts = 1;
+        config.webhook = Some(WebhookConfig {
+            url: format!("{}/hook", base_url),
+            retry: CaRetryConfig::default(),
+            dead_letter_path: None,
+        });
+        let client = SmallstepClient::new(&config).unwrap();
+
+        // The mock serves requests one at a time, so wait for each delivery
+        let wait_for = |count: usize| {
+            let recorded = recorded.clone();
+            async move {
+                while recorded.lock().unwrap().len() < count {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        };
+
+        let (certs, _) = client.load_or_request_cert().await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), wait_for(2)).await.unwrap();
+
+        // A fresh client has no cached identity, so it goes back to the CA
+        fs::remove_file(&config.cert_path).await.unwrap();
+        let client = SmallstepClient::new(&config).unwrap();
+        assert!(client.load_or_request_cert().await.is_err());
+        tokio::time::timeout(Duration::from_secs(5), wait_for(4)).await.unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded[1].path, "/hook");
+        let issued: CertificateEvent = serde_json::from_str(&recorded[1].body).unwrap();
+        assert_eq!(issued.event, crate::ca::CertificateEventKind::CertificateIssued);
+        assert_eq!(issued.spiffe_id, "spiffe://example.org/service/test");
+        assert_eq!(issued.serial.unwrap(), certificate_serial(&certs[0]).unwrap());
+
+        assert_eq!(recorded[3].path, "/hook");
+        let failed: CertificateEvent = serde_json::from_str(&recorded[3].body).unwrap();
+        assert_eq!(failed.event, crate::ca::CertificateEventKind::CertificateIssueFailed);
+        assert!(failed.reason.unwrap().contains("500"));
+    }
+
+    #[tokio::test]
+    async fn test_stored_identity_served_from_cache() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![(500, String::new())]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.retry.max_attempts = 1;
+        let (cert_pem, key_pem) = generate_cert_pem(2000, 2100);
+        fs::write(&config.cert_path, &cert_pem).await.unwrap();
+        fs::write(&config.key_path, &key_pem).await.unwrap();
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let (first, _) = client.load_or_request_cert().await.unwrap();
+
+        // Within the cache TTL the identity is served from memory, not disk or the CA
+        fs::remove_file(&config.cert_path).await.unwrap();
+        fs::remove_file(&config.key_path).await.unwrap();
+        let (cached, _) = client.load_or_request_cert().await.unwrap();
+        assert_eq!(cached, first);
+        assert!(recorded.lock().unwrap().is_empty());
+
+        // Without the cache the removed files force a CA request
+        let client = SmallstepClient::new(&config).unwrap();
+        assert!(client.load_or_request_cert().await.is_err());
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_issues_nothing() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![(200, String::new()), (200, String::new())]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.cert_duration_hours = Some(24);
+        let client = SmallstepClient::new(&config).unwrap();
+
+        // Reachable, but no token to authenticate with
+        assert!(client.dry_run().await.unwrap_err().to_string().contains("No CA token"));
+
+        config.token = "test-token".to_string();
+        let client = SmallstepClient::new(&config).unwrap();
+        let report = client.dry_run().await.unwrap();
+        assert_eq!(
+            report,
+            DryRunReport {
+                ca_url: base_url,
+                sans: vec!["spiffe://example.org/service/test".to_string()],
+                signature_algorithm: "ecdsa-with-SHA256".to_string(),
+                requested_validity: Some("24h".to_string()),
+            }
+        );
+
+        let paths: Vec<String> = recorded.lock().unwrap().iter().map(|r| r.path.clone()).collect();
+        assert_eq!(paths, vec!["/health", "/health"]);
+        assert!(!config.cert_path.exists());
+        assert!(!config.key_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_token_file_reloaded_between_requests() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![(200, sign_response()), (200, sign_response())]).await;
+
+        let token_path = dir.path().join("token");
+        fs::write(&token_path, "first-token\n").await.unwrap();
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token_file = Some(token_path.clone());
+        let client = SmallstepClient::new(&config).unwrap();
+
+        client.request_cert().await.unwrap();
+
+        // Rotate the token on disk; the next request must pick it up
+        fs::write(&token_path, "second-token").await.unwrap();
+        client.request_cert().await.unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].path, "/1.0/sign");
+        assert_eq!(recorded[0].authorization.as_deref(), Some("Bearer first-token"));
+        assert_eq!(recorded[1].authorization.as_deref(), Some("Bearer second-token"));
+    }
+
+    #[tokio::test]
+    async fn test_explicit_token_takes_precedence() {
+        let dir = tempdir().unwrap();
+        let token_path = dir.path().join("token");
+        fs::write(&token_path, "file-token").await.unwrap();
+
+        let mut config = test_config(dir.path(), "http://127.0.0.1:1");
+        config.token = "explicit-token".to_string();
+        config.token_file = Some(token_path);
+        let client = SmallstepClient::new(&config).unwrap();
+
+        assert_eq!(client.current_token().await.unwrap(), "explicit-token");
+
+        config.token = String::new();
+        config.token_file = None;
+        let client = SmallstepClient::new(&config).unwrap();
+        assert!(client.current_token().await.is_err());
+    }
 
     #[tokio::test]
     async fn test_load_existing_cert() {
@@ -221,18 +847,15 @@ vZB8EpnLbJZhXMGnTgOHxJF6Ej8zgVIL5SXDNWrZPD7nM9QukXZMF/w0
 
         // Create client config
         let config = CaConfig {
-            api_url: "https://example.com".to_string(),
-            cert_path: cert_path.clone(),
-            key_path: key_path.clone(),
             token: "test-token".to_string(),
-            spiffe_id: "spiffe://example.org/service/test".to_string(),
+            ..test_config(dir.path(), "https://example.com")
         };
 
         let client = SmallstepClient::new(&config).unwrap();
         let result = client.load_cert_and_key().await;
 
         assert!(result.is_ok());
-        let (certs, key) = result.unwrap();
+        let (certs, key) = result.unwrap().unwrap();
         assert!(!certs.is_empty());
 
         // Just check that we got a key of a valid type
@@ -244,4 +867,319 @@ vZB8EpnLbJZhXMGnTgOHxJF6Ej8zgVIL5SXDNWrZPD7nM9QukXZMF/w0
         }
         // Key is valid if we got this far
     }
+
+    #[tokio::test]
+    async fn test_health_check_retries_server_errors() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (503, r#"{"status":"starting"}"#.to_string()),
+            (200, r#"{"status":"ok"}"#.to_string()),
+        ])
+        .await;
+
+        let client = SmallstepClient::new(&test_config(dir.path(), &base_url)).unwrap();
+        client.check_health().await.unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert!(recorded.iter().all(|r| r.path == "/health"));
+    }
+
+    #[tokio::test]
+    async fn test_health_check_gives_up_after_max_attempts() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (503, String::new()),
+            (503, String::new()),
+        ])
+        .await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.retry.max_attempts = 2;
+        let client = SmallstepClient::new(&config).unwrap();
+
+        assert!(client.check_health().await.is_err());
+        assert_eq!(recorded.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sign_not_retried_after_server_error() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (500, r#"{"message":"internal"}"#.to_string()),
+            (200, sign_response()),
+        ])
+        .await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        let client = SmallstepClient::new(&config).unwrap();
+
+        // The CA may have signed before failing, and the token is single-use
+        assert!(client.request_cert().await.is_err());
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sign_requests_configured_duration() {
+        let dir = tempdir().unwrap();
+        let (base_url, recorded) = spawn_mock_ca(vec![(200, sign_response()), (200, sign_response())]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        SmallstepClient::new(&config).unwrap().request_cert().await.unwrap();
+
+        config.cert_duration_hours = Some(1);
+        SmallstepClient::new(&config).unwrap().request_cert().await.unwrap();
+
+        let recorded = recorded.lock().unwrap();
+        assert!(!recorded[0].body.contains("notAfter"));
+        assert!(recorded[1].body.contains(r#""notAfter":"1h""#));
+    }
+
+    #[tokio::test]
+    async fn test_issued_certificate_is_logged() {
+        let dir = tempdir().unwrap();
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, _) = spawn_mock_ca(vec![(200, sign_response_with(&cert_pem))]).await;
+
+        let log_path = dir.path().join("issuance.log");
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.issuance_log = Some(IssuanceLogConfig {
+            path: log_path.clone(),
+            max_size_bytes: 1024 * 1024,
+            rotate_daily: false,
+        });
+        SmallstepClient::new(&config).unwrap().request_cert().await.unwrap();
+
+        let records = IssuanceLog::tail(&log_path, 10).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].spiffe_id, "spiffe://example.org/service/test");
+        assert_eq!(records[0].ca_type, "smallstep");
+        assert_eq!(records[0].not_after, date_time_ymd(2100, 1, 1).unix_timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_writes_no_files() {
+        let dir = tempdir().unwrap();
+        let (near_expiry_pem, _) = generate_cert_pem(2000, 2030);
+        let (renewed_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (200, sign_response_with(&near_expiry_pem)),
+            (200, sign_response_with(&renewed_pem)),
+        ])
+        .await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.storage = IdentityStorageType::Memory;
+        config.renew_before_seconds = Some(20 * 365 * 24 * 60 * 60);
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let (first, _) = client.load_or_request_cert().await.unwrap();
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+
+        // Close to expiry: renewed and kept in memory
+        let (renewed, _) = client.load_or_request_cert().await.unwrap();
+        assert_ne!(renewed, first);
+        assert_eq!(recorded.lock().unwrap().len(), 2);
+        assert_eq!(
+            client.storage.load_identity().await.unwrap().unwrap().0,
+            format!("{}\n{}", renewed_pem.trim(), renewed_pem.trim())
+        );
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_pkcs12_written_alongside_pem() {
+        let dir = tempdir().unwrap();
+        let p12_path = dir.path().join("identity.p12");
+        let mut config = test_config(dir.path(), "http://127.0.0.1:1");
+        config.pkcs12 = Some(Pkcs12OutputConfig {
+            path: p12_path.clone(),
+            password: "changeit".to_string(),
+        });
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let (cert, key_pair) = self_signed_cert();
+        client.write_pkcs12(cert.pem().as_bytes(), &key_pair.serialize_der()).unwrap();
+
+        let bundle = std::fs::read(&p12_path).unwrap();
+        let parsed = openssl::pkcs12::Pkcs12::from_der(&bundle).unwrap().parse2("changeit").unwrap();
+        assert_eq!(parsed.cert.unwrap().to_der().unwrap(), cert.der().to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_failed_pkcs12_export_keeps_issued_certificate() {
+        let dir = tempdir().unwrap();
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, recorded) = spawn_mock_ca(vec![(200, sign_response_with(&cert_pem))]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.pkcs12 = Some(Pkcs12OutputConfig {
+            path: dir.path().to_path_buf(),
+            password: "changeit".to_string(),
+        });
+        let client = SmallstepClient::new(&config).unwrap();
+        client.request_cert().await.unwrap();
+
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+        assert!(client.storage.load_identity().await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_cert_lifetime_thresholds() {
+        let dir = tempdir().unwrap();
+        let client = SmallstepClient::new(&test_config(dir.path(), "http://127.0.0.1:1")).unwrap();
+
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        let at_year = |year: i32| SystemTime::from(date_time_ymd(year, 1, 1));
+
+        assert_eq!(client.cert_lifetime(&certs, at_year(2050)).unwrap(), CertLifetime::Valid);
+        assert_eq!(client.cert_lifetime(&certs, at_year(2095)).unwrap(), CertLifetime::NearExpiry);
+        assert_eq!(client.cert_lifetime(&certs, at_year(2100)).unwrap(), CertLifetime::Invalid);
+        assert_eq!(client.cert_lifetime(&certs, at_year(1999)).unwrap(), CertLifetime::Invalid);
+        assert!(client.cert_lifetime(&[], at_year(2050)).is_err());
+    }
+
+    #[test]
+    fn test_cert_lifetime_absolute_threshold() {
+        let dir = tempdir().unwrap();
+        let mut config = test_config(dir.path(), "http://127.0.0.1:1");
+        config.renew_before_seconds = Some(20 * 365 * 24 * 60 * 60);
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut cert_pem.as_bytes())
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        let at_year = |year: i32| SystemTime::from(date_time_ymd(year, 1, 1));
+
+        // Fifteen years left is more than 10% of the century, but below the absolute lead time
+        assert_eq!(client.cert_lifetime(&certs, at_year(2070)).unwrap(), CertLifetime::Valid);
+        assert_eq!(client.cert_lifetime(&certs, at_year(2085)).unwrap(), CertLifetime::NearExpiry);
+
+        // The percentage still applies when it is crossed first
+        config.renew_before_seconds = Some(60);
+        let client = SmallstepClient::new(&config).unwrap();
+        assert_eq!(client.cert_lifetime(&certs, at_year(2095)).unwrap(), CertLifetime::NearExpiry);
+    }
+
+    #[tokio::test]
+    async fn test_expired_cert_is_replaced_at_startup() {
+        let dir = tempdir().unwrap();
+        let (expired_cert, expired_key) = generate_cert_pem(2000, 2001);
+        let (fresh_cert, _) = generate_cert_pem(2000, 2100);
+        let (base_url, recorded) = spawn_mock_ca(vec![(200, sign_response_with(&fresh_cert))]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        fs::write(&config.cert_path, &expired_cert).await.unwrap();
+        fs::write(&config.key_path, &expired_key).await.unwrap();
+
+        let client = SmallstepClient::new(&config).unwrap();
+        let (certs, _) = client.load_or_request_cert().await.unwrap();
+
+        assert_eq!(recorded.lock().unwrap().len(), 1);
+        assert_eq!(
+            client.cert_lifetime(&certs, SystemTime::now()).unwrap(),
+            CertLifetime::Valid
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_private_key_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, _) = spawn_mock_ca(vec![(200, sign_response_with(&cert_pem))]).await;
+
+        let mut config = test_config(&dir.path().join("identity"), &base_url);
+        config.token = "test-token".to_string();
+        SmallstepClient::new(&config).unwrap().request_cert().await.unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&config.key_path), 0o600);
+        assert_eq!(mode(&dir.path().join("identity")), 0o700);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_identity_is_replaced_at_startup() {
+        let (stored_cert, stored_key) = generate_cert_pem(2000, 2100);
+        let (_, other_key) = generate_cert_pem(2000, 2100);
+        let (fresh_cert, _) = generate_cert_pem(2000, 2100);
+
+        // A certificate cut off mid-write, and one paired with the wrong key
+        let truncated = &stored_cert[..stored_cert.len() / 2];
+        for (cert, key) in [(truncated, stored_key.as_str()), (stored_cert.as_str(), other_key.as_str())] {
+            let dir = tempdir().unwrap();
+            let (base_url, recorded) = spawn_mock_ca(vec![(200, sign_response_with(&fresh_cert))]).await;
+
+            let mut config = test_config(dir.path(), &base_url);
+            config.token = "test-token".to_string();
+            fs::write(&config.cert_path, cert).await.unwrap();
+            fs::write(&config.key_path, key).await.unwrap();
+
+            let client = SmallstepClient::new(&config).unwrap();
+            client.load_or_request_cert().await.unwrap();
+            assert_eq!(recorded.lock().unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_startup_fails_when_ca_issues_expired_cert() {
+        let dir = tempdir().unwrap();
+        let (expired_cert, _) = generate_cert_pem(2000, 2001);
+        let (base_url, _) = spawn_mock_ca(vec![(200, sign_response_with(&expired_cert))]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let err = client.load_or_request_cert().await.unwrap_err();
+        assert!(err.to_string().contains("expired or not yet valid"));
+    }
+
+    #[tokio::test]
+    async fn test_provisioning_waits_for_ca() {
+        let dir = tempdir().unwrap();
+        let (cert_pem, _) = generate_cert_pem(2000, 2100);
+        let (base_url, recorded) = spawn_mock_ca(vec![
+            (503, String::new()),
+            (503, String::new()),
+            (200, sign_response_with(&cert_pem)),
+        ])
+        .await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.retry.max_attempts = 1;
+        let client = SmallstepClient::new(&config).unwrap();
+
+        // Each failed round is a full load_or_request_cert with a single attempt
+        let (certs, _) = client.provision_identity(Duration::from_secs(10)).await.unwrap();
+        assert_eq!(recorded.lock().unwrap().len(), 3);
+        assert_eq!(client.cert_lifetime(&certs, SystemTime::now()).unwrap(), CertLifetime::Valid);
+    }
+
+    #[tokio::test]
+    async fn test_provisioning_gives_up_after_wait() {
+        let dir = tempdir().unwrap();
+        let (base_url, _) = spawn_mock_ca(vec![(503, String::new()); 10]).await;
+
+        let mut config = test_config(dir.path(), &base_url);
+        config.token = "test-token".to_string();
+        config.retry.max_attempts = 1;
+        let client = SmallstepClient::new(&config).unwrap();
+
+        let err = client.provision_identity(Duration::from_millis(50)).await.unwrap_err();
+        assert!(err.to_string().contains("did not succeed within"));
+    }
 }
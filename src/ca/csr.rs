@@ -2,6 +2,8 @@ use std::str::FromStr;
 use anyhow::{Context, Result};
 use rcgen::{CertificateParams, DnType, KeyPair, SanType};
 use tracing::debug;
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::*;
 
 /// Generate a CSR with SPIFFE ID as a SAN URI
 pub fn generate_csr(spiffe_id: &str) -> Result<(String, Vec<u8>)> {
@@ -50,6 +52,38 @@ pub fn generate_csr(spiffe_id: &str) -> Result<(String, Vec<u8>)> {
     Ok((csr_pem, key_der))
 }
 
+/// Read back the subject alternative names and signature algorithm of a PEM CSR
+pub fn describe_csr(csr_pem: &str) -> Result<(Vec<String>, String)> {
+    let der = rustls_pemfile::csr(&mut csr_pem.as_bytes())
+        .context("Failed to read CSR PEM")?
+        .context("No certificate signing request found")?;
+    let (_, csr) = X509CertificationRequest::from_der(der.as_ref())
+        .context("Failed to parse certificate signing request")?;
+
+    let sans = csr
+        .requested_extensions()
+        .into_iter()
+        .flatten()
+        .filter_map(|extension| match extension {
+            ParsedExtension::SubjectAlternativeName(san) => Some(&san.general_names),
+            _ => None,
+        })
+        .flatten()
+        .map(|name| match name {
+            GeneralName::URI(uri) => uri.to_string(),
+            GeneralName::DNSName(dns) => dns.to_string(),
+            other => other.to_string(),
+        })
+        .collect();
+
+    let algorithm = &csr.signature_algorithm.algorithm;
+    let signature_algorithm = oid2sn(algorithm, oid_registry())
+        .map(str::to_string)
+        .unwrap_or_else(|_| algorithm.to_id_string());
+
+    Ok((sans, signature_algorithm))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,5 +102,9 @@ mod tests {
 
         // Check that we got a non-empty private key
         assert!(!key_der.is_empty());
+
+        let (sans, signature_algorithm) = describe_csr(&csr_pem).unwrap();
+        assert_eq!(sans, vec![spiffe_id.to_string()]);
+        assert_eq!(signature_algorithm, "ecdsa-with-SHA256");
     }
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::info;
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::*;
+
+use crate::common::{certificate_fingerprint, certificate_serial};
+use crate::config::IssuanceLogConfig;
+
+/// Seconds in a UTC day, used for daily rotation
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One issued certificate, written as a JSON line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IssuanceRecord {
+    /// Time of issuance in seconds since the Unix epoch
+    pub timestamp: u64,
+
+    /// SPIFFE ID the certificate was requested for
+    pub spiffe_id: String,
+
+    /// Certificate serial number
+    pub serial: String,
+
+    /// SHA-256 fingerprint of the certificate
+    pub fingerprint: String,
+
+    /// Signature algorithm used by the issuing CA
+    pub signature_algorithm: String,
+
+    /// Start of the validity window in seconds since the Unix epoch
+    pub not_before: i64,
+
+    /// End of the validity window in seconds since the Unix epoch
+    pub not_after: i64,
+
+    /// Kind of CA that issued the certificate
+    pub ca_type: String,
+}
+
+impl IssuanceRecord {
+    /// Describe a DER-encoded certificate issued at `now`
+    pub fn from_certificate(cert_der: &[u8], spiffe_id: &str, ca_type: &str, now: SystemTime) -> Result<Self> {
+        let (_, cert) = X509Certificate::from_der(cert_der)
+            .context("Failed to parse X.509 certificate")?;
+        let algorithm = &cert.signature_algorithm.algorithm;
+
+        Ok(Self {
+            timestamp: now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            spiffe_id: spiffe_id.to_string(),
+            serial: certificate_serial(cert_der)?,
+            fingerprint: certificate_fingerprint(cert_der),
+            signature_algorithm: oid2sn(algorithm, oid_registry())
+                .map(str::to_string)
+                .unwrap_or_else(|_| algorithm.to_id_string()),
+            not_before: cert.validity().not_before.timestamp(),
+            not_after: cert.validity().not_after.timestamp(),
+            ca_type: ca_type.to_string(),
+        })
+    }
+}
+
+/// Append-only JSON-lines log of issued certificates
+///
+/// Every record is flushed to disk before `append` returns. When the file
+/// reaches the size limit, or on the first write of a new UTC day with daily
+/// rotation, it is renamed with a timestamp suffix and a new file is started.
+#[derive(Debug, Clone)]
+pub struct IssuanceLog {
+    /// Path of the active log file
+    path: PathBuf,
+
+    /// Rotate once the file reaches this size
+    max_size_bytes: u64,
+
+    /// Rotate when the UTC day changes
+    rotate_daily: bool,
+}
+
+impl IssuanceLog {
+    /// Create a log writing to the configured path
+    pub fn new(config: &IssuanceLogConfig) -> Self {
+        Self {
+            path: config.path.clone(),
+            max_size_bytes: config.max_size_bytes,
+            rotate_daily: config.rotate_daily,
+        }
+    }
+
+    /// Append a record and sync it to disk
+    pub fn append(&self, record: &IssuanceRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)
+                    .context(format!("Failed to create directory: {}", parent.display()))?;
+            }
+        }
+
+        self.rotate_if_needed(record.timestamp)?;
+
+        let mut line = serde_json::to_string(record).context("Failed to serialize issuance record")?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open issuance log: {}", self.path.display()))?;
+        file.write_all(line.as_bytes())
+            .context("Failed to write issuance record")?;
+        file.sync_data().context("Failed to sync issuance log")?;
+
+        info!(serial = %record.serial, spiffe_id = %record.spiffe_id, "Certificate issuance recorded");
+        Ok(())
+    }
+
+    /// Move the active file aside when it is too large or from a previous day
+    fn rotate_if_needed(&self, now: u64) -> Result<()> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(()),
+        };
+
+        let too_large = metadata.len() >= self.max_size_bytes;
+        let previous_day = self.rotate_daily
+            && metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .is_some_and(|modified| modified.as_secs() / SECONDS_PER_DAY != now / SECONDS_PER_DAY);
+
+        if too_large || previous_day {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(format!(".{}", now));
+            fs::rename(&self.path, &rotated)
+                .context(format!("Failed to rotate issuance log: {}", self.path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the last `count` records from a log file
+    pub fn tail<P: AsRef<Path>>(path: P, count: usize) -> Result<Vec<IssuanceRecord>> {
+        let content = fs::read_to_string(path.as_ref())
+            .context(format!("Failed to read issuance log: {}", path.as_ref().display()))?;
+        let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+
+        lines[lines.len().saturating_sub(count)..]
+            .iter()
+            .map(|line| serde_json::from_str(line).context("Failed to parse issuance record"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::self_signed_svid_der;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_append_and_tail() {
+        let dir = tempdir().unwrap();
+        let log = IssuanceLog::new(&IssuanceLogConfig {
+            path: dir.path().join("audit/issuance.log"),
+            max_size_bytes: 1024 * 1024,
+            rotate_daily: false,
+        });
+
+        let cert = self_signed_svid_der("spiffe://example.org/service/test");
+        for i in 0..3 {
+            let now = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000 + i);
+            let record = IssuanceRecord::from_certificate(&cert, "spiffe://example.org/service/test", "smallstep", now)
+                .unwrap();
+            log.append(&record).unwrap();
+        }
+
+        let records = IssuanceLog::tail(dir.path().join("audit/issuance.log"), 2).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].timestamp, 1_700_000_002);
+        assert_eq!(records[0].signature_algorithm, "ecdsa-with-SHA256");
+        assert_eq!(records[0].fingerprint, certificate_fingerprint(&cert));
+        assert!(records[0].not_before < records[0].not_after);
+    }
+
+    #[test]
+    fn test_rotates_at_size_limit() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("issuance.log");
+        let log = IssuanceLog::new(&IssuanceLogConfig {
+            path: path.clone(),
+            max_size_bytes: 1,
+            rotate_daily: false,
+        });
+
+        let record = IssuanceRecord::from_certificate(
+            &self_signed_svid_der("spiffe://example.org/service/test"),
+            "spiffe://example.org/service/test",
+            "smallstep",
+            SystemTime::now(),
+        )
+        .unwrap();
+        log.append(&record).unwrap();
+        log.append(&record).unwrap();
+
+        assert_eq!(IssuanceLog::tail(&path, 10).unwrap().len(), 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -1,5 +1,15 @@
 mod client;
 mod csr;
+mod issuance_log;
+mod mounted_secret;
+mod notifier;
+mod storage;
 
-pub use client::SmallstepClient;
-pub use csr::generate_csr;
\ No newline at end of file
+pub use client::{DryRunReport, SmallstepClient};
+pub use csr::{describe_csr, generate_csr};
+pub use issuance_log::{IssuanceLog, IssuanceRecord};
+pub use mounted_secret::MountedSecretProvider;
+pub use notifier::{CertificateEvent, CertificateEventKind, EventNotifier};
+pub use storage::{
+    CachingIdentityStorage, FileIdentityStorage, IdentityStorage, MemoryIdentityStorage, StoredIdentity,
+};
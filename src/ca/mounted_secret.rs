@@ -0,0 +1,173 @@
+use anyhow::Result;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::common::{load_cert_chain, load_private_key};
+use crate::config::MountedSecretConfig;
+use crate::crypto::SniCertResolver;
+use crate::identity::SvidPublisher;
+use crate::telemetry;
+
+/// Identity read from certificate files maintained outside the mesh
+///
+/// cert-manager writes `tls.crt` and `tls.key` into a Kubernetes Secret and
+/// replaces them in place when it renews the certificate, so no CA client is
+/// involved: the files are loaded at startup and polled for changes
+/// afterwards.
+#[derive(Debug, Clone)]
+pub struct MountedSecretProvider {
+    /// PEM certificate chain, leaf first
+    cert_path: PathBuf,
+
+    /// PEM private key
+    key_path: PathBuf,
+
+    /// Workload API fed with each renewed identity
+    svid_publisher: Option<SvidPublisher>,
+}
+
+impl MountedSecretProvider {
+    /// Create a provider reading the configured files
+    pub fn new(config: &MountedSecretConfig) -> Self {
+        Self {
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+            svid_publisher: None,
+        }
+    }
+
+    /// Also hand each renewed identity to the Workload API
+    pub fn with_svid_publisher(mut self, publisher: SvidPublisher) -> Self {
+        self.svid_publisher = Some(publisher);
+        self
+    }
+
+    /// Load the current certificate chain and private key
+    pub fn load(&self) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        Ok((load_cert_chain(&self.cert_path)?, load_private_key(&self.key_path)?))
+    }
+
+    /// Spawn a task presenting the renewed identity whenever the files change
+    ///
+    /// The files' modification times are polled every `interval`; Secret
+    /// volumes swap both files at once, so a change is picked up on the next
+    /// poll. An identity that fails to load, for example because the key no
+    /// longer matches the certificate, is logged and the current one is kept
+    /// until the files change again. The task stops when `shutdown` is
+    /// cancelled.
+    pub fn spawn_watcher(
+        self,
+        interval: Duration,
+        resolver: Arc<SniCertResolver>,
+        provider: Arc<CryptoProvider>,
+        shutdown: CancellationToken,
+    ) -> JoinHandle<()> {
+        let mut last_seen = self.file_mtimes();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        debug!("Mounted identity watcher stopped");
+                        return;
+                    }
+                    _ = ticker.tick() => {}
+                }
+
+                let current = self.file_mtimes();
+                if current == last_seen {
+                    continue;
+                }
+                last_seen = current;
+
+                let result = self.load().and_then(|(cert_chain, private_key)| {
+                    resolver.replace_default(cert_chain.clone(), private_key.clone_key(), &provider)?;
+                    if let Some(publisher) = &self.svid_publisher {
+                        if let Err(e) = publisher.publish(&cert_chain, &private_key) {
+                            warn!("Workload API keeps the previous SVID: {:#}", e);
+                        }
+                    }
+                    Ok(())
+                });
+                let cert_path = self.cert_path.display().to_string();
+                match &result {
+                    Ok(()) => info!("Presenting renewed identity from {}", cert_path),
+                    Err(e) => warn!("Keeping current identity: {:#}", e),
+                }
+                telemetry::record_identity_reload(&cert_path, result.is_ok());
+            }
+        })
+    }
+
+    /// Modification times of the certificate and key, `None` where unreadable
+    fn file_mtimes(&self) -> [Option<SystemTime>; 2] {
+        [&self.cert_path, &self.key_path].map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::crypto_provider;
+    use crate::test_support::self_signed_cert;
+    use rcgen::KeyPair;
+    use tempfile::tempdir;
+
+    /// Write a fresh self-signed identity, returning its DER certificate
+    fn write_identity(config: &MountedSecretConfig) -> CertificateDer<'static> {
+        let (cert, key_pair) = self_signed_cert();
+        std::fs::write(&config.cert_path, cert.pem()).unwrap();
+        std::fs::write(&config.key_path, key_pair.serialize_pem()).unwrap();
+        cert.der().clone()
+    }
+
+    #[tokio::test]
+    async fn test_watcher_presents_rotated_identity() {
+        let dir = tempdir().unwrap();
+        let config = MountedSecretConfig {
+            cert_path: dir.path().join("tls.crt"),
+            key_path: dir.path().join("tls.key"),
+            refresh_seconds: 1,
+        };
+        let original = write_identity(&config);
+
+        let provider = crypto_provider();
+        let mounted = MountedSecretProvider::new(&config);
+        let (cert_chain, private_key) = mounted.load().unwrap();
+        assert_eq!(cert_chain, vec![original.clone()]);
+        let resolver = Arc::new(SniCertResolver::new(cert_chain, private_key, &provider).unwrap());
+
+        let shutdown = CancellationToken::new();
+        let watcher = mounted.spawn_watcher(
+            Duration::from_millis(20),
+            resolver.clone(),
+            provider,
+            shutdown.clone(),
+        );
+
+        // Ensure the rewritten files get a different modification time
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let renewed = write_identity(&config);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while resolver.default_identity().end_entity_cert().unwrap() != &renewed {
+            assert!(tokio::time::Instant::now() < deadline, "renewed identity was not picked up");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // A key that does not match the certificate is not presented
+        std::fs::write(&config.key_path, KeyPair::generate().unwrap().serialize_pem()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(resolver.default_identity().end_entity_cert().unwrap(), &renewed);
+
+        shutdown.cancel();
+        watcher.await.unwrap();
+    }
+}
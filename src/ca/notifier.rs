@@ -0,0 +1,276 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, warn};
+
+use crate::config::{CaRetryConfig, WebhookConfig};
+
+/// Kind of certificate lifecycle event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateEventKind {
+    /// A new certificate was obtained from the CA and stored
+    CertificateIssued,
+    /// Requesting a new certificate from the CA failed
+    CertificateIssueFailed,
+}
+
+/// Certificate lifecycle event, sent to the webhook as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CertificateEvent {
+    /// What happened
+    pub event: CertificateEventKind,
+
+    /// SPIFFE ID of the certificate
+    pub spiffe_id: String,
+
+    /// Serial number of the issued certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+
+    /// Time of the event in seconds since the Unix epoch
+    pub timestamp: u64,
+
+    /// Why the operation failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl CertificateEvent {
+    /// A certificate with the given serial was issued at `now`
+    pub fn issued(spiffe_id: &str, serial: String, now: SystemTime) -> Self {
+        Self::new(CertificateEventKind::CertificateIssued, spiffe_id, Some(serial), None, now)
+    }
+
+    /// Requesting a certificate failed at `now`
+    pub fn issue_failed(spiffe_id: &str, reason: String, now: SystemTime) -> Self {
+        Self::new(CertificateEventKind::CertificateIssueFailed, spiffe_id, None, Some(reason), now)
+    }
+
+    fn new(
+        event: CertificateEventKind,
+        spiffe_id: &str,
+        serial: Option<String>,
+        reason: Option<String>,
+        now: SystemTime,
+    ) -> Self {
+        Self {
+            event,
+            spiffe_id: spiffe_id.to_string(),
+            serial,
+            timestamp: now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            reason,
+        }
+    }
+}
+
+/// Posts certificate lifecycle events to a webhook
+///
+/// Failed deliveries are retried with exponential backoff. Events that still
+/// cannot be delivered are appended to the dead-letter log, if configured.
+#[derive(Debug)]
+pub struct EventNotifier {
+    /// HTTP client for webhook requests
+    client: reqwest::Client,
+
+    /// URL to POST events to
+    url: String,
+
+    /// Retry policy for failed deliveries
+    retry: CaRetryConfig,
+
+    /// JSON-lines file receiving undeliverable events
+    dead_letter_path: Option<PathBuf>,
+}
+
+impl EventNotifier {
+    /// Create a notifier for the configured webhook
+    pub fn new(config: &WebhookConfig, request_timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()
+            .context("Failed to create webhook HTTP client")?;
+
+        Ok(Self {
+            client,
+            url: config.url.clone(),
+            retry: config.retry.clone(),
+            dead_letter_path: config.dead_letter_path.clone(),
+        })
+    }
+
+    /// Deliver an event in the background, so CA operations never wait for the webhook
+    pub fn notify(self: &Arc<Self>, event: CertificateEvent) {
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            let _ = notifier.deliver(&event).await;
+        });
+    }
+
+    /// Deliver an event, falling back to the dead-letter log once retries are exhausted
+    pub async fn deliver(&self, event: &CertificateEvent) -> Result<()> {
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut backoff = Duration::from_millis(self.retry.backoff_ms);
+
+        for attempt in 1..=max_attempts {
+            let failure = match self.client.post(&self.url).json(event).send().await {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Delivered {:?} event to webhook", event.event);
+                    return Ok(());
+                }
+                Ok(response) => format!("webhook returned {}", response.status()),
+                Err(e) => e.to_string(),
+            };
+
+            if attempt == max_attempts {
+                error!(
+                    "Failed to deliver {:?} event to webhook after {} attempts: {}",
+                    event.event, max_attempts, failure
+                );
+                if let Some(path) = &self.dead_letter_path {
+                    if let Err(e) = append_dead_letter(path, event) {
+                        error!("Failed to write undelivered event to {}: {}", path.display(), e);
+                    }
+                }
+                return Err(anyhow::anyhow!("Webhook delivery failed: {}", failure));
+            }
+
+            warn!(
+                "Webhook delivery failed: {} (attempt {}/{}), retrying in {:?}",
+                failure, attempt, max_attempts, backoff
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+
+        unreachable!("the final attempt always returns")
+    }
+}
+
+/// Append an undelivered event as a JSON line
+fn append_dead_letter(path: &Path, event: &CertificateEvent) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create directory: {}", parent.display()))?;
+        }
+    }
+
+    let mut line = serde_json::to_string(event).context("Failed to serialize event")?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open dead-letter log: {}", path.display()))?;
+    file.write_all(line.as_bytes()).context("Failed to write dead-letter event")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Start a webhook answering each request with the next status, returning the received bodies
+    async fn spawn_webhook(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let received_clone = received.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            received_clone.lock().unwrap().push(body.to_string());
+                            break;
+                        }
+                    }
+                }
+
+                let response = format!("HTTP/1.1 {} Mock\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (url, received)
+    }
+
+    fn notifier(url: String, dead_letter_path: Option<PathBuf>) -> EventNotifier {
+        EventNotifier::new(
+            &WebhookConfig {
+                url,
+                retry: CaRetryConfig {
+                    max_attempts: 2,
+                    backoff_ms: 10,
+                },
+                dead_letter_path,
+            },
+            Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delivery_is_retried() {
+        let (url, received) = spawn_webhook(vec![503, 200]).await;
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let event = CertificateEvent::issued("spiffe://example.org/service/test", "01:02".to_string(), now);
+
+        notifier(url, None).deliver(&event).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let payload: serde_json::Value = serde_json::from_str(&received[1]).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "event": "certificate_issued",
+                "spiffe_id": "spiffe://example.org/service/test",
+                "serial": "01:02",
+                "timestamp": 1_700_000_000u64,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undelivered_event_is_dead_lettered() {
+        let dir = tempdir().unwrap();
+        let dead_letter = dir.path().join("webhook/dead-letter.log");
+        let (url, _) = spawn_webhook(vec![500, 500]).await;
+        let event = CertificateEvent::issue_failed(
+            "spiffe://example.org/service/test",
+            "CA returned error: 503".to_string(),
+            SystemTime::now(),
+        );
+
+        assert!(notifier(url, Some(dead_letter.clone())).deliver(&event).await.is_err());
+
+        let content = fs::read_to_string(&dead_letter).unwrap();
+        let recorded: CertificateEvent = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(recorded, event);
+        assert!(!content.contains("\"serial\""));
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::fs;
+
+use crate::common::{warn_if_key_exposed, write_file_bytes};
+
+/// Issued identity as stored: the PEM certificate chain, leaf first, and
+/// the private key (DER or PEM)
+pub type StoredIdentity = (String, Vec<u8>);
+
+/// Where the CA client keeps the identity it was issued
+///
+/// The client loads the stored identity at startup to avoid a CA request
+/// and saves every newly issued one.
+#[async_trait::async_trait]
+pub trait IdentityStorage: Send + Sync + Debug {
+    /// Load the stored identity, or `None` if nothing has been stored yet
+    async fn load_identity(&self) -> Result<Option<StoredIdentity>>;
+
+    /// Replace the stored identity
+    async fn save_identity(&self, cert_chain: &str, key: &[u8]) -> Result<()>;
+}
+
+/// Identity kept in the configured certificate and key files
+#[derive(Debug, Clone)]
+pub struct FileIdentityStorage {
+    /// PEM certificate chain
+    cert_path: PathBuf,
+
+    /// Private key, readable by the owner only
+    key_path: PathBuf,
+}
+
+impl FileIdentityStorage {
+    /// Create a storage using the given files
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> Self {
+        Self { cert_path, key_path }
+    }
+}
+
+#[async_trait::async_trait]
+impl IdentityStorage for FileIdentityStorage {
+    async fn load_identity(&self) -> Result<Option<StoredIdentity>> {
+        if !self.cert_path.exists() || !self.key_path.exists() {
+            return Ok(None);
+        }
+
+        let cert_chain = fs::read_to_string(&self.cert_path)
+            .await
+            .context("Failed to read certificate file")?;
+        warn_if_key_exposed(&self.key_path);
+        let key = fs::read(&self.key_path).await.context("Failed to read private key file")?;
+        Ok(Some((cert_chain, key)))
+    }
+
+    async fn save_identity(&self, cert_chain: &str, key: &[u8]) -> Result<()> {
+        write_file_bytes(&self.cert_path, cert_chain.as_bytes()).context("Failed to write certificate file")?;
+        write_file_bytes(&self.key_path, key).context("Failed to write private key file")?;
+        Ok(())
+    }
+}
+
+/// Identity kept only in process memory
+///
+/// For ephemeral workloads that must never write a private key to disk. The
+/// identity is lost on restart, so every start requests a new certificate;
+/// renewals within the process replace it in place.
+#[derive(Debug, Default)]
+pub struct MemoryIdentityStorage {
+    /// Most recently saved identity
+    identity: RwLock<Option<StoredIdentity>>,
+}
+
+impl MemoryIdentityStorage {
+    /// Create an empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl IdentityStorage for MemoryIdentityStorage {
+    async fn load_identity(&self) -> Result<Option<StoredIdentity>> {
+        Ok(self.identity.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    async fn save_identity(&self, cert_chain: &str, key: &[u8]) -> Result<()> {
+        *self.identity.write().unwrap_or_else(|e| e.into_inner()) = Some((cert_chain.to_string(), key.to_vec()));
+        Ok(())
+    }
+}
+
+/// Storage decorator keeping the loaded identity in memory for a while
+///
+/// Repeated loads within `ttl`, such as every provisioning retry while the
+/// CA is unreachable, are served from memory instead of reading and
+/// parsing the underlying storage again. Saves write through and replace
+/// the cached identity, so a renewal is never hidden by the cache.
+#[derive(Debug)]
+pub struct CachingIdentityStorage {
+    /// Storage the identity is loaded from and saved to
+    inner: Arc<dyn IdentityStorage>,
+
+    /// How long a loaded identity is served from memory
+    ttl: Duration,
+
+    /// Cached identity and when it was loaded or saved
+    cached: RwLock<Option<(Instant, StoredIdentity)>>,
+}
+
+impl CachingIdentityStorage {
+    /// Cache identities loaded from `inner` for `ttl`
+    pub fn new(inner: Arc<dyn IdentityStorage>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cached: RwLock::new(None),
+        }
+    }
+
+    /// Drop the cached identity, so the next load reads the underlying storage
+    fn invalidate(&self) {
+        *self.cached.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl IdentityStorage for CachingIdentityStorage {
+    async fn load_identity(&self) -> Result<Option<StoredIdentity>> {
+        if let Some((stored_at, identity)) = &*self.cached.read().unwrap_or_else(|e| e.into_inner()) {
+            if stored_at.elapsed() < self.ttl {
+                return Ok(Some(identity.clone()));
+            }
+        }
+
+        // Nothing stored yet is not cached: it is cheap to find out again
+        let identity = self.inner.load_identity().await?;
+        if let Some(identity) = &identity {
+            *self.cached.write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), identity.clone()));
+        }
+        Ok(identity)
+    }
+
+    async fn save_identity(&self, cert_chain: &str, key: &[u8]) -> Result<()> {
+        // A failed save may have replaced only some of the stored files
+        if let Err(e) = self.inner.save_identity(cert_chain, key).await {
+            self.invalidate();
+            return Err(e);
+        }
+        *self.cached.write().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), (cert_chain.to_string(), key.to_vec())));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Memory storage counting how often it is loaded
+    #[derive(Debug, Default)]
+    struct CountingStorage {
+        inner: MemoryIdentityStorage,
+        loads: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl IdentityStorage for CountingStorage {
+        async fn load_identity(&self) -> Result<Option<StoredIdentity>> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            self.inner.load_identity().await
+        }
+
+        async fn save_identity(&self, cert_chain: &str, key: &[u8]) -> Result<()> {
+            self.inner.save_identity(cert_chain, key).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_caching_storage() {
+        let inner = Arc::new(CountingStorage::default());
+        inner.save_identity("first", b"key").await.unwrap();
+        let storage = CachingIdentityStorage::new(inner.clone(), Duration::from_millis(200));
+
+        // Repeated loads hit memory
+        for _ in 0..3 {
+            assert_eq!(storage.load_identity().await.unwrap().unwrap().0, "first");
+        }
+        assert_eq!(inner.loads.load(Ordering::SeqCst), 1);
+
+        // A save replaces the cached identity without another load
+        storage.save_identity("renewed", b"key").await.unwrap();
+        assert_eq!(storage.load_identity().await.unwrap().unwrap().0, "renewed");
+        assert_eq!(inner.loads.load(Ordering::SeqCst), 1);
+
+        // Changes made behind the cache show once it expires
+        inner.save_identity("external", b"key").await.unwrap();
+        assert_eq!(storage.load_identity().await.unwrap().unwrap().0, "renewed");
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(storage.load_identity().await.unwrap().unwrap().0, "external");
+        assert_eq!(inner.loads.load(Ordering::SeqCst), 2);
+    }
+}
@@ -32,6 +32,15 @@ pub enum PqSecureError {
     #[error("Connection error: {0}")]
     ConnectionError(String),
 
+    #[error("Upstream unreachable: {0}")]
+    UpstreamUnreachable(String),
+
+    #[error("Upstream timed out: {0}")]
+    UpstreamTimeout(String),
+
+    #[error("Upstream reset: {0}")]
+    UpstreamReset(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -39,7 +48,72 @@ pub enum PqSecureError {
     UnexpectedError(String),
 }
 
+impl PqSecureError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            PqSecureError::ConfigError(_) => "config_error",
+            PqSecureError::SpiffeIdError(_) => "invalid_spiffe_id",
+            PqSecureError::CertificateError(_) => "certificate_error",
+            PqSecureError::PolicyError(_) => "policy_error",
+            PqSecureError::ProxyError(_) => "proxy_error",
+            PqSecureError::CaClientError(_) => "ca_error",
+            PqSecureError::TlsError(_) => "tls_error",
+            PqSecureError::AuthenticationError(_) => "unauthenticated",
+            PqSecureError::AuthorizationError(_) => "access_denied",
+            PqSecureError::ConnectionError(_) => "connection_error",
+            PqSecureError::UpstreamUnreachable(_) => "upstream_unreachable",
+            PqSecureError::UpstreamTimeout(_) => "upstream_timeout",
+            PqSecureError::UpstreamReset(_) => "upstream_reset",
+            PqSecureError::IoError(_) => "io_error",
+            PqSecureError::UnexpectedError(_) => "internal_error",
+        }
+    }
+
+    /// HTTP status code to report for this error
+    pub fn http_status(&self) -> u16 {
+        match self {
+            PqSecureError::SpiffeIdError(_) => 400,
+            PqSecureError::TlsError(_) | PqSecureError::AuthenticationError(_) => 401,
+            PqSecureError::AuthorizationError(_) => 403,
+            PqSecureError::CaClientError(_) | PqSecureError::ProxyError(_) | PqSecureError::UpstreamReset(_) => 502,
+            PqSecureError::ConnectionError(_) | PqSecureError::UpstreamUnreachable(_) => 503,
+            PqSecureError::UpstreamTimeout(_) => 504,
+            PqSecureError::ConfigError(_)
+            | PqSecureError::CertificateError(_)
+            | PqSecureError::PolicyError(_)
+            | PqSecureError::IoError(_)
+            | PqSecureError::UnexpectedError(_) => 500,
+        }
+    }
+}
+
 /// Convert any error to an appropriate PqSecureError
 pub fn map_err_to_pqsecure<E: std::fmt::Display>(err: E, context: &str) -> PqSecureError {
     PqSecureError::UnexpectedError(format!("{}: {}", context, err))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_and_statuses() {
+        let cases = [
+            (PqSecureError::AuthorizationError("denied".into()), "access_denied", 403),
+            (PqSecureError::AuthenticationError("no cert".into()), "unauthenticated", 401),
+            (PqSecureError::SpiffeIdError("bad".into()), "invalid_spiffe_id", 400),
+            (PqSecureError::CaClientError("down".into()), "ca_error", 502),
+            (PqSecureError::ConnectionError("refused".into()), "connection_error", 503),
+            (PqSecureError::UpstreamUnreachable("refused".into()), "upstream_unreachable", 503),
+            (PqSecureError::UpstreamTimeout("slow".into()), "upstream_timeout", 504),
+            (PqSecureError::UpstreamReset("reset".into()), "upstream_reset", 502),
+            (PqSecureError::UnexpectedError("boom".into()), "internal_error", 500),
+        ];
+
+        for (error, code, status) in cases {
+            assert_eq!(error.code(), code, "{}", error);
+            assert_eq!(error.http_status(), status, "{}", error);
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::common::PqSecureError;
 
 /// Service identity with SPIFFE ID validation
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -12,6 +16,27 @@ pub struct ServiceIdentity {
     pub path: String,
 }
 
+/// SPIFFE ID given to clients that presented no certificate
+///
+/// Not a valid SPIFFE ID, so it can never collide with a real identity.
+pub const ANONYMOUS_SPIFFE_ID: &str = "anonymous";
+
+impl ServiceIdentity {
+    /// Identity of a client that presented no certificate
+    pub fn anonymous() -> Self {
+        Self {
+            spiffe_id: ANONYMOUS_SPIFFE_ID.to_string(),
+            trust_domain: String::new(),
+            path: String::new(),
+        }
+    }
+
+    /// Whether this is the identity of a client without a certificate
+    pub fn is_anonymous(&self) -> bool {
+        self.spiffe_id == ANONYMOUS_SPIFFE_ID && self.trust_domain.is_empty()
+    }
+}
+
 /// Represents the type of protocol for connection handling
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProtocolType {
@@ -23,6 +48,137 @@ pub enum ProtocolType {
     Grpc,
 }
 
+impl ProtocolType {
+    /// Lowercase protocol name as used in configuration and policy files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolType::Tcp => "tcp",
+            ProtocolType::Http => "http",
+            ProtocolType::Grpc => "grpc",
+        }
+    }
+}
+
+impl fmt::Display for ProtocolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProtocolType {
+    type Err = PqSecureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(ProtocolType::Tcp),
+            "http" => Ok(ProtocolType::Http),
+            "grpc" => Ok(ProtocolType::Grpc),
+            _ => Err(PqSecureError::ConfigError(format!(
+                "Unknown protocol '{}', expected one of: tcp, http, grpc",
+                s
+            ))),
+        }
+    }
+}
+
+/// Why a proxied connection ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CloseReason {
+    /// The client closed its side first
+    ClientEof,
+    /// The upstream closed its side first
+    UpstreamEof,
+    /// No data flowed in either direction for the idle timeout
+    IdleTimeout,
+    /// The connection reached its maximum allowed duration
+    MaxDuration,
+    /// The request was denied by policy
+    PolicyDeny,
+    /// The TLS handshake with the client failed
+    HandshakeFailed,
+    /// Connecting to or forwarding from the upstream failed
+    Error,
+}
+
+impl CloseReason {
+    /// Snake-case reason used as a log and metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::ClientEof => "client_eof",
+            CloseReason::UpstreamEof => "upstream_eof",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::MaxDuration => "max_duration",
+            CloseReason::PolicyDeny => "policy_deny",
+            CloseReason::HandshakeFailed => "handshake_failed",
+            CloseReason::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why a client was refused before any traffic was forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RejectionReason {
+    /// No client certificate was presented
+    NoClientCert,
+    /// The certificate carries no valid SPIFFE ID from a trusted domain
+    InvalidSpiffeId,
+    /// The certificate is expired or not yet valid
+    CertificateExpired,
+    /// The chain does not lead to the trust domain's bundle
+    UntrustedChain,
+    /// The chain exceeds the configured size or depth limits
+    ChainTooLarge,
+    /// The request was denied by policy
+    PolicyDeny,
+    /// Post-quantum key exchange is required but a classical one was negotiated
+    PqcRequired,
+    /// The certificate is listed in its issuer's CRL
+    CertificateRevoked,
+    /// No current CRL covers the certificate and revocation checking is strict
+    RevocationUnknown,
+    /// The request uses an HTTP method the proxy does not serve (CONNECT)
+    UnsupportedMethod,
+    /// The HTTP request head exceeds the configured size or header count
+    HeadersTooLarge,
+    /// The HTTP request head could not be parsed or did not arrive in time
+    MalformedRequest,
+    /// The HTTP bearer token is not a valid JWT-SVID for this proxy
+    InvalidJwtSvid,
+}
+
+impl RejectionReason {
+    /// Snake-case reason used as a log and metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectionReason::NoClientCert => "no_client_cert",
+            RejectionReason::InvalidSpiffeId => "invalid_spiffe_id",
+            RejectionReason::CertificateExpired => "certificate_expired",
+            RejectionReason::UntrustedChain => "untrusted_chain",
+            RejectionReason::ChainTooLarge => "chain_too_large",
+            RejectionReason::PolicyDeny => "policy_deny",
+            RejectionReason::PqcRequired => "pqc_required",
+            RejectionReason::CertificateRevoked => "certificate_revoked",
+            RejectionReason::RevocationUnknown => "revocation_unknown",
+            RejectionReason::UnsupportedMethod => "unsupported_method",
+            RejectionReason::HeadersTooLarge => "headers_too_large",
+            RejectionReason::MalformedRequest => "malformed_request",
+            RejectionReason::InvalidJwtSvid => "invalid_jwt_svid",
+        }
+    }
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Information about a connection for logging and policy decisions
 #[derive(Debug, Clone)]
 pub struct ConnectionInfo {
@@ -50,6 +206,12 @@ impl ConnectionInfo {
         }
     }
 
+    /// Keep the identifier the connection was accepted with
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = id.to_string();
+        self
+    }
+
     /// Set the identity for this connection
     pub fn with_identity(mut self, identity: ServiceIdentity) -> Self {
         self.identity = Some(identity);
@@ -1,7 +1,11 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use std::path::Path;
 use std::fs;
-use tracing::trace;
+use tracing::{trace, warn};
+use x509_parser::prelude::*;
+
+use crate::common::PqSecureError;
 
 /// Read a file as bytes, useful for loading certificates and keys
 pub async fn read_file_bytes<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
@@ -18,37 +22,325 @@ pub async fn read_file_string<P: AsRef<Path>>(path: P) -> Result<String> {
 }
 
 /// Write bytes to a file with proper permissions, useful for saving certificates
+///
+/// The data goes to a temporary file in the same directory, which is synced
+/// and then renamed over `path`, so a crash mid-write never leaves a
+/// truncated file behind.
 pub fn write_file_bytes<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
     let path = path.as_ref();
     trace!("Writing {} bytes to file: {}", data.len(), path.display());
 
     // Create parent directories if they don't exist
     if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)?;
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            create_private_dir_all(parent)?;
         }
     }
 
-    // Write the file with restrictive permissions
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Not a file path: {}", path.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = write_synced(&temp_path, data).and_then(|()| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(written?)
+}
+
+/// Create or replace a file with restrictive permissions and sync its contents
+fn write_synced(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
     #[cfg(unix)]
     {
         use std::os::unix::fs::OpenOptionsExt;
-        let mut options = fs::OpenOptions::new();
-        options.write(true).create(true).truncate(true).mode(0o600);
-        let mut file = options.open(path)?;
-        std::io::Write::write_all(&mut file, data)?;
+        options.mode(0o600);
     }
 
-    #[cfg(not(unix))]
+    let mut file = options.open(path)?;
+    std::io::Write::write_all(&mut file, data)?;
+    file.sync_all()
+}
+
+/// Create a directory and its missing parents, accessible only by the owner
+/// on Unix
+pub fn create_private_dir_all<P: AsRef<Path>>(path: P) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
     {
-        fs::write(path, data)?;
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
     }
 
-    Ok(())
+    builder
+        .create(path.as_ref())
+        .context(format!("Failed to create directory: {}", path.as_ref().display()))
+}
+
+/// Bind a Unix socket at `path` with the given permission bits
+///
+/// A stale socket from an earlier run is replaced, but any other file at the
+/// path is left alone. The socket is bound inside a private staging directory
+/// and its permissions set before it is moved into place, so nobody can
+/// connect while they are still those of the umask.
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &Path, mode: u32) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(PqSecureError::ConfigError(format!("Refusing to replace {}: not a socket", path.display())).into());
+        }
+        fs::remove_file(path).context(format!("Failed to remove stale socket: {}", path.display()))?;
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Not a file path: {}", path.display()))?;
+    let mut staging_name = std::ffi::OsString::from(".");
+    staging_name.push(file_name);
+    staging_name.push(format!(".{}", std::process::id()));
+    let staging_dir = path.with_file_name(staging_name);
+
+    let _ = fs::remove_dir_all(&staging_dir);
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging_dir)
+        .context(format!("Failed to create directory: {}", staging_dir.display()))?;
+
+    let staged = staging_dir.join(file_name);
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .map_err(anyhow::Error::from)
+        .and_then(|listener| {
+            fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+            fs::rename(&staged, path)?;
+            Ok(listener)
+        });
+    let _ = fs::remove_dir_all(&staging_dir);
+    bound.context(format!("Failed to bind Unix socket: {}", path.display()))
+}
+
+/// Warn when a private key file can be read by other users
+///
+/// Returns whether the file is exposed. Always `false` on platforms without
+/// Unix permissions.
+pub fn warn_if_key_exposed<P: AsRef<Path>>(path: P) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let path = path.as_ref();
+        if let Ok(metadata) = fs::metadata(path) {
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                warn!(
+                    "Private key {} is accessible by other users (mode {:o}), restrict it to 0600",
+                    path.display(),
+                    mode
+                );
+                return true;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = path;
+
+    false
 }
 
 /// Check if a file exists and is readable
 pub fn file_exists_and_readable<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
     path.exists() && path.is_file()
-}
\ No newline at end of file
+}
+
+/// Extract the serial number of a DER-encoded X.509 certificate
+///
+/// The serial is returned as colon-separated lowercase hex, matching the
+/// format used by the Smallstep CA and `step certificate inspect`.
+pub fn certificate_serial(cert_der: &[u8]) -> Result<String> {
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .context("Failed to parse X.509 certificate")?;
+    Ok(cert.raw_serial_as_string())
+}
+
+/// Load a PEM certificate chain from a file
+pub fn load_cert_chain<P: AsRef<Path>>(path: P) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = fs::read(path.as_ref())
+        .context(format!("Failed to read certificate file: {}", path.as_ref().display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<std::io::Result<Vec<_>>>()
+        .context(format!("Failed to parse certificates: {}", path.as_ref().display()))?;
+
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("No certificates found in {}", path.as_ref().display()));
+    }
+    Ok(certs)
+}
+
+/// Load the first PEM private key (PKCS#8, PKCS#1 or SEC1) from a file
+pub fn load_private_key<P: AsRef<Path>>(path: P) -> Result<PrivateKeyDer<'static>> {
+    warn_if_key_exposed(path.as_ref());
+    let pem = fs::read(path.as_ref())
+        .context(format!("Failed to read private key file: {}", path.as_ref().display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .context(format!("Failed to parse private key: {}", path.as_ref().display()))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", path.as_ref().display()))
+}
+
+/// SHA-256 fingerprint of a DER-encoded certificate as colon-separated lowercase hex
+pub fn certificate_fingerprint(cert_der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, cert_der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Prefix marking a listen or backend address as a Unix domain socket path
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+/// Join a host and port into an address, bracketing IPv6 literals
+///
+/// Hosts that are already bracketed are left as they are.
+pub fn join_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Split a `host:port` address into its host and port
+///
+/// IPv6 literals must be bracketed (`[::1]:8080`) and may carry a zone
+/// (`[fe80::1%eth0]:8080`); the returned host has the brackets removed.
+pub fn split_host_port(addr: &str) -> Result<(String, u16)> {
+    let (host, port) = match addr.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once("]:")
+            .ok_or_else(|| anyhow::anyhow!("Invalid address '{}': expected [host]:port", addr))?,
+        None => {
+            let (host, port) = addr
+                .rsplit_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Invalid address '{}': missing port", addr))?;
+            if host.contains(':') {
+                return Err(anyhow::anyhow!(
+                    "Invalid address '{}': IPv6 literals must be bracketed, e.g. [{}]:{}",
+                    addr,
+                    host,
+                    port
+                ));
+            }
+            (host, port)
+        }
+    };
+
+    if host.is_empty() {
+        return Err(anyhow::anyhow!("Invalid address '{}': missing host", addr));
+    }
+    let port = port
+        .parse::<u16>()
+        .context(format!("Invalid address '{}': bad port", addr))?;
+
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, KeyPair, SerialNumber};
+
+    #[test]
+    fn test_certificate_serial() {
+        let mut params = CertificateParams::default();
+        params.serial_number = Some(SerialNumber::from_slice(&[0x01, 0xab, 0x3f]));
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        assert_eq!(certificate_serial(cert.der()).unwrap(), "01:ab:3f");
+    }
+
+    #[test]
+    fn test_certificate_serial_invalid_der() {
+        assert!(certificate_serial(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn test_certificate_fingerprint() {
+        let fingerprint = certificate_fingerprint(b"abc");
+        assert!(fingerprint.starts_with("ba:78:16:bf"));
+        assert_eq!(fingerprint.split(':').count(), 32);
+    }
+
+    #[test]
+    fn test_write_file_bytes_replaces_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("certs/cert.pem");
+
+        write_file_bytes(&path, b"first").unwrap();
+        write_file_bytes(&path, b"second").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.path().join("certs")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        // Only the target remains; the temporary file was renamed over it
+        assert_eq!(fs::read_dir(dir.path().join("certs")).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_warn_if_key_exposed() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key.pem");
+
+        write_file_bytes(&path, b"key").unwrap();
+        assert!(!warn_if_key_exposed(&path));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        assert!(warn_if_key_exposed(&path));
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("10.0.0.1:8080").unwrap(), ("10.0.0.1".to_string(), 8080));
+        assert_eq!(split_host_port("[::1]:8080").unwrap(), ("::1".to_string(), 8080));
+        assert_eq!(split_host_port("[fe80::1%eth0]:443").unwrap(), ("fe80::1%eth0".to_string(), 443));
+        assert_eq!(split_host_port("backend.svc:9000").unwrap(), ("backend.svc".to_string(), 9000));
+
+        let err = split_host_port("::1:8080").unwrap_err();
+        assert!(err.to_string().contains("must be bracketed"));
+        assert!(split_host_port("backend.svc").is_err());
+        assert!(split_host_port(":8080").is_err());
+        assert!(split_host_port("[::1]").is_err());
+        assert!(split_host_port("host:http").is_err());
+    }
+
+    #[test]
+    fn test_join_host_port() {
+        assert_eq!(join_host_port("10.0.0.1", 8080), "10.0.0.1:8080");
+        assert_eq!(join_host_port("::1", 8080), "[::1]:8080");
+        assert_eq!(join_host_port("[::1]", 8080), "[::1]:8080");
+        assert_eq!(join_host_port("backend.svc", 9000), "backend.svc:9000");
+
+        let (host, port) = split_host_port(&join_host_port("fe80::1%eth0", 443)).unwrap();
+        assert_eq!((host.as_str(), port), ("fe80::1%eth0", 443));
+    }
+}
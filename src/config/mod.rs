@@ -1,15 +1,22 @@
 use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use spiffe::SpiffeId;
 use std::env;
 use std::fs;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tracing::{debug, info};
 
+use crate::common::{split_host_port, ProtocolType, UNIX_SOCKET_PREFIX};
+
 /// Main configuration structure for PQSecure Mesh
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// CA related configuration
+    /// CA related configuration (unused with the `mounted_secret` identity provider)
+    #[serde(default)]
     pub ca: CaConfig,
 
     /// Identity verification configuration
@@ -32,23 +39,456 @@ pub struct CaConfig {
     pub api_url: String,
 
     /// Path to store/load certificate
+    #[serde(default)]
     pub cert_path: PathBuf,
 
     /// Path to store/load private key
+    #[serde(default)]
     pub key_path: PathBuf,
 
+    /// Where the issued certificate and key are kept
+    #[serde(default)]
+    pub storage: IdentityStorageType,
+
     /// Bearer token for authentication with CA
+    #[serde(default)]
     pub token: String,
 
+    /// File containing the bearer token, re-read before each CA request
+    ///
+    /// Used only when `token` is empty, so rotated tokens take effect without a restart.
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+
     /// SPIFFE ID to use when generating CSR
     pub spiffe_id: String,
+
+    /// Timeout in seconds for a complete CA request
+    #[serde(default = "default_ca_request_timeout")]
+    pub request_timeout_seconds: u64,
+
+    /// Timeout in seconds for establishing a connection to the CA
+    #[serde(default = "default_ca_connect_timeout")]
+    pub connect_timeout_seconds: u64,
+
+    /// Retry policy for failed CA requests
+    #[serde(default)]
+    pub retry: CaRetryConfig,
+
+    /// Request a fresh certificate at startup when less than this percentage
+    /// of the stored certificate's lifetime remains
+    #[serde(default = "default_ca_renew_threshold_percent")]
+    pub renew_threshold_percent: u8,
+
+    /// Also request a fresh certificate when less than this many seconds of
+    /// its lifetime remain, whichever threshold is crossed first
+    #[serde(default)]
+    pub renew_before_seconds: Option<u64>,
+
+    /// Requested certificate validity in hours (the CA provisioner's default when unset)
+    #[serde(default)]
+    pub cert_duration_hours: Option<u64>,
+
+    /// Audit log of issued certificates (disabled when unset)
+    #[serde(default)]
+    pub issuance_log: Option<IssuanceLogConfig>,
+
+    /// Webhook notified of certificate issuance events (disabled when unset)
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+
+    /// Also write each issued identity as a PKCS#12 bundle (disabled when unset)
+    #[serde(default)]
+    pub pkcs12: Option<Pkcs12OutputConfig>,
+
+    /// How long startup keeps retrying identity provisioning before giving up;
+    /// listeners are not started until an identity is available
+    #[serde(default = "default_ca_startup_wait")]
+    pub startup_wait_seconds: u64,
+}
+
+impl Default for CaConfig {
+    fn default() -> Self {
+        Self {
+            api_url: String::new(),
+            cert_path: PathBuf::new(),
+            key_path: PathBuf::new(),
+            storage: IdentityStorageType::File,
+            token: String::new(),
+            token_file: None,
+            spiffe_id: String::new(),
+            request_timeout_seconds: default_ca_request_timeout(),
+            connect_timeout_seconds: default_ca_connect_timeout(),
+            retry: CaRetryConfig::default(),
+            renew_threshold_percent: default_ca_renew_threshold_percent(),
+            renew_before_seconds: None,
+            cert_duration_hours: None,
+            issuance_log: None,
+            webhook: None,
+            pkcs12: None,
+            startup_wait_seconds: default_ca_startup_wait(),
+        }
+    }
+}
+
+/// Storage for the identity issued by the CA
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityStorageType {
+    /// `cert_path` and `key_path`, reused across restarts
+    #[default]
+    File,
+    /// Process memory only; the private key never touches the disk and a
+    /// new certificate is requested on every start
+    Memory,
+}
+
+/// Default timeout for a complete CA request
+fn default_ca_request_timeout() -> u64 {
+    30
+}
+
+/// Default timeout for connecting to the CA
+fn default_ca_connect_timeout() -> u64 {
+    10
+}
+
+/// Default time to wait for the identity at startup
+fn default_ca_startup_wait() -> u64 {
+    60
+}
+
+/// Default remaining-lifetime percentage below which a certificate is replaced
+fn default_ca_renew_threshold_percent() -> u8 {
+    10
+}
+
+/// Append-only audit log of issued certificates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuanceLogConfig {
+    /// JSON-lines file to append records to
+    pub path: PathBuf,
+
+    /// Rotate the file once it reaches this size
+    #[serde(default = "default_issuance_log_max_size")]
+    pub max_size_bytes: u64,
+
+    /// Also rotate the file when the UTC day changes
+    #[serde(default)]
+    pub rotate_daily: bool,
+}
+
+/// Webhook receiving certificate lifecycle events as JSON POSTs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// URL to POST events to
+    pub url: String,
+
+    /// Retry policy for failed deliveries
+    #[serde(default)]
+    pub retry: CaRetryConfig,
+
+    /// JSON-lines file receiving events that could not be delivered
+    #[serde(default)]
+    pub dead_letter_path: Option<PathBuf>,
+}
+
+/// PKCS#12 copy of the identity for backends that cannot read PEM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pkcs12OutputConfig {
+    /// File to write the `.p12` bundle to
+    pub path: PathBuf,
+
+    /// Password protecting the bundle; required, since the bundle holds the
+    /// private key
+    #[serde(default)]
+    pub password: String,
+}
+
+/// Default issuance log size before rotation
+fn default_issuance_log_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+/// Retry policy for CA requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaRetryConfig {
+    /// Total number of attempts, including the first one
+    #[serde(default = "default_ca_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry in milliseconds, doubled on each further retry
+    #[serde(default = "default_ca_backoff_ms")]
+    pub backoff_ms: u64,
+}
+
+impl Default for CaRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_ca_max_attempts(),
+            backoff_ms: default_ca_backoff_ms(),
+        }
+    }
+}
+
+/// Default number of CA request attempts
+fn default_ca_max_attempts() -> u32 {
+    3
+}
+
+/// Default initial CA retry backoff
+fn default_ca_backoff_ms() -> u64 {
+    500
 }
 
 /// Identity verification configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdentityConfig {
-    /// Trusted domain for SPIFFE IDs
+    /// Trusted domain for SPIFFE IDs (shorthand for a single entry in `trusted_domains`)
+    #[serde(default)]
     pub trusted_domain: String,
+
+    /// Trust domains whose SPIFFE IDs are accepted, for federated meshes
+    #[serde(default)]
+    pub trusted_domains: Vec<TrustDomainConfig>,
+
+    /// Largest total size in bytes of a client certificate chain, checked
+    /// before any certificate in it is parsed
+    #[serde(default = "default_max_cert_chain_bytes")]
+    pub max_cert_chain_bytes: usize,
+
+    /// Largest number of certificates in a client chain, including the leaf
+    #[serde(default = "default_max_chain_depth")]
+    pub max_chain_depth: usize,
+
+    /// How often trust bundle files are checked for changes, in seconds
+    /// (0 disables reloading)
+    #[serde(default = "default_bundle_refresh_seconds")]
+    pub bundle_refresh_seconds: u64,
+
+    /// Revocation checking of client certificates
+    #[serde(default)]
+    pub revocation: RevocationConfig,
+
+    /// Whether clients must present a certificate
+    #[serde(default)]
+    pub mtls_mode: MtlsMode,
+
+    /// Where the proxy's own certificate comes from
+    #[serde(default)]
+    pub provider_type: IdentityProviderType,
+
+    /// Certificate files for the `mounted_secret` provider
+    #[serde(default)]
+    pub mounted_secret: Option<MountedSecretConfig>,
+
+    /// Warnings as the proxy's own certificate approaches expiry
+    #[serde(default)]
+    pub expiry_warning: ExpiryWarningConfig,
+
+    /// SPIFFE Workload API serving the proxy's identity to local workloads
+    /// (disabled when unset)
+    #[serde(default)]
+    pub workload_api: Option<WorkloadApiConfig>,
+
+    /// JWT-SVIDs accepted as `Authorization: Bearer` tokens from HTTP clients
+    /// without a certificate (disabled when unset)
+    #[serde(default)]
+    pub jwt_svid: Option<JwtSvidConfig>,
+}
+
+/// Verification of JWT-SVID bearer tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtSvidConfig {
+    /// Audiences a token must be issued for (any one of them suffices)
+    pub audience: Vec<String>,
+
+    /// JWKS bundles of the trust domains whose tokens are accepted
+    pub bundles: Vec<JwtBundleConfig>,
+}
+
+/// JWT authorities of one trust domain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtBundleConfig {
+    /// Trust domain name, e.g. `example.org`
+    pub domain: String,
+
+    /// JWKS document holding the domain's JWT signing keys
+    pub path: PathBuf,
+}
+
+/// Local SPIFFE Workload API endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkloadApiConfig {
+    /// Unix socket to serve on, created so only the proxy's user can connect
+    pub socket_path: PathBuf,
+}
+
+/// Warnings logged as the proxy's certificate runs out of lifetime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpiryWarningConfig {
+    /// Remaining-lifetime percentages that each warn once per certificate
+    /// (empty disables the warnings)
+    #[serde(default = "default_expiry_warning_thresholds")]
+    pub thresholds_percent: Vec<u8>,
+
+    /// How often the certificate is checked, in seconds
+    #[serde(default = "default_expiry_check_seconds")]
+    pub check_seconds: u64,
+}
+
+impl Default for ExpiryWarningConfig {
+    fn default() -> Self {
+        Self {
+            thresholds_percent: default_expiry_warning_thresholds(),
+            check_seconds: default_expiry_check_seconds(),
+        }
+    }
+}
+
+/// Default remaining-lifetime percentages that warn
+fn default_expiry_warning_thresholds() -> Vec<u8> {
+    vec![50, 20, 5]
+}
+
+/// Default interval between certificate expiry checks
+fn default_expiry_check_seconds() -> u64 {
+    300
+}
+
+/// Source of the proxy's own certificate and key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityProviderType {
+    /// Requested from the Smallstep CA configured under `ca`
+    #[default]
+    Smallstep,
+    /// Read from files kept current by another component, such as a
+    /// cert-manager Secret mounted into the pod
+    MountedSecret,
+}
+
+/// Certificate files written by cert-manager or a similar tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountedSecretConfig {
+    /// PEM certificate chain, leaf first (`tls.crt` in a cert-manager Secret)
+    pub cert_path: PathBuf,
+
+    /// PEM private key (`tls.key` in a cert-manager Secret)
+    pub key_path: PathBuf,
+
+    /// How often the files are checked for changes, in seconds
+    #[serde(default = "default_mounted_secret_refresh_seconds")]
+    pub refresh_seconds: u64,
+}
+
+/// Default interval between checks of mounted certificate files
+fn default_mounted_secret_refresh_seconds() -> u64 {
+    10
+}
+
+/// Whether client certificates are requested and required
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MtlsMode {
+    /// Every client must present a valid certificate
+    #[default]
+    Required,
+    /// Clients may omit the certificate and are then served as the
+    /// anonymous identity; certificates that are presented must be valid
+    Optional,
+    /// No certificate is requested and every client is anonymous
+    Disabled,
+}
+
+/// Revocation checking of client certificates against CRLs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationConfig {
+    /// Whether and how strictly revocation is checked
+    #[serde(default)]
+    pub mode: RevocationMode,
+
+    /// CRL files (PEM or DER), one or more per issuing CA
+    #[serde(default)]
+    pub crl_paths: Vec<PathBuf>,
+
+    /// How often the CRL files are re-read, in seconds
+    #[serde(default = "default_crl_refresh_seconds")]
+    pub refresh_seconds: u64,
+}
+
+impl Default for RevocationConfig {
+    fn default() -> Self {
+        Self {
+            mode: RevocationMode::default(),
+            crl_paths: Vec::new(),
+            refresh_seconds: default_crl_refresh_seconds(),
+        }
+    }
+}
+
+/// Default CRL refresh interval
+fn default_crl_refresh_seconds() -> u64 {
+    300
+}
+
+/// How a client certificate with unknown revocation status is treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationMode {
+    /// Revocation is not checked
+    #[default]
+    Off,
+    /// Revoked certificates are rejected; certificates without a current CRL
+    /// for their issuer are accepted with a warning
+    SoftFail,
+    /// Revoked certificates and certificates without a current CRL for
+    /// their issuer are rejected
+    HardFail,
+}
+
+/// Default client chain size limit, room for several ML-DSA-87 certificates
+fn default_max_cert_chain_bytes() -> usize {
+    64 * 1024
+}
+
+/// Default client chain depth limit
+fn default_max_chain_depth() -> usize {
+    8
+}
+
+/// Default trust bundle refresh interval
+fn default_bundle_refresh_seconds() -> u64 {
+    60
+}
+
+/// A trust domain accepted by the SPIFFE verifier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustDomainConfig {
+    /// Trust domain name, e.g. `example.org`
+    pub domain: String,
+
+    /// PEM bundle of root certificates that client certificates from this
+    /// domain must chain to (chains are not checked when unset)
+    #[serde(default)]
+    pub bundle_path: Option<PathBuf>,
+}
+
+impl IdentityConfig {
+    /// All configured trust domains, including the single `trusted_domain` shorthand
+    pub fn trust_domains(&self) -> Vec<TrustDomainConfig> {
+        let mut domains = Vec::with_capacity(self.trusted_domains.len() + 1);
+        if !self.trusted_domain.is_empty()
+            && !self.trusted_domains.iter().any(|d| d.domain == self.trusted_domain)
+        {
+            domains.push(TrustDomainConfig {
+                domain: self.trusted_domain.clone(),
+                bundle_path: None,
+            });
+        }
+        domains.extend(self.trusted_domains.iter().cloned());
+        domains
+    }
 }
 
 /// Policy engine configuration
@@ -56,171 +496,1136 @@ pub struct IdentityConfig {
 pub struct PolicyConfig {
     /// Path to policy definition file
     pub path: PathBuf,
+
+    /// What to do when the policy file does not exist
+    #[serde(default)]
+    pub on_missing: MissingPolicyAction,
+}
+
+/// Behavior when the configured policy file does not exist
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingPolicyAction {
+    /// Refuse to start
+    Fail,
+    /// Serve with a policy that denies every request
+    #[default]
+    DefaultDeny,
+    /// Serve with a policy that allows every request
+    DefaultAllow,
 }
 
 /// Proxy service configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
-    /// Address to listen on for incoming connections
-    pub listen_addr: SocketAddr,
+    /// Address to listen on for incoming connections, either `host:port` or
+    /// `unix:/path/to.sock` for a Unix socket
+    pub listen_addr: String,
 
     /// Backend service configuration
     pub backend: BackendConfig,
 
     /// Enabled protocols
     pub protocols: ProtocolsConfig,
-}
 
-/// Backend service configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BackendConfig {
-    /// Backend service address
-    pub address: String,
+    /// Response sent to HTTP clients denied by policy (connection is closed when unset)
+    #[serde(default)]
+    pub deny_response: Option<DenyResponseConfig>,
 
-    /// Connection timeout in seconds
-    pub timeout_seconds: u64,
+    /// Bounds on the HTTP request head read from clients
+    #[serde(default)]
+    pub http_limits: HttpLimitsConfig,
+
+    /// Headers added to HTTP responses, replacing any the backend sent
+    /// under the same name
+    #[serde(default)]
+    pub response_headers_add: Vec<HeaderConfig>,
+
+    /// Names of headers removed from HTTP responses
+    #[serde(default)]
+    pub response_headers_remove: Vec<String>,
+
+    /// Run a loopback mTLS handshake at startup and refuse to start if it fails
+    #[serde(default = "default_startup_self_test")]
+    pub startup_self_test: bool,
+
+    /// TLS key exchange groups in negotiation preference order
+    #[serde(default = "default_key_exchange_groups")]
+    pub key_exchange_groups: Vec<String>,
+
+    /// Reject clients whose handshake did not use a post-quantum key exchange
+    #[serde(default)]
+    pub require_pqc: bool,
+
+    /// Also accept plaintext clients for this long after startup, as the
+    /// anonymous identity, while they migrate to mTLS
+    #[serde(default)]
+    pub permit_plaintext_during_seconds: Option<u64>,
+
+    /// TCP keepalive for accepted client connections
+    #[serde(default)]
+    pub keepalive: KeepaliveConfig,
+
+    /// Options for the listening socket
+    #[serde(default)]
+    pub socket: ListenSocketConfig,
+
+    /// Additional server identities presented for specific SNI values; the
+    /// CA-issued identity is presented otherwise
+    #[serde(default)]
+    pub sni_identities: Vec<SniIdentityConfig>,
 }
 
-/// Protocol enablement configuration
+/// Server identity presented to clients requesting a given SNI server name
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ProtocolsConfig {
-    /// Enable TCP protocol
-    pub tcp: bool,
+pub struct SniIdentityConfig {
+    /// Server name sent by clients (matched case-insensitively)
+    pub server_name: String,
 
-    /// Enable HTTP protocol
-    pub http: bool,
+    /// PEM certificate chain to present
+    pub cert_path: PathBuf,
 
-    /// Enable gRPC protocol
-    pub grpc: bool,
+    /// PEM private key for the certificate
+    pub key_path: PathBuf,
 }
 
-/// Telemetry configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TelemetryConfig {
-    /// OpenTelemetry collector endpoint
-    pub otel_endpoint: Option<String>,
+/// Startup self-test is enabled unless explicitly turned off
+fn default_startup_self_test() -> bool {
+    true
+}
 
-    /// Service name for telemetry
-    pub service_name: String,
+/// Prefer the post-quantum hybrid, falling back to classical groups
+fn default_key_exchange_groups() -> Vec<String> {
+    crate::crypto::DEFAULT_KEY_EXCHANGE_GROUPS
+        .iter()
+        .map(|group| group.to_string())
+        .collect()
 }
 
-/// Load configuration from file and environment variables
-pub fn load_config() -> Result<Config> {
-    // 1. Determine config path from environment or use default
-    let config_path = env::var("PQSECURE_CONFIG")
-        .unwrap_or_else(|_| "config/config.yaml.example".to_string());
+/// HTTP response returned when a request is denied by policy
+///
+/// The body may contain `{spiffe_id}`, `{method}` and `{rule}` placeholders,
+/// which are replaced with the caller's SPIFFE ID, the request method and
+/// path, and the position of the denying rule (`default` when no rule
+/// matched).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DenyResponseConfig {
+    /// HTTP status code
+    #[serde(default = "default_deny_status")]
+    pub status: u16,
 
-    debug!("Loading configuration from {}", config_path);
+    /// Content-Type header value
+    #[serde(default = "default_deny_content_type")]
+    pub content_type: String,
 
-    // 2. Read and parse YAML configuration
-    let config_str = fs::read_to_string(&config_path)
-        .context(format!("Failed to read config file: {}", config_path))?;
+    /// Response body template
+    #[serde(default = "default_deny_body")]
+    pub body: String,
+}
 
-    let mut config: Config = serde_yaml::from_str(&config_str)
-        .context("Failed to parse YAML configuration")?;
+impl Default for DenyResponseConfig {
+    fn default() -> Self {
+        Self {
+            status: default_deny_status(),
+            content_type: default_deny_content_type(),
+            body: default_deny_body(),
+        }
+    }
+}
 
-    // 3. Override with environment variables if present
-    apply_env_overrides(&mut config);
+/// Default status for policy denials
+fn default_deny_status() -> u16 {
+    403
+}
 
-    // 4. Validate configuration
-    validate_config(&config)?;
+/// Default content type for policy denials
+fn default_deny_content_type() -> String {
+    "text/plain".to_string()
+}
 
-    info!("Configuration loaded successfully");
-    Ok(config)
+/// Default body for policy denials
+fn default_deny_body() -> String {
+    "Access denied by policy".to_string()
 }
 
-/// Apply environment variable overrides to configuration
-fn apply_env_overrides(config: &mut Config) {
-    if let Ok(url) = env::var("PQSECURE_CA_API_URL") {
-        config.ca.api_url = url;
-    }
+/// TCP keepalive settings, so idle connections survive NAT and firewall timeouts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepaliveConfig {
+    /// Enable TCP keepalive probes
+    #[serde(default = "default_keepalive_enabled")]
+    pub enabled: bool,
 
-    if let Ok(token) = env::var("PQSECURE_CA_TOKEN") {
-        config.ca.token = token;
-    }
+    /// Idle time before the first probe, in seconds
+    #[serde(default = "default_keepalive_idle_seconds")]
+    pub idle_seconds: u64,
 
-    if let Ok(addr) = env::var("PQSECURE_LISTEN_ADDR") {
-        if let Ok(socket_addr) = addr.parse() {
-            config.proxy.listen_addr = socket_addr;
+    /// Time between probes, in seconds
+    #[serde(default = "default_keepalive_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// Unanswered probes before the connection is dropped
+    #[serde(default = "default_keepalive_retries")]
+    pub retries: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_keepalive_enabled(),
+            idle_seconds: default_keepalive_idle_seconds(),
+            interval_seconds: default_keepalive_interval_seconds(),
+            retries: default_keepalive_retries(),
         }
     }
+}
 
-    if let Ok(backend) = env::var("PQSECURE_BACKEND_ADDR") {
-        config.proxy.backend.address = backend;
-    }
+/// Keepalive is on unless explicitly turned off
+fn default_keepalive_enabled() -> bool {
+    true
+}
 
-    if let Ok(otel) = env::var("PQSECURE_OTEL_ENDPOINT") {
-        config.telemetry.otel_endpoint = Some(otel);
-    }
+/// Probe well below common NAT idle timeouts
+fn default_keepalive_idle_seconds() -> u64 {
+    60
 }
 
-/// Validate configuration values
-fn validate_config(config: &Config) -> Result<()> {
-    // Validate CA configuration
-    if config.ca.api_url.is_empty() {
-        return Err(anyhow::anyhow!("CA API URL cannot be empty"));
-    }
+/// Default time between probes
+fn default_keepalive_interval_seconds() -> u64 {
+    10
+}
 
-    if config.ca.token.is_empty() {
-        return Err(anyhow::anyhow!("CA token cannot be empty"));
-    }
+/// Default number of unanswered probes
+fn default_keepalive_retries() -> u32 {
+    3
+}
 
-    if config.ca.spiffe_id.is_empty() {
-        return Err(anyhow::anyhow!("SPIFFE ID cannot be empty"));
-    }
+/// Options for the proxy's listening socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenSocketConfig {
+    /// Pending connections the kernel queues before refusing new ones
+    /// (capped by `net.core.somaxconn` on Linux)
+    #[serde(default = "default_listen_backlog")]
+    pub backlog: u32,
 
-    // Validate identity configuration
-    if config.identity.trusted_domain.is_empty() {
-        return Err(anyhow::anyhow!("Trusted domain cannot be empty"));
-    }
+    /// Set SO_REUSEADDR, so a restarted proxy can bind while old connections
+    /// are in TIME_WAIT
+    #[serde(default = "default_reuse_address")]
+    pub reuse_address: bool,
 
-    // Validate policy configuration
-    if !Path::new(&config.policy.path).exists() {
-        return Err(anyhow::anyhow!(
-            "Policy file does not exist: {}",
-            config.policy.path.display()
-        ));
-    }
+    /// Set SO_REUSEPORT, so several proxy processes can share the port
+    /// (Unix only)
+    #[serde(default)]
+    pub reuse_port: bool,
 
-    // Validate proxy configuration
-    if config.proxy.backend.address.is_empty() {
-        return Err(anyhow::anyhow!("Backend address cannot be empty"));
-    }
+    /// Permission bits of the socket file when listening on a Unix socket
+    #[serde(default = "default_unix_socket_mode")]
+    pub unix_mode: u32,
+}
 
-    if config.proxy.backend.timeout_seconds == 0 {
-        return Err(anyhow::anyhow!("Backend timeout cannot be zero"));
+impl Default for ListenSocketConfig {
+    fn default() -> Self {
+        Self {
+            backlog: default_listen_backlog(),
+            reuse_address: default_reuse_address(),
+            reuse_port: false,
+            unix_mode: default_unix_socket_mode(),
+        }
     }
+}
 
-    if !config.proxy.protocols.tcp && !config.proxy.protocols.http && !config.proxy.protocols.grpc {
-        return Err(anyhow::anyhow!("At least one protocol must be enabled"));
-    }
+/// Default listen backlog, enough to absorb connection bursts
+fn default_listen_backlog() -> u32 {
+    1024
+}
 
-    Ok(())
+/// Fast restarts are allowed unless explicitly turned off
+fn default_reuse_address() -> bool {
+    true
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::Write;
-    use tempfile::tempdir;
+/// Default Unix listen socket mode: the proxy's user and group may connect
+fn default_unix_socket_mode() -> u32 {
+    0o660
+}
 
-    #[test]
-    fn test_load_valid_config() {
-        let dir = tempdir().unwrap();
-        let config_path = dir.path().join("config.yaml.example");
+/// An HTTP header name and value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderConfig {
+    /// Header name (matched case-insensitively)
+    pub name: String,
 
-        let config_content = r#"
-ca:
-  api_url: "https://ca.example.com"
-  cert_path: "./certs/cert.pem"
+    /// Header value
+    pub value: String,
+}
+
+/// Bounds on the HTTP request head (request line and headers)
+///
+/// The head is read into memory to find the method, path and host, so these
+/// limits bound what a single client can make the proxy buffer. Requests
+/// exceeding either limit are answered with `431 Request Header Fields Too
+/// Large`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpLimitsConfig {
+    /// Most header fields accepted in a request
+    #[serde(default = "default_max_header_count")]
+    pub max_header_count: usize,
+
+    /// Largest request head accepted, in bytes, including the request line
+    #[serde(default = "default_max_total_header_bytes")]
+    pub max_total_header_bytes: usize,
+}
+
+impl Default for HttpLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_header_count: default_max_header_count(),
+            max_total_header_bytes: default_max_total_header_bytes(),
+        }
+    }
+}
+
+/// Default header count, as accepted by common HTTP servers
+fn default_max_header_count() -> usize {
+    100
+}
+
+/// Default request head size limit
+fn default_max_total_header_bytes() -> usize {
+    16 * 1024
+}
+
+/// Backend service configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendConfig {
+    /// Backend service address
+    pub address: String,
+
+    /// Idle timeout in seconds, used when `forward_idle_timeout_ms` is unset
+    pub timeout_seconds: u64,
+
+    /// Cap on each direction of a forwarded connection, in bytes per second
+    /// (unlimited when unset)
+    #[serde(default)]
+    pub max_bytes_per_second: Option<u64>,
+
+    /// Maximum time to establish a backend connection, in milliseconds
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+
+    /// Close a forwarded connection after no data flows for this many milliseconds
+    #[serde(default)]
+    pub forward_idle_timeout_ms: Option<u64>,
+
+    /// Close a forwarded connection after this many seconds regardless of activity
+    #[serde(default)]
+    pub max_connection_duration_seconds: Option<u64>,
+
+    /// TCP keepalive for backend connections
+    #[serde(default)]
+    pub keepalive: KeepaliveConfig,
+
+    /// Shadow upstream receiving a copy of client traffic
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+
+    /// Skip backend addresses that keep failing (disabled when unset)
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+}
+
+/// Shadow upstream that receives a copy of client traffic
+///
+/// The mirror's responses are discarded and its failures never affect the
+/// connection to the primary backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorConfig {
+    /// Mirror address, in the same forms as the backend address
+    pub address: String,
+
+    /// Fraction of connections to mirror, in (0, 1]
+    #[serde(default = "default_mirror_sample_rate")]
+    pub sample_rate: f64,
+}
+
+/// Passive outlier detection across the addresses the backend resolves to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlierDetectionConfig {
+    /// Consecutive connection failures that eject an address
+    #[serde(default = "default_outlier_consecutive_failures")]
+    pub consecutive_failures: u32,
+
+    /// How long a first ejection lasts, in seconds; repeated ejections last longer
+    #[serde(default = "default_outlier_ejection_duration")]
+    pub ejection_duration_seconds: u64,
+
+    /// Upper bound on the percentage of addresses ejected at once
+    #[serde(default = "default_outlier_max_ejection_percent")]
+    pub max_ejection_percent: u8,
+}
+
+/// Default consecutive failures before ejection
+fn default_outlier_consecutive_failures() -> u32 {
+    5
+}
+
+/// Default duration of a first ejection
+fn default_outlier_ejection_duration() -> u64 {
+    30
+}
+
+/// Default share of addresses that may be ejected at once
+fn default_outlier_max_ejection_percent() -> u8 {
+    50
+}
+
+/// Mirror every connection unless sampled down
+fn default_mirror_sample_rate() -> f64 {
+    1.0
+}
+
+/// Fail fast on unreachable backends
+fn default_connect_timeout_ms() -> u64 {
+    2000
+}
+
+impl BackendConfig {
+    /// Timeout for establishing a backend connection
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    /// Timeout for a forwarded connection without traffic
+    pub fn forward_idle_timeout(&self) -> Duration {
+        self.forward_idle_timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or_else(|| Duration::from_secs(self.timeout_seconds))
+    }
+
+    /// Hard limit on the lifetime of a forwarded connection
+    pub fn max_connection_duration(&self) -> Option<Duration> {
+        self.max_connection_duration_seconds.map(Duration::from_secs)
+    }
+}
+
+/// Protocol enablement configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolsConfig {
+    /// Enable TCP protocol
+    pub tcp: bool,
+
+    /// Enable HTTP protocol
+    pub http: bool,
+
+    /// Enable gRPC protocol
+    pub grpc: bool,
+}
+
+impl ProtocolsConfig {
+    /// Enabled protocols in handler detection order
+    ///
+    /// gRPC and HTTP are probed before raw TCP, which accepts any connection
+    /// and therefore has to be the last handler tried.
+    pub fn enabled(&self) -> Vec<ProtocolType> {
+        let mut protocols = Vec::with_capacity(3);
+        if self.grpc {
+            protocols.push(ProtocolType::Grpc);
+        }
+        if self.http {
+            protocols.push(ProtocolType::Http);
+        }
+        if self.tcp {
+            protocols.push(ProtocolType::Tcp);
+        }
+        protocols
+    }
+}
+
+/// Telemetry configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// OpenTelemetry collector endpoint
+    pub otel_endpoint: Option<String>,
+
+    /// Service name for telemetry
+    pub service_name: String,
+
+    /// Interval in seconds between process CPU/memory samples (disabled when unset)
+    #[serde(default)]
+    pub resource_sample_interval_seconds: Option<u64>,
+}
+
+/// Default config path when neither `--config` nor `PQSECURE_CONFIG` is given
+pub const DEFAULT_CONFIG_PATH: &str = "config/config.yaml.example";
+
+/// Placeholder shown instead of secret values when printing configuration
+const REDACTED: &str = "<redacted>";
+
+impl Config {
+    /// Return a copy of the configuration with secret values redacted
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        if !config.ca.token.is_empty() {
+            config.ca.token = REDACTED.to_string();
+        }
+        if let Some(pkcs12) = config.ca.pkcs12.as_mut().filter(|p| !p.password.is_empty()) {
+            pkcs12.password = REDACTED.to_string();
+        }
+        config
+    }
+}
+
+/// Load configuration from file and environment variables
+pub fn load_config() -> Result<Config> {
+    // Determine config path from environment or use default
+    let config_path = env::var("PQSECURE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+
+    load_config_from_path(config_path)
+}
+
+/// Load configuration from the given file, applying environment variables
+pub fn load_config_from_path<P: AsRef<Path>>(path: P) -> Result<Config> {
+    let config_path = path.as_ref().display().to_string();
+
+    debug!("Loading configuration from {}", config_path);
+
+    // 1. Read and parse YAML configuration
+    let config_str = fs::read_to_string(&config_path)
+        .context(format!("Failed to read config file: {}", config_path))?;
+
+    // 2. Substitute ${VAR} and ${VAR:-default} references
+    let config_str = interpolate_env(&config_str)
+        .context(format!("Failed to interpolate config file: {}", config_path))?;
+
+    let mut config: Config = serde_yaml::from_str(&config_str)
+        .context("Failed to parse YAML configuration")?;
+
+    // 3. Override with environment variables if present
+    apply_env_overrides(&mut config);
+
+    // 4. Validate configuration
+    validate_config(&config)?;
+
+    info!("Configuration loaded successfully");
+    Ok(config)
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` references with environment values
+///
+/// A default is used when the variable is unset or empty. References to unset
+/// variables without a default are reported together as a single error.
+/// References inside `#` comments are left alone. Values are escaped for the
+/// quoted scalar they land in; unquoted references reject values that would
+/// change the structure of the document, such as line breaks or `: `.
+fn interpolate_env(input: &str) -> Result<String> {
+    static ENV_REF: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").expect("valid regex")
+    });
+
+    let mut missing = Vec::new();
+    let mut unsafe_values = Vec::new();
+    let mut output = String::with_capacity(input.len());
+    for (text, scalar) in yaml_segments(input) {
+        let Some(style) = scalar else {
+            output.push_str(text);
+            continue;
+        };
+
+        let replaced = ENV_REF.replace_all(text, |caps: &regex::Captures<'_>| {
+            let name = &caps[1];
+            let value = match (env::var(name), caps.get(2)) {
+                (Ok(value), Some(default)) if value.is_empty() => return default.as_str().to_string(),
+                (Ok(value), _) => value,
+                (Err(_), Some(default)) => return default.as_str().to_string(),
+                (Err(_), None) => {
+                    missing.push(name.to_string());
+                    return String::new();
+                }
+            };
+            let whole = caps.get(0).expect("match");
+            let at_scalar_start = text[..whole.start()].chars().next_back().is_none_or(char::is_whitespace);
+            match style.escape(&value, at_scalar_start) {
+                Some(escaped) => escaped,
+                None => {
+                    unsafe_values.push(name.to_string());
+                    String::new()
+                }
+            }
+        });
+        output.push_str(&replaced);
+    }
+
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Environment variable(s) referenced in config are not set: {}",
+            missing.join(", ")
+        ));
+    }
+
+    if !unsafe_values.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Environment variable(s) contain line breaks or YAML syntax; quote the reference in the config: {}",
+            unsafe_values.join(", ")
+        ));
+    }
+
+    Ok(output)
+}
+
+/// How a scalar is written, which decides how a substituted value is escaped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScalarStyle {
+    Plain,
+    SingleQuoted,
+    DoubleQuoted,
+}
+
+impl ScalarStyle {
+    /// Escape `value` for this style, or `None` if it cannot be represented
+    ///
+    /// Indicator characters only matter at the start of a plain scalar.
+    fn escape(self, value: &str, at_scalar_start: bool) -> Option<String> {
+        if value.contains(['\n', '\r']) {
+            return None;
+        }
+        match self {
+            ScalarStyle::DoubleQuoted => Some(value.replace('\\', "\\\\").replace('"', "\\\"")),
+            ScalarStyle::SingleQuoted => Some(value.replace('\'', "''")),
+            ScalarStyle::Plain => {
+                let structural = value.contains(": ")
+                    || value.contains(" #")
+                    || value.ends_with(':')
+                    || (at_scalar_start && value.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c)));
+                (!structural).then(|| value.to_string())
+            }
+        }
+    }
+}
+
+/// Split a YAML document into runs of scalar text and comments
+///
+/// Comments come back with no style. Quotes only open at the start of a
+/// scalar, so apostrophes inside plain text are not mistaken for quoting.
+fn yaml_segments(input: &str) -> Vec<(&str, Option<ScalarStyle>)> {
+    let mut segments = Vec::new();
+    let mut style = ScalarStyle::Plain;
+    let mut in_comment = false;
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_scalar_start = prev.is_none_or(|p| p.is_whitespace() || "[{,".contains(p));
+        match (in_comment, style, c) {
+            (true, _, '\n') => {
+                segments.push((&input[start..i], None));
+                in_comment = false;
+                start = i;
+            }
+            (true, _, _) => {}
+            (false, ScalarStyle::Plain, '#') if prev.is_none_or(char::is_whitespace) => {
+                segments.push((&input[start..i], Some(style)));
+                in_comment = true;
+                start = i;
+            }
+            (false, ScalarStyle::Plain, '"' | '\'') if at_scalar_start => {
+                segments.push((&input[start..i], Some(style)));
+                style = if c == '"' { ScalarStyle::DoubleQuoted } else { ScalarStyle::SingleQuoted };
+                start = i;
+            }
+            (false, ScalarStyle::DoubleQuoted, '\\') => {
+                chars.next();
+            }
+            (false, ScalarStyle::DoubleQuoted, '"') => {
+                segments.push((&input[start..=i], Some(style)));
+                style = ScalarStyle::Plain;
+                start = i + 1;
+            }
+            (false, ScalarStyle::SingleQuoted, '\'') if chars.peek().is_some_and(|&(_, n)| n == '\'') => {
+                chars.next();
+            }
+            (false, ScalarStyle::SingleQuoted, '\'') => {
+                segments.push((&input[start..=i], Some(style)));
+                style = ScalarStyle::Plain;
+                start = i + 1;
+            }
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    segments.push((&input[start..], (!in_comment).then_some(style)));
+    segments
+}
+
+/// Apply environment variable overrides to configuration
+fn apply_env_overrides(config: &mut Config) {
+    if let Ok(url) = env::var("PQSECURE_CA_API_URL") {
+        config.ca.api_url = url;
+    }
+
+    if let Ok(token) = env::var("PQSECURE_CA_TOKEN") {
+        config.ca.token = token;
+    }
+
+    if let Ok(addr) = env::var("PQSECURE_LISTEN_ADDR") {
+        if addr.starts_with(UNIX_SOCKET_PREFIX) || addr.parse::<SocketAddr>().is_ok() {
+            config.proxy.listen_addr = addr;
+        }
+    }
+
+    if let Ok(backend) = env::var("PQSECURE_BACKEND_ADDR") {
+        config.proxy.backend.address = backend;
+    }
+
+    if let Ok(otel) = env::var("PQSECURE_OTEL_ENDPOINT") {
+        config.telemetry.otel_endpoint = Some(otel);
+    }
+}
+
+/// Validate the settings of the Smallstep CA client
+fn validate_ca_config(ca: &CaConfig) -> Result<()> {
+    if ca.api_url.is_empty() {
+        return Err(anyhow::anyhow!("CA API URL cannot be empty"));
+    }
+
+    if ca.token.is_empty() && ca.token_file.is_none() {
+        return Err(anyhow::anyhow!("Either ca.token or ca.token_file must be set"));
+    }
+
+    if ca.spiffe_id.is_empty() {
+        return Err(anyhow::anyhow!("SPIFFE ID cannot be empty"));
+    }
+
+    match ca.storage {
+        IdentityStorageType::File if ca.cert_path.as_os_str().is_empty() || ca.key_path.as_os_str().is_empty() => {
+            return Err(anyhow::anyhow!("ca.cert_path and ca.key_path are required with file storage"));
+        }
+        IdentityStorageType::Memory if ca.pkcs12.is_some() => {
+            return Err(anyhow::anyhow!("ca.pkcs12 would write the key to disk and cannot be used with memory storage"));
+        }
+        _ => {}
+    }
+
+    if ca.request_timeout_seconds == 0 || ca.connect_timeout_seconds == 0 {
+        return Err(anyhow::anyhow!("CA request and connect timeouts cannot be zero"));
+    }
+
+    if ca.retry.max_attempts == 0 {
+        return Err(anyhow::anyhow!("ca.retry.max_attempts must be at least 1"));
+    }
+
+    if ca.renew_threshold_percent >= 100 {
+        return Err(anyhow::anyhow!("ca.renew_threshold_percent must be below 100"));
+    }
+
+    if ca.renew_before_seconds == Some(0) {
+        return Err(anyhow::anyhow!("ca.renew_before_seconds cannot be zero"));
+    }
+
+    if ca.cert_duration_hours == Some(0) {
+        return Err(anyhow::anyhow!("ca.cert_duration_hours cannot be zero"));
+    }
+
+    if let Some(pkcs12) = &ca.pkcs12 {
+        if pkcs12.path.as_os_str().is_empty() || pkcs12.path.is_dir() {
+            return Err(anyhow::anyhow!("ca.pkcs12.path must name a file"));
+        }
+        if let Some(parent) = pkcs12.path.parent().filter(|parent| parent.is_file()) {
+            return Err(anyhow::anyhow!(
+                "ca.pkcs12.path is inside {}, which is not a directory",
+                parent.display()
+            ));
+        }
+        if pkcs12.password.is_empty() {
+            return Err(anyhow::anyhow!("ca.pkcs12.password is required; the bundle holds the private key"));
+        }
+    }
+
+    if ca.issuance_log.as_ref().is_some_and(|log| log.max_size_bytes == 0) {
+        return Err(anyhow::anyhow!("ca.issuance_log.max_size_bytes cannot be zero"));
+    }
+
+    if let Some(webhook) = &ca.webhook {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            return Err(anyhow::anyhow!("ca.webhook.url must be an http:// or https:// URL"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate configuration values
+fn validate_config(config: &Config) -> Result<()> {
+    // Validate CA configuration, which only the Smallstep provider uses
+    match config.identity.provider_type {
+        IdentityProviderType::Smallstep => validate_ca_config(&config.ca)?,
+        IdentityProviderType::MountedSecret => match &config.identity.mounted_secret {
+            None => {
+                return Err(anyhow::anyhow!(
+                    "identity.mounted_secret must be set when identity.provider_type is mounted_secret"
+                ))
+            }
+            Some(mounted) if mounted.refresh_seconds == 0 => {
+                return Err(anyhow::anyhow!("identity.mounted_secret.refresh_seconds cannot be zero"))
+            }
+            Some(_) => {}
+        },
+    }
+
+    // Validate identity configuration
+    let expiry_warning = &config.identity.expiry_warning;
+    if expiry_warning.thresholds_percent.iter().any(|t| *t == 0 || *t >= 100) {
+        return Err(anyhow::anyhow!(
+            "identity.expiry_warning.thresholds_percent must be between 1 and 99"
+        ));
+    }
+    if !expiry_warning.thresholds_percent.is_empty() && expiry_warning.check_seconds == 0 {
+        return Err(anyhow::anyhow!("identity.expiry_warning.check_seconds cannot be zero"));
+    }
+
+    if config.identity.workload_api.as_ref().is_some_and(|api| api.socket_path.as_os_str().is_empty()) {
+        return Err(anyhow::anyhow!("identity.workload_api.socket_path cannot be empty"));
+    }
+
+    if config.identity.workload_api.is_some()
+        && config.identity.trust_domains().iter().all(|domain| domain.bundle_path.is_none())
+    {
+        return Err(anyhow::anyhow!(
+            "identity.workload_api serves the trust domain's bundle, so identity.trusted_domains needs a bundle_path"
+        ));
+    }
+
+    if let Some(jwt_svid) = &config.identity.jwt_svid {
+        if jwt_svid.audience.is_empty() || jwt_svid.bundles.is_empty() {
+            return Err(anyhow::anyhow!("identity.jwt_svid needs at least one audience and one bundle"));
+        }
+        let trusted = config.identity.trust_domains();
+        if let Some(bundle) = jwt_svid.bundles.iter().find(|b| !trusted.iter().any(|d| d.domain == b.domain)) {
+            return Err(anyhow::anyhow!(
+                "identity.jwt_svid.bundles names {}, which is not a trusted domain",
+                bundle.domain
+            ));
+        }
+    }
+
+    let trust_domains = config.identity.trust_domains();
+    if trust_domains.is_empty() {
+        return Err(anyhow::anyhow!(
+            "At least one of identity.trusted_domain or identity.trusted_domains must be set"
+        ));
+    }
+
+    if config.identity.max_cert_chain_bytes == 0 || config.identity.max_chain_depth == 0 {
+        return Err(anyhow::anyhow!(
+            "identity.max_cert_chain_bytes and identity.max_chain_depth cannot be zero"
+        ));
+    }
+
+    let revocation = &config.identity.revocation;
+    if revocation.mode != RevocationMode::Off {
+        if revocation.crl_paths.is_empty() {
+            return Err(anyhow::anyhow!(
+                "identity.revocation.crl_paths must be set when revocation checking is enabled"
+            ));
+        }
+        if revocation.refresh_seconds == 0 {
+            return Err(anyhow::anyhow!("identity.revocation.refresh_seconds cannot be zero"));
+        }
+    }
+
+    for trust_domain in &trust_domains {
+        if trust_domain.domain.is_empty() {
+            return Err(anyhow::anyhow!("identity.trusted_domains entries need a domain"));
+        }
+
+        if let Some(bundle_path) = &trust_domain.bundle_path {
+            if !bundle_path.exists() {
+                return Err(anyhow::anyhow!(
+                    "Trust bundle for {} does not exist: {}",
+                    trust_domain.domain,
+                    bundle_path.display()
+                ));
+            }
+        }
+    }
+
+    // Validate policy configuration
+    if config.policy.on_missing == MissingPolicyAction::Fail && !Path::new(&config.policy.path).exists() {
+        return Err(anyhow::anyhow!(
+            "Policy file does not exist: {}",
+            config.policy.path.display()
+        ));
+    }
+
+    // Validate proxy configuration
+    if config.proxy.backend.address.is_empty() {
+        return Err(anyhow::anyhow!("Backend address cannot be empty"));
+    }
+
+    if !config.proxy.backend.address.starts_with(UNIX_SOCKET_PREFIX) {
+        split_host_port(&config.proxy.backend.address).context("Invalid proxy.backend.address")?;
+    }
+
+    match config.proxy.listen_addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+        Some("") => {
+            return Err(anyhow::anyhow!("proxy.listen_addr names no Unix socket path"));
+        }
+        Some(_) if !cfg!(unix) => {
+            return Err(anyhow::anyhow!("Unix socket listeners are only supported on Unix platforms"));
+        }
+        Some(_) if config.proxy.permit_plaintext_during_seconds.is_some() => {
+            return Err(anyhow::anyhow!(
+                "proxy.permit_plaintext_during_seconds cannot be used with a Unix socket listener"
+            ));
+        }
+        Some(_) => {}
+        None => {
+            config
+                .proxy
+                .listen_addr
+                .parse::<SocketAddr>()
+                .context("proxy.listen_addr must be host:port or unix:/path")?;
+        }
+    }
+
+    if config.proxy.socket.unix_mode > 0o777 {
+        return Err(anyhow::anyhow!("proxy.socket.unix_mode must be a permission mode such as 0o660"));
+    }
+
+    if config.proxy.socket.backlog == 0 || config.proxy.socket.backlog > i32::MAX as u32 {
+        return Err(anyhow::anyhow!("proxy.socket.backlog must be between 1 and {}", i32::MAX));
+    }
+
+    if config.proxy.socket.reuse_port && !cfg!(unix) {
+        return Err(anyhow::anyhow!("proxy.socket.reuse_port is only supported on Unix platforms"));
+    }
+
+    if config.proxy.backend.timeout_seconds == 0 {
+        return Err(anyhow::anyhow!("Backend timeout cannot be zero"));
+    }
+
+    if config.proxy.backend.max_bytes_per_second == Some(0) {
+        return Err(anyhow::anyhow!("proxy.backend.max_bytes_per_second cannot be zero"));
+    }
+
+    if config.proxy.backend.connect_timeout_ms == 0 {
+        return Err(anyhow::anyhow!("Backend connect timeout cannot be zero"));
+    }
+
+    if config.proxy.backend.forward_idle_timeout_ms == Some(0) {
+        return Err(anyhow::anyhow!("Backend forward idle timeout cannot be zero"));
+    }
+
+    if config.proxy.backend.max_connection_duration_seconds == Some(0) {
+        return Err(anyhow::anyhow!("Backend max connection duration cannot be zero"));
+    }
+
+    validate_keepalive("proxy.keepalive", &config.proxy.keepalive)?;
+    validate_sni_identities(&config.proxy.sni_identities)?;
+    validate_keepalive("proxy.backend.keepalive", &config.proxy.backend.keepalive)?;
+
+    if let Some(mirror) = &config.proxy.backend.mirror {
+        if mirror.address.is_empty() {
+            return Err(anyhow::anyhow!("proxy.backend.mirror.address cannot be empty"));
+        }
+        if !mirror.address.starts_with(UNIX_SOCKET_PREFIX) {
+            split_host_port(&mirror.address).context("Invalid proxy.backend.mirror.address")?;
+        }
+        if !(mirror.sample_rate > 0.0 && mirror.sample_rate <= 1.0) {
+            return Err(anyhow::anyhow!("proxy.backend.mirror.sample_rate must be greater than 0 and at most 1"));
+        }
+    }
+
+    if let Some(outlier) = &config.proxy.backend.outlier_detection {
+        if outlier.consecutive_failures == 0 {
+            return Err(anyhow::anyhow!("proxy.backend.outlier_detection.consecutive_failures cannot be zero"));
+        }
+        if outlier.ejection_duration_seconds == 0 {
+            return Err(anyhow::anyhow!("proxy.backend.outlier_detection.ejection_duration_seconds cannot be zero"));
+        }
+        if !(1..=100).contains(&outlier.max_ejection_percent) {
+            return Err(anyhow::anyhow!("proxy.backend.outlier_detection.max_ejection_percent must be between 1 and 100"));
+        }
+    }
+
+    validate_protocols(&config.proxy.protocols)?;
+
+    if config.proxy.http_limits.max_header_count == 0 {
+        return Err(anyhow::anyhow!("proxy.http_limits.max_header_count cannot be zero"));
+    }
+    if config.proxy.http_limits.max_total_header_bytes == 0 {
+        return Err(anyhow::anyhow!("proxy.http_limits.max_total_header_bytes cannot be zero"));
+    }
+
+    for header in &config.proxy.response_headers_add {
+        if !is_header_name(&header.name) {
+            return Err(anyhow::anyhow!("Invalid header name in proxy.response_headers_add: {:?}", header.name));
+        }
+        if header.value.contains(['\r', '\n']) {
+            return Err(anyhow::anyhow!(
+                "proxy.response_headers_add value for {} cannot contain line breaks",
+                header.name
+            ));
+        }
+    }
+    if let Some(name) = config.proxy.response_headers_remove.iter().find(|name| !is_header_name(name)) {
+        return Err(anyhow::anyhow!("Invalid header name in proxy.response_headers_remove: {:?}", name));
+    }
+
+    if let Some(deny) = &config.proxy.deny_response {
+        if !(400..=599).contains(&deny.status) {
+            return Err(anyhow::anyhow!(
+                "proxy.deny_response.status must be a 4xx or 5xx code, got {}",
+                deny.status
+            ));
+        }
+    }
+
+    let provider = crate::crypto::crypto_provider_with_groups(&config.proxy.key_exchange_groups)
+        .context("Invalid proxy.key_exchange_groups")?;
+
+    if config.proxy.require_pqc
+        && !provider
+            .kx_groups
+            .iter()
+            .any(|group| crate::crypto::is_post_quantum_group(group.name()))
+    {
+        return Err(anyhow::anyhow!(
+            "proxy.require_pqc is set but proxy.key_exchange_groups has no post-quantum group"
+        ));
+    }
+
+    if config.proxy.permit_plaintext_during_seconds == Some(0) {
+        return Err(anyhow::anyhow!("proxy.permit_plaintext_during_seconds cannot be zero"));
+    }
+    if config.proxy.require_pqc && config.proxy.permit_plaintext_during_seconds.is_some() {
+        return Err(anyhow::anyhow!(
+            "proxy.permit_plaintext_during_seconds cannot be combined with proxy.require_pqc"
+        ));
+    }
+
+    // Validate telemetry configuration
+    if config.telemetry.resource_sample_interval_seconds == Some(0) {
+        return Err(anyhow::anyhow!("Resource sample interval cannot be zero"));
+    }
+
+    validate_cross_fields(config)?;
+
+    Ok(())
+}
+
+/// Whether `name` is a valid HTTP header field name (an RFC 9110 token)
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Validate constraints that span several configuration sections
+fn validate_cross_fields(config: &Config) -> Result<()> {
+    // The CSR identity must be a well-formed SPIFFE ID
+    if config.identity.provider_type == IdentityProviderType::Smallstep {
+        SpiffeId::new(&config.ca.spiffe_id)
+            .map_err(|e| anyhow::anyhow!("ca.spiffe_id is not a valid SPIFFE ID: {}", e))?;
+    }
+
+    // Forwarding to our own listener would loop every connection back into the proxy
+    let listen_addr = &config.proxy.listen_addr;
+    let loops = match config.proxy.backend.address.parse::<SocketAddr>() {
+        Ok(backend_addr) => listen_addr.parse::<SocketAddr>().is_ok_and(|listen_addr| listen_addr == backend_addr),
+        Err(_) => listen_addr.starts_with(UNIX_SOCKET_PREFIX) && *listen_addr == config.proxy.backend.address,
+    };
+    if loops {
+        return Err(anyhow::anyhow!(
+            "proxy.backend.address must differ from proxy.listen_addr ({})",
+            listen_addr
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate that SNI names are unique and their files exist
+fn validate_sni_identities(identities: &[SniIdentityConfig]) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for identity in identities {
+        if identity.server_name.trim().is_empty() {
+            return Err(anyhow::anyhow!("proxy.sni_identities: server_name cannot be empty"));
+        }
+        if !seen.insert(identity.server_name.to_ascii_lowercase()) {
+            return Err(anyhow::anyhow!(
+                "proxy.sni_identities: duplicate server_name '{}'",
+                identity.server_name
+            ));
+        }
+        for path in [&identity.cert_path, &identity.key_path] {
+            if !path.exists() {
+                return Err(anyhow::anyhow!(
+                    "proxy.sni_identities: file for '{}' not found: {}",
+                    identity.server_name,
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate keepalive timings when keepalive is enabled
+fn validate_keepalive(name: &str, keepalive: &KeepaliveConfig) -> Result<()> {
+    if keepalive.enabled
+        && (keepalive.idle_seconds == 0 || keepalive.interval_seconds == 0 || keepalive.retries == 0)
+    {
+        return Err(anyhow::anyhow!(
+            "{}: idle_seconds, interval_seconds and retries must be greater than zero",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate the enabled protocol set
+fn validate_protocols(protocols: &ProtocolsConfig) -> Result<()> {
+    if protocols.enabled().is_empty() {
+        return Err(anyhow::anyhow!(
+            "proxy.protocols: at least one of tcp, http or grpc must be enabled"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_valid_config() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml.example");
+
+        // Create policy file
+        let policy_path = dir.path().join("policy.yaml.example");
+        File::create(&policy_path).unwrap();
+
+        let config_content = format!(
+            r#"
+ca:
+  api_url: "https://ca.example.com"
+  cert_path: "./certs/cert.pem"
   key_path: "./certs/key.pem"
   token: "abc123"
   spiffe_id: "spiffe://example.org/service/test"
 identity:
   trusted_domain: "example.org"
 policy:
-  path: "./policy.yaml.example"
+  path: "{}"
 proxy:
   listen_addr: "127.0.0.1:8443"
   backend:
@@ -233,15 +1638,13 @@ proxy:
 telemetry:
   otel_endpoint: "http://otel-collector:4317"
   service_name: "pqsecure-mesh"
-"#;
+"#,
+            policy_path.display()
+        );
 
         let mut file = File::create(&config_path).unwrap();
         file.write_all(config_content.as_bytes()).unwrap();
 
-        // Create policy file
-        let policy_path = dir.path().join("policy.yaml.example");
-        File::create(&policy_path).unwrap();
-
         // Set environment variable to point to our test config
         env::set_var("PQSECURE_CONFIG", config_path.to_str().unwrap());
 
@@ -252,8 +1655,544 @@ telemetry:
         let config = config.unwrap();
         assert_eq!(config.ca.api_url, "https://ca.example.com");
         assert_eq!(config.identity.trusted_domain, "example.org");
-        assert_eq!(config.proxy.listen_addr.to_string(), "127.0.0.1:8443");
-        assert_eq!(config.proxy.protocols.tcp, true);
-        assert_eq!(config.proxy.protocols.grpc, false);
+        assert_eq!(config.proxy.listen_addr, "127.0.0.1:8443");
+        assert!(config.proxy.protocols.tcp);
+        assert!(!config.proxy.protocols.grpc);
+    }
+
+    #[test]
+    fn test_protocols_enabled_order() {
+        let protocols = ProtocolsConfig {
+            tcp: true,
+            http: true,
+            grpc: true,
+        };
+
+        // TCP accepts every connection, so it must be tried last
+        assert_eq!(
+            protocols.enabled(),
+            vec![ProtocolType::Grpc, ProtocolType::Http, ProtocolType::Tcp]
+        );
+    }
+
+    #[test]
+    fn test_validate_protocols() {
+        let none = ProtocolsConfig {
+            tcp: false,
+            http: false,
+            grpc: false,
+        };
+        let err = validate_protocols(&none).unwrap_err();
+        assert!(err.to_string().contains("proxy.protocols"));
+
+        let grpc_only = ProtocolsConfig {
+            tcp: false,
+            http: false,
+            grpc: true,
+        };
+        assert!(validate_protocols(&grpc_only).is_ok());
+    }
+
+    #[test]
+    fn test_protocol_type_from_str() {
+        assert_eq!("tcp".parse::<ProtocolType>().unwrap(), ProtocolType::Tcp);
+        assert_eq!("HTTP".parse::<ProtocolType>().unwrap(), ProtocolType::Http);
+        assert_eq!("grpc".parse::<ProtocolType>().unwrap(), ProtocolType::Grpc);
+
+        let err = "udp".parse::<ProtocolType>().unwrap_err();
+        assert!(err.to_string().contains("udp"));
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        // Every test uses its own variable names; the process environment is
+        // shared between tests running in parallel
+        env::set_var("PQSECURE_TEST_INTERP_BASIC_HOST", "ca.internal");
+        env::set_var("PQSECURE_TEST_INTERP_BASIC_EMPTY", "");
+        env::remove_var("PQSECURE_TEST_INTERP_BASIC_UNSET");
+
+        let input = "url: https://${PQSECURE_TEST_INTERP_BASIC_HOST}:${PQSECURE_TEST_INTERP_BASIC_UNSET:-9000}\n\
+                     name: ${PQSECURE_TEST_INTERP_BASIC_EMPTY:-fallback}\n";
+        let output = interpolate_env(input).unwrap();
+
+        assert_eq!(output, "url: https://ca.internal:9000\nname: fallback\n");
+    }
+
+    #[test]
+    fn test_interpolate_env_missing_variable() {
+        env::remove_var("PQSECURE_TEST_INTERP_MISSING");
+
+        let err = interpolate_env("token: ${PQSECURE_TEST_INTERP_MISSING}").unwrap_err();
+        assert!(err.to_string().contains("PQSECURE_TEST_INTERP_MISSING"));
+    }
+
+    #[test]
+    fn test_interpolate_env_skips_comments() {
+        env::remove_var("PQSECURE_TEST_INTERP_COMMENT_UNSET");
+        env::set_var("PQSECURE_TEST_INTERP_COMMENT_NAME", "mesh");
+
+        let input = "# token: ${PQSECURE_TEST_INTERP_COMMENT_UNSET}\n\
+                     name: ${PQSECURE_TEST_INTERP_COMMENT_NAME} # was ${PQSECURE_TEST_INTERP_COMMENT_UNSET}\n\
+                     path: \"a#${PQSECURE_TEST_INTERP_COMMENT_NAME}\"\n";
+        let output = interpolate_env(input).unwrap();
+
+        assert_eq!(
+            output,
+            "# token: ${PQSECURE_TEST_INTERP_COMMENT_UNSET}\n\
+             name: mesh # was ${PQSECURE_TEST_INTERP_COMMENT_UNSET}\n\
+             path: \"a#mesh\"\n"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_env_escapes_values() {
+        env::set_var("PQSECURE_TEST_INTERP_ESCAPE_VALUE", r#"it's "x": \y"#);
+
+        let input = "double: \"${PQSECURE_TEST_INTERP_ESCAPE_VALUE}\"\n\
+                     single: '${PQSECURE_TEST_INTERP_ESCAPE_VALUE}'\n";
+        let output = interpolate_env(input).unwrap();
+        let parsed: std::collections::HashMap<String, String> = serde_yaml::from_str(&output).unwrap();
+
+        assert_eq!(parsed["double"], r#"it's "x": \y"#);
+        assert_eq!(parsed["single"], r#"it's "x": \y"#);
+    }
+
+    #[test]
+    fn test_interpolate_env_rejects_structural_values() {
+        env::set_var("PQSECURE_TEST_INTERP_STRUCT_MAPPING", "x\nadmin: true");
+        env::set_var("PQSECURE_TEST_INTERP_STRUCT_COLON", "a: b");
+        env::set_var("PQSECURE_TEST_INTERP_STRUCT_DASH", "-x");
+
+        let err = interpolate_env("token: \"${PQSECURE_TEST_INTERP_STRUCT_MAPPING}\"").unwrap_err();
+        assert!(err.to_string().contains("PQSECURE_TEST_INTERP_STRUCT_MAPPING"));
+
+        let err = interpolate_env("token: ${PQSECURE_TEST_INTERP_STRUCT_COLON}").unwrap_err();
+        assert!(err.to_string().contains("PQSECURE_TEST_INTERP_STRUCT_COLON"));
+
+        let err = interpolate_env("token: ${PQSECURE_TEST_INTERP_STRUCT_DASH}").unwrap_err();
+        assert!(err.to_string().contains("PQSECURE_TEST_INTERP_STRUCT_DASH"));
+        assert_eq!(
+            interpolate_env("path: /srv/${PQSECURE_TEST_INTERP_STRUCT_DASH}").unwrap(),
+            "path: /srv/-x"
+        );
+        assert_eq!(
+            interpolate_env("token: '${PQSECURE_TEST_INTERP_STRUCT_COLON}'").unwrap(),
+            "token: 'a: b'"
+        );
+    }
+
+    #[test]
+    fn test_interpolate_env_in_config() {
+        env::set_var("PQSECURE_TEST_INTERP_CONFIG_TOKEN", "interpolated-token");
+        env::remove_var("PQSECURE_TEST_INTERP_CONFIG_SERVICE");
+
+        let yaml = r#"
+ca:
+  api_url: "https://ca.example.com"
+  cert_path: "./certs/cert.pem"
+  key_path: "./certs/key.pem"
+  token: ${PQSECURE_TEST_INTERP_CONFIG_TOKEN}
+  spiffe_id: "spiffe://example.org/service/test"
+identity:
+  trusted_domain: "example.org"
+policy:
+  path: "./policy.yaml"
+proxy:
+  listen_addr: "127.0.0.1:8443"
+  backend:
+    address: "127.0.0.1:8080"
+    timeout_seconds: 30
+  protocols:
+    tcp: true
+    http: false
+    grpc: false
+telemetry:
+  service_name: "${PQSECURE_TEST_INTERP_CONFIG_SERVICE:-pqsecure-mesh}"
+"#;
+
+        let config: Config = serde_yaml::from_str(&interpolate_env(yaml).unwrap()).unwrap();
+        assert_eq!(config.ca.token, "interpolated-token");
+        assert_eq!(config.telemetry.service_name, "pqsecure-mesh");
+    }
+
+    fn write_config(dir: &Path, spiffe_id: &str, backend: &str) -> PathBuf {
+        let policy_path = dir.join("policy.yaml");
+        File::create(&policy_path).unwrap();
+
+        let config_path = dir.join("config.yaml");
+        let config_content = format!(
+            r#"
+ca:
+  api_url: "https://ca.example.com"
+  cert_path: "./certs/cert.pem"
+  key_path: "./certs/key.pem"
+  token: "secret-token"
+  spiffe_id: "{}"
+identity:
+  trusted_domain: "example.org"
+policy:
+  path: "{}"
+proxy:
+  listen_addr: "127.0.0.1:8443"
+  backend:
+    address: "{}"
+    timeout_seconds: 30
+  protocols:
+    tcp: true
+    http: true
+    grpc: true
+telemetry:
+  service_name: "pqsecure-mesh"
+"#,
+            spiffe_id,
+            policy_path.display(),
+            backend
+        );
+        fs::write(&config_path, config_content).unwrap();
+        config_path
+    }
+
+    #[test]
+    fn test_validate_good_config_from_path() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+
+        assert!(load_config_from_path(&path).is_ok());
+    }
+
+    #[test]
+    fn test_validate_bad_config_from_path() {
+        let dir = tempdir().unwrap();
+
+        let path = write_config(dir.path(), "not-a-spiffe-id", "127.0.0.1:8080");
+        let err = load_config_from_path(&path).unwrap_err();
+        assert!(err.to_string().contains("ca.spiffe_id"));
+
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8443");
+        let err = load_config_from_path(&path).unwrap_err();
+        assert!(err.to_string().contains("proxy.backend.address"));
+    }
+
+    #[test]
+    fn test_missing_policy_file() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        fs::remove_file(dir.path().join("policy.yaml")).unwrap();
+
+        // A deny-all policy stands in for the missing file by default
+        let config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.policy.on_missing, MissingPolicyAction::DefaultDeny);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let content = content.replace("policy:\n", "policy:\n  on_missing: fail\n");
+        fs::write(&path, content).unwrap();
+        let err = load_config_from_path(&path).unwrap_err();
+        assert!(err.to_string().contains("Policy file does not exist"));
+    }
+
+    #[test]
+    fn test_redacted_config() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let config = load_config_from_path(&path).unwrap();
+
+        let printed = serde_yaml::to_string(&config.redacted()).unwrap();
+        assert!(!printed.contains("secret-token"));
+        assert!(printed.contains(REDACTED));
+    }
+
+    #[test]
+    fn test_validate_deny_response_status() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert!(config.proxy.deny_response.is_none());
+
+        config.proxy.deny_response = Some(DenyResponseConfig::default());
+        assert!(validate_config(&config).is_ok());
+
+        config.proxy.deny_response = Some(DenyResponseConfig {
+            status: 200,
+            ..DenyResponseConfig::default()
+        });
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.deny_response.status"));
+    }
+
+    #[test]
+    fn test_validate_key_exchange_groups() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.proxy.key_exchange_groups[0], "X25519MLKEM768");
+
+        config.proxy.key_exchange_groups = vec!["X25519".to_string(), "kyber512".to_string()];
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.key_exchange_groups"));
+
+        // Requiring PQC with only classical groups could never accept anyone
+        config.proxy.key_exchange_groups = vec!["X25519".to_string()];
+        config.proxy.require_pqc = true;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("no post-quantum group"));
+    }
+
+    #[test]
+    fn test_validate_http_limits() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.proxy.http_limits.max_header_count, 100);
+        assert_eq!(config.proxy.http_limits.max_total_header_bytes, 16 * 1024);
+
+        config.proxy.http_limits.max_header_count = 0;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.http_limits.max_header_count"));
+
+        config.proxy.http_limits = HttpLimitsConfig {
+            max_header_count: 100,
+            max_total_header_bytes: 0,
+        };
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.http_limits.max_total_header_bytes"));
+    }
+
+    #[test]
+    fn test_validate_response_headers() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+
+        config.proxy.response_headers_add = vec![HeaderConfig {
+            name: "X-Content-Type-Options".to_string(),
+            value: "nosniff".to_string(),
+        }];
+        config.proxy.response_headers_remove = vec!["Server".to_string(), "X-Powered-By".to_string()];
+        validate_config(&config).unwrap();
+
+        // Values must not smuggle extra header lines into the response
+        config.proxy.response_headers_add[0].value = "nosniff\r\nSet-Cookie: a=b".to_string();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("cannot contain line breaks"));
+
+        config.proxy.response_headers_add.clear();
+        config.proxy.response_headers_remove = vec!["X Powered By".to_string()];
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.response_headers_remove"));
+    }
+
+    #[test]
+    fn test_validate_plaintext_window() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.proxy.permit_plaintext_during_seconds, None);
+
+        config.proxy.permit_plaintext_during_seconds = Some(0);
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.permit_plaintext_during_seconds"));
+
+        config.proxy.permit_plaintext_during_seconds = Some(600);
+        validate_config(&config).unwrap();
+
+        // Plaintext clients have no key exchange at all
+        config.proxy.require_pqc = true;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.require_pqc"));
+    }
+
+    #[test]
+    fn test_validate_revocation() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.identity.revocation.mode, RevocationMode::Off);
+
+        let revocation: RevocationConfig = serde_yaml::from_str("mode: hard_fail").unwrap();
+        config.identity.revocation = revocation;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("identity.revocation.crl_paths"));
+
+        config.identity.revocation.crl_paths = vec![PathBuf::from("/etc/pqsecure/ca.crl")];
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_mirror() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert!(config.proxy.backend.mirror.is_none());
+
+        let mirror: MirrorConfig = serde_yaml::from_str(r#"address: "127.0.0.1:9090""#).unwrap();
+        assert_eq!(mirror.sample_rate, 1.0);
+        config.proxy.backend.mirror = Some(mirror);
+        assert!(validate_config(&config).is_ok());
+
+        config.proxy.backend.mirror.as_mut().unwrap().sample_rate = 0.0;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.backend.mirror.sample_rate"));
+    }
+
+    #[test]
+    fn test_validate_memory_storage() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.ca.storage, IdentityStorageType::File);
+
+        // Memory storage needs no file paths
+        config.ca.storage = IdentityStorageType::Memory;
+        config.ca.cert_path = PathBuf::new();
+        config.ca.key_path = PathBuf::new();
+        assert!(validate_config(&config).is_ok());
+
+        config.ca.pkcs12 = Some(Pkcs12OutputConfig {
+            path: dir.path().join("identity.p12"),
+            password: String::new(),
+        });
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("ca.pkcs12"));
+
+        config.ca.pkcs12 = None;
+        config.ca.storage = IdentityStorageType::File;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("ca.cert_path"));
+    }
+
+    #[test]
+    fn test_validate_unix_listener() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+
+        config.proxy.listen_addr = "unix:/run/pqsecure/proxy.sock".to_string();
+        config.proxy.socket = serde_yaml::from_str("unix_mode: 0o600").unwrap();
+        assert_eq!(config.proxy.socket.unix_mode, 0o600);
+        assert!(validate_config(&config).is_ok());
+
+        config.proxy.permit_plaintext_during_seconds = Some(60);
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("Unix socket listener"));
+        config.proxy.permit_plaintext_during_seconds = None;
+
+        config.proxy.backend.address = "unix:/run/pqsecure/proxy.sock".to_string();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("must differ"));
+
+        for listen_addr in ["unix:", "localhost"] {
+            config.proxy.listen_addr = listen_addr.to_string();
+            assert!(validate_config(&config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_jwt_svid() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+
+        config.identity.jwt_svid = Some(serde_yaml::from_str(
+            "audience: [\"spiffe://example.org/service/test\"]\nbundles:\n  - domain: example.org\n    path: jwks.json",
+        ).unwrap());
+        assert!(validate_config(&config).is_ok());
+
+        config.identity.jwt_svid.as_mut().unwrap().bundles[0].domain = "other.org".to_string();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("other.org"));
+
+        config.identity.jwt_svid.as_mut().unwrap().audience.clear();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("identity.jwt_svid"));
+    }
+
+    #[test]
+    fn test_validate_pkcs12_output() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+
+        config.ca.pkcs12 = Some(Pkcs12OutputConfig {
+            path: dir.path().join("identity.p12"),
+            password: "changeit".to_string(),
+        });
+        assert!(validate_config(&config).is_ok());
+
+        config.ca.pkcs12.as_mut().unwrap().password = String::new();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("ca.pkcs12.password"));
+
+        config.ca.pkcs12.as_mut().unwrap().password = "changeit".to_string();
+        config.ca.pkcs12.as_mut().unwrap().path = dir.path().to_path_buf();
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("ca.pkcs12.path"));
+
+        config.ca.pkcs12.as_mut().unwrap().path = path.join("identity.p12");
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("ca.pkcs12.path"));
+    }
+
+    #[test]
+    fn test_validate_outlier_detection() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert!(config.proxy.backend.outlier_detection.is_none());
+
+        let outlier: OutlierDetectionConfig = serde_yaml::from_str("consecutive_failures: 3").unwrap();
+        assert_eq!(outlier.ejection_duration_seconds, 30);
+        assert_eq!(outlier.max_ejection_percent, 50);
+        config.proxy.backend.outlier_detection = Some(outlier);
+        assert!(validate_config(&config).is_ok());
+
+        config.proxy.backend.outlier_detection.as_mut().unwrap().max_ejection_percent = 0;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("proxy.backend.outlier_detection.max_ejection_percent"));
+    }
+
+    #[test]
+    fn test_validate_mounted_secret() {
+        let dir = tempdir().unwrap();
+        let path = write_config(dir.path(), "spiffe://example.org/service/test", "127.0.0.1:8080");
+        let mut config = load_config_from_path(&path).unwrap();
+        assert_eq!(config.identity.provider_type, IdentityProviderType::Smallstep);
+
+        // The CA settings are not needed once the identity comes from files
+        config.ca = CaConfig::default();
+        assert!(validate_config(&config).is_err());
+        config.identity.provider_type = IdentityProviderType::MountedSecret;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.to_string().contains("identity.mounted_secret"));
+
+        let mounted: MountedSecretConfig = serde_yaml::from_str(
+            r#"
+cert_path: "/var/run/secrets/pqsecure/tls.crt"
+key_path: "/var/run/secrets/pqsecure/tls.key"
+"#,
+        )
+        .unwrap();
+        assert_eq!(mounted.refresh_seconds, 10);
+        config.identity.mounted_secret = Some(mounted);
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_trust_domains_shorthand() {
+        let yaml = r#"
+trusted_domain: "example.org"
+trusted_domains:
+  - domain: "partner.org"
+    bundle_path: "/etc/pqsecure/partner.pem"
+  - domain: "example.org"
+"#;
+        let identity: IdentityConfig = serde_yaml::from_str(yaml).unwrap();
+        let domains: Vec<String> = identity.trust_domains().into_iter().map(|d| d.domain).collect();
+        assert_eq!(domains, vec!["partner.org", "example.org"]);
+
+        let identity: IdentityConfig = serde_yaml::from_str(r#"trusted_domain: "example.org""#).unwrap();
+        let domains = identity.trust_domains();
+        assert_eq!(domains.len(), 1);
+        assert_eq!(domains[0].domain, "example.org");
+        assert!(domains[0].bundle_path.is_none());
     }
-}
\ No newline at end of file
+}
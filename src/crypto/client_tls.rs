@@ -0,0 +1,366 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::verify_server_cert_signed_by_trust_anchor;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+use crate::crypto::{crypto_provider, TlsConfigBuilder};
+use crate::identity::SpiffeVerifier;
+
+/// SPIFFE ID the server is expected to present
+#[derive(Debug, Clone)]
+pub enum ExpectedPeer {
+    /// The server must present exactly this SPIFFE ID
+    Exact(String),
+    /// The server's SPIFFE ID must match this regex
+    Pattern(Regex),
+}
+
+impl ExpectedPeer {
+    /// Parse an expected peer, using the policy file convention of a `regex:` prefix for patterns
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.strip_prefix("regex:") {
+            Some(pattern) => Ok(ExpectedPeer::Pattern(
+                Regex::new(pattern).context(format!("Invalid regex pattern: {}", pattern))?,
+            )),
+            None => Ok(ExpectedPeer::Exact(value.to_string())),
+        }
+    }
+
+    /// Check whether a SPIFFE ID satisfies the expectation
+    pub fn matches(&self, spiffe_id: &str) -> bool {
+        match self {
+            ExpectedPeer::Exact(expected) => expected == spiffe_id,
+            ExpectedPeer::Pattern(regex) => regex.is_match(spiffe_id),
+        }
+    }
+}
+
+/// Server certificate verifier that authenticates peers by SPIFFE ID
+///
+/// The chain must lead to the trust bundle and the leaf must carry a SPIFFE
+/// ID from a trusted domain, and the expected peer when one is set. DNS
+/// server names are not checked, since mesh services are identified by
+/// SPIFFE ID rather than hostname.
+#[derive(Debug)]
+pub struct SpiffeServerCertVerifier {
+    /// Roots the server chain must lead to
+    roots: Arc<RootCertStore>,
+    /// SPIFFE ID verifier for the server certificate
+    spiffe_verifier: Arc<SpiffeVerifier>,
+    /// SPIFFE ID the server must present, if any
+    expected_peer: Option<ExpectedPeer>,
+}
+
+impl SpiffeServerCertVerifier {
+    /// Create a new server certificate verifier
+    pub fn new(
+        roots: Arc<RootCertStore>,
+        spiffe_verifier: Arc<SpiffeVerifier>,
+        expected_peer: Option<ExpectedPeer>,
+    ) -> Self {
+        Self {
+            roots,
+            spiffe_verifier,
+            expected_peer,
+        }
+    }
+}
+
+impl ServerCertVerifier for SpiffeServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = ParsedCertificate::try_from(end_entity)?;
+        verify_server_cert_signed_by_trust_anchor(
+            &cert,
+            &self.roots,
+            intermediates,
+            now,
+            crypto_provider().signature_verification_algorithms.all,
+        )?;
+
+        let identity = match self.spiffe_verifier.extract_spiffe_id(end_entity) {
+            Ok(identity) => identity,
+            Err(e) => {
+                error!("Server SPIFFE ID verification failed: {}", e);
+                return Err(rustls::Error::General("Invalid server SPIFFE ID".to_string()));
+            }
+        };
+
+        // Reaching a valid peer is not enough; it has to be the intended service
+        if let Some(expected) = &self.expected_peer {
+            if !expected.matches(&identity.spiffe_id) {
+                error!(
+                    "Server presented SPIFFE ID {} but {:?} was expected",
+                    identity.spiffe_id, expected
+                );
+                return Err(rustls::Error::General(format!(
+                    "Unexpected server SPIFFE ID: {}",
+                    identity.spiffe_id
+                )));
+            }
+        }
+
+        debug!("Server presented SPIFFE ID {}", identity.spiffe_id);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &crypto_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &crypto_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        crypto_provider().signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Build TLS configuration for initiating mTLS connections to other mesh services
+///
+/// The client presents the given certificate chain and authenticates the
+/// server with [`SpiffeServerCertVerifier`], optionally pinned to an expected
+/// peer SPIFFE ID. Key exchange groups come from the same provider as the
+/// server side, so post-quantum hybrids are offered whenever the peer
+/// supports them.
+pub fn build_client_tls_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    private_key: PrivateKeyDer<'static>,
+    trust_bundle: Arc<RootCertStore>,
+    spiffe_verifier: Arc<SpiffeVerifier>,
+    expected_peer: Option<ExpectedPeer>,
+) -> Result<Arc<ClientConfig>> {
+    let server_cert_verifier = Arc::new(SpiffeServerCertVerifier::new(
+        trust_bundle,
+        spiffe_verifier,
+        expected_peer,
+    ));
+
+    // Same provider and ALPN list as the server side
+    TlsConfigBuilder::new()
+        .with_identity(cert_chain, private_key)
+        .with_server_verifier(server_cert_verifier)
+        .build_client()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        build_tls_config, build_tls_config_with_provider, build_tls_config_with_resolver, crypto_provider,
+        crypto_provider_with_groups, NegotiatedCrypto, SniCertResolver,
+    };
+    use crate::test_support::{issue_identity, test_ca};
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    async fn handshake(
+        server_identity: (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+        client_config: Arc<ClientConfig>,
+        spiffe_verifier: Arc<SpiffeVerifier>,
+    ) -> Result<()> {
+        let server_config = build_tls_config(server_identity.0, server_identity.1, spiffe_verifier)?;
+        handshake_with(server_config, client_config).await.map(|_| ())
+    }
+
+    /// Complete a handshake, returning what the server negotiated
+    async fn handshake_with(
+        server_config: Arc<rustls::ServerConfig>,
+        client_config: Arc<ClientConfig>,
+    ) -> Result<NegotiatedCrypto> {
+        handshake_as(server_config, client_config, "server.example.org")
+            .await
+            .map(|(negotiated, _)| negotiated)
+    }
+
+    /// Complete a handshake requesting the given SNI, also returning the
+    /// certificate the server presented
+    async fn handshake_as(
+        server_config: Arc<rustls::ServerConfig>,
+        client_config: Arc<ClientConfig>,
+        server_name: &str,
+    ) -> Result<(NegotiatedCrypto, CertificateDer<'static>)> {
+        let acceptor = TlsAcceptor::from(server_config);
+        let connector = TlsConnector::from(client_config);
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+
+        let server = tokio::spawn(async move { acceptor.accept(server_io).await });
+        let server_name = ServerName::try_from(server_name.to_string()).unwrap();
+        let client = connector.connect(server_name, client_io).await;
+        let server = server.await?;
+
+        // Report the server-side failure first, it carries the rejection reason
+        let server = server?;
+        let client = client?;
+        let presented = client.get_ref().1.peer_certificates().unwrap()[0].clone();
+        Ok((NegotiatedCrypto::from_connection(server.get_ref().1), presented))
+    }
+
+    #[tokio::test]
+    async fn test_loopback_mtls_with_client_config() {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let ca = test_ca();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+
+        let (client_chain, client_key) = issue_identity("spiffe://example.org/service/client", &ca);
+        let client_config =
+            build_client_tls_config(client_chain, client_key, Arc::new(roots), spiffe_verifier.clone(), None)
+                .unwrap();
+
+        let server_identity = issue_identity("spiffe://example.org/service/server", &ca);
+        handshake(server_identity, client_config, spiffe_verifier).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_outside_trust_bundle_rejected() {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let ca = test_ca();
+        let other_ca = test_ca();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+
+        let (client_chain, client_key) = issue_identity("spiffe://example.org/service/client", &ca);
+        let client_config =
+            build_client_tls_config(client_chain, client_key, Arc::new(roots), spiffe_verifier.clone(), None)
+                .unwrap();
+
+        let server_identity = issue_identity("spiffe://example.org/service/server", &other_ca);
+        assert!(handshake(server_identity, client_config, spiffe_verifier).await.is_err());
+    }
+
+    async fn connect_expecting(expected_peer: &str) -> Result<()> {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let ca = test_ca();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+
+        let (client_chain, client_key) = issue_identity("spiffe://example.org/service/client", &ca);
+        let client_config = build_client_tls_config(
+            client_chain,
+            client_key,
+            Arc::new(roots),
+            spiffe_verifier.clone(),
+            Some(ExpectedPeer::parse(expected_peer).unwrap()),
+        )
+        .unwrap();
+
+        let server_identity = issue_identity("spiffe://example.org/service/server", &ca);
+        handshake(server_identity, client_config, spiffe_verifier).await
+    }
+
+    #[tokio::test]
+    async fn test_expected_peer_matches() {
+        connect_expecting("spiffe://example.org/service/server").await.unwrap();
+        connect_expecting("regex:^spiffe://example.org/service/(server|api)$").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_peer_rejected() {
+        assert!(connect_expecting("spiffe://example.org/service/billing").await.is_err());
+        assert!(connect_expecting("regex:^spiffe://example.org/service/api$").await.is_err());
+    }
+
+    #[test]
+    fn test_expected_peer_parse() {
+        assert!(ExpectedPeer::parse("regex:(").is_err());
+        assert!(ExpectedPeer::parse("spiffe://example.org/a").unwrap().matches("spiffe://example.org/a"));
+        assert!(!ExpectedPeer::parse("spiffe://example.org/a").unwrap().matches("spiffe://example.org/ab"));
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_group_follows_server_preference() {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let ca = test_ca();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+        let roots = Arc::new(roots);
+
+        for (server_groups, expected, post_quantum) in [
+            (vec!["X25519MLKEM768", "X25519"], rustls::NamedGroup::X25519MLKEM768, true),
+            (vec!["X25519"], rustls::NamedGroup::X25519, false),
+        ] {
+            let (client_chain, client_key) = issue_identity("spiffe://example.org/service/client", &ca);
+            let client_config =
+                build_client_tls_config(client_chain, client_key, roots.clone(), spiffe_verifier.clone(), None)
+                    .unwrap();
+
+            let (server_chain, server_key) = issue_identity("spiffe://example.org/service/server", &ca);
+            let server_config = build_tls_config_with_provider(
+                server_chain,
+                server_key,
+                spiffe_verifier.clone(),
+                crypto_provider_with_groups(&server_groups).unwrap(),
+            )
+            .unwrap();
+
+            let negotiated = handshake_with(server_config, client_config).await.unwrap();
+            assert_eq!(negotiated.key_exchange, Some(expected));
+            assert_eq!(negotiated.post_quantum, post_quantum);
+            assert_eq!(negotiated.key_exchange_name(), format!("{:?}", expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_identity_selected_by_sni() {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let ca = test_ca();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+        let roots = Arc::new(roots);
+        let provider = crypto_provider();
+
+        let (default_chain, default_key) = issue_identity("spiffe://example.org/service/default", &ca);
+        let (billing_chain, billing_key) = issue_identity("spiffe://example.org/service/billing", &ca);
+        let mut resolver = SniCertResolver::new(default_chain.clone(), default_key, &provider).unwrap();
+        resolver
+            .add("Billing.internal", billing_chain.clone(), billing_key, &provider)
+            .unwrap();
+        let server_config =
+            build_tls_config_with_resolver(Arc::new(resolver), spiffe_verifier.clone(), provider).unwrap();
+
+        for (server_name, expected) in [
+            ("billing.internal", &billing_chain[0]),
+            ("server.example.org", &default_chain[0]),
+        ] {
+            let (client_chain, client_key) = issue_identity("spiffe://example.org/service/client", &ca);
+            let client_config =
+                build_client_tls_config(client_chain, client_key, roots.clone(), spiffe_verifier.clone(), None)
+                    .unwrap();
+
+            let (_, presented) = handshake_as(server_config.clone(), client_config, server_name).await.unwrap();
+            assert_eq!(&presented, expected);
+        }
+    }
+}
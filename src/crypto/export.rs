@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::stack::Stack;
+use openssl::x509::X509;
+
+use crate::common::PqSecureError;
+
+/// Friendly name given to the identity inside exported PKCS#12 bundles
+const PKCS12_FRIENDLY_NAME: &str = "pqsecure-mesh";
+
+/// Bundle a certificate chain and private key as PKCS#12 (`.p12`/`.pfx`)
+///
+/// `cert_pem` holds the leaf certificate, optionally followed by its chain as
+/// the CA client stores it; certificates in `chain_pem` are appended after
+/// those. The key may be PEM or the DER the CA client writes. Java keystores
+/// and Windows consume the result directly.
+pub fn to_pkcs12(cert_pem: &[u8], key_pem: &[u8], chain_pem: Option<&[u8]>, password: &str) -> Result<Vec<u8>> {
+    let mut certs = X509::stack_from_pem(cert_pem).context("Failed to parse certificate PEM")?;
+    if certs.is_empty() {
+        return Err(PqSecureError::CertificateError("No certificate found".to_string()).into());
+    }
+    let leaf = certs.remove(0);
+
+    if let Some(chain_pem) = chain_pem {
+        certs.extend(X509::stack_from_pem(chain_pem).context("Failed to parse chain PEM")?);
+    }
+    let mut chain = Stack::new()?;
+    for cert in certs {
+        chain.push(cert)?;
+    }
+
+    let key = parse_private_key(key_pem)?;
+    if !leaf.public_key()?.public_eq(&key) {
+        return Err(PqSecureError::CertificateError("Private key does not match the certificate".to_string()).into());
+    }
+
+    let pkcs12 = Pkcs12::builder()
+        .name(PKCS12_FRIENDLY_NAME)
+        .pkey(&key)
+        .cert(&leaf)
+        .ca(chain)
+        .build2(password)
+        .context("Failed to build PKCS#12 bundle")?;
+    Ok(pkcs12.to_der()?)
+}
+
+/// Bundle a certificate chain and private key as a single PEM file, key last
+pub fn to_pem_bundle(cert_pem: &[u8], key_pem: &[u8]) -> Result<Vec<u8>> {
+    let mut bundle = cert_pem.to_vec();
+    if !bundle.ends_with(b"\n") {
+        bundle.push(b'\n');
+    }
+    bundle.extend(parse_private_key(key_pem)?.private_key_to_pem_pkcs8()?);
+    Ok(bundle)
+}
+
+/// Parse a PEM private key, falling back to DER
+fn parse_private_key(key: &[u8]) -> Result<PKey<Private>> {
+    PKey::private_key_from_pem(key)
+        .or_else(|_| PKey::private_key_from_der(key))
+        .context("Failed to parse private key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{issue_svid, test_ca};
+    use rcgen::KeyPair;
+
+    /// Leaf PEM, its key PEM and the issuing CA's PEM
+    fn generate_identity() -> (String, KeyPair, String) {
+        let ca = test_ca();
+        let (leaf, key_pair) = issue_svid("spiffe://example.org/service/test", &ca);
+        (leaf.pem(), key_pair, ca.0.pem())
+    }
+
+    #[test]
+    fn test_pkcs12_round_trip() {
+        let (leaf_pem, key_pair, ca_pem) = generate_identity();
+
+        let der = to_pkcs12(
+            leaf_pem.as_bytes(),
+            key_pair.serialize_pem().as_bytes(),
+            Some(ca_pem.as_bytes()),
+            "changeit",
+        )
+        .unwrap();
+        let parsed = Pkcs12::from_der(&der).unwrap().parse2("changeit").unwrap();
+
+        let leaf = X509::from_pem(leaf_pem.as_bytes()).unwrap();
+        assert_eq!(parsed.cert.unwrap().to_der().unwrap(), leaf.to_der().unwrap());
+        assert!(parsed.pkey.unwrap().public_eq(&leaf.public_key().unwrap()));
+        let chain: Vec<Vec<u8>> = parsed.ca.unwrap().iter().map(|c| c.to_der().unwrap()).collect();
+        assert_eq!(chain, vec![X509::from_pem(ca_pem.as_bytes()).unwrap().to_der().unwrap()]);
+
+        assert!(Pkcs12::from_der(&der).unwrap().parse2("wrong").is_err());
+    }
+
+    #[test]
+    fn test_pkcs12_from_stored_identity() {
+        // The CA client stores the chain in one file and the key as DER
+        let (leaf_pem, key_pair, ca_pem) = generate_identity();
+        let stored_chain = format!("{}\n{}", leaf_pem, ca_pem);
+
+        let der = to_pkcs12(stored_chain.as_bytes(), &key_pair.serialize_der(), None, "").unwrap();
+        let parsed = Pkcs12::from_der(&der).unwrap().parse2("").unwrap();
+        assert_eq!(parsed.ca.unwrap().len(), 1);
+
+        // A key from another identity is refused
+        let other_key = KeyPair::generate().unwrap();
+        assert!(to_pkcs12(leaf_pem.as_bytes(), &other_key.serialize_der(), None, "").is_err());
+    }
+
+    #[test]
+    fn test_pem_bundle() {
+        let (leaf_pem, key_pair, _) = generate_identity();
+
+        let bundle = to_pem_bundle(leaf_pem.as_bytes(), &key_pair.serialize_der()).unwrap();
+        let mut reader = bundle.as_slice();
+        assert_eq!(rustls_pemfile::certs(&mut reader).count(), 1);
+        let mut reader = bundle.as_slice();
+        assert!(rustls_pemfile::private_key(&mut reader).unwrap().is_some());
+    }
+}
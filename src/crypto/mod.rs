@@ -1,3 +1,13 @@
+mod client_tls;
+mod export;
 mod pqc_verifier;
+mod self_test;
+mod sni_resolver;
+mod tls;
 
-pub use pqc_verifier::*;
\ No newline at end of file
+pub use client_tls::*;
+pub use export::{to_pem_bundle, to_pkcs12};
+pub use pqc_verifier::*;
+pub use self_test::run_self_test;
+pub use sni_resolver::SniCertResolver;
+pub use tls::{TlsConfigBuilder, DEFAULT_ALPN_PROTOCOLS};
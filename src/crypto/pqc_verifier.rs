@@ -1,7 +1,10 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use rustls::client::danger::HandshakeSignatureValid;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
 use rustls::server::danger::{ClientCertVerifier, ClientCertVerified};
+use rustls::crypto::{CryptoProvider, SupportedKxGroup};
+use rustls::server::ResolvesServerCert;
+use rustls::{CertificateError, CipherSuite, CommonState, NamedGroup};
 use rustls::server::ServerConfig;
 use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
 use std::sync::Arc;
@@ -9,7 +12,11 @@ use std::time::SystemTime;
 use tracing::{error, warn};
 use x509_parser::prelude::*;
 
-use crate::identity::SpiffeVerifier;
+use crate::common::{PqSecureError, RejectionReason};
+use crate::crypto::TlsConfigBuilder;
+use crate::config::{MtlsMode, RevocationMode};
+use crate::identity::{RevocationStatus, SpiffeVerifier};
+use crate::telemetry;
 
 // Custom certificate verifier
 #[derive(Debug)]
@@ -60,11 +67,11 @@ impl CustomClientCertVerifier {
 
 impl ClientCertVerifier for CustomClientCertVerifier {
     fn offer_client_auth(&self) -> bool {
-        true
+        self.spiffe_verifier.mtls_mode() != MtlsMode::Disabled
     }
 
     fn client_auth_mandatory(&self) -> bool {
-        true
+        self.spiffe_verifier.mtls_mode() == MtlsMode::Required
     }
 
     fn root_hint_subjects(&self) -> &[DistinguishedName] {
@@ -74,20 +81,63 @@ impl ClientCertVerifier for CustomClientCertVerifier {
     fn verify_client_cert(
         &self,
         end_entity: &CertificateDer<'_>,
-        _intermediates: &[CertificateDer<'_>],
-        _now: UnixTime,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
     ) -> Result<ClientCertVerified, rustls::Error> {
+        // Bound the chain before parsing anything in it
+        if let Err(e) = self.spiffe_verifier.check_chain_size(end_entity, intermediates) {
+            warn!("Rejecting client certificate chain: {}", e);
+            telemetry::record_rejected(RejectionReason::ChainTooLarge);
+            return Err(e);
+        }
+
         // Check certificate validity
-        self.check_validity(end_entity)?;
+        if let Err(e) = self.check_validity(end_entity) {
+            telemetry::record_rejected(RejectionReason::CertificateExpired);
+            return Err(e);
+        }
 
         // Verify SPIFFE ID
-        match self.spiffe_verifier.verify_client_cert(end_entity) {
-            Ok(_) => Ok(ClientCertVerified::assertion()),
+        let identity = match self.spiffe_verifier.extract_spiffe_id(end_entity) {
+            Ok(identity) => identity,
             Err(e) => {
                 error!("SPIFFE ID verification failed: {}", e);
-                Err(rustls::Error::General("Invalid SPIFFE ID".to_string()))
+                telemetry::record_rejected(RejectionReason::InvalidSpiffeId);
+                return Err(rustls::Error::General("Invalid SPIFFE ID".to_string()));
             }
+        };
+
+        // Verify the chain against the trust domain's bundle, if one is configured
+        if let Err(e) = self.spiffe_verifier.verify_chain(&identity, end_entity, intermediates, now) {
+            error!("Certificate chain verification failed for {}: {}", identity.spiffe_id, e);
+            telemetry::record_rejected(RejectionReason::UntrustedChain);
+            return Err(e);
         }
+
+        // Check the leaf against its issuer's CRL, if revocation checking is enabled
+        if let Some(revocation) = self.spiffe_verifier.revocation_checker() {
+            match revocation.status(end_entity, now) {
+                RevocationStatus::Good => {}
+                RevocationStatus::Revoked => {
+                    error!("Client certificate for {} is revoked", identity.spiffe_id);
+                    telemetry::record_rejected(RejectionReason::CertificateRevoked);
+                    return Err(rustls::Error::InvalidCertificate(CertificateError::Revoked));
+                }
+                RevocationStatus::Unknown if revocation.mode() == RevocationMode::HardFail => {
+                    warn!("No current CRL covers the certificate for {}", identity.spiffe_id);
+                    telemetry::record_rejected(RejectionReason::RevocationUnknown);
+                    return Err(rustls::Error::InvalidCertificate(CertificateError::UnknownRevocationStatus));
+                }
+                RevocationStatus::Unknown => {
+                    warn!(
+                        "No current CRL covers the certificate for {}, accepting it (soft_fail)",
+                        identity.spiffe_id
+                    );
+                }
+            }
+        }
+
+        Ok(ClientCertVerified::assertion())
     }
 
     fn verify_tls12_signature(
@@ -100,7 +150,7 @@ impl ClientCertVerifier for CustomClientCertVerifier {
             message,
             cert,
             dss,
-            &rustls::crypto::ring::default_provider().signature_verification_algorithms,
+            &crypto_provider().signature_verification_algorithms,
         )
     }
 
@@ -114,48 +164,173 @@ impl ClientCertVerifier for CustomClientCertVerifier {
             message,
             cert,
             dss,
-            &rustls::crypto::ring::default_provider().signature_verification_algorithms,
+            &crypto_provider().signature_verification_algorithms,
         )
     }
 
     fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
-        rustls::crypto::ring::default_provider().signature_verification_algorithms.supported_schemes()
+        crypto_provider().signature_verification_algorithms.supported_schemes()
     }
 }
 
+/// Key exchange groups in default preference order: the post-quantum hybrid
+/// first, then classical groups for peers without PQC support
+pub const DEFAULT_KEY_EXCHANGE_GROUPS: &[&str] = &["X25519MLKEM768", "X25519", "secp256r1", "secp384r1"];
+
+/// Look up a key exchange group by its configuration name (case-insensitive)
+pub fn key_exchange_group(name: &str) -> Result<&'static dyn SupportedKxGroup> {
+    use rustls::crypto::aws_lc_rs::kx_group;
+
+    match name.to_ascii_lowercase().as_str() {
+        "x25519mlkem768" => Ok(kx_group::X25519MLKEM768),
+        "mlkem768" => Ok(kx_group::MLKEM768),
+        "x25519" => Ok(kx_group::X25519),
+        "secp256r1" => Ok(kx_group::SECP256R1),
+        "secp384r1" => Ok(kx_group::SECP384R1),
+        _ => Err(PqSecureError::ConfigError(format!(
+            "Unsupported key exchange group '{}', expected one of: X25519MLKEM768, MLKEM768, X25519, secp256r1, secp384r1",
+            name
+        ))
+        .into()),
+    }
+}
+
+/// Crypto provider offering the given key exchange groups in preference order
+pub fn crypto_provider_with_groups<S: AsRef<str>>(names: &[S]) -> Result<Arc<CryptoProvider>> {
+    if names.is_empty() {
+        return Err(PqSecureError::ConfigError(
+            "At least one key exchange group must be configured".to_string(),
+        )
+        .into());
+    }
+
+    let kx_groups = names
+        .iter()
+        .map(|name| key_exchange_group(name.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Arc::new(CryptoProvider {
+        kx_groups,
+        ..rustls::crypto::aws_lc_rs::default_provider()
+    }))
+}
+
+/// Cryptographic parameters agreed in a completed TLS handshake
+#[derive(Debug, Clone, PartialEq)]
+pub struct NegotiatedCrypto {
+    /// Key exchange group, absent for resumed TLS 1.2 sessions
+    pub key_exchange: Option<NamedGroup>,
+
+    /// Negotiated cipher suite
+    pub cipher_suite: Option<CipherSuite>,
+
+    /// Whether the key exchange was post-quantum (pure or hybrid ML-KEM)
+    pub post_quantum: bool,
+}
+
+impl NegotiatedCrypto {
+    /// Read the negotiated parameters from a connection
+    pub fn from_connection(connection: &CommonState) -> Self {
+        let key_exchange = connection.negotiated_key_exchange_group().map(|group| group.name());
+        Self {
+            post_quantum: key_exchange.is_some_and(is_post_quantum_group),
+            key_exchange,
+            cipher_suite: connection.negotiated_cipher_suite().map(|suite| suite.suite()),
+        }
+    }
+
+    /// Key exchange group name for logs and metrics
+    pub fn key_exchange_name(&self) -> String {
+        self.key_exchange
+            .map(|group| format!("{:?}", group))
+            .unwrap_or_else(|| "none".to_string())
+    }
+
+    /// Cipher suite name for logs and metrics
+    pub fn cipher_suite_name(&self) -> String {
+        self.cipher_suite
+            .map(|suite| format!("{:?}", suite))
+            .unwrap_or_else(|| "none".to_string())
+    }
+}
+
+/// Whether a key exchange group is resistant to quantum attacks
+pub fn is_post_quantum_group(group: NamedGroup) -> bool {
+    matches!(
+        group,
+        NamedGroup::MLKEM512
+            | NamedGroup::MLKEM768
+            | NamedGroup::MLKEM1024
+            | NamedGroup::secp256r1MLKEM768
+            | NamedGroup::X25519MLKEM768
+    )
+}
+
+/// Crypto provider used for all TLS configurations
+///
+/// Both the ring and aws-lc-rs backends are compiled in, so rustls cannot
+/// pick a process default on its own; aws-lc-rs is used for its
+/// post-quantum key exchange support, preferring the groups in
+/// [`DEFAULT_KEY_EXCHANGE_GROUPS`].
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    crypto_provider_with_groups(DEFAULT_KEY_EXCHANGE_GROUPS).expect("default key exchange groups are supported")
+}
+
 /// Build TLS configuration for server with PQC support
 pub fn build_tls_config(
     cert_chain: Vec<CertificateDer<'static>>,
     private_key: PrivateKeyDer<'static>,
     spiffe_verifier: Arc<SpiffeVerifier>,
 ) -> Result<Arc<ServerConfig>> {
-    // Create custom certificate verifier
-    let client_cert_verifier = Arc::new(CustomClientCertVerifier::new(spiffe_verifier));
-
-    // 使用新版API建立設定
-    let mut config = ServerConfig::builder()
-        .with_client_cert_verifier(client_cert_verifier)
-        .with_single_cert(cert_chain, private_key)
-        .context("Failed to set up server certificate")?;
+    build_tls_config_with_provider(cert_chain, private_key, spiffe_verifier, crypto_provider())
+}
 
-    // Configure ALPN protocols
-    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+/// Build TLS configuration for server using the given crypto provider
+///
+/// The server picks the first group in the provider's order that the client
+/// supports, so the provider's key exchange group order is the negotiation
+/// preference.
+pub fn build_tls_config_with_provider(
+    cert_chain: Vec<CertificateDer<'static>>,
+    private_key: PrivateKeyDer<'static>,
+    spiffe_verifier: Arc<SpiffeVerifier>,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<ServerConfig>> {
+    TlsConfigBuilder::new()
+        .with_provider(provider)
+        .with_identity(cert_chain, private_key)
+        .require_client_auth(spiffe_verifier)
+        .build_server()
+}
 
-    Ok(Arc::new(config))
+/// Build TLS configuration for server choosing its certificate per connection
+///
+/// Used with [`SniCertResolver`](crate::crypto::SniCertResolver) to present
+/// different identities for different SNI values.
+pub fn build_tls_config_with_resolver(
+    cert_resolver: Arc<dyn ResolvesServerCert>,
+    spiffe_verifier: Arc<SpiffeVerifier>,
+    provider: Arc<CryptoProvider>,
+) -> Result<Arc<ServerConfig>> {
+    TlsConfigBuilder::new()
+        .with_provider(provider)
+        .with_cert_resolver(cert_resolver)
+        .require_client_auth(spiffe_verifier)
+        .build_server()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::identity::SpiffeVerifier;
-    use rcgen::{CertificateParams, DnType, SanType, KeyPair};
+    use crate::identity::{ChainLimits, SpiffeVerifier};
+    use crate::test_support::{spiffe_params, test_ca};
+    use rcgen::{DnType, KeyPair};
     use std::time::{SystemTime, Duration};
 
     // Helper to generate a test certificate with a SPIFFE ID
     fn generate_test_cert(spiffe_id: &str, valid: bool) -> CertificateDer<'static> {
-        let mut params = CertificateParams::default();
+        let mut params = spiffe_params(spiffe_id);
         params.distinguished_name.push(DnType::CommonName, "Test");
-        params.subject_alt_names.push(SanType::URI(rcgen::Ia5String::try_from(spiffe_id).unwrap()));
 
         // Set validity period
         if !valid {
@@ -209,4 +384,118 @@ mod tests {
         let invalid_format_cert = generate_test_cert("not-a-spiffe-id", true);
         assert!(verifier.spiffe_verifier().extract_spiffe_id(&invalid_format_cert).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_crypto_provider_with_groups() {
+        let provider = crypto_provider_with_groups(&["x25519", "X25519MLKEM768"]).unwrap();
+        let names: Vec<_> = provider.kx_groups.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec![rustls::NamedGroup::X25519, rustls::NamedGroup::X25519MLKEM768]);
+
+        assert!(crypto_provider_with_groups(&["Kyber768"]).is_err());
+        assert!(crypto_provider_with_groups::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_oversized_chain_rejected() {
+        let spiffe_verifier = SpiffeVerifier::new("example.org".to_string()).with_chain_limits(ChainLimits {
+            max_bytes: 16 * 1024,
+            max_depth: 3,
+        });
+        let verifier = CustomClientCertVerifier::new(Arc::new(spiffe_verifier));
+        let leaf = generate_test_cert("spiffe://example.org/service/test", true);
+        let now = UnixTime::now();
+
+        // Junk intermediates are rejected on size alone, before parsing
+        let oversized = vec![CertificateDer::from(vec![0u8; 16 * 1024])];
+        let err = verifier.verify_client_cert(&leaf, &oversized, now).unwrap_err();
+        assert!(err.to_string().contains("bytes, limit is 16384"));
+
+        let too_deep = vec![CertificateDer::from(vec![0u8; 16]); 3];
+        let err = verifier.verify_client_cert(&leaf, &too_deep, now).unwrap_err();
+        assert!(err.to_string().contains("4 certificates, limit is 3"));
+
+        assert!(verifier.verify_client_cert(&leaf, &[], now).is_ok());
+    }
+
+    #[test]
+    fn test_revoked_certificate_rejected() {
+        use crate::config::RevocationConfig;
+        use crate::identity::RevocationChecker;
+        use rcgen::{
+            date_time_ymd, CertificateRevocationListParams, KeyIdMethod, RevokedCertParams, SerialNumber,
+        };
+
+        let (ca, ca_key) = test_ca();
+        let issue = |serial: u64| {
+            let mut params = spiffe_params("spiffe://example.org/service/test");
+            params.serial_number = Some(SerialNumber::from(serial));
+            params.signed_by(&KeyPair::generate().unwrap(), &ca, &ca_key).unwrap().der().clone()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let crl_path = dir.path().join("ca.crl");
+        let crl = CertificateRevocationListParams {
+            this_update: date_time_ymd(2024, 1, 1),
+            next_update: date_time_ymd(2100, 1, 1),
+            crl_number: SerialNumber::from(1u64),
+            issuing_distribution_point: None,
+            revoked_certs: vec![RevokedCertParams {
+                serial_number: SerialNumber::from(7u64),
+                revocation_time: date_time_ymd(2024, 1, 1),
+                reason_code: None,
+                invalidity_date: None,
+            }],
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&ca, &ca_key)
+        .unwrap();
+        std::fs::write(&crl_path, crl.der()).unwrap();
+
+        let verifier_with = |mode: RevocationMode| {
+            let checker = RevocationChecker::from_config(&RevocationConfig {
+                mode,
+                crl_paths: vec![crl_path.clone()],
+                refresh_seconds: 300,
+            })
+            .unwrap()
+            .unwrap();
+            CustomClientCertVerifier::new(Arc::new(
+                SpiffeVerifier::new("example.org".to_string()).with_revocation_checker(Arc::new(checker)),
+            ))
+        };
+        let now = UnixTime::now();
+        // Self-signed, so no CRL covers it
+        let uncovered = generate_test_cert("spiffe://example.org/service/test", true);
+
+        let verifier = verifier_with(RevocationMode::SoftFail);
+        assert!(verifier.verify_client_cert(&issue(6), &[], now).is_ok());
+        assert_eq!(
+            verifier.verify_client_cert(&issue(7), &[], now).unwrap_err(),
+            rustls::Error::InvalidCertificate(CertificateError::Revoked)
+        );
+        assert!(verifier.verify_client_cert(&uncovered, &[], now).is_ok());
+
+        let verifier = verifier_with(RevocationMode::HardFail);
+        assert!(verifier.verify_client_cert(&issue(6), &[], now).is_ok());
+        assert_eq!(
+            verifier.verify_client_cert(&uncovered, &[], now).unwrap_err(),
+            rustls::Error::InvalidCertificate(CertificateError::UnknownRevocationStatus)
+        );
+    }
+
+    #[test]
+    fn test_client_auth_follows_mtls_mode() {
+        let verifier_with = |mtls_mode: MtlsMode| {
+            CustomClientCertVerifier::new(Arc::new(
+                SpiffeVerifier::new("example.org".to_string()).with_mtls_mode(mtls_mode),
+            ))
+        };
+
+        let verifier = verifier_with(MtlsMode::Required);
+        assert!(verifier.offer_client_auth() && verifier.client_auth_mandatory());
+        let verifier = verifier_with(MtlsMode::Optional);
+        assert!(verifier.offer_client_auth() && !verifier.client_auth_mandatory());
+        let verifier = verifier_with(MtlsMode::Disabled);
+        assert!(!verifier.offer_client_auth() && !verifier.client_auth_mandatory());
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, ServerConfig, SignatureScheme};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tracing::{debug, info};
+
+use crate::common::{PqSecureError, ServiceIdentity};
+use crate::crypto::{crypto_provider, TlsConfigBuilder};
+use crate::identity::SpiffeVerifier;
+
+/// Maximum time allowed for the loopback handshake
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Server name used by the loopback client; the server certificate is pinned instead
+const SELF_TEST_SERVER_NAME: &str = "pqsecure-mesh.self-test";
+
+/// Server certificate verifier that only accepts our own certificate
+#[derive(Debug)]
+struct PinnedServerCertVerifier {
+    expected: CertificateDer<'static>,
+}
+
+impl ServerCertVerifier for PinnedServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.expected.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "Server presented an unexpected certificate".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &crypto_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &crypto_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        crypto_provider().signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Perform an in-process mTLS handshake against the server TLS configuration
+///
+/// The proxy's own certificate is presented as the client certificate, so
+/// this checks that the certificate, key, client verifier and ALPN settings
+/// work together before any real client connects. Returns the SPIFFE
+/// identity extracted from the client certificate on the server side.
+pub async fn run_self_test(
+    server_config: Arc<ServerConfig>,
+    cert_chain: Vec<CertificateDer<'static>>,
+    private_key: PrivateKeyDer<'static>,
+    spiffe_verifier: &SpiffeVerifier,
+) -> Result<ServiceIdentity> {
+    let leaf = cert_chain
+        .first()
+        .cloned()
+        .ok_or_else(|| PqSecureError::CertificateError("Certificate chain is empty".to_string()))?;
+
+    let client_config = TlsConfigBuilder::new()
+        .with_identity(cert_chain, private_key)
+        .with_server_verifier(Arc::new(PinnedServerCertVerifier { expected: leaf }))
+        .with_alpn(&server_config.alpn_protocols)
+        .build_client()
+        .context("Failed to set up self-test client configuration")?;
+    let expects_alpn = !server_config.alpn_protocols.is_empty();
+
+    let acceptor = TlsAcceptor::from(server_config);
+    let connector = TlsConnector::from(client_config);
+    let server_name = ServerName::try_from(SELF_TEST_SERVER_NAME)
+        .context("Invalid self-test server name")?;
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+
+    debug!("Running loopback mTLS self-test");
+    let (server_result, client_result) = timeout(SELF_TEST_TIMEOUT, async {
+        tokio::join!(acceptor.accept(server_io), connector.connect(server_name, client_io))
+    })
+    .await
+    .map_err(|_| PqSecureError::TlsError("Self-test handshake timed out".to_string()))?;
+
+    let server_stream = server_result
+        .map_err(|e| PqSecureError::TlsError(format!("Self-test handshake rejected by server: {}", e)))?;
+    client_result
+        .map_err(|e| PqSecureError::TlsError(format!("Self-test handshake failed on client: {}", e)))?;
+
+    let connection = server_stream.get_ref().1;
+    if expects_alpn && connection.alpn_protocol().is_none() {
+        return Err(PqSecureError::TlsError(
+            "Self-test did not negotiate an ALPN protocol".to_string(),
+        )
+        .into());
+    }
+
+    let client_cert = connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| PqSecureError::TlsError("Self-test client certificate was not received".to_string()))?;
+
+    let identity = spiffe_verifier
+        .extract_spiffe_id(client_cert)
+        .context("Self-test could not extract a SPIFFE ID from the client certificate")?;
+
+    info!("Loopback mTLS self-test passed for {}", identity.spiffe_id);
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::build_tls_config;
+    use crate::test_support::self_signed_identity;
+
+    #[tokio::test]
+    async fn test_self_test_passes_for_trusted_identity() {
+        let verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let (certs, key) = self_signed_identity("spiffe://example.org/service/test");
+        let server_config = build_tls_config(certs.clone(), key.clone_key(), verifier.clone()).unwrap();
+
+        let identity = run_self_test(server_config, certs, key, &verifier).await.unwrap();
+        assert_eq!(identity.spiffe_id, "spiffe://example.org/service/test");
+    }
+
+    #[tokio::test]
+    async fn test_self_test_fails_for_untrusted_domain() {
+        let verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let (certs, key) = self_signed_identity("spiffe://other.org/service/test");
+        let server_config = build_tls_config(certs.clone(), key.clone_key(), verifier.clone()).unwrap();
+
+        let err = run_self_test(server_config, certs, key, &verifier).await.unwrap_err();
+        assert!(err.to_string().contains("rejected by server"));
+    }
+}
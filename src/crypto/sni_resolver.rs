@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::trace;
+
+/// Presents a different server identity depending on the ClientHello SNI
+///
+/// rustls' own `ResolvesServerCertUsingSni` requires each certificate to be
+/// valid for its DNS name, which SPIFFE SVIDs (URI SANs only) never are, so
+/// names are matched against the configured map as-is. Clients without SNI,
+/// or with an unknown name, get the default identity, which can be replaced
+/// while the server runs to pick up a renewed certificate.
+#[derive(Debug)]
+pub struct SniCertResolver {
+    /// Identity for clients without a matching server name
+    default: RwLock<Arc<CertifiedKey>>,
+
+    /// Identities keyed by lowercase server name
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl SniCertResolver {
+    /// Create a resolver presenting the given identity by default
+    pub fn new(
+        cert_chain: Vec<CertificateDer<'static>>,
+        private_key: PrivateKeyDer<'static>,
+        provider: &CryptoProvider,
+    ) -> Result<Self> {
+        Ok(Self {
+            default: RwLock::new(Arc::new(
+                CertifiedKey::from_der(cert_chain, private_key, provider)
+                    .context("Failed to load default server identity")?,
+            )),
+            by_name: HashMap::new(),
+        })
+    }
+
+    /// Present the given identity to clients requesting `server_name`
+    pub fn add(
+        &mut self,
+        server_name: &str,
+        cert_chain: Vec<CertificateDer<'static>>,
+        private_key: PrivateKeyDer<'static>,
+        provider: &CryptoProvider,
+    ) -> Result<()> {
+        let key = CertifiedKey::from_der(cert_chain, private_key, provider)
+            .context(format!("Failed to load server identity for SNI '{}'", server_name))?;
+        self.by_name.insert(server_name.to_ascii_lowercase(), Arc::new(key));
+        Ok(())
+    }
+
+    /// Present the given identity by default from now on
+    ///
+    /// Handshakes already in progress keep the identity they resolved.
+    pub fn replace_default(
+        &self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        private_key: PrivateKeyDer<'static>,
+        provider: &CryptoProvider,
+    ) -> Result<()> {
+        let key = CertifiedKey::from_der(cert_chain, private_key, provider)
+            .context("Failed to load default server identity")?;
+        *self.default.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(())
+    }
+
+    /// Identity currently presented by default
+    pub fn default_identity(&self) -> Arc<CertifiedKey> {
+        self.default.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let identity = client_hello
+            .server_name()
+            .and_then(|name| self.by_name.get(&name.to_ascii_lowercase()));
+        trace!(
+            "SNI {:?} matched a configured identity: {}",
+            client_hello.server_name(),
+            identity.is_some()
+        );
+
+        Some(identity.cloned().unwrap_or_else(|| self.default_identity()))
+    }
+}
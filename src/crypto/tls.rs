@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use rustls::client::danger::ServerCertVerifier;
+use rustls::client::Resumption;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::{NoServerSessionStorage, ResolvesServerCert};
+use rustls::{ClientConfig, ServerConfig};
+use std::sync::Arc;
+
+use crate::common::PqSecureError;
+use crate::crypto::{crypto_provider, CustomClientCertVerifier};
+use crate::identity::SpiffeVerifier;
+
+/// ALPN protocols offered by default on both sides of a mesh connection
+pub const DEFAULT_ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// Certificate a TLS endpoint presents
+enum TlsIdentity {
+    /// A fixed certificate chain and key
+    Single(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+    /// A resolver choosing the certificate per connection (server side only)
+    Resolver(Arc<dyn ResolvesServerCert>),
+}
+
+/// Builder for the server and client TLS configurations used by the mesh
+///
+/// Every configuration starts from the same crypto provider, protocol
+/// versions and ALPN list, so the server and client sides cannot drift
+/// apart. Server configurations always require client authentication.
+pub struct TlsConfigBuilder {
+    /// Crypto provider, which also fixes the key exchange preference
+    provider: Arc<CryptoProvider>,
+    /// Certificate to present
+    identity: Option<TlsIdentity>,
+    /// Verifier for client certificates (server side)
+    client_verifier: Option<Arc<dyn ClientCertVerifier>>,
+    /// Verifier for server certificates (client side)
+    server_verifier: Option<Arc<dyn ServerCertVerifier>>,
+    /// ALPN protocols in preference order
+    alpn_protocols: Vec<Vec<u8>>,
+    /// Whether sessions may be resumed
+    session_resumption: bool,
+}
+
+impl Default for TlsConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConfigBuilder {
+    /// Start a configuration with the default crypto provider and ALPN list
+    pub fn new() -> Self {
+        Self {
+            provider: crypto_provider(),
+            identity: None,
+            client_verifier: None,
+            server_verifier: None,
+            alpn_protocols: DEFAULT_ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect(),
+            session_resumption: true,
+        }
+    }
+
+    /// Use the given crypto provider instead of the default one
+    pub fn with_provider(mut self, provider: Arc<CryptoProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Present the given certificate chain and key
+    pub fn with_identity(
+        mut self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        private_key: PrivateKeyDer<'static>,
+    ) -> Self {
+        self.identity = Some(TlsIdentity::Single(cert_chain, private_key));
+        self
+    }
+
+    /// Choose the presented certificate per connection (server side only)
+    pub fn with_cert_resolver(mut self, cert_resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        self.identity = Some(TlsIdentity::Resolver(cert_resolver));
+        self
+    }
+
+    /// Verify client certificates with the given verifier
+    pub fn with_client_verifier(mut self, verifier: Arc<dyn ClientCertVerifier>) -> Self {
+        self.client_verifier = Some(verifier);
+        self
+    }
+
+    /// Require clients to present a certificate with a trusted SPIFFE ID
+    pub fn require_client_auth(self, spiffe_verifier: Arc<SpiffeVerifier>) -> Self {
+        self.with_client_verifier(Arc::new(CustomClientCertVerifier::new(spiffe_verifier)))
+    }
+
+    /// Verify server certificates with the given verifier
+    pub fn with_server_verifier(mut self, verifier: Arc<dyn ServerCertVerifier>) -> Self {
+        self.server_verifier = Some(verifier);
+        self
+    }
+
+    /// Offer the given ALPN protocols in preference order
+    pub fn with_alpn<P: AsRef<[u8]>>(mut self, protocols: &[P]) -> Self {
+        self.alpn_protocols = protocols.iter().map(|p| p.as_ref().to_vec()).collect();
+        self
+    }
+
+    /// Allow or disable TLS session resumption (enabled by default)
+    pub fn with_session_resumption(mut self, enabled: bool) -> Self {
+        self.session_resumption = enabled;
+        self
+    }
+
+    /// Build a server configuration
+    pub fn build_server(self) -> Result<Arc<ServerConfig>> {
+        let client_verifier = self.client_verifier.ok_or_else(|| {
+            PqSecureError::TlsError("Server TLS configuration requires a client certificate verifier".to_string())
+        })?;
+
+        let builder = ServerConfig::builder_with_provider(self.provider)
+            .with_safe_default_protocol_versions()
+            .context("Failed to select TLS protocol versions")?
+            .with_client_cert_verifier(client_verifier);
+
+        let mut config = match self.identity {
+            Some(TlsIdentity::Single(cert_chain, private_key)) => builder
+                .with_single_cert(cert_chain, private_key)
+                .context("Failed to set up server certificate")?,
+            Some(TlsIdentity::Resolver(cert_resolver)) => builder.with_cert_resolver(cert_resolver),
+            None => {
+                return Err(PqSecureError::TlsError(
+                    "Server TLS configuration requires a certificate".to_string(),
+                )
+                .into())
+            }
+        };
+
+        config.alpn_protocols = self.alpn_protocols;
+        if !self.session_resumption {
+            config.session_storage = Arc::new(NoServerSessionStorage {});
+            config.send_tls13_tickets = 0;
+        }
+
+        Ok(Arc::new(config))
+    }
+
+    /// Build a client configuration
+    pub fn build_client(self) -> Result<Arc<ClientConfig>> {
+        let server_verifier = self.server_verifier.ok_or_else(|| {
+            PqSecureError::TlsError("Client TLS configuration requires a server certificate verifier".to_string())
+        })?;
+
+        let (cert_chain, private_key) = match self.identity {
+            Some(TlsIdentity::Single(cert_chain, private_key)) => (cert_chain, private_key),
+            Some(TlsIdentity::Resolver(_)) => {
+                return Err(PqSecureError::TlsError(
+                    "Certificate resolvers are only supported for server configurations".to_string(),
+                )
+                .into())
+            }
+            None => {
+                return Err(PqSecureError::TlsError(
+                    "Client TLS configuration requires a certificate".to_string(),
+                )
+                .into())
+            }
+        };
+
+        let mut config = ClientConfig::builder_with_provider(self.provider)
+            .with_safe_default_protocol_versions()
+            .context("Failed to select TLS protocol versions")?
+            .dangerous()
+            .with_custom_certificate_verifier(server_verifier)
+            .with_client_auth_cert(cert_chain, private_key)
+            .context("Failed to set up client certificate")?;
+
+        config.alpn_protocols = self.alpn_protocols;
+        if !self.session_resumption {
+            config.resumption = Resumption::disabled();
+        }
+
+        Ok(Arc::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::self_signed_identity;
+
+    #[test]
+    fn test_server_settings() {
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()));
+        let (chain, key) = self_signed_identity("spiffe://example.org/service/test");
+
+        let config = TlsConfigBuilder::new()
+            .with_identity(chain.clone(), key.clone_key())
+            .require_client_auth(spiffe_verifier.clone())
+            .build_server()
+            .unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        assert!(config.send_tls13_tickets > 0);
+
+        let config = TlsConfigBuilder::new()
+            .with_identity(chain.clone(), key.clone_key())
+            .require_client_auth(spiffe_verifier.clone())
+            .with_alpn(&["http/1.1"])
+            .with_session_resumption(false)
+            .build_server()
+            .unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"http/1.1".to_vec()]);
+        assert_eq!(config.send_tls13_tickets, 0);
+
+        // Serving without client authentication or a certificate is refused
+        assert!(TlsConfigBuilder::new().with_identity(chain, key).build_server().is_err());
+        assert!(TlsConfigBuilder::new().require_client_auth(spiffe_verifier).build_server().is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use spiffe::{JwtBundle, JwtBundleSet, JwtSvid, TrustDomain};
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+use crate::common::{PqSecureError, ServiceIdentity};
+use crate::config::JwtSvidConfig;
+
+/// Verifies JWT-SVIDs against the JWT authorities of trusted domains
+///
+/// A token is accepted when it is signed by a key in the JWKS bundle of the
+/// trust domain in its `sub` claim, names one of the expected audiences and
+/// has not expired. Tokens from domains without a bundle are rejected.
+#[derive(Debug)]
+pub struct JwtSvidVerifier {
+    /// JWT authorities keyed by trust domain
+    bundles: JwtBundleSet,
+
+    /// Audiences a token must be issued for (any one of them suffices)
+    audience: Vec<String>,
+}
+
+impl JwtSvidVerifier {
+    /// Create a verifier accepting tokens for the given audience
+    pub fn new(audience: Vec<String>) -> Self {
+        Self {
+            bundles: JwtBundleSet::new(),
+            audience,
+        }
+    }
+
+    /// Create a verifier from configuration, loading every JWKS bundle
+    pub fn from_config(config: &JwtSvidConfig) -> Result<Self> {
+        let mut verifier = Self::new(config.audience.clone());
+        for bundle in &config.bundles {
+            verifier.add_bundle_file(&bundle.domain, &bundle.path)?;
+        }
+        Ok(verifier)
+    }
+
+    /// Trust the JWT authorities in a JWKS document for `domain`
+    pub fn add_bundle(&mut self, domain: &str, jwks: &[u8]) -> Result<()> {
+        let trust_domain = TrustDomain::new(domain)
+            .map_err(|e| PqSecureError::SpiffeIdError(format!("Invalid trust domain '{}': {}", domain, e)))?;
+        let bundle = JwtBundle::from_jwt_authorities(trust_domain, jwks)
+            .context(format!("Failed to parse JWT bundle for {}", domain))?;
+        self.bundles.add_bundle(bundle);
+        Ok(())
+    }
+
+    /// Trust the JWT authorities in a JWKS file for `domain`
+    pub fn add_bundle_file<P: AsRef<Path>>(&mut self, domain: &str, path: P) -> Result<()> {
+        let jwks = fs::read(path.as_ref())
+            .context(format!("Failed to read JWT bundle: {}", path.as_ref().display()))?;
+        self.add_bundle(domain, &jwks)
+    }
+
+    /// Validate a JWT-SVID and return the identity it asserts
+    pub fn verify(&self, token: &str) -> Result<ServiceIdentity> {
+        let svid = JwtSvid::parse_and_validate(token, &self.bundles, &self.audience).map_err(|e| {
+            debug!("Rejected JWT-SVID: {}", e);
+            PqSecureError::AuthenticationError(format!("Invalid JWT-SVID: {}", e))
+        })?;
+
+        let spiffe_id = svid.spiffe_id();
+        Ok(ServiceIdentity {
+            spiffe_id: spiffe_id.to_string(),
+            trust_domain: spiffe_id.trust_domain().to_string(),
+            path: spiffe_id.path().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{jwks_for, sign_jwt_svid};
+    use rcgen::{KeyPair, PKCS_ECDSA_P256_SHA256};
+
+    fn verifier(key_pair: &KeyPair) -> JwtSvidVerifier {
+        let mut verifier = JwtSvidVerifier::new(vec!["spiffe://example.org/service/backend".to_string()]);
+        verifier.add_bundle("example.org", &jwks_for(key_pair)).unwrap();
+        verifier
+    }
+
+    #[test]
+    fn test_verify_jwt_svid() {
+        let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let token = sign_jwt_svid(
+            &key_pair,
+            "spiffe://example.org/service/frontend",
+            "spiffe://example.org/service/backend",
+        );
+
+        let identity = verifier(&key_pair).verify(&token).unwrap();
+        assert_eq!(identity.spiffe_id, "spiffe://example.org/service/frontend");
+        assert_eq!(identity.trust_domain, "example.org");
+        assert_eq!(identity.path, "/service/frontend");
+    }
+
+    #[test]
+    fn test_rejects_wrong_audience_or_signer() {
+        let key_pair = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let verifier = verifier(&key_pair);
+
+        let token = sign_jwt_svid(
+            &key_pair,
+            "spiffe://example.org/service/frontend",
+            "spiffe://example.org/service/other",
+        );
+        assert!(verifier.verify(&token).is_err());
+
+        // Same key ID, but signed by a key the bundle does not contain
+        let other_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256).unwrap();
+        let token = sign_jwt_svid(
+            &other_key,
+            "spiffe://example.org/service/frontend",
+            "spiffe://example.org/service/backend",
+        );
+        assert!(verifier.verify(&token).is_err());
+
+        // No bundle for the token's trust domain
+        let token = sign_jwt_svid(
+            &key_pair,
+            "spiffe://other.org/service/frontend",
+            "spiffe://example.org/service/backend",
+        );
+        assert!(verifier.verify(&token).is_err());
+    }
+}
@@ -1,3 +1,9 @@
+mod jwt;
+mod revocation;
 mod verifier;
+mod workload_api;
 
-pub use verifier::*;
\ No newline at end of file
+pub use jwt::JwtSvidVerifier;
+pub use revocation::{RevocationChecker, RevocationStatus};
+pub use verifier::*;
+pub use workload_api::{proto, SvidPublisher, WorkloadApiServer, WorkloadSvid};
@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, UnixTime};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+use x509_parser::prelude::*;
+
+use crate::common::PqSecureError;
+use crate::config::{RevocationConfig, RevocationMode};
+
+/// Revocation status of a certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevocationStatus {
+    /// The issuer's current CRL does not list the certificate
+    Good,
+    /// The issuer's CRL lists the certificate
+    Revoked,
+    /// No CRL for the issuer is loaded, or it is past its next update
+    Unknown,
+}
+
+/// Serials revoked by one issuer, from its CRL
+#[derive(Debug, Default)]
+struct IssuerCrl {
+    /// Raw serial numbers of revoked certificates
+    revoked: HashSet<Vec<u8>>,
+    /// When the CRL stops being current, in seconds since the Unix epoch
+    next_update: Option<i64>,
+}
+
+/// Checks client certificates against CRLs read from local files
+///
+/// The CRLs are cached keyed by issuer and re-read periodically. A CRL is
+/// trusted for as long as its `nextUpdate` lies in the future; after that
+/// its issuer's certificates have unknown status until a fresh CRL is
+/// loaded. The files are managed by the operator, like trust bundles, so
+/// CRL signatures are not checked.
+#[derive(Debug)]
+pub struct RevocationChecker {
+    /// How strictly unknown status is treated
+    mode: RevocationMode,
+    /// CRL files to load
+    crl_paths: Vec<PathBuf>,
+    /// Loaded CRLs keyed by raw issuer name
+    crls: RwLock<HashMap<Vec<u8>, IssuerCrl>>,
+}
+
+impl RevocationChecker {
+    /// Create a checker and load its CRLs, or `None` when checking is off
+    pub fn from_config(config: &RevocationConfig) -> Result<Option<Self>> {
+        if config.mode == RevocationMode::Off {
+            return Ok(None);
+        }
+
+        let checker = Self {
+            mode: config.mode,
+            crl_paths: config.crl_paths.clone(),
+            crls: RwLock::new(HashMap::new()),
+        };
+        checker.reload()?;
+        Ok(Some(checker))
+    }
+
+    /// How strictly unknown status is treated
+    pub fn mode(&self) -> RevocationMode {
+        self.mode
+    }
+
+    /// Re-read every CRL file, keeping the current CRLs if any of them fails to load
+    ///
+    /// Returns the number of issuers covered.
+    pub fn reload(&self) -> Result<usize> {
+        let mut crls: HashMap<Vec<u8>, IssuerCrl> = HashMap::new();
+        for path in &self.crl_paths {
+            let data = std::fs::read(path).context(format!("Failed to read CRL {}", path.display()))?;
+            for der in parse_crl_file(&data).context(format!("Failed to parse CRL {}", path.display()))? {
+                let (_, crl) = CertificateRevocationList::from_der(der.as_ref())
+                    .map_err(|e| PqSecureError::CertificateError(format!("Invalid CRL in {}: {}", path.display(), e)))?;
+
+                let entry = crls.entry(crl.issuer().as_raw().to_vec()).or_default();
+                entry
+                    .revoked
+                    .extend(crl.iter_revoked_certificates().map(|revoked| revoked.raw_serial().to_vec()));
+                let next_update = crl.next_update().map(|time| time.timestamp());
+                // With several CRLs for one issuer, the earliest expiry decides
+                entry.next_update = match (entry.next_update, next_update) {
+                    (Some(current), Some(next)) => Some(current.min(next)),
+                    (current, next) => current.or(next),
+                };
+            }
+        }
+
+        let issuers = crls.len();
+        *self.crls.write().unwrap_or_else(|e| e.into_inner()) = crls;
+        info!("Loaded CRLs for {} issuer(s)", issuers);
+        Ok(issuers)
+    }
+
+    /// Look up a certificate in its issuer's CRL
+    pub fn status(&self, cert: &CertificateDer<'_>, now: UnixTime) -> RevocationStatus {
+        let (_, cert) = match X509Certificate::from_der(cert.as_ref()) {
+            Ok(cert) => cert,
+            Err(_) => return RevocationStatus::Unknown,
+        };
+
+        let crls = self.crls.read().unwrap_or_else(|e| e.into_inner());
+        let crl = match crls.get(cert.issuer().as_raw()) {
+            Some(crl) => crl,
+            None => return RevocationStatus::Unknown,
+        };
+
+        if crl.revoked.contains(cert.raw_serial()) {
+            return RevocationStatus::Revoked;
+        }
+
+        match crl.next_update {
+            Some(next_update) if next_update < now.as_secs() as i64 => RevocationStatus::Unknown,
+            _ => RevocationStatus::Good,
+        }
+    }
+
+    /// Spawn a task that re-reads the CRLs every `interval`
+    ///
+    /// A failed reload is logged and the previously loaded CRLs stay in use.
+    /// The task stops when `shutdown` is cancelled.
+    pub fn spawn_refresh(self: Arc<Self>, interval: Duration, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        debug!("CRL refresh stopped");
+                        return;
+                    }
+                    _ = ticker.tick() => {}
+                }
+
+                if let Err(e) = self.reload() {
+                    warn!("Keeping current CRLs: {:#}", e);
+                }
+            }
+        })
+    }
+}
+
+/// Split a CRL file into DER CRLs, accepting PEM with any number of CRLs or a single DER CRL
+fn parse_crl_file(data: &[u8]) -> Result<Vec<CertificateRevocationListDer<'static>>> {
+    let crls = rustls_pemfile::crls(&mut &data[..])
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to parse PEM CRL")?;
+
+    if crls.is_empty() {
+        return Ok(vec![CertificateRevocationListDer::from(data.to_vec())]);
+    }
+    Ok(crls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_ca_named;
+    use rcgen::{
+        date_time_ymd, Certificate, CertificateParams, CertificateRevocationListParams, KeyIdMethod, KeyPair,
+        RevokedCertParams, SerialNumber,
+    };
+
+    /// Issue certificates with the given serials from a fresh CA named `ca_name`
+    fn ca_and_leaves(ca_name: &str, serials: &[u64]) -> (Certificate, KeyPair, Vec<CertificateDer<'static>>) {
+        let (ca, ca_key) = test_ca_named(ca_name);
+
+        let leaves = serials
+            .iter()
+            .map(|serial| {
+                let mut params = CertificateParams::default();
+                params.serial_number = Some(SerialNumber::from(*serial));
+                let key = KeyPair::generate().unwrap();
+                params.signed_by(&key, &ca, &ca_key).unwrap().der().clone()
+            })
+            .collect();
+        (ca, ca_key, leaves)
+    }
+
+    fn crl_pem(ca: &Certificate, ca_key: &KeyPair, revoked: &[u64], next_update_year: i32) -> String {
+        CertificateRevocationListParams {
+            this_update: date_time_ymd(2024, 1, 1),
+            next_update: date_time_ymd(next_update_year, 1, 1),
+            crl_number: SerialNumber::from(1u64),
+            issuing_distribution_point: None,
+            revoked_certs: revoked
+                .iter()
+                .map(|serial| RevokedCertParams {
+                    serial_number: SerialNumber::from(*serial),
+                    revocation_time: date_time_ymd(2024, 1, 1),
+                    reason_code: None,
+                    invalidity_date: None,
+                })
+                .collect(),
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(ca, ca_key)
+        .unwrap()
+        .pem()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_crl_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let crl_path = dir.path().join("ca.crl");
+        let (ca, ca_key, leaves) = ca_and_leaves("Mesh CA", &[1, 2]);
+        let (_, _, other_issuer) = ca_and_leaves("Other CA", &[1]);
+        std::fs::write(&crl_path, crl_pem(&ca, &ca_key, &[2], 2100)).unwrap();
+
+        let checker = RevocationChecker::from_config(&RevocationConfig {
+            mode: RevocationMode::HardFail,
+            crl_paths: vec![crl_path.clone()],
+            refresh_seconds: 300,
+        })
+        .unwrap()
+        .unwrap();
+        let now = UnixTime::now();
+        assert_eq!(checker.status(&leaves[0], now), RevocationStatus::Good);
+        assert_eq!(checker.status(&leaves[1], now), RevocationStatus::Revoked);
+        assert_eq!(checker.status(&other_issuer[0], now), RevocationStatus::Unknown);
+
+        // A CRL past its next update no longer vouches for anything
+        std::fs::write(&crl_path, crl_pem(&ca, &ca_key, &[2], 2025)).unwrap();
+        checker.reload().unwrap();
+        assert_eq!(checker.status(&leaves[0], now), RevocationStatus::Unknown);
+        assert_eq!(checker.status(&leaves[1], now), RevocationStatus::Revoked);
+
+        // A broken file keeps the CRLs already loaded
+        std::fs::write(&crl_path, crl_pem(&ca, &ca_key, &[2], 2100)).unwrap();
+        checker.reload().unwrap();
+        std::fs::write(&crl_path, "garbage").unwrap();
+        assert!(checker.reload().is_err());
+        assert_eq!(checker.status(&leaves[0], now), RevocationStatus::Good);
+    }
+
+    #[test]
+    fn test_off_mode_loads_nothing() {
+        assert!(RevocationChecker::from_config(&RevocationConfig::default()).unwrap().is_none());
+    }
+}
@@ -1,11 +1,23 @@
 use anyhow::{Context, Result};
-use rustls::pki_types::CertificateDer;
+use rustls::pki_types::{CertificateDer, UnixTime};
+use rustls::server::danger::ClientCertVerifier;
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
 use spiffe::SpiffeId;
-use tracing::{debug, error, trace};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, trace, warn};
 use x509_parser::extensions::GeneralName;
 use x509_parser::prelude::*;
 
 use crate::common::{PqSecureError, ServiceIdentity};
+use crate::config::{IdentityConfig, MtlsMode};
+use crate::crypto::crypto_provider;
+use crate::identity::RevocationChecker;
 
 /// Trait for extracting identity from different sources
 #[async_trait::async_trait]
@@ -13,17 +25,133 @@ pub trait IdentityExtractor: Send + Sync {
     async fn extract_identity(&self, cert: &CertificateDer<'_>) -> Result<ServiceIdentity>;
 }
 
+/// Size bounds for client certificate chains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainLimits {
+    /// Largest total DER size of the leaf and intermediates
+    pub max_bytes: usize,
+    /// Largest number of certificates, including the leaf
+    pub max_depth: usize,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024,
+            max_depth: 8,
+        }
+    }
+}
+
+/// A trust domain's root bundle and the chain verifier built from it
+#[derive(Debug)]
+struct TrustBundle {
+    /// PEM file the roots are loaded from
+    path: PathBuf,
+    /// Verifier for the currently loaded roots, replaced on reload
+    verifier: RwLock<Arc<dyn ClientCertVerifier>>,
+}
+
+impl TrustBundle {
+    /// Load the bundle at `path`
+    fn load(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            verifier: RwLock::new(load_bundle_verifier(path)?),
+        })
+    }
+
+    /// Verifier for the current roots
+    fn verifier(&self) -> Arc<dyn ClientCertVerifier> {
+        self.verifier.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
 /// SPIFFE ID verifier for X.509 certificates
+///
+/// Clones share the loaded trust bundles, so a reload is seen by every
+/// TLS configuration holding the verifier.
 #[derive(Debug, Clone)]
 pub struct SpiffeVerifier {
-    /// Trusted domain for SPIFFE IDs
-    trusted_domain: String,
+    /// Trusted domains for SPIFFE IDs, with an optional root bundle that
+    /// client chains must verify against
+    trusted_domains: HashMap<String, Option<Arc<TrustBundle>>>,
+    /// Bounds applied to client chains before they are parsed
+    chain_limits: ChainLimits,
+    /// CRL-based revocation checking, when enabled
+    revocation: Option<Arc<RevocationChecker>>,
+    /// Whether clients must present a certificate
+    mtls_mode: MtlsMode,
 }
 
 impl SpiffeVerifier {
     /// Create a new SPIFFE verifier with the given trusted domain
     pub fn new(trusted_domain: String) -> Self {
-        Self { trusted_domain }
+        Self {
+            trusted_domains: HashMap::from([(trusted_domain, None)]),
+            chain_limits: ChainLimits::default(),
+            revocation: None,
+            mtls_mode: MtlsMode::Required,
+        }
+    }
+
+    /// Set the bounds applied to client certificate chains
+    pub fn with_chain_limits(mut self, chain_limits: ChainLimits) -> Self {
+        self.chain_limits = chain_limits;
+        self
+    }
+
+    /// Check client certificates against the given CRLs
+    pub fn with_revocation_checker(mut self, revocation: Arc<RevocationChecker>) -> Self {
+        self.revocation = Some(revocation);
+        self
+    }
+
+    /// Set whether clients must present a certificate
+    pub fn with_mtls_mode(mut self, mtls_mode: MtlsMode) -> Self {
+        self.mtls_mode = mtls_mode;
+        self
+    }
+
+    /// Whether clients must present a certificate
+    pub fn mtls_mode(&self) -> MtlsMode {
+        self.mtls_mode
+    }
+
+    /// Revocation checker, when revocation checking is enabled
+    pub fn revocation_checker(&self) -> Option<&Arc<RevocationChecker>> {
+        self.revocation.as_ref()
+    }
+
+    /// Create a verifier for all configured trust domains, loading their root bundles
+    pub fn from_config(config: &IdentityConfig) -> Result<Self> {
+        let mut trusted_domains = HashMap::new();
+
+        for trust_domain in config.trust_domains() {
+            let bundle = match &trust_domain.bundle_path {
+                Some(path) => Some(Arc::new(TrustBundle::load(path).context(format!(
+                    "Failed to load trust bundle for {}",
+                    trust_domain.domain
+                ))?)),
+                None => None,
+            };
+            trusted_domains.insert(trust_domain.domain, bundle);
+        }
+
+        Ok(Self {
+            trusted_domains,
+            chain_limits: ChainLimits {
+                max_bytes: config.max_cert_chain_bytes,
+                max_depth: config.max_chain_depth,
+            },
+            revocation: RevocationChecker::from_config(&config.revocation)?.map(Arc::new),
+            mtls_mode: config.mtls_mode,
+        })
+    }
+
+    /// Check whether SPIFFE IDs from the given trust domain are accepted
+    pub fn is_trusted_domain(&self, trust_domain: &str) -> bool {
+        self.trusted_domains.contains_key(trust_domain)
     }
 
     /// Extract and verify SPIFFE ID from X.509 certificate
@@ -54,11 +182,10 @@ impl SpiffeVerifier {
                         .map_err(|e| PqSecureError::SpiffeIdError(e.to_string()))?;
 
                     // Validate trust domain
-                    if spiffe_id.trust_domain().to_string() != self.trusted_domain {
+                    if !self.is_trusted_domain(spiffe_id.trust_domain().as_ref()) {
                         return Err(PqSecureError::AuthenticationError(format!(
-                            "SPIFFE ID trust domain '{}' does not match trusted domain '{}'",
-                            spiffe_id.trust_domain(),
-                            self.trusted_domain
+                            "SPIFFE ID trust domain '{}' is not a trusted domain",
+                            spiffe_id.trust_domain()
                         ))
                             .into());
                     }
@@ -79,6 +206,131 @@ impl SpiffeVerifier {
             .into())
     }
 
+    /// Reject chains that are too deep or too large, without parsing them
+    ///
+    /// Runs before any other verification step so an oversized chain cannot
+    /// make the verifier spend memory or time on its contents.
+    pub fn check_chain_size(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> Result<(), rustls::Error> {
+        let depth = intermediates.len() + 1;
+        if depth > self.chain_limits.max_depth {
+            return Err(rustls::Error::General(format!(
+                "Certificate chain has {} certificates, limit is {}",
+                depth, self.chain_limits.max_depth
+            )));
+        }
+
+        let bytes = end_entity.len() + intermediates.iter().map(|cert| cert.len()).sum::<usize>();
+        if bytes > self.chain_limits.max_bytes {
+            return Err(rustls::Error::General(format!(
+                "Certificate chain is {} bytes, limit is {}",
+                bytes, self.chain_limits.max_bytes
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Verify that a client certificate chains to its trust domain's root bundle
+    ///
+    /// Domains configured without a bundle accept any chain.
+    pub fn verify_chain(
+        &self,
+        identity: &ServiceIdentity,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<(), rustls::Error> {
+        match self.trusted_domains.get(&identity.trust_domain) {
+            Some(Some(bundle)) => bundle
+                .verifier()
+                .verify_client_cert(end_entity, intermediates, now)
+                .map(|_| ()),
+            Some(None) => Ok(()),
+            None => Err(rustls::Error::General(format!(
+                "Untrusted trust domain '{}'",
+                identity.trust_domain
+            ))),
+        }
+    }
+
+    /// Reload every trust domain's root bundle from disk
+    ///
+    /// All bundles are loaded before any is replaced, so if one of them is
+    /// missing or invalid the error is returned and the current roots are kept
+    /// for every domain. Returns the number of bundles reloaded.
+    pub fn reload_trust_bundles(&self) -> Result<usize> {
+        let mut reloaded = Vec::new();
+        for (domain, bundle) in &self.trusted_domains {
+            if let Some(bundle) = bundle {
+                let verifier = load_bundle_verifier(&bundle.path)
+                    .context(format!("Failed to reload trust bundle for {}", domain))?;
+                reloaded.push((bundle, verifier));
+            }
+        }
+
+        let count = reloaded.len();
+        for (bundle, verifier) in reloaded {
+            *bundle.verifier.write().unwrap_or_else(|e| e.into_inner()) = verifier;
+        }
+
+        info!("Reloaded {} trust bundle(s)", count);
+        Ok(count)
+    }
+
+    /// Spawn a task that reloads the trust bundles whenever one of the files changes
+    ///
+    /// The files' modification times are polled every `interval`. A bundle
+    /// that fails to load is logged and retried after its next change. The
+    /// task stops when `shutdown` is cancelled.
+    pub fn spawn_bundle_watcher(self: Arc<Self>, interval: Duration, shutdown: CancellationToken) -> JoinHandle<()> {
+        let mut last_seen = self.bundle_mtimes();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        debug!("Trust bundle watcher stopped");
+                        return;
+                    }
+                    _ = ticker.tick() => {}
+                }
+
+                let current = self.bundle_mtimes();
+                if current == last_seen {
+                    continue;
+                }
+                last_seen = current;
+
+                if let Err(e) = self.reload_trust_bundles() {
+                    warn!("Keeping current trust bundles: {:#}", e);
+                }
+            }
+        })
+    }
+
+    /// Whether any trust domain has a root bundle to watch
+    pub fn has_trust_bundles(&self) -> bool {
+        self.trusted_domains.values().any(Option::is_some)
+    }
+
+    /// Modification times of the bundle files, `None` where unreadable
+    fn bundle_mtimes(&self) -> HashMap<PathBuf, Option<SystemTime>> {
+        self.trusted_domains
+            .values()
+            .flatten()
+            .map(|bundle| {
+                let modified = std::fs::metadata(&bundle.path).and_then(|m| m.modified()).ok();
+                (bundle.path.clone(), modified)
+            })
+            .collect()
+    }
+
     /// Verify client certificate (for rustls integration)
     pub fn verify_client_cert(
         &self,
@@ -94,6 +346,32 @@ impl SpiffeVerifier {
     }
 }
 
+/// Build a chain verifier that accepts certificates issued by the roots in a PEM bundle
+fn load_bundle_verifier(path: &Path) -> Result<Arc<dyn ClientCertVerifier>> {
+    let pem = std::fs::read(path).context(format!("Failed to read {}", path.display()))?;
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+        roots
+            .add(cert.context("Failed to parse certificate in trust bundle")?)
+            .context("Invalid root certificate in trust bundle")?;
+    }
+
+    if roots.is_empty() {
+        return Err(PqSecureError::CertificateError(format!(
+            "Trust bundle {} contains no certificates",
+            path.display()
+        ))
+        .into());
+    }
+
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), crypto_provider())
+        .build()
+        .context("Failed to build trust bundle verifier")?;
+
+    Ok(verifier)
+}
+
 #[async_trait::async_trait]
 impl IdentityExtractor for SpiffeVerifier {
     async fn extract_identity(&self, cert: &CertificateDer<'_>) -> Result<ServiceIdentity> {
@@ -104,31 +382,36 @@ impl IdentityExtractor for SpiffeVerifier {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rcgen::{CertificateParams, DnType, SanType, KeyPair};
-
-    fn generate_test_cert(spiffe_id: &str) -> CertificateDer<'static> {
-        let mut params = CertificateParams::default();
-        params.distinguished_name.push(DnType::CommonName, "Test");
-
-        // Add SPIFFE ID as URI directly
-        params
-            .subject_alt_names
-            .push(SanType::URI(rcgen::Ia5String::try_from(spiffe_id).unwrap()));
+    use crate::config::{ExpiryWarningConfig, IdentityProviderType, MtlsMode, RevocationConfig, TrustDomainConfig};
+    use crate::test_support::{issue_svid, self_signed_svid_der, test_ca, test_ca_named};
+    use std::path::PathBuf;
 
-        // Generate key pair
-        let key_pair = KeyPair::generate().unwrap();
-
-        // Create the certificate with the key pair
-        let cert = params.self_signed(&key_pair).unwrap();
-        // Clone the DER data to create a new CertificateDer
-        let der_bytes = cert.der().as_ref().to_vec();
-        CertificateDer::from(der_bytes)
+    /// Identity config trusting `trusted_domain` plus partner.org, whose
+    /// roots are read from `bundle_path` when given
+    fn partner_config(trusted_domain: &str, bundle_path: Option<PathBuf>) -> IdentityConfig {
+        IdentityConfig {
+            trusted_domain: trusted_domain.to_string(),
+            trusted_domains: vec![TrustDomainConfig {
+                domain: "partner.org".to_string(),
+                bundle_path,
+            }],
+            max_cert_chain_bytes: 64 * 1024,
+            max_chain_depth: 8,
+            bundle_refresh_seconds: 0,
+            revocation: RevocationConfig::default(),
+            mtls_mode: MtlsMode::Required,
+            provider_type: IdentityProviderType::Smallstep,
+            mounted_secret: None,
+            expiry_warning: ExpiryWarningConfig::default(),
+            workload_api: None,
+            jwt_svid: None,
+        }
     }
 
     #[test]
     fn test_valid_spiffe_id() {
         let verifier = SpiffeVerifier::new("example.org".to_string());
-        let cert = generate_test_cert("spiffe://example.org/service/test");
+        let cert = self_signed_svid_der("spiffe://example.org/service/test");
 
         let result = verifier.extract_spiffe_id(&cert);
         assert!(result.is_ok());
@@ -142,16 +425,129 @@ mod tests {
     #[test]
     fn test_invalid_trust_domain() {
         let verifier = SpiffeVerifier::new("example.org".to_string());
-        let cert = generate_test_cert("spiffe://wrong-domain.org/service/test");
+        let cert = self_signed_svid_der("spiffe://wrong-domain.org/service/test");
 
         let result = verifier.extract_spiffe_id(&cert);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_multiple_trust_domains() {
+        let config = partner_config("example.org", None);
+        let verifier = SpiffeVerifier::from_config(&config).unwrap();
+
+        let identity = verifier
+            .extract_spiffe_id(&self_signed_svid_der("spiffe://partner.org/service/billing"))
+            .unwrap();
+        assert_eq!(identity.trust_domain, "partner.org");
+
+        let identity = verifier
+            .extract_spiffe_id(&self_signed_svid_der("spiffe://example.org/service/test"))
+            .unwrap();
+        assert_eq!(identity.trust_domain, "example.org");
+
+        assert!(verifier
+            .extract_spiffe_id(&self_signed_svid_der("spiffe://other.org/service/test"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_trust_bundle_chain_verification() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Root CA for the federated domain, written as its trust bundle
+        let ca = test_ca_named("Partner Root");
+        let bundle_path = dir.path().join("partner.pem");
+        std::fs::write(&bundle_path, ca.0.pem()).unwrap();
+
+        let config = partner_config("", Some(bundle_path));
+        let verifier = SpiffeVerifier::from_config(&config).unwrap();
+
+        // Leaf issued by the partner root
+        let leaf_der = issue_svid("spiffe://partner.org/service/billing", &ca).0.der().clone();
+
+        let now = UnixTime::now();
+        let identity = verifier.extract_spiffe_id(&leaf_der).unwrap();
+        assert!(verifier.verify_chain(&identity, &leaf_der, &[], now).is_ok());
+
+        // Self-signed leaf claiming the same domain does not chain to the bundle
+        let forged = self_signed_svid_der("spiffe://partner.org/service/billing");
+        let identity = verifier.extract_spiffe_id(&forged).unwrap();
+        assert!(verifier.verify_chain(&identity, &forged, &[], now).is_err());
+    }
+
+    /// A self-signed CA and a leaf for `spiffe_id` issued by it
+    fn generate_ca_and_leaf(spiffe_id: &str) -> (String, CertificateDer<'static>) {
+        let ca = test_ca();
+        let leaf = issue_svid(spiffe_id, &ca).0;
+        (ca.0.pem(), leaf.der().clone())
+    }
+
+    #[test]
+    fn test_trust_bundle_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_path = dir.path().join("partner.pem");
+        let (old_root, old_leaf) = generate_ca_and_leaf("spiffe://partner.org/service/billing");
+        let (new_root, new_leaf) = generate_ca_and_leaf("spiffe://partner.org/service/billing");
+        std::fs::write(&bundle_path, &old_root).unwrap();
+
+        let config = partner_config("", Some(bundle_path.clone()));
+        let verifier = SpiffeVerifier::from_config(&config).unwrap();
+        // TLS configurations hold their own clone of the verifier
+        let in_use = verifier.clone();
+        let identity = verifier.extract_spiffe_id(&old_leaf).unwrap();
+        let now = UnixTime::now();
+        assert!(in_use.verify_chain(&identity, &old_leaf, &[], now).is_ok());
+        assert!(in_use.verify_chain(&identity, &new_leaf, &[], now).is_err());
+
+        // An invalid bundle is rejected and the old roots are kept
+        std::fs::write(&bundle_path, "not a certificate").unwrap();
+        assert!(verifier.reload_trust_bundles().is_err());
+        assert!(in_use.verify_chain(&identity, &old_leaf, &[], now).is_ok());
+
+        // A new root set replaces the old one
+        std::fs::write(&bundle_path, &new_root).unwrap();
+        assert_eq!(verifier.reload_trust_bundles().unwrap(), 1);
+        assert!(in_use.verify_chain(&identity, &new_leaf, &[], now).is_ok());
+        assert!(in_use.verify_chain(&identity, &old_leaf, &[], now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_watcher_picks_up_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_path = dir.path().join("partner.pem");
+        let (old_root, _) = generate_ca_and_leaf("spiffe://partner.org/service/billing");
+        let (new_root, new_leaf) = generate_ca_and_leaf("spiffe://partner.org/service/billing");
+        std::fs::write(&bundle_path, &old_root).unwrap();
+
+        let verifier = Arc::new(
+            SpiffeVerifier::from_config(&partner_config("", Some(bundle_path.clone())))
+            .unwrap(),
+        );
+        let shutdown = CancellationToken::new();
+        let watcher = verifier.clone().spawn_bundle_watcher(Duration::from_millis(20), shutdown.clone());
+
+        // Make sure the modification time moves even on coarse-grained filesystems
+        std::fs::write(&bundle_path, &new_root).unwrap();
+        let file = std::fs::File::options().write(true).open(&bundle_path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+
+        let identity = verifier.extract_spiffe_id(&new_leaf).unwrap();
+        let accepted = async {
+            while verifier.verify_chain(&identity, &new_leaf, &[], UnixTime::now()).is_err() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), accepted).await.unwrap();
+
+        shutdown.cancel();
+        watcher.await.unwrap();
+    }
+
     #[test]
     fn test_invalid_spiffe_id_format() {
         let verifier = SpiffeVerifier::new("example.org".to_string());
-        let cert = generate_test_cert("invalid-spiffe-id");
+        let cert = self_signed_svid_der("invalid-spiffe-id");
 
         let result = verifier.extract_spiffe_id(&cert);
         assert!(result.is_err());
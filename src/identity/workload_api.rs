@@ -0,0 +1,440 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use spiffe::X509Svid;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+#[cfg(unix)]
+use tokio_stream::wrappers::UnixListenerStream;
+use tokio_stream::wrappers::WatchStream;
+use futures::StreamExt;
+use tokio_util::sync::CancellationToken;
+use tonic::codegen::{http, Body, BoxFuture, BoxStream, Context as TaskContext, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, ServerStreamingService};
+use tonic::{Request, Response, Status};
+use tracing::{debug, error, info};
+
+use crate::common::{load_cert_chain, PqSecureError};
+#[cfg(unix)]
+use crate::common::bind_unix_socket;
+use crate::config::TrustDomainConfig;
+
+/// Metadata every Workload API request must carry, per the SPIFFE specification
+const SECURITY_HEADER: &str = "workload.spiffe.io";
+
+/// Messages of the SPIFFE Workload API (`workload.proto`) served here
+pub mod proto {
+    use std::collections::HashMap;
+
+    /// Request for the workload's X.509-SVIDs; it has no parameters
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct X509SvidRequest {}
+
+    /// X.509-SVIDs the workload is entitled to
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct X509SvidResponse {
+        /// SVIDs with their keys and trust bundles
+        #[prost(message, repeated, tag = "1")]
+        pub svids: Vec<X509Svid>,
+
+        /// DER certificate revocation lists
+        #[prost(bytes = "vec", repeated, tag = "2")]
+        pub crl: Vec<Vec<u8>>,
+
+        /// DER bundles of federated trust domains, keyed by trust domain SPIFFE ID
+        #[prost(map = "string, bytes", tag = "3")]
+        pub federated_bundles: HashMap<String, Vec<u8>>,
+    }
+
+    /// One X.509-SVID
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct X509Svid {
+        /// SPIFFE ID of the SVID
+        #[prost(string, tag = "1")]
+        pub spiffe_id: String,
+
+        /// DER certificate chain, leaf first
+        #[prost(bytes = "vec", tag = "2")]
+        pub x509_svid: Vec<u8>,
+
+        /// Unencrypted PKCS#8 DER private key
+        #[prost(bytes = "vec", tag = "3")]
+        pub x509_svid_key: Vec<u8>,
+
+        /// DER X.509 bundle of the SVID's trust domain
+        #[prost(bytes = "vec", tag = "4")]
+        pub bundle: Vec<u8>,
+    }
+}
+
+/// The proxy's identity in the form the Workload API hands out
+#[derive(Debug, Clone)]
+pub struct WorkloadSvid {
+    /// SPIFFE ID from the leaf certificate
+    spiffe_id: String,
+
+    /// Trust domain of the SPIFFE ID
+    trust_domain: String,
+
+    /// Concatenated DER certificate chain, leaf first
+    cert_chain: Vec<u8>,
+
+    /// PKCS#8 DER private key
+    private_key: Vec<u8>,
+
+    /// Concatenated DER root certificates of the trust domain
+    bundle: Vec<u8>,
+}
+
+impl WorkloadSvid {
+    /// Build an SVID from a certificate chain and its key
+    ///
+    /// Keys in PKCS#1 or SEC1 form are converted to the PKCS#8 the API
+    /// requires. The trust bundle starts out empty; see `with_bundle`.
+    pub fn new(cert_chain: &[CertificateDer<'_>], private_key: &PrivateKeyDer<'_>) -> Result<Self> {
+        let private_key = match private_key {
+            PrivateKeyDer::Pkcs8(key) => key.secret_pkcs8_der().to_vec(),
+            other => openssl::pkey::PKey::private_key_from_der(other.secret_der())
+                .and_then(|key| key.private_key_to_pkcs8())
+                .context("Failed to convert private key to PKCS#8")?,
+        };
+        let der_chain: Vec<u8> = cert_chain.iter().flat_map(|cert| cert.as_ref().to_vec()).collect();
+
+        // Only hand out what SPIFFE libraries will accept
+        let svid = X509Svid::parse_from_der(&der_chain, &private_key)
+            .map_err(|e| PqSecureError::SpiffeIdError(format!("Identity is not a valid X.509-SVID: {}", e)))?;
+
+        Ok(Self {
+            spiffe_id: svid.spiffe_id().to_string(),
+            trust_domain: svid.spiffe_id().trust_domain().to_string(),
+            cert_chain: der_chain,
+            private_key,
+            bundle: Vec::new(),
+        })
+    }
+
+    /// Serve `roots` as the trust bundle of the SVID's trust domain
+    pub fn with_bundle(mut self, roots: &[CertificateDer<'_>]) -> Self {
+        self.bundle = roots.iter().flat_map(|cert| cert.as_ref().to_vec()).collect();
+        self
+    }
+
+    /// SPIFFE ID of the SVID
+    pub fn spiffe_id(&self) -> &str {
+        &self.spiffe_id
+    }
+
+    /// Trust domain of the SVID
+    pub fn trust_domain(&self) -> &str {
+        &self.trust_domain
+    }
+
+    fn to_proto(&self) -> proto::X509SvidResponse {
+        proto::X509SvidResponse {
+            svids: vec![proto::X509Svid {
+                spiffe_id: self.spiffe_id.clone(),
+                x509_svid: self.cert_chain.clone(),
+                x509_svid_key: self.private_key.clone(),
+                bundle: self.bundle.clone(),
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+/// Hands renewed identities to the Workload API server
+#[derive(Debug, Clone)]
+pub struct SvidPublisher {
+    sender: Arc<watch::Sender<Option<Arc<WorkloadSvid>>>>,
+
+    /// Root bundle file of each trust domain, served alongside its SVIDs
+    trust_bundles: Arc<HashMap<String, PathBuf>>,
+}
+
+impl SvidPublisher {
+    /// Serve the configured root bundles as the SVIDs' trust bundles
+    ///
+    /// Domains without a `bundle_path` have no bundle to serve, so their
+    /// identities cannot be published.
+    pub fn with_trust_bundles(mut self, trust_domains: &[TrustDomainConfig]) -> Self {
+        let bundles = trust_domains
+            .iter()
+            .filter_map(|domain| Some((domain.domain.clone(), domain.bundle_path.clone()?)))
+            .collect();
+        self.trust_bundles = Arc::new(bundles);
+        self
+    }
+
+    /// Serve a new identity to current and future `FetchX509SVID` streams
+    ///
+    /// The trust domain's bundle is read again on every call, so a renewal
+    /// also picks up rotated roots.
+    pub fn publish(&self, cert_chain: &[CertificateDer<'_>], private_key: &PrivateKeyDer<'_>) -> Result<()> {
+        let svid = WorkloadSvid::new(cert_chain, private_key)?;
+        let bundle_path = self.trust_bundles.get(svid.trust_domain()).ok_or_else(|| {
+            PqSecureError::ConfigError(format!(
+                "No trust bundle configured for {}; set identity.trusted_domains[].bundle_path",
+                svid.trust_domain()
+            ))
+        })?;
+        let roots = load_cert_chain(bundle_path).context("Failed to load the Workload API trust bundle")?;
+        let svid = svid.with_bundle(&roots);
+        info!("Workload API now serving {}", svid.spiffe_id());
+        self.sender.send_replace(Some(Arc::new(svid)));
+        Ok(())
+    }
+}
+
+/// Minimal SPIFFE Workload API serving the proxy's own identity
+///
+/// Local workloads using a SPIFFE SDK can point `SPIFFE_ENDPOINT_SOCKET` at
+/// the server's Unix socket and fetch the X.509-SVID the proxy was issued.
+/// Only `FetchX509SVID` is implemented: each stream sends the current SVID
+/// and then every identity published afterwards. The other RPCs answer
+/// `UNIMPLEMENTED`. Only the proxy's user can connect to the socket; callers
+/// are not attested beyond that.
+#[derive(Debug, Clone)]
+pub struct WorkloadApiServer {
+    /// Identity updates from the publisher
+    updates: watch::Receiver<Option<Arc<WorkloadSvid>>>,
+
+    /// Ends open streams so shutdown does not wait on connected workloads
+    shutdown: CancellationToken,
+}
+
+impl WorkloadApiServer {
+    /// Create a server with no identity yet, and the publisher feeding it
+    pub fn new() -> (Self, SvidPublisher) {
+        let (sender, updates) = watch::channel(None);
+        (
+            Self {
+                updates,
+                shutdown: CancellationToken::new(),
+            },
+            SvidPublisher {
+                sender: Arc::new(sender),
+                trust_bundles: Arc::new(HashMap::new()),
+            },
+        )
+    }
+
+    /// Serve the API on a Unix socket until `shutdown` is cancelled
+    ///
+    /// The API hands out the proxy's private key, so the socket is only
+    /// accessible to the proxy's own user. A stale socket from an earlier run
+    /// is replaced, but any other file at the path is left alone.
+    #[cfg(unix)]
+    pub fn spawn(mut self, socket_path: &Path, shutdown: CancellationToken) -> Result<JoinHandle<()>> {
+        self.shutdown = shutdown.clone();
+        let listener = bind_unix_socket(socket_path, 0o600)
+            .context(format!("Failed to bind Workload API socket: {}", socket_path.display()))?;
+        info!("Workload API listening on {}", socket_path.display());
+
+        let socket_path = socket_path.to_path_buf();
+        Ok(tokio::spawn(async move {
+            let result = tonic::transport::Server::builder()
+                .add_service(self)
+                .serve_with_incoming_shutdown(UnixListenerStream::new(listener), shutdown.cancelled())
+                .await;
+            if let Err(e) = result {
+                error!("Workload API server failed: {}", e);
+            }
+            let _ = std::fs::remove_file(&socket_path);
+            debug!("Workload API stopped");
+        }))
+    }
+
+    /// Unix sockets are not available on this platform
+    #[cfg(not(unix))]
+    pub fn spawn(self, _socket_path: &Path, _shutdown: CancellationToken) -> Result<JoinHandle<()>> {
+        Err(anyhow::anyhow!("The Workload API requires Unix domain sockets"))
+    }
+}
+
+impl ServerStreamingService<proto::X509SvidRequest> for WorkloadApiServer {
+    type Response = proto::X509SvidResponse;
+    type ResponseStream = BoxStream<proto::X509SvidResponse>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<proto::X509SvidRequest>) -> Self::Future {
+        let updates = self.updates.clone();
+        let shutdown = self.shutdown.clone();
+        Box::pin(async move {
+            if request.metadata().get(SECURITY_HEADER).and_then(|v| v.to_str().ok()) != Some("true") {
+                return Err(Status::invalid_argument("security header missing from request"));
+            }
+
+            // Until an identity is published the stream stays open and empty
+            let stream = WatchStream::new(updates)
+                .filter_map(|svid| std::future::ready(svid.map(|svid| svid.to_proto())))
+                .map(Ok)
+                .take_until(shutdown.cancelled_owned());
+            Ok(Response::new(Box::pin(stream) as Self::ResponseStream))
+        })
+    }
+}
+
+impl<B> Service<http::Request<B>> for WorkloadApiServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != "/SpiffeWorkloadAPI/FetchX509SVID" {
+            return Box::pin(async move { Ok(Status::unimplemented("not supported by this Workload API").into_http()) });
+        }
+
+        let service = self.clone();
+        Box::pin(async move {
+            let mut grpc = Grpc::new(tonic::codec::ProstCodec::default());
+            Ok(grpc.server_streaming(service, request).await)
+        })
+    }
+}
+
+impl NamedService for WorkloadApiServer {
+    const NAME: &'static str = "SpiffeWorkloadAPI";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{issue_identity, private_key_der, self_signed_cert, test_ca};
+    use rcgen::Certificate;
+    use spiffe::{TrustDomain, WorkloadApiClient};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    /// Server and publisher serving `ca` as the example.org bundle from a file in `dir`
+    fn server_with_bundle(dir: &Path, ca: &Certificate) -> (WorkloadApiServer, SvidPublisher) {
+        let bundle_path = dir.join("bundle.pem");
+        std::fs::write(&bundle_path, ca.pem()).unwrap();
+        let (server, publisher) = WorkloadApiServer::new();
+        let publisher = publisher.with_trust_bundles(&[TrustDomainConfig {
+            domain: "example.org".to_string(),
+            bundle_path: Some(bundle_path),
+        }]);
+        (server, publisher)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_fetch_and_rotate_x509_svid() {
+        let dir = tempdir().unwrap();
+        let socket_path = dir.path().join("workload.sock");
+        let ca = test_ca();
+        let (server, publisher) = server_with_bundle(dir.path(), &ca.0);
+        let (first_chain, first_key) = issue_identity("spiffe://example.org/service/web", &ca);
+        publisher.publish(&first_chain, &first_key).unwrap();
+
+        let shutdown = CancellationToken::new();
+        let task = server.spawn(&socket_path, shutdown.clone()).unwrap();
+
+        let endpoint = format!("unix:{}", socket_path.display());
+        let mut client = WorkloadApiClient::new_from_path(&endpoint).await.unwrap();
+        let svid = client.fetch_x509_svid().await.unwrap();
+        assert_eq!(svid.spiffe_id().to_string(), "spiffe://example.org/service/web");
+        assert_eq!(svid.leaf().content(), first_chain[0].as_ref());
+
+        // A stream receives the current SVID, then the rotated one
+        let mut stream = client.stream_x509_svids().await.unwrap();
+        let current = stream.next().await.unwrap().unwrap();
+        assert_eq!(current.leaf().content(), first_chain[0].as_ref());
+
+        let (rotated_chain, rotated_key) = issue_identity("spiffe://example.org/service/web", &ca);
+        publisher.publish(&rotated_chain, &rotated_key).unwrap();
+        let rotated = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(rotated.leaf().content(), rotated_chain[0].as_ref());
+
+        // Shutdown does not wait for the open stream
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bundle_holds_trust_domain_root() {
+        let dir = tempdir().unwrap();
+        let socket_path = dir.path().join("workload.sock");
+        let ca = test_ca();
+        let (server, publisher) = server_with_bundle(dir.path(), &ca.0);
+        let (chain, key) = issue_identity("spiffe://example.org/service/web", &ca);
+        publisher.publish(&chain, &key).unwrap();
+
+        let shutdown = CancellationToken::new();
+        let _task = server.spawn(&socket_path, shutdown.clone()).unwrap();
+
+        // The chain holds only the leaf, and the bundle still holds the CA
+        let endpoint = format!("unix:{}", socket_path.display());
+        let context = WorkloadApiClient::new_from_path(&endpoint)
+            .await
+            .unwrap()
+            .fetch_x509_context()
+            .await
+            .unwrap();
+        let bundle = context
+            .bundle_set()
+            .get_bundle(&TrustDomain::new("example.org").unwrap())
+            .unwrap();
+        let authorities: Vec<&[u8]> = bundle.authorities().iter().map(|cert| cert.content()).collect();
+        assert_eq!(authorities, vec![ca.0.der().as_ref()]);
+        shutdown.cancel();
+    }
+
+    #[test]
+    fn test_publish_requires_trust_bundle() {
+        let ca = test_ca();
+        let (chain, key) = issue_identity("spiffe://example.org/service/web", &ca);
+        let (_server, publisher) = WorkloadApiServer::new();
+
+        let err = publisher.publish(&chain, &key).unwrap_err();
+        assert!(err.to_string().contains("No trust bundle configured for example.org"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_is_private_and_files_are_kept() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let shutdown = CancellationToken::new();
+
+        // Only the proxy's user may connect
+        let socket_path = dir.path().join("workload.sock");
+        let (server, _publisher) = WorkloadApiServer::new();
+        let _task = server.spawn(&socket_path, shutdown.clone()).unwrap();
+        let mode = std::fs::metadata(&socket_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // A regular file at the socket path is never deleted
+        let file_path = dir.path().join("not-a-socket");
+        std::fs::write(&file_path, "keep me").unwrap();
+        let (server, _publisher) = WorkloadApiServer::new();
+        assert!(server.spawn(&file_path, shutdown.clone()).is_err());
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "keep me");
+        shutdown.cancel();
+    }
+
+    #[test]
+    fn test_rejects_identity_without_spiffe_id() {
+        let (cert, key_pair) = self_signed_cert();
+        let key = private_key_der(&key_pair);
+
+        assert!(WorkloadSvid::new(&[cert.der().clone()], &key).is_err());
+    }
+}
@@ -5,4 +5,6 @@ pub mod crypto;
 pub mod identity;
 pub mod policy;
 pub mod proxy;
-pub mod telemetry;
\ No newline at end of file
+pub mod telemetry;
+#[cfg(test)]
+mod test_support;
@@ -1,80 +1,376 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use pqsecure_mesh::{
-    ca::SmallstepClient,
-    config::load_config,
-    crypto::build_tls_config,
-    identity::SpiffeVerifier,
-    policy::YamlPolicyEngine,
+    ca::{IssuanceLog, MountedSecretProvider, SmallstepClient},
+    common::{create_private_dir_all, load_cert_chain, load_private_key, write_file_bytes, ProtocolType},
+    config::{load_config_from_path, IdentityProviderType, IdentityStorageType, DEFAULT_CONFIG_PATH},
+    crypto::{
+        build_tls_config_with_provider, build_tls_config_with_resolver, crypto_provider_with_groups,
+        run_self_test, to_pem_bundle, to_pkcs12, SniCertResolver,
+    },
+    identity::{JwtSvidVerifier, SpiffeVerifier, WorkloadApiServer},
+    policy::{PolicyTestHarness, YamlPolicyEngine},
     proxy::{
         handler::DefaultConnectionHandler,
         pqc_acceptor::PqcAcceptor,
-        protocol::{grpc::GrpcHandler, http_tls::HttpHandler, raw_tcp::TcpHandler},
+        protocol::{
+            grpc::GrpcHandler, http_tls::HttpHandler, raw_tcp::TcpHandler, response_headers::ResponseHeaderRules,
+        },
     },
-    telemetry,
+    telemetry::{self, ExpiryMonitor, ResourceSampler},
 };
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
+/// Post-Quantum Secure Zero-Trust Network Proxy for Microservices
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Path to the configuration file
+    #[arg(long, env = "PQSECURE_CONFIG", default_value = DEFAULT_CONFIG_PATH)]
+    config: PathBuf,
+
+    /// Load and validate the configuration, then exit without starting the proxy
+    #[arg(long, conflicts_with = "print_config")]
+    validate_config: bool,
+
+    /// Print the effective configuration with secrets redacted, then exit
+    #[arg(long)]
+    print_config: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Policy maintenance tools
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommand,
+    },
+
+    /// Identity and certificate tools
+    Identity {
+        #[command(subcommand)]
+        command: IdentityCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum IdentityCommand {
+    /// Print the most recent certificate issuance records
+    Log {
+        /// Number of records to print
+        #[arg(long, default_value_t = 10)]
+        tail: usize,
+
+        /// Issuance log to read (defaults to ca.issuance_log.path from the configuration)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
+    /// Check CA reachability, the token and CSR generation without issuing a certificate
+    DryRun,
+
+    /// Write the current certificate chain and key to a single bundle
+    Export {
+        /// Bundle format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Pem)]
+        format: ExportFormat,
+
+        /// File to write the bundle to
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Password protecting a PKCS#12 bundle
+        #[arg(long, env = "PQSECURE_EXPORT_PASSWORD", default_value = "", hide_env_values = true)]
+        password: String,
+    },
+}
+
+/// Output format of `identity export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExportFormat {
+    /// Certificate chain followed by the PKCS#8 key, PEM encoded
+    Pem,
+    /// PKCS#12 (`.p12`/`.pfx`) for Java keystores and Windows
+    Pkcs12,
+}
+
+#[derive(Debug, Subcommand)]
+enum PolicyCommand {
+    /// Replay a file of expected decisions against a policy, failing on any mismatch
+    Test {
+        /// Policy file to test
+        #[arg(long)]
+        policy: PathBuf,
+
+        /// YAML list of cases with spiffe_id, method, protocol and expected
+        #[arg(long)]
+        cases: PathBuf,
+
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Run the policy test harness, returning whether every case passed
+fn run_policy_test(policy: &Path, cases: &Path, json: bool) -> Result<bool> {
+    let harness = PolicyTestHarness::from_path(policy)?;
+    let cases = PolicyTestHarness::load_cases(cases)?;
+    let results = harness.run(&cases);
+    let failures = results.iter().filter(|r| !r.passed).count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for result in &results {
+            println!(
+                "{} {} {} {}: expected {}, got {} by {}",
+                if result.passed { "PASS" } else { "FAIL" },
+                result.case.spiffe_id,
+                result.case.protocol.as_deref().unwrap_or("*"),
+                result.case.method,
+                if result.case.expected { "allow" } else { "deny" },
+                if result.decision.allowed { "allow" } else { "deny" },
+                harness.explain(result)
+            );
+        }
+        println!("{} cases, {} passed, {} failed", results.len(), results.len() - failures, failures);
+    }
+
+    Ok(failures == 0)
+}
+
+/// Print the last `tail` issuance records as JSON lines
+fn run_identity_log(config_path: &Path, path: Option<&Path>, tail: usize) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => load_config_from_path(config_path)?
+            .ca
+            .issuance_log
+            .map(|log| log.path)
+            .context("ca.issuance_log is not configured; pass --path")?,
+    };
+
+    for record in IssuanceLog::tail(&path, tail)? {
+        println!("{}", serde_json::to_string(&record)?);
+    }
+
+    Ok(())
+}
+
+/// Print what a certificate request would contain, without issuing one
+async fn run_identity_dry_run(config_path: &Path) -> Result<()> {
+    let config = load_config_from_path(config_path)?;
+    let report = SmallstepClient::new(&config.ca)?.dry_run().await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Bundle the identity named by the configuration into `out`
+fn run_identity_export(config_path: &Path, format: ExportFormat, out: &Path, password: &str) -> Result<()> {
+    let config = load_config_from_path(config_path)?;
+    let (cert_path, key_path) = match (config.identity.provider_type, &config.identity.mounted_secret) {
+        (IdentityProviderType::MountedSecret, Some(mounted)) => (&mounted.cert_path, &mounted.key_path),
+        _ if config.ca.storage == IdentityStorageType::Memory => {
+            anyhow::bail!("ca.storage is memory, so there are no identity files to export");
+        }
+        _ => (&config.ca.cert_path, &config.ca.key_path),
+    };
+
+    let cert_pem = std::fs::read(cert_path).context(format!("Failed to read certificate: {}", cert_path.display()))?;
+    let key = std::fs::read(key_path).context(format!("Failed to read private key: {}", key_path.display()))?;
+    let bundle = match format {
+        ExportFormat::Pem => to_pem_bundle(&cert_pem, &key)?,
+        ExportFormat::Pkcs12 => to_pkcs12(&cert_pem, &key, None, password)?,
+    };
+
+    // The bundle contains the private key, so it gets the same owner-only mode
+    write_file_bytes(out, &bundle)?;
+    println!("Wrote identity bundle to {}", out.display());
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    // Offline commands run before telemetry so their output stays clean
+    if let Some(Command::Policy {
+        command: PolicyCommand::Test { policy, cases, json },
+    }) = &cli.command
+    {
+        if !run_policy_test(policy, cases, *json)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Identity {
+        command: IdentityCommand::Log { tail, path },
+    }) = &cli.command
+    {
+        return run_identity_log(&cli.config, path.as_deref(), *tail);
+    }
+
+    if let Some(Command::Identity {
+        command: IdentityCommand::DryRun,
+    }) = &cli.command
+    {
+        return run_identity_dry_run(&cli.config).await;
+    }
+
+    if let Some(Command::Identity {
+        command: IdentityCommand::Export { format, out, password },
+    }) = &cli.command
+    {
+        return run_identity_export(&cli.config, *format, out, password);
+    }
+
+    if cli.validate_config {
+        load_config_from_path(&cli.config)?;
+        println!("Configuration {} is valid", cli.config.display());
+        return Ok(());
+    }
+
+    if cli.print_config {
+        let config = load_config_from_path(&cli.config)?;
+        print!("{}", serde_yaml::to_string(&config.redacted())?);
+        return Ok(());
+    }
+
     // 1. Initialize telemetry first
     telemetry::init()?;
     info!("Starting PQSecure Mesh...");
 
     // 2. Load configuration
-    let config = load_config()?;
+    let config = load_config_from_path(&cli.config)?;
     info!("Configuration loaded successfully");
 
-    // 3. Create directories for certificates if they don't exist
-    std::fs::create_dir_all(std::path::Path::new(&config.ca.cert_path).parent().unwrap_or(std::path::Path::new("./certs"))).ok();
+    // 3. Read the identity from a mounted Secret instead of the CA if configured
+    let mut mounted_secret = match (config.identity.provider_type, &config.identity.mounted_secret) {
+        (IdentityProviderType::MountedSecret, Some(mounted)) => Some(mounted),
+        _ => None,
+    }
+    .map(|mounted| (MountedSecretProvider::new(mounted), Duration::from_secs(mounted.refresh_seconds)));
 
-    // 4. Initialize Smallstep CA client and fetch certificates
-    let ca_client = SmallstepClient::new(&config.ca)?;
-    let (cert_chain, private_key) = ca_client.load_or_request_cert().await?;
+    // 4. Provision the identity before any listener starts
+    let (cert_chain, private_key) = match &mounted_secret {
+        Some((mounted, _)) => mounted.load()?,
+        None => {
+            // Create directories for certificates if they don't exist
+            if config.ca.storage == IdentityStorageType::File {
+                create_private_dir_all(std::path::Path::new(&config.ca.cert_path).parent().unwrap_or(std::path::Path::new("./certs"))).ok();
+            }
+
+            let ca_client = SmallstepClient::new(&config.ca)?;
+            ca_client
+                .provision_identity(Duration::from_secs(config.ca.startup_wait_seconds))
+                .await?
+        }
+    };
     info!("Certificate loaded successfully");
+    let leaf_cert = cert_chain.first().cloned();
+
+    // Serve the identity to local SPIFFE-aware workloads, following renewals
+    let workload_api = match &config.identity.workload_api {
+        Some(api) => {
+            let (server, publisher) = WorkloadApiServer::new();
+            let publisher = publisher.with_trust_bundles(&config.identity.trust_domains());
+            publisher
+                .publish(&cert_chain, &private_key)
+                .context("Cannot serve the identity over the Workload API")?;
+            mounted_secret = mounted_secret
+                .take()
+                .map(|(mounted, interval)| (mounted.with_svid_publisher(publisher), interval));
+            Some((server, api.socket_path.clone()))
+        }
+        None => None,
+    };
 
     // 5. Initialize policy engine
-    let policy_engine = Arc::new(YamlPolicyEngine::from_path(&config.policy.path)?);
-    info!("Policy engine initialized with rules from {}", config.policy.path.display());
+    let policy_engine = Arc::new(YamlPolicyEngine::from_config(&config.policy)?);
+    info!("Policy engine initialized from {}", config.policy.path.display());
 
     // 6. Setup SPIFFE verifier
-    let spiffe_verifier = Arc::new(SpiffeVerifier::new(config.identity.trusted_domain.clone()));
+    let spiffe_verifier = Arc::new(SpiffeVerifier::from_config(&config.identity)?);
 
     // 7. Setup TLS configuration
-    let tls_config = build_tls_config(cert_chain, private_key, spiffe_verifier.clone())?;
-    info!("TLS configuration built successfully");
-
-    // 8. Setup protocol handlers based on config
-    let mut handlers = Vec::new();
-    if config.proxy.protocols.tcp {
-        let tcp_handler = TcpHandler::new(
-            config.proxy.backend.clone(),
-            policy_engine.clone(),
+    let provider = crypto_provider_with_groups(&config.proxy.key_exchange_groups)?;
+    let mut identity_resolver = None;
+    let tls_config = if config.proxy.sni_identities.is_empty() && mounted_secret.is_none() {
+        build_tls_config_with_provider(
+            cert_chain.clone(),
+            private_key.clone_key(),
             spiffe_verifier.clone(),
-        )?;
-        handlers.push(Arc::new(tcp_handler) as Arc<dyn DefaultConnectionHandler>);
-        info!("TCP protocol handler initialized");
-    }
+            provider.clone(),
+        )?
+    } else {
+        let mut resolver = SniCertResolver::new(cert_chain.clone(), private_key.clone_key(), &provider)?;
+        for identity in &config.proxy.sni_identities {
+            resolver.add(
+                &identity.server_name,
+                load_cert_chain(&identity.cert_path)?,
+                load_private_key(&identity.key_path)?,
+                &provider,
+            )?;
+            info!("Presenting {} for SNI {}", identity.cert_path.display(), identity.server_name);
+        }
+        let resolver = Arc::new(resolver);
+        identity_resolver = Some(resolver.clone());
+        build_tls_config_with_resolver(resolver, spiffe_verifier.clone(), provider.clone())?
+    };
+    info!("TLS configuration built successfully");
 
-    if config.proxy.protocols.http {
-        let http_handler = HttpHandler::new(
-            config.proxy.backend.clone(),
-            policy_engine.clone(),
-            spiffe_verifier.clone(),
-        )?;
-        handlers.push(Arc::new(http_handler) as Arc<dyn DefaultConnectionHandler>);
-        info!("HTTP protocol handler initialized");
+    // Catch certificate, key or verifier mistakes before real clients hit them
+    if config.proxy.startup_self_test {
+        run_self_test(tls_config.clone(), cert_chain, private_key, &spiffe_verifier)
+            .await
+            .context("Startup mTLS self-test failed")?;
     }
 
-    if config.proxy.protocols.grpc {
-        let grpc_handler = GrpcHandler::new(
-            config.proxy.backend.clone(),
-            policy_engine.clone(),
-            spiffe_verifier.clone(),
-        )?;
-        handlers.push(Arc::new(grpc_handler) as Arc<dyn DefaultConnectionHandler>);
-        info!("gRPC protocol handler initialized");
+    // 8. Setup protocol handlers based on config, in detection order
+    let mut jwt_verifier = config
+        .identity
+        .jwt_svid
+        .as_ref()
+        .map(JwtSvidVerifier::from_config)
+        .transpose()
+        .context("Failed to load JWT-SVID bundles")?;
+    let mut handlers: Vec<Arc<dyn DefaultConnectionHandler>> = Vec::new();
+    for protocol in config.proxy.protocols.enabled() {
+        let backend = config.proxy.backend.clone();
+        let handler: Arc<dyn DefaultConnectionHandler> = match protocol {
+            ProtocolType::Grpc => Arc::new(GrpcHandler::new(
+                backend,
+                policy_engine.clone(),
+                spiffe_verifier.clone(),
+            )?),
+            ProtocolType::Http => Arc::new(
+                HttpHandler::new(backend, policy_engine.clone(), spiffe_verifier.clone())?
+                    .with_deny_response(config.proxy.deny_response.clone())
+                    .with_limits(config.proxy.http_limits.clone())
+                    .with_jwt_verifier(jwt_verifier.take())
+                    .with_response_headers(ResponseHeaderRules::new(
+                        &config.proxy.response_headers_add,
+                        &config.proxy.response_headers_remove,
+                    )),
+            ),
+            ProtocolType::Tcp => Arc::new(TcpHandler::new(
+                backend,
+                policy_engine.clone(),
+                spiffe_verifier.clone(),
+            )?),
+        };
+        info!("{} protocol handler initialized", handler.protocol_name());
+        handlers.push(handler);
     }
 
     // 9. Create connection acceptor
@@ -82,22 +378,98 @@ async fn main() -> Result<()> {
         config.proxy.listen_addr.to_string(),
         tls_config,
         handlers,
-    )?;
+    )?
+    .with_keepalive(config.proxy.keepalive.clone())
+    .with_socket_config(config.proxy.socket.clone())
+    .with_require_pqc(config.proxy.require_pqc)
+    .with_plaintext_window(config.proxy.permit_plaintext_during_seconds.map(Duration::from_secs));
+
+    // Background tasks stop when this token is cancelled at shutdown
+    let shutdown = CancellationToken::new();
+
+    // 10. Start the resource sampler if enabled
+    let sampler_task = config
+        .telemetry
+        .resource_sample_interval_seconds
+        .map(|secs| ResourceSampler::new(Duration::from_secs(secs)).spawn(shutdown.clone()));
+
+    // Pick up rotated trust bundles without a restart
+    let bundle_watcher = (spiffe_verifier.has_trust_bundles() && config.identity.bundle_refresh_seconds > 0)
+        .then(|| {
+            spiffe_verifier.clone().spawn_bundle_watcher(
+                Duration::from_secs(config.identity.bundle_refresh_seconds),
+                shutdown.clone(),
+            )
+        });
+
+    // Keep revocation lists current
+    let crl_refresh = spiffe_verifier.revocation_checker().map(|revocation| {
+        revocation.clone().spawn_refresh(
+            Duration::from_secs(config.identity.revocation.refresh_seconds),
+            shutdown.clone(),
+        )
+    });
+
+    // Warn ahead of the proxy's own certificate expiring
+    let expiry_monitor = (!config.identity.expiry_warning.thresholds_percent.is_empty()).then(|| {
+        let monitor = ExpiryMonitor::new(&config.identity.expiry_warning.thresholds_percent);
+        let interval = Duration::from_secs(config.identity.expiry_warning.check_seconds);
+        match identity_resolver.clone() {
+            Some(resolver) => monitor.spawn(
+                interval,
+                move || {
+                    let identity = resolver.default_identity();
+                    identity.end_entity_cert().ok().map(|cert| cert.clone().into_owned())
+                },
+                shutdown.clone(),
+            ),
+            None => monitor.spawn(interval, move || leaf_cert.clone(), shutdown.clone()),
+        }
+    });
+
+    let workload_api_task = workload_api
+        .map(|(server, socket_path)| server.spawn(&socket_path, shutdown.clone()))
+        .transpose()?;
 
-    // 10. Start the proxy
+    // Present certificates renewed in the mounted Secret without a restart
+    let identity_watcher = mounted_secret
+        .zip(identity_resolver)
+        .map(|((mounted, interval), resolver)| mounted.spawn_watcher(interval, resolver, provider, shutdown.clone()));
+
+    // 11. Start the proxy
+    let proxy_shutdown = shutdown.clone();
     let proxy_task = tokio::spawn(async move {
-        if let Err(e) = acceptor.run().await {
+        if let Err(e) = acceptor.run(proxy_shutdown).await {
             error!("Proxy error: {}", e);
         }
     });
 
-    // 11. Wait for shutdown signal
+    // 12. Wait for shutdown signal
     info!("PQSecure Mesh started successfully and listening on {}", config.proxy.listen_addr);
     signal::ctrl_c().await?;
     info!("Shutdown signal received, stopping PQSecure Mesh...");
 
     // Proper cleanup before exit
-    proxy_task.abort();
+    shutdown.cancel();
+    proxy_task.await.ok();
+    if let Some(sampler_task) = sampler_task {
+        sampler_task.await.ok();
+    }
+    if let Some(bundle_watcher) = bundle_watcher {
+        bundle_watcher.await.ok();
+    }
+    if let Some(crl_refresh) = crl_refresh {
+        crl_refresh.await.ok();
+    }
+    if let Some(identity_watcher) = identity_watcher {
+        identity_watcher.await.ok();
+    }
+    if let Some(expiry_monitor) = expiry_monitor {
+        expiry_monitor.await.ok();
+    }
+    if let Some(workload_api_task) = workload_api_task {
+        workload_api_task.await.ok();
+    }
     info!("PQSecure Mesh stopped successfully");
 
     Ok(())
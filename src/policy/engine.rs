@@ -4,14 +4,39 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use std::sync::Mutex;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 // use crate::common::PqSecureError;
+use crate::common::{ProtocolType, ANONYMOUS_SPIFFE_ID};
+use crate::config::{MissingPolicyAction, PolicyConfig};
 use crate::policy::model::*;
 
 /// Policy engine trait for access control decisions
 pub trait PolicyEngine: Send + Sync {
-    /// Check if a request is allowed
-    fn allow(&self, spiffe_id: &str, method: &str) -> bool;
+    /// Check if a request over `protocol` is allowed
+    fn allow(&self, spiffe_id: &str, protocol: ProtocolType, method: &str) -> bool;
+
+    /// Check if a request for the given host (HTTP `Host` or TLS SNI) is allowed
+    ///
+    /// Engines without host matching ignore the host.
+    fn allow_for_host(&self, spiffe_id: &str, protocol: ProtocolType, method: &str, _host: Option<&str>) -> bool {
+        self.allow(spiffe_id, protocol, method)
+    }
+
+    /// Check a request for the given host, also returning which rule decided
+    ///
+    /// Engines without numbered rules report every decision as the default.
+    fn allow_for_host_detailed(
+        &self,
+        spiffe_id: &str,
+        protocol: ProtocolType,
+        method: &str,
+        host: Option<&str>,
+    ) -> PolicyDecision {
+        PolicyDecision {
+            allowed: self.allow_for_host(spiffe_id, protocol, method, host),
+            rule: None,
+        }
+    }
 }
 
 /// YAML-based policy engine
@@ -32,6 +57,42 @@ impl YamlPolicyEngine {
         Self::from_yaml(&content)
     }
 
+    /// Create a policy engine from configuration, applying `on_missing` when
+    /// the policy file does not exist
+    pub fn from_config(config: &PolicyConfig) -> Result<Self> {
+        if config.path.exists() {
+            return Self::from_path(&config.path);
+        }
+
+        let default_action = match config.on_missing {
+            MissingPolicyAction::Fail => {
+                return Err(anyhow::anyhow!(
+                    "Policy file does not exist: {}",
+                    config.path.display()
+                ));
+            }
+            MissingPolicyAction::DefaultDeny => {
+                warn!(
+                    "Policy file {} does not exist, denying all requests",
+                    config.path.display()
+                );
+                false
+            }
+            MissingPolicyAction::DefaultAllow => {
+                warn!(
+                    "Policy file {} does not exist, ALLOWING ALL REQUESTS (policy.on_missing: default_allow)",
+                    config.path.display()
+                );
+                true
+            }
+        };
+
+        Self::from_definition(PolicyDefinition {
+            default_action,
+            rules: Vec::new(),
+        })
+    }
+
     /// Create a new policy engine from YAML content
     pub fn from_yaml(yaml: &str) -> Result<Self> {
         let policy_def: PolicyDefinition = serde_yaml::from_str(yaml)
@@ -41,10 +102,22 @@ impl YamlPolicyEngine {
     }
 
     /// Create a new policy engine from a policy definition
+    ///
+    /// Rules are evaluated by descending `priority` (unset counts as 0) and the
+    /// first matching rule wins. Rules with equal priority keep their file order.
     pub fn from_definition(def: PolicyDefinition) -> Result<Self> {
-        let mut compiled_rules = Vec::with_capacity(def.rules.len());
-
-        for rule in def.rules {
+        let mut rules: Vec<(usize, PolicyRule)> = def
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| (i + 1, rule))
+            .collect();
+        // Stable sort so file order remains the tiebreaker
+        rules.sort_by_key(|(_, rule)| std::cmp::Reverse(rule.priority.unwrap_or(0)));
+
+        let mut compiled_rules = Vec::with_capacity(rules.len());
+
+        for (position, rule) in rules {
             let spiffe_id = if rule.spiffe_id.starts_with("regex:") {
                 let pattern = &rule.spiffe_id[6..];
                 // Validate regex
@@ -53,13 +126,20 @@ impl YamlPolicyEngine {
                 SpiffeIdPattern::Regex(pattern.to_string())
             } else if rule.spiffe_id == "*" {
                 SpiffeIdPattern::Any
+            } else if rule.spiffe_id == ANONYMOUS_SPIFFE_ID {
+                SpiffeIdPattern::Anonymous
             } else {
                 SpiffeIdPattern::Exact(rule.spiffe_id)
             };
 
             let protocol = match rule.protocol {
                 Some(ref p) if p == "*" => ProtocolPattern::Any,
-                Some(ref p) => ProtocolPattern::Exact(p.clone()),
+                Some(ref p) => {
+                    // Reject protocols no handler can ever report
+                    p.parse::<ProtocolType>()
+                        .context(format!("Invalid protocol in policy rule: {}", p))?;
+                    ProtocolPattern::Exact(p.clone())
+                },
                 None => ProtocolPattern::Any,
             };
 
@@ -76,11 +156,25 @@ impl YamlPolicyEngine {
                 None => MethodPattern::Any,
             };
 
+            let host = match rule.host {
+                Some(ref h) if h.starts_with("regex:") => {
+                    let pattern = &h[6..];
+                    // Validate regex
+                    Regex::new(pattern)
+                        .context(format!("Invalid regex pattern: {}", pattern))?;
+                    HostPattern::from(h.as_str())
+                },
+                Some(ref h) => HostPattern::from(h.as_str()),
+                None => HostPattern::Any,
+            };
+
             compiled_rules.push(CompiledRule {
                 spiffe_id,
                 protocol,
                 method,
+                host,
                 allow: rule.allow,
+                position,
             });
         }
 
@@ -94,10 +188,16 @@ impl YamlPolicyEngine {
     }
 
     /// Match a SPIFFE ID against a pattern
+    ///
+    /// Only an explicit `anonymous` pattern matches clients without a
+    /// certificate; wildcards and regexes never do.
     fn match_spiffe_id(&self, pattern: &SpiffeIdPattern, spiffe_id: &str) -> bool {
+        let anonymous = spiffe_id == ANONYMOUS_SPIFFE_ID;
         match pattern {
-            SpiffeIdPattern::Any => true,
+            SpiffeIdPattern::Any => !anonymous,
+            SpiffeIdPattern::Anonymous => anonymous,
             SpiffeIdPattern::Exact(expected) => expected == spiffe_id,
+            SpiffeIdPattern::Regex(_) if anonymous => false,
             SpiffeIdPattern::Regex(regex_str) => {
                 let mut cache = self.regex_cache.lock().unwrap();
                 let regex = match cache.get(regex_str) {
@@ -139,6 +239,37 @@ impl YamlPolicyEngine {
         }
     }
 
+    /// Match the requested host against a pattern
+    ///
+    /// A rule naming a host never matches a request whose host is unknown.
+    fn match_host(&self, pattern: &HostPattern, host: Option<&str>) -> bool {
+        let host = match (pattern, host) {
+            (HostPattern::Any, _) => return true,
+            (_, None) => return false,
+            (_, Some(host)) => host.to_ascii_lowercase(),
+        };
+
+        match pattern {
+            HostPattern::Any => true,
+            HostPattern::Exact(expected) => *expected == host,
+            HostPattern::Regex(regex_str) => {
+                let mut cache = self.regex_cache.lock().unwrap();
+                let regex = match cache.get(regex_str) {
+                    Some(r) => r,
+                    None => {
+                        let r = match Regex::new(regex_str) {
+                            Ok(r) => r,
+                            Err(_) => return false,
+                        };
+                        cache.insert(regex_str.clone(), r);
+                        cache.get(regex_str).unwrap()
+                    }
+                };
+                regex.is_match(&host)
+            }
+        }
+    }
+
     /// Match protocol against a pattern
     fn match_protocol(&self, pattern: &ProtocolPattern, protocol: &str) -> bool {
         match pattern {
@@ -148,13 +279,25 @@ impl YamlPolicyEngine {
     }
 }
 
-impl PolicyEngine for YamlPolicyEngine {
-    fn allow(&self, spiffe_id: &str, method: &str) -> bool {
-        trace!("Evaluating policy for SPIFFE ID: {}, method: {}", spiffe_id, method);
-
-        // Default to TCP protocol for simple policy evaluation
-        let protocol = "tcp";
+impl YamlPolicyEngine {
+    /// Evaluate a request and report which rule decided it
+    ///
+    /// Protocol patterns only constrain a rule when a protocol is supplied;
+    /// without protocol context every rule is considered for its SPIFFE ID and
+    /// method alone. Rules with a host pattern only match when the host is
+    /// known.
+    pub fn allow_detailed(
+        &self,
+        spiffe_id: &str,
+        protocol: Option<&str>,
+        method: &str,
+        host: Option<&str>,
+    ) -> PolicyDecision {
+        self.evaluate(spiffe_id, protocol, method, host)
+    }
 
+    /// Evaluate the rules in order, returning the decision of the first match
+    fn evaluate(&self, spiffe_id: &str, protocol: Option<&str>, method: &str, host: Option<&str>) -> PolicyDecision {
         // Evaluate each rule in order
         for rule in &self.policy.rules {
             // Check if SPIFFE ID matches
@@ -163,8 +306,10 @@ impl PolicyEngine for YamlPolicyEngine {
             }
 
             // Check if protocol matches
-            if !self.match_protocol(&rule.protocol, protocol) {
-                continue;
+            if let Some(protocol) = protocol {
+                if !self.match_protocol(&rule.protocol, protocol) {
+                    continue;
+                }
             }
 
             // Check if method matches
@@ -172,12 +317,20 @@ impl PolicyEngine for YamlPolicyEngine {
                 continue;
             }
 
+            // Check if host matches
+            if !self.match_host(&rule.host, host) {
+                continue;
+            }
+
             // Rule matched, return its action
             debug!(
-                "Policy rule matched - SPIFFE ID: {}, method: {}, allow: {}",
-                spiffe_id, method, rule.allow
+                "Policy rule #{} matched - SPIFFE ID: {}, method: {}, allow: {}",
+                rule.position, spiffe_id, method, rule.allow
             );
-            return rule.allow;
+            return PolicyDecision {
+                allowed: rule.allow,
+                rule: Some(rule.position),
+            };
         }
 
         // No rules matched, use default action
@@ -185,7 +338,37 @@ impl PolicyEngine for YamlPolicyEngine {
             "No policy rules matched - SPIFFE ID: {}, method: {}, using default action: {}",
             spiffe_id, method, self.policy.default_action
         );
-        self.policy.default_action
+        PolicyDecision {
+            allowed: self.policy.default_action,
+            rule: None,
+        }
+    }
+}
+
+impl PolicyEngine for YamlPolicyEngine {
+    fn allow(&self, spiffe_id: &str, protocol: ProtocolType, method: &str) -> bool {
+        trace!("Evaluating policy for SPIFFE ID: {}, protocol: {}, method: {}", spiffe_id, protocol, method);
+
+        self.evaluate(spiffe_id, Some(protocol.as_str()), method, None).allowed
+    }
+
+    fn allow_for_host(&self, spiffe_id: &str, protocol: ProtocolType, method: &str, host: Option<&str>) -> bool {
+        self.allow_for_host_detailed(spiffe_id, protocol, method, host).allowed
+    }
+
+    fn allow_for_host_detailed(
+        &self,
+        spiffe_id: &str,
+        protocol: ProtocolType,
+        method: &str,
+        host: Option<&str>,
+    ) -> PolicyDecision {
+        trace!(
+            "Evaluating policy for SPIFFE ID: {}, protocol: {}, method: {}, host: {:?}",
+            spiffe_id, protocol, method, host
+        );
+
+        self.evaluate(spiffe_id, Some(protocol.as_str()), method, host)
     }
 }
 
@@ -206,9 +389,9 @@ mod tests {
 
         let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
 
-        assert!(engine.allow("spiffe://example.org/service/allowed", "any"));
-        assert!(!engine.allow("spiffe://example.org/service/denied", "any"));
-        assert!(!engine.allow("spiffe://example.org/service/unknown", "any"));
+        assert!(engine.allow("spiffe://example.org/service/allowed", ProtocolType::Http, "any"));
+        assert!(!engine.allow("spiffe://example.org/service/denied", ProtocolType::Http, "any"));
+        assert!(!engine.allow("spiffe://example.org/service/unknown", ProtocolType::Http, "any"));
     }
 
     #[test]
@@ -225,9 +408,9 @@ mod tests {
 
         let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
 
-        assert!(engine.allow("spiffe://example.org/service/web", "get_users"));
-        assert!(!engine.allow("spiffe://example.org/service/web", "delete"));
-        assert!(!engine.allow("spiffe://example.org/admin/root", "any"));
+        assert!(engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "get_users"));
+        assert!(!engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "delete"));
+        assert!(!engine.allow("spiffe://example.org/admin/root", ProtocolType::Http, "any"));
     }
 
     #[test]
@@ -241,8 +424,8 @@ mod tests {
 
         let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
 
-        assert!(!engine.allow("spiffe://example.org/service/denied", "any"));
-        assert!(engine.allow("spiffe://example.org/service/other", "any"));
+        assert!(!engine.allow("spiffe://example.org/service/denied", ProtocolType::Http, "any"));
+        assert!(engine.allow("spiffe://example.org/service/other", ProtocolType::Http, "any"));
     }
     
     #[test]
@@ -256,7 +439,7 @@ mod tests {
             allow: true
           - spiffe_id: "spiffe://example.org/service/api"
             protocol: "grpc"
-            method: "UserService.GetUsers"
+            method: "/api.UserService/GetUsers"
             allow: true
           - spiffe_id: "spiffe://example.org/service/api"
             protocol: "tcp"
@@ -266,16 +449,39 @@ mod tests {
         let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
         
         // HTTP endpoint should be allowed
-        assert!(engine.allow("spiffe://example.org/service/api", "GET /api/users"));
+        assert!(engine.allow("spiffe://example.org/service/api", ProtocolType::Http, "GET /api/users"));
         
         // Different HTTP endpoint should be denied
-        assert!(!engine.allow("spiffe://example.org/service/api", "POST /api/users"));
+        assert!(!engine.allow("spiffe://example.org/service/api", ProtocolType::Http, "POST /api/users"));
         
         // gRPC method should be allowed
-        assert!(engine.allow("spiffe://example.org/service/api", "UserService.GetUsers"));
+        assert!(engine.allow("spiffe://example.org/service/api", ProtocolType::Grpc, "/api.UserService/GetUsers"));
         
         // When protocol is detected as TCP, should be denied
-        assert!(!engine.allow("spiffe://example.org/service/api", "connect"));
+        assert!(!engine.allow("spiffe://example.org/service/api", ProtocolType::Tcp, "TCP"));
+    }
+
+    #[test]
+    fn test_protocol_scoped_rules_match_only_their_protocol() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "spiffe://example.org/service/api"
+            protocol: "grpc"
+            allow: true
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        assert!(engine.allow("spiffe://example.org/service/api", ProtocolType::Grpc, "/api.UserService/GetUsers"));
+        assert!(!engine.allow("spiffe://example.org/service/api", ProtocolType::Http, "GET /admin"));
+        assert!(!engine.allow("spiffe://example.org/service/api", ProtocolType::Tcp, "TCP"));
+        assert!(!engine.allow_for_host(
+            "spiffe://example.org/service/api",
+            ProtocolType::Http,
+            "GET /admin",
+            Some("api.example.org")
+        ));
     }
     
     #[test]
@@ -305,21 +511,193 @@ mod tests {
         let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
         
         // Monitoring service should be allowed regardless of method
-        assert!(engine.allow("spiffe://example.org/service/monitoring-1", "any_method"));
+        assert!(engine.allow("spiffe://example.org/service/monitoring-1", ProtocolType::Http, "any_method"));
         
         // Any service with auth.* method should be allowed
-        assert!(engine.allow("spiffe://example.org/service/unknown", "auth.login"));
+        assert!(engine.allow("spiffe://example.org/service/unknown", ProtocolType::Http, "auth.login"));
         
         // Dangerous methods should be blocked for any service
-        assert!(!engine.allow("spiffe://example.org/service/trusted/db", "delete_all"));
+        assert!(!engine.allow("spiffe://example.org/service/trusted/db", ProtocolType::Http, "delete_all"));
         
         // Trusted services should be allowed for normal operations
-        assert!(engine.allow("spiffe://example.org/service/trusted/api", "get_users"));
+        assert!(engine.allow("spiffe://example.org/service/trusted/api", ProtocolType::Http, "get_users"));
         
         // Untrusted services should be denied
-        assert!(!engine.allow("spiffe://example.org/service/untrusted", "get_users"));
+        assert!(!engine.allow("spiffe://example.org/service/untrusted", ProtocolType::Http, "get_users"));
         
         // External domain should be denied
-        assert!(!engine.allow("spiffe://attacker.org/service/trusted", "get_users"));
+        assert!(!engine.allow("spiffe://attacker.org/service/trusted", ProtocolType::Http, "get_users"));
+    }
+
+    #[test]
+    fn test_invalid_rule_protocol_rejected() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "spiffe://example.org/service/api"
+            protocol: "htp"
+            allow: true
+        "#;
+
+        assert!(YamlPolicyEngine::from_yaml(yaml).is_err());
+    }
+
+    #[test]
+    fn test_rule_priority_overrides_file_order() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "regex:spiffe://example.org/service/.*"
+            allow: true
+          - spiffe_id: "spiffe://example.org/service/banned"
+            allow: false
+            priority: 100
+          - spiffe_id: "spiffe://example.org/service/web"
+            method: "GET /health"
+            allow: true
+            priority: -1
+          - spiffe_id: "spiffe://example.org/service/web"
+            allow: false
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        // High-priority deny wins even though a broader allow appears first
+        assert!(!engine.allow("spiffe://example.org/service/banned", ProtocolType::Http, "any"));
+
+        // Equal priority keeps file order: the regex allow precedes the web deny
+        assert!(engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "any"));
+        assert!(engine.allow("spiffe://example.org/service/other", ProtocolType::Http, "any"));
+    }
+
+    #[test]
+    fn test_allow_detailed_reports_matching_rule() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "spiffe://example.org/service/web"
+            protocol: "http"
+            allow: true
+          - spiffe_id: "spiffe://example.org/service/web"
+            allow: false
+            priority: 10
+          - spiffe_id: "spiffe://example.org/service/api"
+            protocol: "grpc"
+            allow: true
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        // Positions refer to the file, not the priority-sorted order
+        let decision = engine.allow_detailed("spiffe://example.org/service/web", Some("http"), "GET /", None);
+        assert_eq!(decision, PolicyDecision { allowed: false, rule: Some(2) });
+
+        let decision = engine.allow_detailed("spiffe://example.org/service/api", Some("grpc"), "Get", None);
+        assert_eq!(decision, PolicyDecision { allowed: true, rule: Some(3) });
+
+        // Protocol mismatch falls through to the default action
+        let decision = engine.allow_detailed("spiffe://example.org/service/api", Some("tcp"), "", None);
+        assert_eq!(decision, PolicyDecision { allowed: false, rule: None });
+    }
+
+    #[test]
+    fn test_anonymous_subject() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "anonymous"
+            protocol: "http"
+            method: "GET /health"
+            allow: true
+          - spiffe_id: "regex:spiffe://example.org/service/.*"
+            allow: true
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        let decision = engine.allow_detailed(ANONYMOUS_SPIFFE_ID, Some("http"), "GET /health", None);
+        assert_eq!(decision, PolicyDecision { allowed: true, rule: Some(1) });
+        assert!(!engine.allow(ANONYMOUS_SPIFFE_ID, ProtocolType::Http, "GET /admin"));
+
+        // The anonymous rule never matches an authenticated client
+        let decision = engine.allow_detailed("spiffe://example.org/service/web", Some("http"), "GET /health", None);
+        assert_eq!(decision, PolicyDecision { allowed: true, rule: Some(2) });
+        assert!(!engine.allow("spiffe://other.org/service/web", ProtocolType::Http, "GET /health"));
+    }
+
+    #[test]
+    fn test_wildcards_exclude_anonymous() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "*"
+            method: "GET /public"
+            allow: true
+          - spiffe_id: "regex:.*"
+            method: "GET /internal"
+            allow: true
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        assert!(engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "GET /public"));
+        assert!(engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "GET /internal"));
+        assert!(!engine.allow(ANONYMOUS_SPIFFE_ID, ProtocolType::Http, "GET /public"));
+        assert!(!engine.allow(ANONYMOUS_SPIFFE_ID, ProtocolType::Http, "GET /internal"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_host_matching() {
+        let yaml = r#"
+        default_action: false
+        rules:
+          - spiffe_id: "spiffe://example.org/service/web"
+            host: "API.example.org"
+            allow: true
+          - spiffe_id: "regex:spiffe://example.org/service/.*"
+            host: "regex:^[a-z]+\\.internal\\.example\\.org$"
+            allow: true
+          - spiffe_id: "spiffe://example.org/service/admin"
+            allow: true
+        "#;
+
+        let engine = YamlPolicyEngine::from_yaml(yaml).unwrap();
+
+        // The web service may only reach the API host, matched case-insensitively
+        assert!(engine.allow_for_host("spiffe://example.org/service/web", ProtocolType::Http, "GET /", Some("api.example.org")));
+        assert!(!engine.allow_for_host("spiffe://example.org/service/web", ProtocolType::Http, "GET /", Some("db.example.org")));
+
+        // Any service may reach internal hosts, but only its own rules apply
+        assert!(engine.allow_for_host("spiffe://example.org/service/batch", ProtocolType::Http, "GET /", Some("jobs.internal.example.org")));
+        assert!(!engine.allow_for_host("spiffe://other.org/service/batch", ProtocolType::Http, "GET /", Some("jobs.internal.example.org")));
+
+        // Host rules never match an unknown host; rules without one match any host
+        assert!(!engine.allow("spiffe://example.org/service/web", ProtocolType::Http, "GET /"));
+        let decision = engine.allow_detailed("spiffe://example.org/service/admin", None, "GET /", None);
+        assert_eq!(decision, PolicyDecision { allowed: true, rule: Some(3) });
+        assert!(engine.allow_for_host("spiffe://example.org/service/admin", ProtocolType::Http, "GET /", Some("db.example.org")));
+
+        let invalid = r#"
+        rules:
+          - spiffe_id: "*"
+            host: "regex:("
+        "#;
+        assert!(YamlPolicyEngine::from_yaml(invalid).is_err());
+    }
+
+    #[test]
+    fn test_missing_policy_file() {
+        let config = |on_missing| PolicyConfig {
+            path: "/nonexistent/policy.yaml".into(),
+            on_missing,
+        };
+
+        assert!(YamlPolicyEngine::from_config(&config(MissingPolicyAction::Fail)).is_err());
+
+        let engine = YamlPolicyEngine::from_config(&config(MissingPolicyAction::DefaultDeny)).unwrap();
+        assert!(!engine.allow("spiffe://example.org/service/a", ProtocolType::Http, "GET /"));
+
+        let engine = YamlPolicyEngine::from_config(&config(MissingPolicyAction::DefaultAllow)).unwrap();
+        assert!(engine.allow("spiffe://example.org/service/a", ProtocolType::Http, "GET /"));
+    }
+}
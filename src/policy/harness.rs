@@ -0,0 +1,185 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::common::ProtocolType;
+use crate::policy::engine::YamlPolicyEngine;
+use crate::policy::model::{PolicyDecision, PolicyDefinition, PolicyRule};
+
+/// A request and the decision the policy is expected to make for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyTestCase {
+    /// SPIFFE ID of the caller
+    pub spiffe_id: String,
+
+    /// Method or path of the request
+    #[serde(default)]
+    pub method: String,
+
+    /// Protocol of the request (tcp, http, grpc); protocol-specific rules are
+    /// considered for any protocol when unset
+    #[serde(default)]
+    pub protocol: Option<String>,
+
+    /// Host the request targets (HTTP `Host` or TLS SNI); rules with a host
+    /// pattern never match when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    /// Whether the request is expected to be allowed
+    pub expected: bool,
+}
+
+/// Result of evaluating one test case
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyTestResult {
+    /// The evaluated case
+    #[serde(flatten)]
+    pub case: PolicyTestCase,
+
+    /// Decision made by the policy
+    pub decision: PolicyDecision,
+
+    /// Whether the decision matched the expectation
+    pub passed: bool,
+}
+
+/// Replays expected decisions against a policy file
+pub struct PolicyTestHarness {
+    /// Engine compiled from the policy file
+    engine: YamlPolicyEngine,
+
+    /// Rules in file order, used to explain decisions
+    rules: Vec<PolicyRule>,
+}
+
+impl PolicyTestHarness {
+    /// Load the policy under test
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(path.as_ref())
+            .context(format!("Failed to read policy file: {}", path.as_ref().display()))?;
+        let definition: PolicyDefinition = serde_yaml::from_str(&content)
+            .context("Failed to parse policy YAML")?;
+
+        Ok(Self {
+            rules: definition.rules.clone(),
+            engine: YamlPolicyEngine::from_definition(definition)?,
+        })
+    }
+
+    /// Load test cases from a YAML list
+    pub fn load_cases<P: AsRef<Path>>(path: P) -> Result<Vec<PolicyTestCase>> {
+        let content = fs::read_to_string(path.as_ref())
+            .context(format!("Failed to read test cases: {}", path.as_ref().display()))?;
+        let cases: Vec<PolicyTestCase> = serde_yaml::from_str(&content)
+            .context("Failed to parse test cases YAML")?;
+
+        for (i, case) in cases.iter().enumerate() {
+            if let Some(protocol) = &case.protocol {
+                protocol
+                    .parse::<ProtocolType>()
+                    .context(format!("Invalid protocol in test case #{}", i + 1))?;
+            }
+        }
+
+        Ok(cases)
+    }
+
+    /// Evaluate every case against the policy
+    pub fn run(&self, cases: &[PolicyTestCase]) -> Vec<PolicyTestResult> {
+        cases
+            .iter()
+            .map(|case| {
+                let decision = self.engine.allow_detailed(
+                    &case.spiffe_id,
+                    case.protocol.as_deref(),
+                    &case.method,
+                    case.host.as_deref(),
+                );
+                PolicyTestResult {
+                    passed: decision.allowed == case.expected,
+                    case: case.clone(),
+                    decision,
+                }
+            })
+            .collect()
+    }
+
+    /// Describe why a result was decided the way it was
+    pub fn explain(&self, result: &PolicyTestResult) -> String {
+        match result.decision.rule {
+            Some(position) => match self.rules.get(position - 1) {
+                Some(rule) => format!(
+                    "rule #{} (spiffe_id: {}, protocol: {}, method: {}{})",
+                    position,
+                    rule.spiffe_id,
+                    rule.protocol.as_deref().unwrap_or("*"),
+                    rule.method.as_deref().unwrap_or("*"),
+                    rule.host.as_ref().map(|host| format!(", host: {}", host)).unwrap_or_default()
+                ),
+                None => format!("rule #{}", position),
+            },
+            None => "default action".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const POLICY: &str = r#"
+default_action: false
+rules:
+  - spiffe_id: "spiffe://example.org/service/web"
+    protocol: "http"
+    method: "regex:^GET /api/.*$"
+    allow: true
+  - spiffe_id: "spiffe://example.org/service/banned"
+    allow: false
+"#;
+
+    const CASES: &str = r#"
+- spiffe_id: "spiffe://example.org/service/web"
+  protocol: "http"
+  method: "GET /api/users"
+  expected: true
+- spiffe_id: "spiffe://example.org/service/web"
+  protocol: "http"
+  method: "DELETE /api/users"
+  expected: true
+- spiffe_id: "spiffe://example.org/service/banned"
+  expected: false
+"#;
+
+    #[test]
+    fn test_harness_reports_mismatches() {
+        let dir = tempdir().unwrap();
+        let policy_path = dir.path().join("policy.yaml");
+        let cases_path = dir.path().join("cases.yaml");
+        fs::write(&policy_path, POLICY).unwrap();
+        fs::write(&cases_path, CASES).unwrap();
+
+        let harness = PolicyTestHarness::from_path(&policy_path).unwrap();
+        let cases = PolicyTestHarness::load_cases(&cases_path).unwrap();
+        let results = harness.run(&cases);
+
+        let passed: Vec<bool> = results.iter().map(|r| r.passed).collect();
+        assert_eq!(passed, vec![true, false, true]);
+
+        assert!(harness.explain(&results[0]).starts_with("rule #1"));
+        assert_eq!(harness.explain(&results[1]), "default action");
+        assert!(harness.explain(&results[2]).starts_with("rule #2"));
+    }
+
+    #[test]
+    fn test_invalid_case_protocol_rejected() {
+        let dir = tempdir().unwrap();
+        let cases_path = dir.path().join("cases.yaml");
+        fs::write(&cases_path, "- spiffe_id: \"spiffe://example.org/a\"\n  protocol: \"ftp\"\n  expected: true\n").unwrap();
+
+        assert!(PolicyTestHarness::load_cases(&cases_path).is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::common::ProtocolType;
+
+/// Method string for connections whose gRPC method has not been parsed yet
+pub const GRPC_UNPARSED_METHOD: &str = "grpc:CONNECT";
+
+/// Build the method string a policy rule is matched against
+///
+/// Every protocol handler goes through this so policy authors can rely on
+/// one convention:
+///
+/// - TCP: always `TCP`
+/// - gRPC: the request path `/package.Service/Method`, or `grpc:CONNECT`
+///   when the method is not known
+/// - HTTP: `<METHOD> <path>` with the method uppercased, e.g. `GET /api/v1/users`
+pub fn policy_method_for(protocol: ProtocolType, raw: Option<&str>) -> String {
+    let raw = raw.map(str::trim).filter(|raw| !raw.is_empty());
+
+    match (protocol, raw) {
+        (ProtocolType::Tcp, _) => "TCP".to_string(),
+        (ProtocolType::Grpc, Some(path)) if path.starts_with('/') => path.to_string(),
+        (ProtocolType::Grpc, Some(path)) => format!("/{}", path),
+        (ProtocolType::Grpc, None) => GRPC_UNPARSED_METHOD.to_string(),
+        (ProtocolType::Http, Some(request)) => match request.split_once(' ') {
+            Some((method, path)) => format!("{} {}", method.to_ascii_uppercase(), path.trim_start()),
+            None => format!("{} /", request.to_ascii_uppercase()),
+        },
+        (ProtocolType::Http, None) => "UNKNOWN /".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_method_convention() {
+        assert_eq!(policy_method_for(ProtocolType::Tcp, None), "TCP");
+        assert_eq!(policy_method_for(ProtocolType::Tcp, Some("anything")), "TCP");
+
+        assert_eq!(
+            policy_method_for(ProtocolType::Grpc, Some("/api.UserService/GetUser")),
+            "/api.UserService/GetUser"
+        );
+        assert_eq!(
+            policy_method_for(ProtocolType::Grpc, Some("api.UserService/GetUser")),
+            "/api.UserService/GetUser"
+        );
+        assert_eq!(policy_method_for(ProtocolType::Grpc, None), "grpc:CONNECT");
+
+        assert_eq!(policy_method_for(ProtocolType::Http, Some("get /api/v1/users")), "GET /api/v1/users");
+        assert_eq!(policy_method_for(ProtocolType::Http, Some("DELETE")), "DELETE /");
+        assert_eq!(policy_method_for(ProtocolType::Http, Some("  ")), "UNKNOWN /");
+    }
+}
@@ -1,5 +1,9 @@
 mod engine;
+mod harness;
+mod method;
 mod model;
 
 pub use engine::{PolicyEngine, YamlPolicyEngine};
-pub use model::{PolicyDefinition, PolicyRule};
\ No newline at end of file
+pub use harness::{PolicyTestCase, PolicyTestHarness, PolicyTestResult};
+pub use method::{policy_method_for, GRPC_UNPARSED_METHOD};
+pub use model::{PolicyDecision, PolicyDefinition, PolicyRule};
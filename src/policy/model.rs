@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 
+use crate::common::ANONYMOUS_SPIFFE_ID;
+
 /// Policy rule for access control
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PolicyRule {
-    /// SPIFFE ID pattern to match (exact string or regex)
+    /// SPIFFE ID pattern to match (exact string, regex, or `anonymous` for
+    /// clients without a certificate)
     pub spiffe_id: String,
 
     /// Protocol for this rule (tcp, http, grpc)
@@ -12,9 +15,18 @@ pub struct PolicyRule {
     /// Method or path pattern (for HTTP/gRPC)
     pub method: Option<String>,
 
+    /// Host the client asked for: the HTTP `Host` header, or the TLS SNI
+    /// for other protocols (exact name, regex, or `*`; any host when unset)
+    #[serde(default)]
+    pub host: Option<String>,
+
     /// Whether to allow or deny the request
     #[serde(default = "default_action")]
     pub allow: bool,
+
+    /// Evaluation priority; higher values are evaluated first (defaults to 0)
+    #[serde(default)]
+    pub priority: Option<i32>,
 }
 
 /// Default action for policy rules
@@ -45,7 +57,7 @@ pub enum MethodPattern {
     Any,
     /// Match exact method name
     Exact(String),
-    /// Match regex pattern
+    /// Match regex pattern against authenticated SPIFFE IDs
     Regex(String),
 }
 
@@ -64,11 +76,13 @@ impl From<&str> for MethodPattern {
 /// Type for SPIFFE ID patterns with special handling
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum SpiffeIdPattern {
-    /// Match any SPIFFE ID
+    /// Match any authenticated SPIFFE ID
     Any,
+    /// Match clients that presented no certificate
+    Anonymous,
     /// Match exact SPIFFE ID
     Exact(String),
-    /// Match regex pattern
+    /// Match regex pattern against authenticated SPIFFE IDs
     Regex(String),
 }
 
@@ -76,6 +90,7 @@ impl From<&str> for SpiffeIdPattern {
     fn from(s: &str) -> Self {
         match s {
             "*" => SpiffeIdPattern::Any,
+            ANONYMOUS_SPIFFE_ID => SpiffeIdPattern::Anonymous,
             _ if s.starts_with("regex:") => {
                 SpiffeIdPattern::Regex(s[6..].to_string())
             },
@@ -84,6 +99,29 @@ impl From<&str> for SpiffeIdPattern {
     }
 }
 
+/// Type for host patterns, matched case-insensitively
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HostPattern {
+    /// Match any host, including none
+    Any,
+    /// Match exact host name (stored lowercase)
+    Exact(String),
+    /// Match regex pattern against authenticated SPIFFE IDs
+    Regex(String),
+}
+
+impl From<&str> for HostPattern {
+    fn from(s: &str) -> Self {
+        match s {
+            "*" => HostPattern::Any,
+            _ if s.starts_with("regex:") => {
+                HostPattern::Regex(s[6..].to_string())
+            },
+            _ => HostPattern::Exact(s.to_ascii_lowercase()),
+        }
+    }
+}
+
 /// Type for protocol matching
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProtocolPattern {
@@ -114,8 +152,25 @@ pub struct CompiledRule {
     /// Method pattern
     pub method: MethodPattern,
 
+    /// Host pattern
+    pub host: HostPattern,
+
     /// Allow or deny
     pub allow: bool,
+
+    /// Position of the rule in the policy file (1-based)
+    pub position: usize,
+}
+
+/// Outcome of a policy evaluation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyDecision {
+    /// Whether the request is allowed
+    pub allowed: bool,
+
+    /// Position of the matching rule in the policy file (1-based), or `None`
+    /// when no rule matched and the default action applied
+    pub rule: Option<usize>,
 }
 
 /// Compiled policy for efficient evaluation
@@ -0,0 +1,207 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+/// Byte stream a client connection can be served over
+pub trait ClientIo: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> ClientIo for T {}
+
+/// A client connection whose first bytes can be inspected before it is handled
+///
+/// Bytes read ahead with `peek_more` are kept and returned again by the
+/// first reads, so protocol detection never takes data away from the
+/// handler. In the proxy this wraps the decrypted TLS stream, which, unlike
+/// a socket, cannot be peeked at directly.
+pub struct ClientStream {
+    /// Underlying connection
+    inner: Box<dyn ClientIo>,
+
+    /// Address of the client
+    peer_addr: SocketAddr,
+
+    /// Identifier tagging every log line about this connection
+    connection_id: String,
+
+    /// Server name the client sent in its TLS ClientHello (SNI)
+    server_name: Option<String>,
+
+    /// Connection accepted without TLS during the plaintext bootstrap window
+    plaintext: bool,
+
+    /// Bytes read ahead of the handler
+    peeked: Vec<u8>,
+
+    /// How many of the peeked bytes have been read again
+    consumed: usize,
+}
+
+impl ClientStream {
+    /// Wrap a connection from `peer_addr`
+    pub fn new<S: ClientIo + 'static>(inner: S, peer_addr: SocketAddr) -> Self {
+        Self {
+            inner: Box::new(inner),
+            peer_addr,
+            connection_id: uuid::Uuid::new_v4().to_string(),
+            server_name: None,
+            plaintext: false,
+            peeked: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Wrap a plain TCP connection
+    pub fn from_tcp(stream: TcpStream) -> io::Result<Self> {
+        let peer_addr = stream.peer_addr()?;
+        Ok(Self::new(stream, peer_addr))
+    }
+
+    /// Address of the client
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Use the identifier the acceptor generated for this connection
+    pub fn with_connection_id(mut self, connection_id: String) -> Self {
+        self.connection_id = connection_id;
+        self
+    }
+
+    /// Identifier of this connection, generated when it was accepted
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    /// Record the server name the client requested during the TLS handshake
+    pub fn with_server_name(mut self, server_name: Option<String>) -> Self {
+        self.server_name = server_name;
+        self
+    }
+
+    /// Server name the client requested via SNI, if any
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Mark a connection accepted without TLS
+    pub fn with_plaintext(mut self, plaintext: bool) -> Self {
+        self.plaintext = plaintext;
+        self
+    }
+
+    /// Whether the client connected without TLS, and so without an identity
+    pub fn is_plaintext(&self) -> bool {
+        self.plaintext
+    }
+
+    /// Bytes read ahead that the handler has not read yet
+    pub fn peeked(&self) -> &[u8] {
+        &self.peeked[self.consumed..]
+    }
+
+    /// Replace the first `len` unread peeked bytes, so the handler can
+    /// rewrite what it has read ahead before forwarding it
+    pub fn replace_peeked(&mut self, len: usize, replacement: &[u8]) {
+        let start = self.consumed;
+        let end = start + len.min(self.peeked.len() - start);
+        self.peeked.splice(start..end, replacement.iter().copied());
+    }
+
+    /// Read ahead once, keeping at most `max_bytes` unread bytes
+    ///
+    /// Returns the number of bytes added, 0 at end of stream or when
+    /// `max_bytes` are already peeked. Cancel safe: nothing is lost if the
+    /// future is dropped before it completes.
+    pub async fn peek_more(&mut self, max_bytes: usize) -> io::Result<usize> {
+        self.peeked.drain(..self.consumed);
+        self.consumed = 0;
+
+        let wanted = max_bytes.saturating_sub(self.peeked.len());
+        if wanted == 0 {
+            return Ok(0);
+        }
+
+        let mut buf = vec![0u8; wanted];
+        let n = self.inner.read(&mut buf).await?;
+        self.peeked.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Hand back the peeked bytes before reading anything new
+        if this.consumed < this.peeked.len() {
+            let pending = &this.peeked[this.consumed..];
+            let n = pending.len().min(buf.remaining());
+            buf.put_slice(&pending[..n]);
+            this.consumed += n;
+            if this.consumed == this.peeked.len() {
+                this.peeked.clear();
+                this.consumed = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_peeked_bytes_are_read_again() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = ClientStream::new(server, "127.0.0.1:4000".parse().unwrap());
+
+        client.write_all(b"GET /hello HTTP/1.1\r\n\r\n").await.unwrap();
+        client.shutdown().await.unwrap();
+
+        assert_eq!(stream.peek_more(4).await.unwrap(), 4);
+        assert_eq!(stream.peeked(), b"GET ");
+        // Already holding the requested amount
+        assert_eq!(stream.peek_more(4).await.unwrap(), 0);
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"GET /hello HTTP/1.1\r\n\r\n");
+        assert!(stream.peeked().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replaced_peek_is_read_instead() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = ClientStream::new(server, "127.0.0.1:4000".parse().unwrap());
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\nbody").await.unwrap();
+        client.shutdown().await.unwrap();
+        while stream.peek_more(64).await.unwrap() > 0 {}
+
+        stream.replace_peeked(18, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"GET / HTTP/1.1\r\nConnection: close\r\n\r\nbody");
+    }
+}
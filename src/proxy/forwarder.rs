@@ -1,91 +1,560 @@
 use anyhow::Result;
-use tokio::io::{AsyncRead, AsyncWrite};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
-use tokio::time::timeout;
-use tracing::{debug, error, trace};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::time::{sleep_until, timeout, Instant};
+use socket2::{SockRef, TcpKeepalive};
+use thiserror::Error;
+use tracing::{debug, error, info, trace, warn};
 
-use crate::common::{ConnectionInfo, PqSecureError};
+use crate::common::{split_host_port, CloseReason, ConnectionInfo, PqSecureError, UNIX_SOCKET_PREFIX};
+use crate::config::{BackendConfig, KeepaliveConfig};
+use crate::proxy::mirror::{MirrorTap, TrafficMirror};
+use crate::proxy::outlier::OutlierDetector;
 use crate::telemetry;
 use std::time::Duration;
 
+/// Buffer size for each forwarding direction
+const COPY_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Report transferred bytes at least this often on long-lived connections
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Report transferred bytes early once this many are unreported
+const PROGRESS_BYTES: u64 = 1024 * 1024;
+
+/// Connection to a backend over TCP or a Unix domain socket
+#[derive(Debug)]
+pub enum BackendStream {
+    /// TCP connection
+    Tcp(TcpStream),
+    /// Unix domain socket connection
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AsyncRead for BackendStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            BackendStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BackendStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            BackendStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            BackendStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            BackendStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            BackendStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Direction of data flow through the proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the client towards the backend
+    ClientToBackend,
+    /// From the backend towards the client
+    BackendToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ClientToBackend => f.write_str("client to backend"),
+            Direction::BackendToClient => f.write_str("backend to client"),
+        }
+    }
+}
+
+/// Failure while copying one direction of a connection
+#[derive(Debug, Error)]
+pub enum PumpError {
+    /// Reading from the source failed
+    #[error("{direction}: read failed after {copied} bytes: {source}")]
+    Read {
+        direction: Direction,
+        copied: u64,
+        source: io::Error,
+    },
+
+    /// Writing to the destination failed; the chunk being written may be partially sent
+    #[error("{direction}: write failed after {copied} bytes: {source}")]
+    Write {
+        direction: Direction,
+        copied: u64,
+        source: io::Error,
+    },
+}
+
+impl PumpError {
+    /// Direction that failed
+    pub fn direction(&self) -> Direction {
+        match self {
+            PumpError::Read { direction, .. } | PumpError::Write { direction, .. } => *direction,
+        }
+    }
+
+    /// Whether the failing side is the backend rather than the client
+    pub fn is_upstream(&self) -> bool {
+        matches!(
+            self,
+            PumpError::Read { direction: Direction::BackendToClient, .. }
+                | PumpError::Write { direction: Direction::ClientToBackend, .. }
+        )
+    }
+}
+
+/// Copy from `reader` to `writer` until EOF, then half-close the writer
+///
+/// `on_chunk` is called with every chunk once it is fully written. Returns
+/// the number of bytes copied.
+pub async fn pump<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    direction: Direction,
+    buf_size: usize,
+    mut on_chunk: F,
+) -> Result<u64, PumpError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: FnMut(&[u8]),
+{
+    let mut buf = vec![0u8; buf_size.max(1)];
+    let mut copied = 0u64;
+    let write_error = |copied, source| PumpError::Write { direction, copied, source };
+
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .await
+            .map_err(|source| PumpError::Read { direction, copied, source })?;
+        if n == 0 {
+            writer.shutdown().await.map_err(|e| write_error(copied, e))?;
+            return Ok(copied);
+        }
+
+        // write_all turns a zero-length write into a WriteZero error
+        writer.write_all(&buf[..n]).await.map_err(|e| write_error(copied, e))?;
+        writer.flush().await.map_err(|e| write_error(copied, e))?;
+        copied += n as u64;
+        on_chunk(&buf[..n]);
+    }
+}
+
 /// Bidirectional data forwarder
 pub struct Forwarder {
-    /// Connection timeout in seconds
-    timeout_seconds: u64,
+    /// Maximum time to establish the backend connection
+    connect_timeout: Duration,
+
+    /// Close the connection after no data flows in either direction for this long
+    idle_timeout: Duration,
+
+    /// TCP keepalive applied to backend connections
+    keepalive: Option<KeepaliveConfig>,
+
+    /// Close the connection after this long regardless of activity
+    max_duration: Option<Duration>,
+
+    /// Resolves TCP backend addresses for each new connection
+    resolver: Arc<dyn UpstreamResolver>,
+
+    /// Shadow upstream receiving a copy of client traffic
+    mirror: Option<Arc<TrafficMirror>>,
+
+    /// Skips resolved backend addresses that keep failing
+    outlier: Option<Arc<OutlierDetector>>,
+
+    /// End the whole connection once the backend closes, without waiting for the client
+    close_on_upstream_eof: bool,
+}
+
+/// Resolves a backend `host:port` to socket addresses
+///
+/// The forwarder asks the resolver on every new backend connection, so a
+/// changed DNS record (for example a rescheduled headless service) takes
+/// effect without restarting the proxy.
+#[async_trait::async_trait]
+pub trait UpstreamResolver: Send + Sync {
+    /// Resolve the address, returning candidates in preference order
+    async fn resolve(&self, backend_addr: &str) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolver using the system's DNS configuration
+pub struct DnsResolver;
+
+#[async_trait::async_trait]
+impl UpstreamResolver for DnsResolver {
+    async fn resolve(&self, backend_addr: &str) -> io::Result<Vec<SocketAddr>> {
+        // Resolving the split host lets the system resolver handle IPv6 zones
+        let (host, port) =
+            split_host_port(backend_addr).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        let addrs = tokio::net::lookup_host((host.as_str(), port)).await?;
+        Ok(addrs.collect())
+    }
+}
+
+/// Enable TCP keepalive probes on a socket, or disable them if configured off
+pub fn set_keepalive(stream: &TcpStream, config: &KeepaliveConfig) -> io::Result<()> {
+    let socket = SockRef::from(stream);
+    if !config.enabled {
+        return socket.set_keepalive(false);
+    }
+
+    let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(config.idle_seconds));
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+    let keepalive = keepalive
+        .with_interval(Duration::from_secs(config.interval_seconds))
+        .with_retries(config.retries);
+
+    socket.set_tcp_keepalive(&keepalive)
+}
+
+/// Traffic counters shared by both forwarding directions
+struct Activity {
+    /// When forwarding started
+    started: Instant,
+
+    /// Milliseconds since `started` at which data last flowed
+    last_ms: AtomicU64,
+
+    /// Bytes copied from the client to the backend
+    from_client: AtomicU64,
+
+    /// Bytes copied from the backend to the client
+    from_backend: AtomicU64,
+
+    /// Bytes from the client already reported to telemetry
+    reported_client: AtomicU64,
+
+    /// Bytes from the backend already reported to telemetry
+    reported_backend: AtomicU64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_ms: AtomicU64::new(0),
+            from_client: AtomicU64::new(0),
+            from_backend: AtomicU64::new(0),
+            reported_client: AtomicU64::new(0),
+            reported_backend: AtomicU64::new(0),
+        }
+    }
+
+    /// Count copied bytes, reporting early once enough are unreported
+    fn record(&self, counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+        self.touch();
+
+        let unreported = self.from_client.load(Ordering::Relaxed) + self.from_backend.load(Ordering::Relaxed)
+            - self.reported_client.load(Ordering::Relaxed)
+            - self.reported_backend.load(Ordering::Relaxed);
+        if unreported >= PROGRESS_BYTES {
+            self.report_progress();
+        }
+    }
+
+    /// Report the bytes copied since the last report, per direction
+    fn report_progress(&self) {
+        let from_client = self.from_client.load(Ordering::Relaxed);
+        let from_backend = self.from_backend.load(Ordering::Relaxed);
+        let client_delta = from_client - self.reported_client.swap(from_client, Ordering::Relaxed);
+        let backend_delta = from_backend - self.reported_backend.swap(from_backend, Ordering::Relaxed);
+
+        if client_delta > 0 || backend_delta > 0 {
+            telemetry::record_data_transfer(client_delta as usize, backend_delta as usize);
+        }
+    }
+
+    /// Note that data just flowed
+    fn touch(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// When data last flowed
+    fn last(&self) -> Instant {
+        self.started + Duration::from_millis(self.last_ms.load(Ordering::Relaxed))
+    }
 }
 
 impl Forwarder {
     /// Create a new forwarder
-    pub fn new(timeout_seconds: u64) -> Self {
-        Self { timeout_seconds }
+    pub fn new(connect_timeout: Duration, idle_timeout: Duration) -> Self {
+        Self {
+            connect_timeout,
+            idle_timeout,
+            keepalive: None,
+            max_duration: None,
+            resolver: Arc::new(DnsResolver),
+            mirror: None,
+            outlier: None,
+            close_on_upstream_eof: false,
+        }
+    }
+
+    /// Create a forwarder using the backend's timeouts and keepalive settings
+    pub fn from_config(backend_config: &BackendConfig) -> Self {
+        Self::new(backend_config.connect_timeout(), backend_config.forward_idle_timeout())
+            .with_keepalive(backend_config.keepalive.clone())
+            .with_max_duration(backend_config.max_connection_duration())
+            .with_mirror(backend_config.mirror.as_ref().map(TrafficMirror::new))
+            .with_outlier_detection(backend_config.outlier_detection.as_ref().map(OutlierDetector::new))
+    }
+
+    /// Enable TCP keepalive on backend connections
+    pub fn with_keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Use a custom resolver for TCP backend addresses
+    pub fn with_resolver(mut self, resolver: Arc<dyn UpstreamResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Limit how long a forwarded connection may stay open
+    pub fn with_max_duration(mut self, max_duration: Option<Duration>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Copy client traffic to a shadow upstream
+    pub fn with_mirror(mut self, mirror: Option<TrafficMirror>) -> Self {
+        self.mirror = mirror.map(Arc::new);
+        self
+    }
+
+    /// Eject resolved backend addresses that keep failing to connect
+    pub fn with_outlier_detection(mut self, outlier: Option<OutlierDetector>) -> Self {
+        self.outlier = outlier.map(Arc::new);
+        self
+    }
+
+    /// Close the client connection as soon as the backend closes its side
+    ///
+    /// By default the client may keep sending after the backend's EOF, as a
+    /// half-closed TCP connection allows.
+    pub fn with_close_on_upstream_eof(mut self, close_on_upstream_eof: bool) -> Self {
+        self.close_on_upstream_eof = close_on_upstream_eof;
+        self
     }
 
     /// Forward data between client and backend
-    pub async fn forward<C, B>(&self, mut client: C, mut backend: B, connection_info: &ConnectionInfo) -> Result<()>
+    ///
+    /// Returns why the connection ended; the close is also recorded in telemetry.
+    pub async fn forward<C, B>(&self, client: C, backend: B, connection_info: &ConnectionInfo) -> Result<CloseReason>
     where
         C: AsyncRead + AsyncWrite + Unpin,
         B: AsyncRead + AsyncWrite + Unpin,
     {
-        let timeout_duration = Duration::from_secs(self.timeout_seconds);
+        let source = connection_info.source_addr.to_string();
+        let activity = Activity::new();
+        let mirror = self
+            .mirror
+            .as_ref()
+            .and_then(|mirror| mirror.start(self.resolver.clone(), self.connect_timeout));
 
-        // Use tokio's built-in bidirectional copy
         debug!(
             "Starting bidirectional forwarding for {} ({})",
             connection_info.id, connection_info.source_addr
         );
 
-        match timeout(
-            timeout_duration,
-            tokio::io::copy_bidirectional(&mut client, &mut backend)
-        ).await {
-            Ok(Ok((from_client, from_backend))) => {
+        let result = tokio::select! {
+            result = Self::relay(client, backend, &activity, mirror, self.close_on_upstream_eof) => result,
+            _ = Self::idle_watchdog(&activity, self.idle_timeout) => {
+                debug!(
+                    "Idle timeout for {} ({}) after {:?} without traffic",
+                    connection_info.id, connection_info.source_addr, self.idle_timeout
+                );
+                Ok(CloseReason::IdleTimeout)
+            }
+            _ = Self::max_duration_elapsed(self.max_duration) => {
+                info!(
+                    "Closing {} ({}) after reaching the maximum connection duration of {:?}",
+                    connection_info.id, connection_info.source_addr, self.max_duration.unwrap_or_default()
+                );
+                Ok(CloseReason::MaxDuration)
+            }
+            _ = Self::progress_reporter(&activity) => unreachable!("the progress reporter never finishes"),
+        };
+
+        // Report whatever the periodic reports have not covered yet
+        activity.report_progress();
+
+        let from_client = activity.from_client.load(Ordering::Relaxed);
+        let from_backend = activity.from_backend.load(Ordering::Relaxed);
+
+        match result {
+            Ok(reason) => {
                 debug!(
                     "Bidirectional forwarding completed for {} ({}): {} bytes from client, {} bytes from backend",
                     connection_info.id, connection_info.source_addr, from_client, from_backend
                 );
 
-                telemetry::record_data_transfer(from_client as usize, from_backend as usize);
-                Ok(())
+                telemetry::record_connection_closed(&connection_info.id, &source, reason, from_client, from_backend);
+                Ok(reason)
             }
-            Ok(Err(e)) => {
+            Err(e) => {
                 error!(
                     "Bidirectional forwarding error for {} ({}): {}",
                     connection_info.id, connection_info.source_addr, e
                 );
-                Err(PqSecureError::ConnectionError(e.to_string()).into())
+                let failure = if e.is_upstream() {
+                    PqSecureError::UpstreamReset(e.to_string())
+                } else {
+                    PqSecureError::ConnectionError(e.to_string())
+                };
+                telemetry::record_failed_request(&source, &failure);
+                telemetry::record_connection_closed(&connection_info.id, &source, CloseReason::Error, from_client, from_backend);
+                Err(failure.into())
             }
-            Err(_) => {
-                error!(
-                    "Bidirectional forwarding timeout for {} ({})",
-                    connection_info.id, connection_info.source_addr
-                );
-                Err(PqSecureError::ConnectionError("Connection timed out".to_string()).into())
+        }
+    }
+
+    /// Copy both directions until both sides are done, noting which side closed first
+    ///
+    /// With `close_on_upstream_eof`, the backend closing first ends both directions.
+    async fn relay<C, B>(
+        client: C,
+        backend: B,
+        activity: &Activity,
+        mut mirror: Option<MirrorTap>,
+        close_on_upstream_eof: bool,
+    ) -> Result<CloseReason, PumpError>
+    where
+        C: AsyncRead + AsyncWrite + Unpin,
+        B: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (mut client_read, mut client_write) = tokio::io::split(client);
+        let (mut backend_read, mut backend_write) = tokio::io::split(backend);
+
+        let client_to_backend = pump(
+            &mut client_read,
+            &mut backend_write,
+            Direction::ClientToBackend,
+            COPY_BUFFER_SIZE,
+            |chunk| {
+                activity.record(&activity.from_client, chunk.len());
+                if let Some(mirror) = mirror.as_mut() {
+                    mirror.send(chunk);
+                }
+            },
+        );
+        let backend_to_client = pump(
+            &mut backend_read,
+            &mut client_write,
+            Direction::BackendToClient,
+            COPY_BUFFER_SIZE,
+            |chunk| activity.record(&activity.from_backend, chunk.len()),
+        );
+        tokio::pin!(client_to_backend, backend_to_client);
+
+        tokio::select! {
+            result = &mut client_to_backend => {
+                result?;
+                backend_to_client.await?;
+                Ok(CloseReason::ClientEof)
+            }
+            result = &mut backend_to_client => {
+                result?;
+                if !close_on_upstream_eof {
+                    client_to_backend.await?;
+                }
+                Ok(CloseReason::UpstreamEof)
             }
         }
     }
 
+    /// Report transferred bytes periodically so long-lived connections show up
+    /// in byte counters before they close
+    async fn progress_reporter(activity: &Activity) {
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            activity.report_progress();
+        }
+    }
+
+    /// Resolve once the maximum connection duration has passed, or never without one
+    async fn max_duration_elapsed(max_duration: Option<Duration>) {
+        match max_duration {
+            Some(max_duration) => tokio::time::sleep(max_duration).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Resolve once no data has flowed for the idle timeout
+    async fn idle_watchdog(activity: &Activity, idle_timeout: Duration) {
+        loop {
+            let deadline = activity.last() + idle_timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            sleep_until(deadline).await;
+        }
+    }
+
     /// Connect to backend
-    pub async fn connect_to_backend(&self, backend_addr: &str) -> Result<TcpStream> {
+    ///
+    /// Addresses of the form `unix:/path/to.sock` connect to a Unix domain socket.
+    pub async fn connect_to_backend(&self, backend_addr: &str) -> Result<BackendStream> {
         trace!("Connecting to backend: {}", backend_addr);
 
-        // Set a timeout for the connection attempt
-        match timeout(
-            Duration::from_secs(self.timeout_seconds),
-            TcpStream::connect(backend_addr)
-        ).await {
+        // Bound the connect phase separately so a dead backend fails fast
+        let connect = open_backend(self.resolver.as_ref(), backend_addr, self.outlier.as_deref());
+        match timeout(self.connect_timeout, connect).await {
             Ok(Ok(stream)) => {
                 debug!("Connected to backend: {}", backend_addr);
+                if let (BackendStream::Tcp(tcp), Some(keepalive)) = (&stream, &self.keepalive) {
+                    if let Err(e) = set_keepalive(tcp, keepalive) {
+                        warn!("Failed to set keepalive for backend {}: {}", backend_addr, e);
+                    }
+                }
                 Ok(stream)
             }
             Ok(Err(e)) => {
                 error!("Failed to connect to backend {}: {}", backend_addr, e);
-                Err(PqSecureError::ConnectionError(format!(
+                Err(PqSecureError::UpstreamUnreachable(format!(
                     "Failed to connect to backend {}: {}", backend_addr, e
                 )).into())
             }
             Err(_) => {
                 error!("Timeout connecting to backend: {}", backend_addr);
-                Err(PqSecureError::ConnectionError(format!(
+                Err(PqSecureError::UpstreamTimeout(format!(
                     "Timeout connecting to backend: {}", backend_addr
                 )).into())
             }
@@ -93,6 +562,62 @@ impl Forwarder {
     }
 }
 
+/// Open a connection to a backend address without a timeout
+///
+/// Addresses of the form `unix:/path/to.sock` connect to a Unix domain socket;
+/// TCP addresses are resolved and each result is tried in order, skipping
+/// those the outlier detector has ejected.
+pub(crate) async fn open_backend(
+    resolver: &dyn UpstreamResolver,
+    backend_addr: &str,
+    outlier: Option<&OutlierDetector>,
+) -> io::Result<BackendStream> {
+    match backend_addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+        #[cfg(unix)]
+        Some(path) => UnixStream::connect(path).await.map(BackendStream::Unix),
+        #[cfg(not(unix))]
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain socket backends are not supported on this platform",
+        )),
+        None => open_tcp(resolver, backend_addr, outlier).await.map(BackendStream::Tcp),
+    }
+}
+
+/// Resolve the address and try each result in order until one connects
+async fn open_tcp(
+    resolver: &dyn UpstreamResolver,
+    backend_addr: &str,
+    outlier: Option<&OutlierDetector>,
+) -> io::Result<TcpStream> {
+    let mut addrs = resolver.resolve(backend_addr).await?;
+    if let Some(outlier) = outlier {
+        addrs = outlier.admit(addrs, std::time::Instant::now());
+    }
+
+    let mut last_error = None;
+    for addr in addrs {
+        trace!("Trying backend address {} for {}", addr, backend_addr);
+        let attempt = outlier.map(|outlier| outlier.attempt(addr));
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                if let Some(attempt) = attempt {
+                    attempt.succeeded();
+                }
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to any address", backend_addr),
+        )
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +626,7 @@ mod tests {
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::TcpListener;
     use crate::common::ProtocolType;
+    use crate::test_support::CapturedLogs;
 
     // Custom reader/writer for testing
     struct TestStream {
@@ -179,7 +705,7 @@ mod tests {
     #[tokio::test]
     async fn test_bidirectional_copy() {
         // Create a forwarder
-        let forwarder = Forwarder::new(5);
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
 
         // Create test streams
         let client_data = b"Hello from client!".to_vec();
@@ -219,7 +745,7 @@ mod tests {
         });
 
         // Create a forwarder
-        let forwarder = Forwarder::new(5);
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
 
         // Connect to backend
         let result = forwarder.connect_to_backend(&server_addr).await;
@@ -234,4 +760,588 @@ mod tests {
 
         assert_eq!(&buf[..n], b"Hello from test server!");
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_connect_to_unix_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("backend.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.write_all(b"Hello from unix backend!").await.unwrap();
+        });
+
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
+        let mut stream = forwarder
+            .connect_to_backend(&format!("unix:{}", socket_path.display()))
+            .await
+            .unwrap();
+        assert!(matches!(stream, BackendStream::Unix(_)));
+
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"Hello from unix backend!");
+    }
+
+    #[tokio::test]
+    async fn test_forward_reports_which_side_closed() {
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
+        let conn_info = ConnectionInfo::new(
+            "127.0.0.1:12345".parse::<SocketAddr>().unwrap(),
+            ProtocolType::Tcp,
+        );
+
+        // Client sends a request and closes; backend replies after seeing EOF
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            client_peer.write_all(b"ping").await.unwrap();
+            client_peer.shutdown().await.unwrap();
+            let mut reply = Vec::new();
+            client_peer.read_to_end(&mut reply).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            backend_peer.read_to_end(&mut request).await.unwrap();
+            backend_peer.write_all(b"pong").await.unwrap();
+        });
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::ClientEof);
+
+        // Backend closes while the client is still connected
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            backend_peer.write_all(b"bye").await.unwrap();
+        });
+        tokio::spawn(async move {
+            let mut reply = Vec::new();
+            client_peer.read_to_end(&mut reply).await.unwrap();
+            client_peer.shutdown().await.unwrap();
+        });
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::UpstreamEof);
+    }
+
+    /// Forwarder mirroring the given fraction of connections to `mirror_addr`
+    fn mirroring_forwarder(mirror_addr: String, sample_rate: f64) -> Forwarder {
+        Forwarder::new(Duration::from_secs(5), Duration::from_secs(5)).with_mirror(Some(TrafficMirror::new(
+            &crate::config::MirrorConfig { address: mirror_addr, sample_rate },
+        )))
+    }
+
+    /// Relay `request` through a forwarder mirroring to `mirror_addr`,
+    /// returning what the client got back
+    async fn forward_mirrored(mirror_addr: String, request: Vec<u8>) -> Vec<u8> {
+        forward_through(&mirroring_forwarder(mirror_addr, 1.0), request).await
+    }
+
+    /// Relay `request` through `forwarder`, returning what the client got back
+    async fn forward_through(forwarder: &Forwarder, request: Vec<u8>) -> Vec<u8> {
+        let conn_info = ConnectionInfo::new("127.0.0.1:12345".parse::<SocketAddr>().unwrap(), ProtocolType::Http);
+
+        let (client, mut client_peer) = tokio::io::duplex(64 * 1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(64 * 1024);
+        let client_task = tokio::spawn(async move {
+            client_peer.write_all(&request).await.unwrap();
+            client_peer.shutdown().await.unwrap();
+            let mut reply = Vec::new();
+            client_peer.read_to_end(&mut reply).await.unwrap();
+            reply
+        });
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            backend_peer.read_to_end(&mut request).await.unwrap();
+            backend_peer.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+        });
+
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::ClientEof);
+        client_task.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mirror_receives_copy_of_client_traffic() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror_addr = listener.local_addr().unwrap().to_string();
+        let mirrored = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            socket.read_to_end(&mut request).await.unwrap();
+            // The mirror's response goes nowhere
+            socket.write_all(b"HTTP/1.1 500 Internal Server Error\r\n\r\n").await.unwrap();
+            request
+        });
+
+        let reply = forward_mirrored(mirror_addr, b"GET /health HTTP/1.1\r\n\r\n".to_vec()).await;
+        assert_eq!(reply, b"HTTP/1.1 200 OK\r\n\r\n");
+        assert_eq!(mirrored.await.unwrap(), b"GET /health HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_mirror_receives_sampled_fraction() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror_addr = listener.local_addr().unwrap().to_string();
+        let (mirrored_tx, mut mirrored_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mirrored_tx = mirrored_tx.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    socket.read_to_end(&mut request).await.unwrap();
+                    let _ = mirrored_tx.send(request);
+                });
+            }
+        });
+
+        // Every client is answered by the primary; half of them are mirrored
+        let forwarder = mirroring_forwarder(mirror_addr, 0.5);
+        for path in ["/1", "/2", "/3", "/4"] {
+            let request = format!("GET {} HTTP/1.1\r\n\r\n", path).into_bytes();
+            assert_eq!(forward_through(&forwarder, request).await, b"HTTP/1.1 200 OK\r\n\r\n");
+        }
+
+        let mut mirrored = Vec::new();
+        for _ in 0..2 {
+            let request = timeout(Duration::from_secs(5), mirrored_rx.recv()).await.unwrap().unwrap();
+            mirrored.push(String::from_utf8(request).unwrap());
+        }
+        mirrored.sort();
+        assert_eq!(mirrored, vec!["GET /2 HTTP/1.1\r\n\r\n", "GET /4 HTTP/1.1\r\n\r\n"]);
+        assert!(timeout(Duration::from_millis(200), mirrored_rx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_mirror_does_not_affect_primary() {
+        // Nothing listens on a port whose listener was dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let reply = forward_mirrored(refused, b"GET / HTTP/1.1\r\n\r\n".to_vec()).await;
+        assert_eq!(reply, b"HTTP/1.1 200 OK\r\n\r\n");
+
+        // A mirror that never reads falls behind and is abandoned instead of
+        // stalling the client
+        let stalled = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stalled_addr = stalled.local_addr().unwrap().to_string();
+        let request = vec![b'x'; 32 * 1024 * 1024];
+        let reply = timeout(Duration::from_secs(10), forward_mirrored(stalled_addr, request))
+            .await
+            .unwrap();
+        assert_eq!(reply, b"HTTP/1.1 200 OK\r\n\r\n");
+        drop(stalled);
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_is_independent() {
+        let forwarder = Forwarder::new(Duration::from_millis(200), Duration::from_secs(60))
+            .with_resolver(Arc::new(HangingResolver));
+        let started = std::time::Instant::now();
+        let err = forwarder.connect_to_backend("backend.example.org:8080").await.unwrap_err();
+
+        assert!(err.to_string().contains("Timeout connecting to backend"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(error_type(&err), "upstream_timeout");
+    }
+
+    /// Resolver that never answers, so connects hang like a black-holed address
+    struct HangingResolver;
+
+    #[async_trait::async_trait]
+    impl UpstreamResolver for HangingResolver {
+        async fn resolve(&self, _backend_addr: &str) -> std::io::Result<Vec<SocketAddr>> {
+            std::future::pending().await
+        }
+    }
+
+    /// Failed-request label recorded for an error
+    fn error_type(err: &anyhow::Error) -> &'static str {
+        err.downcast_ref::<PqSecureError>().unwrap().code()
+    }
+
+    #[tokio::test]
+    async fn test_refused_connect_is_unreachable() {
+        // Bind then drop to find a port nobody listens on
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
+        let err = forwarder.connect_to_backend(&addr.to_string()).await.unwrap_err();
+        assert_eq!(error_type(&err), "upstream_unreachable");
+    }
+
+    #[tokio::test]
+    async fn test_backend_reset_mid_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.write_all(b"partial").await.unwrap();
+            // Closing with a zero linger sends RST instead of FIN
+            socket.set_linger(Some(Duration::ZERO)).unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        });
+
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
+        let backend = forwarder.connect_to_backend(&addr.to_string()).await.unwrap();
+        let conn_info = ConnectionInfo::new(
+            "127.0.0.1:12345".parse::<SocketAddr>().unwrap(),
+            ProtocolType::Tcp,
+        );
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut reply = Vec::new();
+            client_peer.read_to_end(&mut reply).await.ok();
+        });
+
+        let err = forwarder.forward(client, backend, &conn_info).await.unwrap_err();
+        assert_eq!(error_type(&err), "upstream_reset");
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_closes_quiet_connection() {
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_millis(150));
+        let conn_info = ConnectionInfo::new(
+            "127.0.0.1:12345".parse::<SocketAddr>().unwrap(),
+            ProtocolType::Tcp,
+        );
+
+        // Nobody sends anything
+        let (client, _client_peer) = tokio::io::duplex(1024);
+        let (backend, _backend_peer) = tokio::io::duplex(1024);
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::IdleTimeout);
+
+        // Traffic that keeps flowing outlives the idle timeout
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            for _ in 0..6 {
+                backend_peer.write_all(b"tick").await.unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+        tokio::spawn(async move {
+            let mut received = Vec::new();
+            client_peer.read_to_end(&mut received).await.unwrap();
+            client_peer.shutdown().await.unwrap();
+        });
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::UpstreamEof);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_backend_keepalive_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        let keepalive = KeepaliveConfig {
+            enabled: true,
+            idle_seconds: 30,
+            interval_seconds: 5,
+            retries: 4,
+        };
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5)).with_keepalive(keepalive);
+        let stream = forwarder.connect_to_backend(&addr).await.unwrap();
+
+        let BackendStream::Tcp(tcp) = &stream else {
+            panic!("expected a TCP backend stream");
+        };
+        let socket = SockRef::from(tcp);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+        assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+        assert_eq!(socket.keepalive_retries().unwrap(), 4);
+
+        set_keepalive(tcp, &KeepaliveConfig { enabled: false, ..KeepaliveConfig::default() }).unwrap();
+        assert!(!socket.keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_max_duration_closes_busy_connection() {
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5))
+            .with_max_duration(Some(Duration::from_millis(200)));
+        let conn_info = ConnectionInfo::new(
+            "127.0.0.1:12345".parse::<SocketAddr>().unwrap(),
+            ProtocolType::Tcp,
+        );
+
+        // Traffic never stops, so only the duration limit can end the connection
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            while backend_peer.write_all(b"tick").await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            while matches!(client_peer.read(&mut buf).await, Ok(n) if n > 0) {}
+        });
+
+        let started = std::time::Instant::now();
+        let reason = forwarder.forward(client, backend, &conn_info).await.unwrap();
+        assert_eq!(reason, CloseReason::MaxDuration);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Resolver returning whatever address the test last set
+    struct StubResolver(std::sync::Mutex<SocketAddr>);
+
+    #[async_trait::async_trait]
+    impl UpstreamResolver for StubResolver {
+        async fn resolve(&self, _backend_addr: &str) -> std::io::Result<Vec<SocketAddr>> {
+            Ok(vec![*self.0.lock().unwrap()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_connections_follow_resolver() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let resolver = Arc::new(StubResolver(std::sync::Mutex::new(first.local_addr().unwrap())));
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5)).with_resolver(resolver.clone());
+
+        let stream = forwarder.connect_to_backend("backend.example:8080").await.unwrap();
+        let BackendStream::Tcp(tcp) = &stream else {
+            panic!("expected a TCP backend stream");
+        };
+        assert_eq!(tcp.peer_addr().unwrap(), first.local_addr().unwrap());
+
+        // The record changes: the next connection goes to the new address
+        *resolver.0.lock().unwrap() = second.local_addr().unwrap();
+        let stream = forwarder.connect_to_backend("backend.example:8080").await.unwrap();
+        let BackendStream::Tcp(tcp) = &stream else {
+            panic!("expected a TCP backend stream");
+        };
+        assert_eq!(tcp.peer_addr().unwrap(), second.local_addr().unwrap());
+    }
+
+    /// Resolver returning a fixed list of addresses
+    struct ListResolver(Vec<SocketAddr>);
+
+    #[async_trait::async_trait]
+    impl UpstreamResolver for ListResolver {
+        async fn resolve(&self, _backend_addr: &str) -> std::io::Result<Vec<SocketAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failing_address_is_ejected() {
+        // Bind and drop a listener for an address that refuses connections
+        let dead = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let resolver = Arc::new(ListResolver(vec![dead, live.local_addr().unwrap()]));
+        let outlier = OutlierDetector::new(&crate::config::OutlierDetectionConfig {
+            consecutive_failures: 2,
+            ejection_duration_seconds: 60,
+            max_ejection_percent: 50,
+        });
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5))
+            .with_resolver(resolver)
+            .with_outlier_detection(Some(outlier));
+
+        for _ in 0..3 {
+            forwarder.connect_to_backend("backend.example:8080").await.unwrap();
+        }
+
+        let outlier = forwarder.outlier.as_ref().unwrap();
+        assert_eq!(outlier.ejected_count(std::time::Instant::now()), 1);
+        assert_eq!(
+            outlier.admit(vec![dead, live.local_addr().unwrap()], std::time::Instant::now()),
+            vec![live.local_addr().unwrap()]
+        );
+    }
+
+    /// Writer accepting a fixed number of bytes before failing
+    struct FailingWriter {
+        remaining: usize,
+    }
+
+    impl AsyncWrite for FailingWriter {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let me = self.get_mut();
+            if me.remaining == 0 {
+                return std::task::Poll::Ready(Err(std::io::Error::new(ErrorKind::BrokenPipe, "peer gone")));
+            }
+            let n = buf.len().min(me.remaining);
+            me.remaining -= n;
+            std::task::Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_resolver_address_forms() {
+        let addrs = DnsResolver.resolve("[::1]:8080").await.unwrap();
+        assert_eq!(addrs, vec!["[::1]:8080".parse::<SocketAddr>().unwrap()]);
+
+        let addrs = DnsResolver.resolve("127.0.0.1:8080").await.unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:8080".parse::<SocketAddr>().unwrap()]);
+
+        let err = DnsResolver.resolve("::1:8080").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_progress_reported_during_large_transfer() {
+        let (logs, _guard) = CapturedLogs::install(tracing::Level::DEBUG);
+
+        const TOTAL: usize = 4 * 1024 * 1024;
+        let forwarder = Forwarder::new(Duration::from_secs(5), Duration::from_secs(5));
+        let conn_info = ConnectionInfo::new(
+            "127.0.0.1:12345".parse::<SocketAddr>().unwrap(),
+            ProtocolType::Tcp,
+        );
+        let (client, mut client_peer) = tokio::io::duplex(64 * 1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            backend_peer.write_all(&vec![7u8; TOTAL]).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let mut received = Vec::new();
+            client_peer.read_to_end(&mut received).await.unwrap();
+            client_peer.shutdown().await.unwrap();
+        });
+        forwarder.forward(client, backend, &conn_info).await.unwrap();
+
+        let output = logs.contents();
+        let reports: Vec<usize> = output
+            .lines()
+            .filter(|line| line.contains("Data transfer"))
+            .filter_map(|line| line.split("bytes_sent=").nth(1)?.split_whitespace().next()?.parse().ok())
+            .collect();
+
+        // Reported in roughly 1 MiB steps rather than once at close
+        assert!(reports.len() >= 4, "{:?}", reports);
+        assert_eq!(reports.iter().sum::<usize>(), TOTAL);
+    }
+
+    #[tokio::test]
+    async fn test_pump_copies_until_eof() {
+        let mut reader = Cursor::new(b"hello world".to_vec());
+        let mut writer = TestStream::new(Vec::new());
+        let mut chunks = Vec::new();
+
+        let copied = pump(&mut reader, &mut writer, Direction::ClientToBackend, 4, |chunk| chunks.push(chunk.len()))
+            .await
+            .unwrap();
+
+        assert_eq!(copied, 11);
+        assert_eq!(chunks, vec![4, 4, 3]);
+        assert_eq!(writer.written_data(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_pump_reports_failed_write_mid_stream() {
+        let mut reader = Cursor::new(vec![7u8; 64]);
+        let mut writer = FailingWriter { remaining: 20 };
+
+        let err = pump(&mut reader, &mut writer, Direction::BackendToClient, 16, |_| {})
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.direction(), Direction::BackendToClient);
+        assert!(matches!(err, PumpError::Write { copied: 16, .. }));
+        assert!(err.to_string().starts_with("backend to client: write failed"));
+    }
+
+    /// Size of the transfer used by the throughput benchmark
+    const BENCH_BYTES: u64 = 256 * 1024 * 1024;
+
+    /// Time a one-way transfer of `BENCH_BYTES` through `copy`, returning MiB/s
+    async fn bench_transfer<F, Fut>(copy: F) -> f64
+    where
+        F: FnOnce(tokio::io::ReadHalf<tokio::io::DuplexStream>, tokio::io::DuplexStream) -> Fut,
+        Fut: std::future::Future<Output = u64>,
+    {
+        let (mut source, source_peer) = tokio::io::duplex(COPY_BUFFER_SIZE * 4);
+        let (sink_peer, mut sink) = tokio::io::duplex(COPY_BUFFER_SIZE * 4);
+        let (reader, _) = tokio::io::split(source_peer);
+
+        let producer = tokio::spawn(async move {
+            let chunk = vec![0x5au8; COPY_BUFFER_SIZE];
+            let mut sent = 0;
+            while sent < BENCH_BYTES {
+                source.write_all(&chunk).await.unwrap();
+                sent += chunk.len() as u64;
+            }
+        });
+        let consumer = tokio::spawn(async move {
+            let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+            let mut received = 0;
+            while received < BENCH_BYTES {
+                received += sink.read(&mut buf).await.unwrap() as u64;
+            }
+        });
+
+        let started = std::time::Instant::now();
+        let copied = copy(reader, sink_peer).await;
+        producer.await.unwrap();
+        consumer.await.unwrap();
+        assert_eq!(copied, BENCH_BYTES);
+        BENCH_BYTES as f64 / (1024.0 * 1024.0) / started.elapsed().as_secs_f64()
+    }
+
+    /// Compare the instrumented pump with the uninstrumented pump it replaced
+    /// and with copy_buf on a large transfer
+    ///
+    /// Run with `cargo test --release bench_pump_throughput -- --ignored --nocapture`.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+    #[ignore = "benchmark"]
+    async fn bench_pump_throughput() {
+        let baseline = bench_transfer(|mut reader, mut writer| async move {
+            let activity = Activity::new();
+            pump(&mut reader, &mut writer, Direction::ClientToBackend, COPY_BUFFER_SIZE, |chunk| {
+                activity.from_client.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                activity.touch();
+            })
+            .await
+            .unwrap()
+        })
+        .await;
+
+        let instrumented = bench_transfer(|mut reader, mut writer| async move {
+            let activity = Activity::new();
+            pump(&mut reader, &mut writer, Direction::ClientToBackend, COPY_BUFFER_SIZE, |chunk| {
+                activity.record(&activity.from_client, chunk.len())
+            })
+            .await
+            .unwrap()
+        })
+        .await;
+
+        let copy_buf = bench_transfer(|reader, mut writer| async move {
+            let mut reader = tokio::io::BufReader::with_capacity(COPY_BUFFER_SIZE, reader);
+            tokio::io::copy_buf(&mut reader, &mut writer).await.unwrap()
+        })
+        .await;
+
+        println!("baseline pump:     {:>8.0} MiB/s", baseline);
+        println!("instrumented pump: {:>8.0} MiB/s", instrumented);
+        println!("copy_buf:          {:>8.0} MiB/s", copy_buf);
+    }
+}
@@ -1,18 +1,24 @@
 use anyhow::Result;
 use std::sync::Arc;
-use tokio::net::TcpStream;
-use tracing::{error, info};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{debug, error, info};
 
-use crate::common::{ConnectionInfo, ProtocolType, PqSecureError, ServiceIdentity};
-use crate::config::BackendConfig;
+use crate::common::{
+    CloseReason, ConnectionInfo, ProtocolType, PqSecureError, RejectionReason, ServiceIdentity, ANONYMOUS_SPIFFE_ID,
+};
+use crate::config::{BackendConfig, MtlsMode};
 use crate::identity::SpiffeVerifier;
 use crate::policy::PolicyEngine;
-use crate::proxy::forwarder::Forwarder;
+use crate::proxy::client_stream::ClientStream;
+use crate::proxy::forwarder::{BackendStream, Forwarder};
+use crate::proxy::pqc_acceptor::get_current_client_cert;
+use crate::proxy::throttle::ThrottledStream;
+use crate::telemetry;
 
 /// Trait for handling client connections
 #[async_trait::async_trait]
 pub trait ConnectionHandler: Send + Sync {
-    async fn handle(&self, stream: TcpStream) -> anyhow::Result<()>;
+    async fn handle(&self, stream: ClientStream) -> anyhow::Result<()>;
 }
 
 /// Trait for default connection handling logic
@@ -22,7 +28,10 @@ pub trait DefaultConnectionHandler: ConnectionHandler {
     fn protocol_name(&self) -> &'static str;
 
     /// Check if this handler should process this connection
-    async fn can_handle(&self, stream: &TcpStream) -> bool;
+    ///
+    /// The acceptor has already read ahead on the stream; handlers decide
+    /// from `stream.peeked()` and must not read from it here.
+    async fn can_handle(&self, stream: &ClientStream) -> bool;
 }
 
 /// Base handler with common functionality for all protocol handlers
@@ -47,7 +56,7 @@ impl BaseHandler {
         policy_engine: Arc<dyn PolicyEngine>,
         spiffe_verifier: Arc<SpiffeVerifier>,
     ) -> Result<Self> {
-        let forwarder = Forwarder::new(backend_config.timeout_seconds);
+        let forwarder = Forwarder::from_config(&backend_config);
 
         Ok(Self {
             backend_config,
@@ -62,27 +71,109 @@ impl BaseHandler {
         self.spiffe_verifier.extract_spiffe_id(cert)
     }
 
+    /// Identify the client of a connection
+    ///
+    /// Plaintext clients, only accepted during the bootstrap window, are
+    /// anonymous whatever the mTLS mode. Otherwise the client is identified
+    /// from the certificate of the current connection.
+    pub fn stream_identity(&self, stream: &ClientStream) -> Result<ServiceIdentity> {
+        if stream.is_plaintext() {
+            tracing::Span::current().record("spiffe_id", ANONYMOUS_SPIFFE_ID);
+            return Ok(ServiceIdentity::anonymous());
+        }
+        self.client_identity(get_current_client_cert().as_ref())
+    }
+
+    /// Identify the client from its certificate, recording why it was rejected otherwise
+    ///
+    /// Without a certificate the client is anonymous if mTLS is optional or
+    /// disabled, and rejected otherwise. With mTLS disabled no certificate is
+    /// requested, so every client is anonymous.
+    pub fn client_identity(&self, cert: Option<&rustls::pki_types::CertificateDer<'_>>) -> Result<ServiceIdentity> {
+        let mtls_mode = self.spiffe_verifier.mtls_mode();
+        let identity = match cert {
+            Some(cert) if mtls_mode != MtlsMode::Disabled => self.extract_spiffe_id(cert).map_err(|e| {
+                telemetry::record_rejected(RejectionReason::InvalidSpiffeId);
+                e.context("Failed to extract SPIFFE ID from certificate")
+            })?,
+            None if mtls_mode == MtlsMode::Required => {
+                telemetry::record_rejected(RejectionReason::NoClientCert);
+                return Err(PqSecureError::AuthenticationError("No client certificate found".to_string()).into());
+            }
+            _ => {
+                debug!("Serving client without a certificate as {}", ANONYMOUS_SPIFFE_ID);
+                ServiceIdentity::anonymous()
+            }
+        };
+
+        // Tag the rest of the connection's logs with the client's identity
+        tracing::Span::current().record("spiffe_id", tracing::field::display(&identity.spiffe_id));
+        Ok(identity)
+    }
+
     /// Connect to backend and forward data
+    ///
+    /// Callers run this inside the acceptor's client span, which tags its
+    /// logs with the connection ID.
     pub async fn connect_and_forward(
         &self, 
-        client_stream: TcpStream, 
+        client_stream: ClientStream,
         connection_info: &ConnectionInfo,
         spiffe_id: &str, 
         method: &str,
         allowed: bool
     ) -> Result<()> {
+        self.connect_and_forward_with(client_stream, connection_info, spiffe_id, method, allowed, |backend| backend)
+            .await
+    }
+
+    /// Connect to backend and forward data, reading the backend through
+    /// `wrap_backend`, for handlers that rewrite what the backend sends
+    ///
+    /// Logs are tagged with the connection ID by the acceptor's client span.
+    pub async fn connect_and_forward_with<W, B>(
+        &self,
+        client_stream: ClientStream,
+        connection_info: &ConnectionInfo,
+        spiffe_id: &str,
+        method: &str,
+        allowed: bool,
+        wrap_backend: W,
+    ) -> Result<()>
+    where
+        W: FnOnce(BackendStream) -> B,
+        B: AsyncRead + AsyncWrite + Unpin,
+    {
         if !allowed {
             error!(
                 "Connection denied by policy: {} -> {} (method: {})",
                 spiffe_id, self.backend_config.address, method
             );
+            telemetry::record_rejected(RejectionReason::PolicyDeny);
+            telemetry::record_connection_closed(
+                &connection_info.id,
+                &connection_info.source_addr.to_string(),
+                CloseReason::PolicyDeny,
+                0,
+                0,
+            );
             return Err(PqSecureError::AuthorizationError(
                 format!("{:?} request denied by policy", connection_info.protocol_type)
             ).into());
         }
 
         // Connect to backend
-        let backend_stream = self.forwarder.connect_to_backend(&self.backend_config.address).await?;
+        let backend_stream = match self.forwarder.connect_to_backend(&self.backend_config.address).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                let source = connection_info.source_addr.to_string();
+                if let Some(failure) = e.downcast_ref::<PqSecureError>() {
+                    telemetry::record_failed_request(&source, failure);
+                }
+                telemetry::record_connection_closed(&connection_info.id, &source, CloseReason::Error, 0, 0);
+                return Err(e);
+            }
+        };
 
         // Get client address for logging
         let client_addr = connection_info.source_addr.to_string();
@@ -109,6 +200,143 @@ impl BaseHandler {
             },
         }
 
-        self.forwarder.forward(client_stream, backend_stream, connection_info).await
+        // Both directions of the copy go through the client side, so pacing
+        // it caps the connection whatever the copy loop
+        let mut client_stream = ThrottledStream::new(client_stream, self.backend_config.max_bytes_per_second);
+        let result = self
+            .forwarder
+            .forward(&mut client_stream, wrap_backend(backend_stream), connection_info)
+            .await;
+        telemetry::record_connection_bytes(
+            &connection_info.id,
+            client_stream.bytes_read(),
+            client_stream.bytes_written(),
+        );
+        result?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{deny_all_handler, self_signed_cert, self_signed_svid, spiffe_params, CapturedLogs};
+    use tokio::net::{TcpListener, TcpStream};
+    use tracing::{info_span, Instrument};
+
+    #[tokio::test]
+    async fn test_connection_id_in_logs() {
+        let (logs, _guard) = CapturedLogs::install(tracing::Level::INFO);
+        let handler = deny_all_handler(MtlsMode::Required);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let connection_info = ConnectionInfo::new(client.local_addr().unwrap(), ProtocolType::Tcp);
+        let span = info_span!("client", id = %connection_info.id);
+
+        let result = handler
+            .connect_and_forward(ClientStream::from_tcp(client).unwrap(), &connection_info, "spiffe://example.org/a", "TCP", false)
+            .instrument(span)
+            .await;
+        assert!(result.is_err());
+
+        // The acceptor's span carries the ID; the handler adds no second copy
+        let output = logs.contents();
+        let denied = output.lines().find(|line| line.contains("Connection denied by policy")).unwrap();
+        assert!(denied.contains(&format!("client{{id={}}}", connection_info.id)));
+        assert_eq!(denied.matches(connection_info.id.as_str()).count(), 1);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_client_span_tagged_with_identity() {
+        let (logs, _guard) = CapturedLogs::install(tracing::Level::INFO);
+        let handler = deny_all_handler(MtlsMode::Disabled);
+
+        // The acceptor's span leaves the SPIFFE ID empty until the client is identified
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let client_stream = ClientStream::from_tcp(client).unwrap();
+        let connection_info = ConnectionInfo::new(client_stream.peer_addr(), ProtocolType::Tcp)
+            .with_id(client_stream.connection_id());
+        let span = info_span!(
+            "client",
+            id = %client_stream.connection_id(),
+            spiffe_id = tracing::field::Empty
+        );
+
+        let result = async {
+            let identity = handler.client_identity(None).unwrap();
+            handler
+                .connect_and_forward(client_stream, &connection_info, &identity.spiffe_id, "TCP", false)
+                .await
+        }
+        .instrument(span)
+        .await;
+        assert!(result.is_err());
+
+        let output = logs.contents();
+        let closed = output.lines().find(|line| line.contains("Connection closed")).unwrap();
+        assert!(closed.contains(&format!("client{{id={} spiffe_id={}}}", connection_info.id, ANONYMOUS_SPIFFE_ID)));
+        assert!(closed.contains(&format!("connection_id={}", connection_info.id)));
+    }
+
+    #[test]
+    fn test_rejection_reasons_are_labelled() {
+        let (logs, _guard) = CapturedLogs::install(tracing::Level::INFO);
+        let handler = deny_all_handler(MtlsMode::Required);
+
+        assert!(handler.client_identity(None).is_err());
+        let (cert, _) = self_signed_cert();
+        assert!(handler.client_identity(Some(cert.der())).is_err());
+
+        let output = logs.contents();
+        let labels: Vec<&str> = output
+            .lines()
+            .filter(|line| line.contains("pqsm_rejected_total"))
+            .filter_map(|line| line.split("reason=").nth(1)?.split_whitespace().next())
+            .collect();
+        assert_eq!(labels, vec!["no_client_cert", "invalid_spiffe_id"]);
+    }
+
+    #[test]
+    fn test_identity_from_large_certificate() {
+        let handler = deny_all_handler(MtlsMode::Required);
+
+        // Well past a single 64-column PEM line, as production SVIDs are
+        let mut params = spiffe_params("spiffe://example.org/service/orders");
+        for i in 0..40 {
+            params.subject_alt_names.push(rcgen::SanType::DnsName(
+                rcgen::Ia5String::try_from(format!("orders-{}.prod.svc.cluster.local", i)).unwrap(),
+            ));
+        }
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+        assert!(cert.der().len() > 1024);
+
+        let identity = handler.client_identity(Some(cert.der())).unwrap();
+        assert_eq!(identity.spiffe_id, "spiffe://example.org/service/orders");
+    }
+
+    #[test]
+    fn test_client_identity_by_mtls_mode() {
+        let (cert, _) = self_signed_svid("spiffe://example.org/service/orders");
+
+        let handler = deny_all_handler(MtlsMode::Required);
+        assert!(handler.client_identity(None).is_err());
+        assert_eq!(
+            handler.client_identity(Some(cert.der())).unwrap().spiffe_id,
+            "spiffe://example.org/service/orders"
+        );
+
+        let handler = deny_all_handler(MtlsMode::Optional);
+        assert!(handler.client_identity(None).unwrap().is_anonymous());
+        assert_eq!(
+            handler.client_identity(Some(cert.der())).unwrap().spiffe_id,
+            "spiffe://example.org/service/orders"
+        );
+
+        let handler = deny_all_handler(MtlsMode::Disabled);
+        assert!(handler.client_identity(None).unwrap().is_anonymous());
+        assert!(handler.client_identity(Some(cert.der())).unwrap().is_anonymous());
+    }
+}
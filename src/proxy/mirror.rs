@@ -0,0 +1,191 @@
+use bytes::Bytes;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::config::MirrorConfig;
+use crate::proxy::forwarder::{open_backend, UpstreamResolver};
+use crate::telemetry;
+
+/// Client chunks buffered for a slow mirror before mirroring of the
+/// connection is abandoned
+const MIRROR_QUEUE_CHUNKS: usize = 64;
+
+/// How long to keep reading the mirror's responses after the client is done
+const MIRROR_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Copies a sample of client connections to a shadow upstream
+///
+/// The mirror receives the same bytes the client sends to the primary
+/// backend, and its responses are read and discarded. Each mirrored
+/// connection runs on its own task fed through a bounded queue, so a slow or
+/// dead mirror never delays the primary: once the queue is full, mirroring of
+/// that connection stops.
+#[derive(Debug)]
+pub struct TrafficMirror {
+    /// Shadow upstream address
+    address: String,
+
+    /// Fraction of connections to mirror
+    sample_rate: f64,
+
+    /// Connections seen so far, for sampling
+    seen: AtomicU64,
+}
+
+impl TrafficMirror {
+    /// Create a mirror from configuration
+    pub fn new(config: &MirrorConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            sample_rate: config.sample_rate,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the next connection is mirrored
+    ///
+    /// Sampling is deterministic: at a rate of 0.25 every fourth connection
+    /// is mirrored.
+    fn sample(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * self.sample_rate).floor() > (seen * self.sample_rate).floor()
+    }
+
+    /// Start mirroring a connection if it is sampled
+    ///
+    /// The returned tap is fed with the client's chunks; dropping it ends the
+    /// mirrored connection.
+    pub fn start(&self, resolver: Arc<dyn UpstreamResolver>, connect_timeout: Duration) -> Option<MirrorTap> {
+        if !self.sample() {
+            return None;
+        }
+
+        let (tx, rx) = mpsc::channel(MIRROR_QUEUE_CHUNKS);
+        let overflowed = Arc::new(AtomicBool::new(false));
+        tokio::spawn(run_mirror(
+            self.address.clone(),
+            resolver,
+            connect_timeout,
+            rx,
+            overflowed.clone(),
+        ));
+
+        Some(MirrorTap {
+            tx: Some(tx),
+            overflowed,
+        })
+    }
+}
+
+/// Feeds one client connection's traffic to its mirror task
+pub struct MirrorTap {
+    /// Queue to the mirror task, dropped once mirroring is abandoned
+    tx: Option<mpsc::Sender<Bytes>>,
+
+    /// Set when the mirror fell behind and chunks were dropped
+    overflowed: Arc<AtomicBool>,
+}
+
+impl MirrorTap {
+    /// Queue a copy of a client chunk without ever waiting for the mirror
+    pub fn send(&mut self, chunk: &[u8]) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+
+        match tx.try_send(Bytes::copy_from_slice(chunk)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.overflowed.store(true, Ordering::Relaxed);
+                self.tx = None;
+            }
+            // The mirror task already failed and reported it
+            Err(TrySendError::Closed(_)) => self.tx = None,
+        }
+    }
+}
+
+/// Mirror one connection and record the outcome
+async fn run_mirror(
+    address: String,
+    resolver: Arc<dyn UpstreamResolver>,
+    connect_timeout: Duration,
+    mut rx: mpsc::Receiver<Bytes>,
+    overflowed: Arc<AtomicBool>,
+) {
+    let result = mirror_connection(&address, resolver.as_ref(), connect_timeout, &mut rx).await;
+    let overflowed = overflowed.load(Ordering::Relaxed);
+
+    match &result {
+        Err(e) => debug!("Mirroring to {} failed: {}", address, e),
+        Ok(()) if overflowed => debug!("Mirror {} fell behind, stopped mirroring the connection", address),
+        Ok(()) => {}
+    }
+    telemetry::record_mirror(&address, result.is_ok() && !overflowed);
+}
+
+/// Send the queued client chunks to the mirror, discarding whatever it answers
+async fn mirror_connection(
+    address: &str,
+    resolver: &dyn UpstreamResolver,
+    connect_timeout: Duration,
+    rx: &mut mpsc::Receiver<Bytes>,
+) -> io::Result<()> {
+    let stream = timeout(connect_timeout, open_backend(resolver, address, None))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    let (mut reader, mut writer) = tokio::io::split(stream);
+
+    let send = async {
+        while let Some(chunk) = rx.recv().await {
+            writer.write_all(&chunk).await?;
+        }
+        writer.shutdown().await
+    };
+    // Keep reading so a mirror that answers early never stalls on a full socket
+    let discard = async {
+        let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+    };
+    tokio::pin!(send, discard);
+
+    let mut discarded = false;
+    let sent = tokio::select! {
+        sent = &mut send => sent,
+        _ = &mut discard => {
+            discarded = true;
+            (&mut send).await
+        }
+    };
+    if sent.is_ok() && !discarded {
+        let _ = timeout(MIRROR_DRAIN_TIMEOUT, discard).await;
+    }
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_rate() {
+        let mirror = |sample_rate| {
+            TrafficMirror::new(&MirrorConfig {
+                address: "127.0.0.1:1".to_string(),
+                sample_rate,
+            })
+        };
+
+        let all = mirror(1.0);
+        assert!((0..10).all(|_| all.sample()));
+
+        let quarter = mirror(0.25);
+        let sampled: Vec<bool> = (0..8).map(|_| quarter.sample()).collect();
+        assert_eq!(sampled, vec![false, false, false, true, false, false, false, true]);
+    }
+}
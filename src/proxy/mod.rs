@@ -1,4 +1,9 @@
+pub mod client_stream;
 pub mod forwarder;
 pub mod handler;
+pub mod mirror;
+pub mod outlier;
 pub mod pqc_acceptor;
-pub mod protocol;
\ No newline at end of file
+pub mod protocol;
+pub mod sniffer;
+pub mod throttle;
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::OutlierDetectionConfig;
+use crate::telemetry;
+
+/// Longest ejection as a multiple of the configured duration
+const MAX_EJECTION_MULTIPLIER: u32 = 10;
+
+/// Passive outlier detection for the addresses a backend resolves to
+///
+/// A backend name can resolve to several addresses, for example the pods
+/// behind a headless service. An address failing `consecutive_failures`
+/// connection attempts in a row is skipped for the ejection duration instead
+/// of costing every new connection a failed attempt. No more than
+/// `max_ejection_percent` of the currently resolved addresses are ejected at
+/// once, and when every resolved address is ejected all of them are tried
+/// anyway. Addresses that drop out of resolution are forgotten.
+///
+/// Re-admission is gradual: a returning address is on probation and is
+/// ejected again after a single failure, each time for longer, until it
+/// accepts a connection.
+#[derive(Debug)]
+pub struct OutlierDetector {
+    /// Consecutive failures that eject an address
+    consecutive_failures: u32,
+
+    /// How long a first ejection lasts
+    ejection_duration: Duration,
+
+    /// Upper bound on the share of known addresses ejected at once
+    max_ejection_percent: u8,
+
+    /// Health of the addresses from the latest resolution
+    targets: Mutex<HashMap<SocketAddr, TargetHealth>>,
+}
+
+/// Failure tracking for one address
+#[derive(Debug, Default)]
+struct TargetHealth {
+    /// Failed attempts since the last success or ejection
+    consecutive_failures: u32,
+
+    /// End of the current ejection
+    ejected_until: Option<Instant>,
+
+    /// Ejections since the last success, scaling the next one
+    ejections: u32,
+}
+
+impl OutlierDetector {
+    /// Create a detector from configuration
+    pub fn new(config: &OutlierDetectionConfig) -> Self {
+        Self {
+            consecutive_failures: config.consecutive_failures,
+            ejection_duration: Duration::from_secs(config.ejection_duration_seconds),
+            max_ejection_percent: config.max_ejection_percent,
+            targets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Drop ejected addresses from a resolution result, keeping its order
+    ///
+    /// The result also becomes the set of addresses tracked from now on.
+    /// Returns the addresses unchanged if all of them are ejected.
+    pub fn admit(&self, addrs: Vec<SocketAddr>, now: Instant) -> Vec<SocketAddr> {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+
+        let known = targets.len();
+        targets.retain(|addr, _| addrs.contains(addr));
+        let mut changed = targets.len() < known;
+        for addr in &addrs {
+            let health = targets.entry(*addr).or_default();
+            if health.ejected_until.is_some_and(|until| until <= now) {
+                health.ejected_until = None;
+                changed = true;
+                info!("Upstream {} returned to rotation on probation", addr);
+            }
+        }
+        if changed {
+            telemetry::record_ejected_upstreams(ejected_count(&targets, now));
+        }
+
+        let admitted: Vec<SocketAddr> = addrs
+            .iter()
+            .copied()
+            .filter(|addr| targets.get(addr).is_none_or(|health| health.ejected_until.is_none()))
+            .collect();
+        if admitted.is_empty() {
+            addrs
+        } else {
+            admitted
+        }
+    }
+
+    /// Start a connection attempt, counted as failed unless marked successful
+    pub fn attempt(&self, addr: SocketAddr) -> ConnectAttempt<'_> {
+        ConnectAttempt {
+            detector: self,
+            addr,
+            succeeded: false,
+        }
+    }
+
+    /// Note that an address accepted a connection
+    pub fn record_success(&self, addr: SocketAddr) {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        let health = targets.entry(addr).or_default();
+        health.consecutive_failures = 0;
+        health.ejections = 0;
+    }
+
+    /// Note that a connection attempt to an address failed, returning whether it was ejected
+    pub fn record_failure(&self, addr: SocketAddr, now: Instant) -> bool {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+        let ejected = ejected_count(&targets, now);
+        let max_ejected = targets.len().max(1) * self.max_ejection_percent as usize / 100;
+
+        let health = targets.entry(addr).or_default();
+        if health.ejected_until.is_some_and(|until| until > now) {
+            return false;
+        }
+        health.consecutive_failures += 1;
+
+        // A target on probation gets no second chance
+        let threshold = if health.ejections > 0 { 1 } else { self.consecutive_failures };
+        if health.consecutive_failures < threshold || ejected >= max_ejected {
+            return false;
+        }
+
+        health.consecutive_failures = 0;
+        health.ejections = (health.ejections + 1).min(MAX_EJECTION_MULTIPLIER);
+        let duration = self.ejection_duration * health.ejections;
+        health.ejected_until = Some(now + duration);
+        warn!("Ejecting upstream {} for {:?} after repeated connection failures", addr, duration);
+
+        telemetry::record_ejected_upstreams(ejected + 1);
+        true
+    }
+
+    /// Number of addresses currently ejected
+    pub fn ejected_count(&self, now: Instant) -> usize {
+        ejected_count(&self.targets.lock().unwrap_or_else(|e| e.into_inner()), now)
+    }
+}
+
+/// Number of addresses ejected at `now`
+fn ejected_count(targets: &HashMap<SocketAddr, TargetHealth>, now: Instant) -> usize {
+    targets
+        .values()
+        .filter(|health| health.ejected_until.is_some_and(|until| until > now))
+        .count()
+}
+
+/// Outcome of one connection attempt, recorded when dropped
+///
+/// An attempt abandoned by a connect timeout is dropped without being marked
+/// successful, so it counts as a failure like a refused connection.
+pub struct ConnectAttempt<'a> {
+    detector: &'a OutlierDetector,
+    addr: SocketAddr,
+    succeeded: bool,
+}
+
+impl ConnectAttempt<'_> {
+    /// Mark the attempt successful
+    pub fn succeeded(mut self) {
+        self.succeeded = true;
+    }
+}
+
+impl Drop for ConnectAttempt<'_> {
+    fn drop(&mut self) {
+        if self.succeeded {
+            self.detector.record_success(self.addr);
+        } else {
+            self.detector.record_failure(self.addr, Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_detector(max_ejection_percent: u8) -> OutlierDetector {
+        OutlierDetector::new(&OutlierDetectionConfig {
+            consecutive_failures: 3,
+            ejection_duration_seconds: 30,
+            max_ejection_percent,
+        })
+    }
+
+    fn addrs(count: u16) -> Vec<SocketAddr> {
+        (1..=count).map(|port| SocketAddr::from(([10, 0, 0, 1], port))).collect()
+    }
+
+    #[test]
+    fn test_ejection_and_probation() {
+        let detector = new_detector(50);
+        let targets = addrs(2);
+        let now = Instant::now();
+        assert_eq!(detector.admit(targets.clone(), now), targets);
+
+        // A success in between resets the count
+        assert!(!detector.record_failure(targets[0], now));
+        assert!(!detector.record_failure(targets[0], now));
+        detector.record_success(targets[0]);
+        assert!(!detector.record_failure(targets[0], now));
+        assert!(!detector.record_failure(targets[0], now));
+        assert!(detector.record_failure(targets[0], now));
+        assert_eq!(detector.ejected_count(now), 1);
+        assert_eq!(detector.admit(targets.clone(), now), vec![targets[1]]);
+
+        // Back after the cooldown, but one failure ejects it for twice as long
+        let later = now + Duration::from_secs(30);
+        assert_eq!(detector.admit(targets.clone(), later), targets);
+        assert!(detector.record_failure(targets[0], later));
+        assert_eq!(detector.admit(targets.clone(), later + Duration::from_secs(59)), vec![targets[1]]);
+
+        // A success ends the probation
+        let recovered = later + Duration::from_secs(60);
+        assert_eq!(detector.admit(targets.clone(), recovered), targets);
+        detector.record_success(targets[0]);
+        assert!(!detector.record_failure(targets[0], recovered));
+    }
+
+    #[test]
+    fn test_max_ejection_percent() {
+        let detector = new_detector(50);
+        let targets = addrs(4);
+        let now = Instant::now();
+        detector.admit(targets.clone(), now);
+
+        for addr in &targets {
+            for _ in 0..3 {
+                detector.record_failure(*addr, now);
+            }
+        }
+        assert_eq!(detector.ejected_count(now), 2);
+        assert_eq!(detector.admit(targets.clone(), now), targets[2..].to_vec());
+
+        // A single target is never ejected below 100%
+        let single = new_detector(50);
+        single.admit(addrs(1), now);
+        assert!((0..5).all(|_| !single.record_failure(addrs(1)[0], now)));
+    }
+
+    #[test]
+    fn test_all_ejected_admits_everything() {
+        let detector = new_detector(100);
+        let targets = addrs(2);
+        let now = Instant::now();
+        detector.admit(targets.clone(), now);
+        for addr in &targets {
+            for _ in 0..3 {
+                detector.record_failure(*addr, now);
+            }
+        }
+
+        assert_eq!(detector.ejected_count(now), 2);
+        assert_eq!(detector.admit(targets.clone(), now), targets);
+    }
+
+    #[test]
+    fn test_cap_follows_current_resolution() {
+        let detector = new_detector(50);
+        let now = Instant::now();
+
+        // Addresses from an earlier resolution do not widen the cap
+        detector.admit(addrs(4), now);
+        let current = vec![SocketAddr::from(([10, 0, 0, 2], 1))];
+        detector.admit(current.clone(), now);
+        assert!((0..5).all(|_| !detector.record_failure(current[0], now)));
+
+        // Ejected addresses that are no longer resolved are forgotten
+        let targets = addrs(2);
+        detector.admit(targets.clone(), now);
+        for _ in 0..3 {
+            detector.record_failure(targets[0], now);
+        }
+        assert_eq!(detector.ejected_count(now), 1);
+        assert_eq!(detector.admit(targets[1..].to_vec(), now), targets[1..].to_vec());
+        assert_eq!(detector.ejected_count(now), 0);
+        assert_eq!(detector.admit(targets.clone(), now), targets);
+    }
+
+    #[test]
+    fn test_dropped_attempt_counts_as_failure() {
+        let detector = new_detector(100);
+        let addr = addrs(1)[0];
+
+        for _ in 0..3 {
+            drop(detector.attempt(addr));
+        }
+        assert_eq!(detector.ejected_count(Instant::now()), 1);
+    }
+}
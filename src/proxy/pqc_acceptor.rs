@@ -1,26 +1,52 @@
 use anyhow::{Context, Result};
 use rustls::{ServerConfig, pki_types::CertificateDer};
-use std::cell::RefCell;
-use std::net::ToSocketAddrs;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout_at, Instant};
 use tokio_rustls::TlsAcceptor;
-use tracing::{debug, error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-use crate::common::PqSecureError;
+#[cfg(unix)]
+use crate::common::bind_unix_socket;
+use crate::common::{CloseReason, PqSecureError, RejectionReason, UNIX_SOCKET_PREFIX};
+use crate::config::{KeepaliveConfig, ListenSocketConfig};
+use crate::crypto::NegotiatedCrypto;
+use crate::proxy::client_stream::{ClientIo, ClientStream};
+use crate::proxy::forwarder::set_keepalive;
 use crate::proxy::handler::DefaultConnectionHandler;
+use crate::proxy::sniffer::ProtocolSniffer;
 use crate::telemetry;
 
-// Thread-local storage for client certificate during connection handling
-thread_local! {
-    static CURRENT_CLIENT_CERT: RefCell<Option<CertificateDer<'static>>> = RefCell::new(None);
+// Client certificate of the connection being handled, scoped to its task
+//
+// Task-local rather than thread-local: handlers await between reading it and
+// the runtime may resume them on a different worker thread.
+tokio::task_local! {
+    static CURRENT_CLIENT_CERT: Option<CertificateDer<'static>>;
 }
 
-/// Get the current client certificate from thread-local storage
+/// Get the client certificate of the connection handled by the current task
+///
+/// `None` outside a connection task, or when the client presented no
+/// certificate (only possible when mTLS is optional or disabled).
 pub fn get_current_client_cert() -> Option<CertificateDer<'static>> {
-    CURRENT_CLIENT_CERT.with(|cell| cell.borrow().clone())
+    CURRENT_CLIENT_CERT.try_with(|cert| cert.clone()).ok().flatten()
 }
 
+/// Content type of the TLS record carrying a ClientHello
+const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/// Reported address of clients connected over a Unix socket, which have none
+pub const UNIX_PEER_ADDR: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED), 0);
+
+/// Pause after a failed accept, so running out of file descriptors does not spin the loop
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
 /// PQC TLS connection acceptor
 pub struct PqcAcceptor {
     /// Address to listen on
@@ -31,6 +57,18 @@ pub struct PqcAcceptor {
 
     /// Protocol handlers
     handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+
+    /// TCP keepalive applied to accepted connections
+    keepalive: Option<KeepaliveConfig>,
+
+    /// Reject handshakes that did not negotiate a post-quantum key exchange
+    require_pqc: bool,
+
+    /// Options for the listening socket
+    socket: ListenSocketConfig,
+
+    /// How long after startup plaintext clients are still accepted
+    plaintext_window: Option<Duration>,
 }
 
 impl PqcAcceptor {
@@ -54,11 +92,50 @@ impl PqcAcceptor {
             listen_addr,
             tls_acceptor,
             handlers,
+            keepalive: None,
+            require_pqc: false,
+            socket: ListenSocketConfig::default(),
+            plaintext_window: None,
         })
     }
 
-    /// Run the acceptor
-    pub async fn run(&self) -> Result<()> {
+    /// Enable TCP keepalive on accepted connections
+    pub fn with_keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Reject clients that negotiate a classical-only key exchange
+    pub fn with_require_pqc(mut self, require_pqc: bool) -> Self {
+        self.require_pqc = require_pqc;
+        self
+    }
+
+    /// Also accept plaintext clients, as anonymous, for `window` after the
+    /// acceptor starts
+    ///
+    /// Plaintext connections still open when the window ends are closed.
+    pub fn with_plaintext_window(mut self, window: Option<Duration>) -> Self {
+        self.plaintext_window = window;
+        self
+    }
+
+    /// Use the given backlog and address reuse options for the listening socket
+    pub fn with_socket_config(mut self, socket: ListenSocketConfig) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Run the acceptor until `shutdown` is cancelled
+    ///
+    /// The listener is dropped on return, releasing the port; connections
+    /// already accepted keep running on their own tasks.
+    pub async fn run(&self, shutdown: CancellationToken) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(path) = self.listen_addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return self.run_unix(std::path::Path::new(path), shutdown).await;
+        }
+
         // 將字串解析為 SocketAddr
         let addr = self.listen_addr.to_socket_addrs()
             .context(format!("Failed to parse address: {}", self.listen_addr))?
@@ -66,105 +143,865 @@ impl PqcAcceptor {
             .ok_or_else(|| anyhow::anyhow!("Failed to resolve address: {}", self.listen_addr))?;
 
         // Create TCP listener
-        let listener = TcpListener::bind(addr)
-            .await
+        let listener = bind_listener(addr, &self.socket)
             .context(format!("Failed to bind to {}", self.listen_addr))?;
 
         info!("PQC acceptor listening on {}", self.listen_addr);
 
+        let plaintext_until = self.plaintext_window.map(|window| {
+            warn!(
+                "Accepting insecure plaintext connections on {} for the next {:?}",
+                self.listen_addr, window
+            );
+            Instant::now() + window
+        });
+        let mut plaintext_closed = false;
+
         // Accept connections
         loop {
-            match listener.accept().await {
+            let accepted = tokio::select! {
+                _ = shutdown.cancelled() => {
+                    info!("PQC acceptor on {} stopped", self.listen_addr);
+                    return Ok(());
+                }
+                accepted = listener.accept() => accepted,
+            };
+
+            match accepted {
                 Ok((stream, addr)) => {
                     debug!("New connection from {}", addr);
 
+                    if let Some(keepalive) = &self.keepalive {
+                        if let Err(e) = set_keepalive(&stream, keepalive) {
+                            warn!("Failed to set keepalive for {}: {}", addr, e);
+                        }
+                    }
+
                     // Clone handlers and acceptor for the task
                     let handlers = self.handlers.clone();
                     let acceptor = self.tls_acceptor.clone();
-                    let client_addr = addr.to_string();
+                    let require_pqc = self.require_pqc;
+                    let permit_plaintext = plaintext_until.filter(|until| Instant::now() < *until);
+                    if plaintext_until.is_some() && permit_plaintext.is_none() && !plaintext_closed {
+                        plaintext_closed = true;
+                        warn!("Plaintext window on {} has ended; only TLS clients are accepted", self.listen_addr);
+                    }
+
+                    // Spawn a task to handle the connection. Its span tags every
+                    // log line with the connection ID; the handler fills in the
+                    // SPIFFE ID once the client is identified.
+                    let connection_id = uuid::Uuid::new_v4().to_string();
+                    let span = info_span!(
+                        "client",
+                        id = %connection_id,
+                        source = %addr,
+                        spiffe_id = tracing::field::Empty
+                    );
+                    tokio::spawn(
+                        async move {
+                            let result = Self::handle_connection(
+                                stream,
+                                addr,
+                                connection_id,
+                                acceptor,
+                                handlers,
+                                require_pqc,
+                                permit_plaintext,
+                            )
+                            .await;
+                            if let Err(e) = result {
+                                error!("Connection error from {}: {}", addr, e);
+                            }
+                        }
+                        .instrument(span),
+                    );
+                }
+                Err(e) => {
+                    // Typically EMFILE/ENFILE; the listener stays usable once resources free up
+                    error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                }
+            }
+        }
+    }
+
+    /// Run the acceptor on a Unix socket until `shutdown` is cancelled
+    ///
+    /// Clients are served over TLS exactly like TCP clients, but have no
+    /// network address and are reported as `UNIX_PEER_ADDR`. The socket file
+    /// is created with `proxy.socket.unix_mode` and removed when the acceptor
+    /// stops.
+    #[cfg(unix)]
+    async fn run_unix(&self, path: &std::path::Path, shutdown: CancellationToken) -> Result<()> {
+        let listener = bind_unix_socket(path, self.socket.unix_mode)?;
+        info!("PQC acceptor listening on {}", self.listen_addr);
+
+        loop {
+            let accepted = tokio::select! {
+                _ = shutdown.cancelled() => {
+                    let _ = std::fs::remove_file(path);
+                    info!("PQC acceptor on {} stopped", self.listen_addr);
+                    return Ok(());
+                }
+                accepted = listener.accept() => accepted,
+            };
+
+            match accepted {
+                Ok((stream, _)) => {
+                    let handlers = self.handlers.clone();
+                    let acceptor = self.tls_acceptor.clone();
+                    let require_pqc = self.require_pqc;
 
-                    // Spawn a task to handle the connection
-                    tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(stream, client_addr, acceptor, handlers).await {
-                            error!("Connection error from {}: {}", addr, e);
+                    let connection_id = uuid::Uuid::new_v4().to_string();
+                    let span = info_span!(
+                        "client",
+                        id = %connection_id,
+                        source = %self.listen_addr,
+                        spiffe_id = tracing::field::Empty
+                    );
+                    tokio::spawn(
+                        async move {
+                            let result =
+                                Self::serve_tls(stream, UNIX_PEER_ADDR, connection_id, acceptor, handlers, require_pqc)
+                                    .await;
+                            if let Err(e) = result {
+                                error!("Connection error on Unix socket: {}", e);
+                            }
                         }
-                    });
+                        .instrument(span),
+                    );
                 }
                 Err(e) => {
                     error!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
                 }
             }
         }
     }
 
     /// Handle a single connection
+    ///
+    /// Until `plaintext_until`, a client whose first byte is not a TLS
+    /// handshake record is served over the raw TCP stream, and the connection
+    /// is closed once that time has passed. Everyone else is served over TLS.
     async fn handle_connection(
         original_stream: TcpStream,
-        client_addr: String,
+        peer_addr: SocketAddr,
+        connection_id: String,
         acceptor: TlsAcceptor,
         handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+        require_pqc: bool,
+        plaintext_until: Option<Instant>,
     ) -> Result<()> {
-        // Clone the TCP stream for protocol detection after TLS handshake
-        let std_stream = original_stream.into_std().expect("Failed to convert to std TcpStream");
-        let std_stream_clone = std_stream.try_clone().expect("Failed to clone TcpStream");
-        let stream_for_detection = TcpStream::from_std(std_stream_clone).expect("Failed to convert from std TcpStream");
-        let original_stream = TcpStream::from_std(std_stream).expect("Failed to convert back to tokio TcpStream");
-        
+        let client_addr = peer_addr.to_string();
+
+        if let Some(until) = plaintext_until {
+            if !starts_with_tls_handshake(&original_stream).await {
+                telemetry::record_plaintext_connection(&client_addr);
+                let client_stream = ClientStream::new(original_stream, peer_addr)
+                    .with_connection_id(connection_id)
+                    .with_plaintext(true);
+                return match timeout_at(until, Self::dispatch(client_stream, None, handlers)).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        warn!("Closing plaintext connection from {}: the plaintext window has ended", client_addr);
+                        Err(PqSecureError::ProxyError("Plaintext window ended".to_string()).into())
+                    }
+                };
+            }
+        }
+
+        Self::serve_tls(original_stream, peer_addr, connection_id, acceptor, handlers, require_pqc).await
+    }
+
+    /// Complete the TLS handshake and serve the connection
+    ///
+    /// Handlers are served the decrypted TLS stream. Protocol detection reads
+    /// ahead on that same stream and the bytes it saw are replayed to the
+    /// chosen handler.
+    async fn serve_tls<S: ClientIo + 'static>(
+        original_stream: S,
+        peer_addr: SocketAddr,
+        connection_id: String,
+        acceptor: TlsAcceptor,
+        handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+        require_pqc: bool,
+    ) -> Result<()> {
+        let client_addr = peer_addr.to_string();
+
         // Perform TLS handshake first - this is essential for the Zero Trust model
         let tls_stream = match acceptor.accept(original_stream).await {
             Ok(s) => {
                 telemetry::record_connection_attempt(&client_addr, true);
                 debug!("TLS handshake successful from {}", client_addr);
+
+                let negotiated = NegotiatedCrypto::from_connection(s.get_ref().1);
+                telemetry::record_tls_handshake(
+                    &client_addr,
+                    &negotiated.key_exchange_name(),
+                    &negotiated.cipher_suite_name(),
+                    negotiated.post_quantum,
+                );
+
+                // Fail closed when post-quantum protection is mandatory
+                if require_pqc && !negotiated.post_quantum {
+                    warn!(
+                        "Rejecting {}: PQC required but {} was negotiated",
+                        client_addr,
+                        negotiated.key_exchange_name()
+                    );
+                    telemetry::record_rejected(RejectionReason::PqcRequired);
+                    telemetry::record_connection_closed(&connection_id, &client_addr, CloseReason::PolicyDeny, 0, 0);
+                    return Err(PqSecureError::TlsError(
+                        "PQC required but classical KX negotiated".to_string(),
+                    )
+                    .into());
+                }
                 s
             }
             Err(e) => {
                 telemetry::record_connection_attempt(&client_addr, false);
+                telemetry::record_connection_closed(&connection_id, &client_addr, CloseReason::HandshakeFailed, 0, 0);
                 return Err(anyhow::anyhow!("TLS handshake failed: {}", e));
             }
         };
         
-        // Extract client certificate and SPIFFE ID
-        let client_cert = match tls_stream.get_ref().1.peer_certificates() {
-            Some(certs) if !certs.is_empty() => {
-                certs[0].clone()
-            },
-            _ => {
-                error!("No client certificate found in TLS session from {}", client_addr);
-                return Err(anyhow::anyhow!("No client certificate found"));
+        // Extract the client certificate. The verifier already refused clients
+        // without one unless mTLS is optional; handlers decide how to serve them.
+        let client_cert = tls_stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()).cloned();
+        if client_cert.is_none() {
+            debug!("No client certificate in TLS session from {}", client_addr);
+        }
+
+        // Keep the requested server name for host-based policy rules
+        let server_name = tls_stream.get_ref().1.server_name().map(str::to_string);
+
+        let client_stream = ClientStream::new(tls_stream, peer_addr)
+            .with_connection_id(connection_id)
+            .with_server_name(server_name);
+        Self::dispatch(client_stream, client_cert, handlers).await
+    }
+
+    /// Serve a connection with the first handler that accepts it
+    async fn dispatch(
+        mut client_stream: ClientStream,
+        client_cert: Option<CertificateDer<'static>>,
+        handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+    ) -> Result<()> {
+        let client_addr = client_stream.peer_addr();
+
+        // Read the first bytes once so every handler can inspect them
+        let sniffed = ProtocolSniffer::default().sniff(&mut client_stream).await;
+        debug!("Detected {:?} from {}", sniffed, client_addr);
+
+        // The client certificate stays available to handlers for the whole connection
+        CURRENT_CLIENT_CERT
+            .scope(client_cert, async move {
+                for handler in handlers.iter() {
+                    if handler.can_handle(&client_stream).await {
+                        debug!("Using {} handler for connection from {}", handler.protocol_name(), client_addr);
+                        return handler.handle(client_stream).await;
+                    }
+                }
+
+                // Return an error when no handler can process the connection
+                warn!("No suitable handler found for connection from {}", client_addr);
+                Err(PqSecureError::ProxyError(
+                    "No suitable protocol handler found".to_string(),
+                ).into())
+            })
+            .await
+    }
+}
+
+/// Whether the client opened with a TLS handshake record
+///
+/// Waits for the first byte without consuming it. A connection that fails or
+/// closes before sending anything is left to the TLS handshake to reject.
+async fn starts_with_tls_handshake(stream: &TcpStream) -> bool {
+    let mut first = [0u8; 1];
+    match stream.peek(&mut first).await {
+        Ok(1) => first[0] == TLS_HANDSHAKE_RECORD,
+        _ => true,
+    }
+}
+
+/// Create a listening socket with the configured backlog and reuse options
+fn bind_listener(addr: SocketAddr, config: &ListenSocketConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(config.reuse_address)?;
+    if config.reuse_port {
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SO_REUSEPORT is not supported on this platform",
+        ));
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(config.backlog.min(i32::MAX as u32) as i32)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{
+        build_client_tls_config, build_tls_config, crypto_provider_with_groups, SpiffeServerCertVerifier,
+        TlsConfigBuilder,
+    };
+    use crate::config::MtlsMode;
+    use crate::identity::SpiffeVerifier;
+    use crate::test_support::{issue_svid, private_key_der, self_signed_identity, test_ca};
+    use rustls::pki_types::{PrivateKeyDer, ServerName};
+    use rustls::RootCertStore;
+    use std::sync::Mutex;
+    use crate::proxy::sniffer::SniffedProtocol;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    /// Handler that accepts nothing, for acceptors that never see traffic
+    struct NoopHandler;
+
+    #[async_trait::async_trait]
+    impl crate::proxy::handler::ConnectionHandler for NoopHandler {
+        async fn handle(&self, _stream: ClientStream) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DefaultConnectionHandler for NoopHandler {
+        fn protocol_name(&self) -> &'static str {
+            "noop"
+        }
+
+        async fn can_handle(&self, _stream: &ClientStream) -> bool {
+            true
+        }
+    }
+
+    fn test_acceptor(listen_addr: String) -> PqcAcceptor {
+        let (chain, key) = self_signed_identity("spiffe://example.org/service/test");
+        let tls_config = build_tls_config(
+            chain,
+            key,
+            Arc::new(SpiffeVerifier::new("example.org".to_string())),
+        )
+        .unwrap();
+
+        PqcAcceptor::new(listen_addr, tls_config, vec![Arc::new(NoopHandler)]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_cancel_and_releases_port() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let acceptor = test_acceptor(addr.to_string());
+        let task = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { acceptor.run(shutdown).await }
+        });
+
+        // Wait until the acceptor is listening
+        while TcpStream::connect(addr).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(TcpListener::bind(addr).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failed_connections_do_not_stop_acceptor() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let acceptor = test_acceptor(addr.to_string());
+        let task = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { acceptor.run(shutdown).await }
+        });
+
+        // Connections that fail the handshake in different ways are closed one by one
+        for garbage in [&b"not a tls record"[..], &[0x16, 0x03, 0x01, 0xff, 0xff, 0x01][..], &[][..]] {
+            let mut stream = loop {
+                match TcpStream::connect(addr).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            stream.write_all(garbage).await.unwrap();
+            stream.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            let _ = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf))
+                .await
+                .unwrap();
+        }
+
+        assert!(!task.is_finished());
+        shutdown.cancel();
+        task.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reuse_port_shares_listen_address() {
+        let shared = ListenSocketConfig {
+            reuse_port: true,
+            ..ListenSocketConfig::default()
+        };
+        let first = bind_listener("127.0.0.1:0".parse().unwrap(), &shared).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        // A second process-style listener can join the same port
+        let second = bind_listener(addr, &shared).unwrap();
+        assert_eq!(second.local_addr().unwrap(), addr);
+
+        // Without SO_REUSEPORT the address stays exclusive
+        assert!(bind_listener(addr, &ListenSocketConfig::default()).is_err());
+        drop((first, second));
+    }
+
+    /// Handler recording the client certificate it sees after yielding
+    #[derive(Default)]
+    struct RecordingHandler {
+        seen: Mutex<Option<CertificateDer<'static>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::proxy::handler::ConnectionHandler for RecordingHandler {
+        async fn handle(&self, _stream: ClientStream) -> Result<()> {
+            // Give the runtime chances to move the task to another worker
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
             }
+            *self.seen.lock().unwrap() = get_current_client_cert();
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DefaultConnectionHandler for RecordingHandler {
+        fn protocol_name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn can_handle(&self, _stream: &ClientStream) -> bool {
+            tokio::task::yield_now().await;
+            true
+        }
+    }
+
+    /// Server and client TLS configurations issued by a common test CA
+    struct TestPki {
+        server_config: Arc<ServerConfig>,
+        client_cert: CertificateDer<'static>,
+        client_key: PrivateKeyDer<'static>,
+        roots: Arc<RootCertStore>,
+        spiffe_verifier: Arc<SpiffeVerifier>,
+    }
+
+    fn test_pki() -> TestPki {
+        test_pki_with(MtlsMode::Required)
+    }
+
+    /// Test PKI whose server treats client certificates according to `mtls_mode`
+    fn test_pki_with(mtls_mode: MtlsMode) -> TestPki {
+        let ca = test_ca();
+        let issue = |spiffe_id: &str| {
+            let (cert, key) = issue_svid(spiffe_id, &ca);
+            (cert.der().clone(), private_key_der(&key))
         };
-        
-        // Store client certificate in thread local storage for handlers to access
-        CURRENT_CLIENT_CERT.with(|cell| {
-            *cell.borrow_mut() = Some(client_cert);
+
+        let spiffe_verifier = Arc::new(SpiffeVerifier::new("example.org".to_string()).with_mtls_mode(mtls_mode));
+        let (server_cert, server_key) = issue("spiffe://example.org/service/server");
+        let (client_cert, client_key) = issue("spiffe://example.org/service/client");
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.0.der().clone()).unwrap();
+
+        TestPki {
+            server_config: build_tls_config(vec![server_cert], server_key, spiffe_verifier.clone()).unwrap(),
+            client_cert,
+            client_key,
+            roots: Arc::new(roots),
+            spiffe_verifier,
+        }
+    }
+
+    /// Connect a TLS client to a single connection handled by `handle_connection`
+    ///
+    /// The client sends `request` and then closes its side of the connection.
+    async fn serve_one(
+        server_config: Arc<ServerConfig>,
+        client_config: Arc<rustls::ClientConfig>,
+        handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+        request: &'static [u8],
+        require_pqc: bool,
+    ) -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let server_name = ServerName::try_from("server.example.org").unwrap();
+            if let Ok(mut tls) = TlsConnector::from(client_config).connect(server_name, stream).await {
+                if tls.write_all(request).await.is_ok() {
+                    let _ = tls.shutdown().await;
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                drop(tls);
+            }
         });
-        
-        // After successful TLS handshake, try each protocol handler
-        for handler in handlers.iter() {
-            if handler.can_handle(&stream_for_detection).await {
-                debug!("Using {} handler for connection from {}", handler.protocol_name(), client_addr);
-                
-                // Call handler with the stream for protocol-specific handling
-                let result = handler.handle(stream_for_detection).await;
-                
-                // Clear the thread local certificate after handling
-                CURRENT_CLIENT_CERT.with(|cell| {
-                    *cell.borrow_mut() = None;
-                });
-                
-                return result;
+
+        let (stream, peer) = listener.accept().await.unwrap();
+        let result = PqcAcceptor::handle_connection(
+            stream,
+            peer,
+            uuid::Uuid::new_v4().to_string(),
+            TlsAcceptor::from(server_config),
+            handlers,
+            require_pqc,
+            None,
+        )
+        .await;
+        client.await.unwrap();
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_client_cert_available_across_awaits() {
+        let pki = test_pki();
+        let client_config = build_client_tls_config(
+            vec![pki.client_cert.clone()],
+            pki.client_key,
+            pki.roots,
+            pki.spiffe_verifier,
+            None,
+        )
+        .unwrap();
+
+        let handler = Arc::new(RecordingHandler::default());
+        serve_one(pki.server_config, client_config, vec![handler.clone()], b"", false)
+            .await
+            .unwrap();
+
+        assert_eq!(handler.seen.lock().unwrap().as_ref(), Some(&pki.client_cert));
+        assert!(get_current_client_cert().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_require_pqc_rejects_classical_key_exchange() {
+        let pki = test_pki();
+        let client_config = |groups: &[&str]| {
+            TlsConfigBuilder::new()
+                .with_provider(crypto_provider_with_groups(groups).unwrap())
+                .with_identity(vec![pki.client_cert.clone()], pki.client_key.clone_key())
+                .with_server_verifier(Arc::new(SpiffeServerCertVerifier::new(
+                    pki.roots.clone(),
+                    pki.spiffe_verifier.clone(),
+                    None,
+                )))
+                .build_client()
+                .unwrap()
+        };
+
+        // A classical-only client is turned away after the handshake
+        let handler = Arc::new(RecordingHandler::default());
+        let err = serve_one(pki.server_config.clone(), client_config(&["X25519"]), vec![handler.clone()], b"", true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("PQC required but classical KX negotiated"));
+        assert!(handler.seen.lock().unwrap().is_none());
+
+        // The same client is served when PQC is not required
+        serve_one(pki.server_config.clone(), client_config(&["X25519"]), vec![handler.clone()], b"", false)
+            .await
+            .unwrap();
+
+        // A client offering the hybrid group is served when PQC is required
+        let handler = Arc::new(RecordingHandler::default());
+        serve_one(
+            pki.server_config,
+            client_config(&["X25519MLKEM768", "X25519"]),
+            vec![handler.clone()],
+            b"",
+            true,
+        )
+            .await
+            .unwrap();
+        assert!(handler.seen.lock().unwrap().is_some());
+    }
+
+    /// Handler taking connections of one detected protocol and keeping what it reads
+    struct CapturingHandler {
+        /// Protocol accepted, or `None` for everything
+        protocol: Option<SniffedProtocol>,
+        received: Mutex<Option<Vec<u8>>>,
+    }
+
+    impl CapturingHandler {
+        fn new(protocol: Option<SniffedProtocol>) -> Arc<Self> {
+            Arc::new(Self {
+                protocol,
+                received: Mutex::new(None),
+            })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl crate::proxy::handler::ConnectionHandler for CapturingHandler {
+        async fn handle(&self, mut stream: ClientStream) -> Result<()> {
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).await?;
+            *self.received.lock().unwrap() = Some(received);
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DefaultConnectionHandler for CapturingHandler {
+        fn protocol_name(&self) -> &'static str {
+            "capturing"
+        }
+
+        async fn can_handle(&self, stream: &ClientStream) -> bool {
+            self.protocol
+                .is_none_or(|protocol| ProtocolSniffer::classify(stream.peeked()) == protocol)
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_listener() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proxy.sock");
+        let pki = test_pki();
+        let handler = CapturingHandler::new(None);
+        let acceptor = PqcAcceptor::new(format!("unix:{}", path.display()), pki.server_config.clone(), vec![handler.clone()])
+            .unwrap()
+            .with_socket_config(ListenSocketConfig {
+                unix_mode: 0o600,
+                ..ListenSocketConfig::default()
+            });
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { acceptor.run(shutdown).await }
+        });
+
+        let stream = loop {
+            match tokio::net::UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
             }
+        };
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Clients on the socket still complete the mTLS handshake
+        let client_config = build_client_tls_config(
+            vec![pki.client_cert.clone()],
+            pki.client_key.clone_key(),
+            pki.roots.clone(),
+            pki.spiffe_verifier.clone(),
+            None,
+        )
+        .unwrap();
+        let server_name = ServerName::try_from("server.example.org").unwrap();
+        let mut tls = TlsConnector::from(client_config).connect(server_name, stream).await.unwrap();
+        tls.write_all(b"ping").await.unwrap();
+        tls.shutdown().await.unwrap();
+        while handler.received.lock().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
+        assert_eq!(handler.received.lock().unwrap().as_deref(), Some(&b"ping"[..]));
+
+        shutdown.cancel();
+        task.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_detected_handler_reads_full_stream() {
+        let pki = test_pki();
+        let client_config = build_client_tls_config(
+            vec![pki.client_cert.clone()],
+            pki.client_key,
+            pki.roots,
+            pki.spiffe_verifier,
+            None,
+        )
+        .unwrap();
 
-        // Clear the thread local certificate if no handler was found
-        CURRENT_CLIENT_CERT.with(|cell| {
-            *cell.borrow_mut() = None;
+        let request: &'static [u8] = b"GET /hello HTTP/1.1\r\nHost: server.example.org\r\n\r\n";
+        let http = CapturingHandler::new(Some(SniffedProtocol::Http1));
+        let tcp = CapturingHandler::new(None);
+        serve_one(
+            pki.server_config.clone(),
+            client_config.clone(),
+            vec![http.clone(), tcp.clone()],
+            request,
+            false,
+        )
+        .await
+        .unwrap();
+        // The decrypted request arrives whole, including the bytes used for detection
+        assert_eq!(http.received.lock().unwrap().as_deref(), Some(request));
+        assert!(tcp.received.lock().unwrap().is_none());
+
+        let request: &'static [u8] = b"\x00\x01opaque payload";
+        let http = CapturingHandler::new(Some(SniffedProtocol::Http1));
+        let tcp = CapturingHandler::new(None);
+        serve_one(pki.server_config, client_config, vec![http.clone(), tcp.clone()], request, false)
+            .await
+            .unwrap();
+        assert!(http.received.lock().unwrap().is_none());
+        assert_eq!(tcp.received.lock().unwrap().as_deref(), Some(request));
+    }
+
+    /// Handle one connection from a client writing `request` without TLS
+    async fn serve_plaintext(
+        server_config: Arc<ServerConfig>,
+        handlers: Vec<Arc<dyn DefaultConnectionHandler>>,
+        request: &'static [u8],
+        plaintext_until: Option<Instant>,
+    ) -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(request).await.unwrap();
+            let _ = stream.shutdown().await;
+            let mut rest = Vec::new();
+            let _ = stream.read_to_end(&mut rest).await;
         });
 
-        // Return an error when no handler can process the connection
-        warn!("No suitable handler found for connection from {}", client_addr);
-        Err(PqSecureError::ProxyError(
-            "No suitable protocol handler found".to_string(),
-        ).into())
+        let (stream, peer) = listener.accept().await.unwrap();
+        let result = PqcAcceptor::handle_connection(
+            stream,
+            peer,
+            uuid::Uuid::new_v4().to_string(),
+            TlsAcceptor::from(server_config),
+            handlers,
+            false,
+            plaintext_until,
+        )
+        .await;
+        client.await.unwrap();
+        result
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_plaintext_served_only_when_permitted() {
+        let pki = test_pki();
+        let request: &'static [u8] = b"GET /hello HTTP/1.1\r\nHost: server.example.org\r\n\r\n";
+
+        // During the bootstrap window the request is served over plaintext
+        let http = CapturingHandler::new(Some(SniffedProtocol::Http1));
+        let until = Instant::now() + Duration::from_secs(60);
+        serve_plaintext(pki.server_config.clone(), vec![http.clone()], request, Some(until))
+            .await
+            .unwrap();
+        assert_eq!(http.received.lock().unwrap().as_deref(), Some(request));
+
+        // Afterwards it fails the TLS handshake
+        let http = CapturingHandler::new(Some(SniffedProtocol::Http1));
+        let err = serve_plaintext(pki.server_config.clone(), vec![http.clone()], request, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("TLS handshake failed"));
+        assert!(http.received.lock().unwrap().is_none());
+    }
+
+    /// Handler that never finishes with a connection
+    struct StallingHandler;
+
+    #[async_trait::async_trait]
+    impl crate::proxy::handler::ConnectionHandler for StallingHandler {
+        async fn handle(&self, _stream: ClientStream) -> Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DefaultConnectionHandler for StallingHandler {
+        fn protocol_name(&self) -> &'static str {
+            "stalling"
+        }
+
+        async fn can_handle(&self, _stream: &ClientStream) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_connection_closed_when_window_ends() {
+        let pki = test_pki();
+        let until = Instant::now() + Duration::from_millis(200);
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            serve_plaintext(pki.server_config, vec![Arc::new(StallingHandler)], b"GET / HTTP/1.1\r\n\r\n", Some(until)),
+        )
+        .await
+        .expect("plaintext connection outlived the window");
+
+        assert!(result.unwrap_err().to_string().contains("Plaintext window ended"));
+        assert!(Instant::now() >= until);
+    }
+
+    #[tokio::test]
+    async fn test_optional_mtls_serves_clients_without_certificate() {
+        let certless_client = |pki: &TestPki| {
+            let config = rustls::ClientConfig::builder_with_provider(crate::crypto::crypto_provider())
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SpiffeServerCertVerifier::new(
+                    pki.roots.clone(),
+                    pki.spiffe_verifier.clone(),
+                    None,
+                )))
+                .with_no_client_auth();
+            Arc::new(config)
+        };
+
+        // Required: the handshake fails and no handler runs
+        let pki = test_pki();
+        let handler = CapturingHandler::new(None);
+        let client_config = certless_client(&pki);
+        let result = serve_one(pki.server_config, client_config, vec![handler.clone()], b"ping", false);
+        assert!(result.await.is_err());
+        assert!(handler.received.lock().unwrap().is_none());
+
+        // Optional: the client is served without a certificate
+        let pki = test_pki_with(MtlsMode::Optional);
+        let handler = CapturingHandler::new(None);
+        serve_one(pki.server_config.clone(), certless_client(&pki), vec![handler.clone()], b"ping", false)
+            .await
+            .unwrap();
+        assert_eq!(handler.received.lock().unwrap().as_deref(), Some(&b"ping"[..]));
+
+        // Optional: a client presenting a certificate still has it available
+        let client_config = build_client_tls_config(
+            vec![pki.client_cert.clone()],
+            pki.client_key.clone_key(),
+            pki.roots.clone(),
+            pki.spiffe_verifier.clone(),
+            None,
+        )
+        .unwrap();
+        let recording = Arc::new(RecordingHandler::default());
+        serve_one(pki.server_config, client_config, vec![recording.clone()], b"", false)
+            .await
+            .unwrap();
+        assert_eq!(recording.seen.lock().unwrap().as_ref(), Some(&pki.client_cert));
+    }
+}
@@ -1,19 +1,46 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::sync::Arc;
-use tokio::net::TcpStream;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, info_span, warn, Instrument};
 
-use crate::common::{ConnectionInfo, ProtocolType, PqSecureError};
-use crate::config::BackendConfig;
-use crate::identity::SpiffeVerifier;
-use crate::policy::PolicyEngine;
+use crate::common::{ConnectionInfo, PqSecureError, ProtocolType, RejectionReason, ServiceIdentity};
+use crate::config::{BackendConfig, DenyResponseConfig, HttpLimitsConfig};
+use crate::identity::{JwtSvidVerifier, SpiffeVerifier};
+use crate::policy::{policy_method_for, PolicyEngine};
+use crate::proxy::client_stream::ClientStream;
+use crate::proxy::forwarder::Forwarder;
 use crate::proxy::handler::{BaseHandler, DefaultConnectionHandler};
-use crate::proxy::pqc_acceptor::get_current_client_cert;
+use crate::proxy::protocol::response_headers::{ResponseHeaderRewriter, ResponseHeaderRules};
+use crate::proxy::sniffer::{ProtocolSniffer, SniffedProtocol};
 use crate::telemetry;
 
+/// How long to wait for the client to finish sending its request head
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Header carrying the ID that ties a request to its logs and response
+pub(crate) const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest inbound request ID kept; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
 /// Handler for HTTP/HTTPS connections
 pub struct HttpHandler {
     /// Common base handler with shared functionality
     base: BaseHandler,
+
+    /// Response written to clients denied by policy
+    deny_response: Option<DenyResponseConfig>,
+
+    /// Bounds on the request head read ahead
+    limits: HttpLimitsConfig,
+
+    /// Rewriting of the backend's response headers, if configured
+    response_headers: Option<Arc<ResponseHeaderRules>>,
+
+    /// Verifier for bearer JWT-SVIDs from clients without a certificate
+    jwt_verifier: Option<Arc<JwtSvidVerifier>>,
 }
 
 impl HttpHandler {
@@ -23,45 +50,95 @@ impl HttpHandler {
         policy_engine: Arc<dyn PolicyEngine>,
         spiffe_verifier: Arc<SpiffeVerifier>,
     ) -> Result<Self> {
-        let base = BaseHandler::new(backend_config, policy_engine, spiffe_verifier)?;
-        
-        Ok(Self { base })
+        let mut base = BaseHandler::new(backend_config, policy_engine, spiffe_verifier)?;
+
+        // Only the first request on a connection is checked against policy, so
+        // the backend is asked to close after answering it, and so is the client
+        base.forwarder = Forwarder::from_config(&base.backend_config).with_close_on_upstream_eof(true);
+
+        Ok(Self {
+            base,
+            deny_response: None,
+            limits: HttpLimitsConfig::default(),
+            response_headers: None,
+            jwt_verifier: None,
+        })
+    }
+
+    /// Send the given response to clients denied by policy instead of just closing
+    pub fn with_deny_response(mut self, deny_response: Option<DenyResponseConfig>) -> Self {
+        self.deny_response = deny_response;
+        self
     }
 
-    /// Detect if the connection is an HTTP connection
-    async fn is_http(&self, stream: &TcpStream) -> bool {
+    /// Add and remove headers in the backend's response
+    pub fn with_response_headers(mut self, rules: Option<ResponseHeaderRules>) -> Self {
+        self.response_headers = rules.map(Arc::new);
+        self
+    }
 
-        // Create a peek buffer
-        let mut buf = [0u8; 8];
-        
-        // Clone the stream
-        let peek_stream = stream;
+    /// Identify clients without a certificate by an `Authorization: Bearer`
+    /// JWT-SVID
+    pub fn with_jwt_verifier(mut self, verifier: Option<JwtSvidVerifier>) -> Self {
+        self.jwt_verifier = verifier.map(Arc::new);
+        self
+    }
 
-        // Set to non-blocking to prevent hanging
-        if let Err(_) = peek_stream.set_nodelay(true) {
-            return false;
-        }
-        
-        // Peek at the first few bytes
-        match tokio::time::timeout(
-            std::time::Duration::from_millis(100), 
-            peek_stream.peek(&mut buf)
-        ).await {
-            Ok(Ok(n)) if n >= 3 => {
-                // Check for common HTTP method prefixes
-                // GET, POST, PUT, HEAD, etc.
-                let start = String::from_utf8_lossy(&buf[0..3]).to_ascii_uppercase();
-                matches!(start.as_ref(), "GET" | "POS" | "PUT" | "HEA" | "DEL" | "OPT" | "PAT")
-            },
-            _ => false,
+    /// Replace an anonymous identity with the one asserted by the request's
+    /// bearer JWT-SVID
+    ///
+    /// Certificate identities are kept as they are, as are anonymous clients
+    /// sending no `Authorization` header. Fails when the header is repeated,
+    /// is not a bearer token, or the token does not verify.
+    fn bearer_identity(&self, identity: ServiceIdentity, head: &[u8]) -> Result<ServiceIdentity> {
+        let Some(verifier) = self.jwt_verifier.as_ref().filter(|_| identity.is_anonymous()) else {
+            return Ok(identity);
+        };
+        match header_values(head, "authorization").as_slice() {
+            [] => Ok(identity),
+            [value] => {
+                let token = value
+                    .split_once(' ')
+                    .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+                    .map(|(_, token)| token.trim())
+                    .ok_or_else(|| PqSecureError::AuthenticationError("Authorization is not a bearer token".to_string()))?;
+                verifier.verify(token)
+            }
+            _ => Err(PqSecureError::AuthenticationError("Repeated Authorization header".to_string()).into()),
         }
     }
 
-    /// Extract method and path from HTTP request
-    async fn extract_method_and_path(&self, _stream: &TcpStream) -> Option<(String, String)> {
-        // In a real implementation, we would parse the HTTP headers to extract method and path
-        // For this simplified version, we'll just return a placeholder
-        Some(("GET".to_string(), "/api/v1/resource".to_string()))
+    /// Bound the request head size and header count
+    pub fn with_limits(mut self, limits: HttpLimitsConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Read ahead until the request head is complete and return its method,
+    /// path and `Host` header
+    ///
+    /// The bytes stay in the stream, so the backend still receives the full
+    /// request. Fails when the head exceeds the configured limits. Returns
+    /// `None` when the head cannot be parsed, or is not complete when the
+    /// client stops sending or the timeout expires.
+    async fn extract_request_head(
+        &self,
+        stream: &mut ClientStream,
+    ) -> Result<Option<(String, String, Option<String>)>> {
+        let max_bytes = self.limits.max_total_header_bytes;
+        let deadline = Instant::now() + REQUEST_HEAD_TIMEOUT;
+        while !stream.peeked().windows(4).any(|w| w == b"\r\n\r\n") {
+            match timeout_at(deadline, stream.peek_more(max_bytes)).await {
+                Ok(Ok(n)) if n > 0 => {}
+                _ => break,
+            }
+        }
+
+        check_head_limits(stream.peeked(), &self.limits)?;
+        if head_len(stream.peeked()).is_none() {
+            return Ok(None);
+        }
+        Ok(parse_request_head(stream.peeked()))
     }
 }
 
@@ -71,49 +148,1001 @@ impl DefaultConnectionHandler for HttpHandler {
         "HTTP"
     }
 
-    async fn can_handle(&self, stream: &TcpStream) -> bool {
-        self.is_http(stream).await
+    async fn can_handle(&self, stream: &ClientStream) -> bool {
+        ProtocolSniffer::classify(stream.peeked()) == SniffedProtocol::Http1
     }
 }
 
 #[async_trait::async_trait]
 impl crate::proxy::handler::ConnectionHandler for HttpHandler {
-    async fn handle(&self, client_stream: TcpStream) -> Result<()> {
+    async fn handle(&self, client_stream: ClientStream) -> Result<()> {
         // Get client address
-        let client_addr = client_stream.peer_addr()?;
+        let client_addr = client_stream.peer_addr();
 
         // Create connection info
-        let mut connection_info = ConnectionInfo::new(client_addr, ProtocolType::Http);
+        let mut connection_info = ConnectionInfo::new(client_addr, ProtocolType::Http)
+            .with_id(client_stream.connection_id());
 
-        // Get client certificate from thread-local storage
-        let client_cert = get_current_client_cert()
-            .ok_or_else(|| PqSecureError::AuthenticationError("No client certificate found".to_string()))?;
+        // Identify the client from its certificate, or as anonymous over plaintext
+        let identity = self.base.stream_identity(&client_stream)?;
 
-        // Extract SPIFFE ID from certificate
-        let identity = self.base.extract_spiffe_id(&client_cert)
-            .context("Failed to extract SPIFFE ID from certificate")?;
+        // Extract method, path and host from the request head
+        let mut client_stream = client_stream;
+        let head = match self.extract_request_head(&mut client_stream).await {
+            Ok(head) => head,
+            Err(e) => {
+                warn!("Rejecting request from {}: {}", client_addr, e);
+                telemetry::record_rejected(RejectionReason::HeadersTooLarge);
+                let response = render_error_response("431 Request Header Fields Too Large", "Request header fields too large");
+                if let Err(e) = client_stream.write_all(&response).await {
+                    debug!("Failed to send header rejection to {}: {}", client_addr, e);
+                }
+                let _ = client_stream.shutdown().await;
+                return Err(e);
+            }
+        };
+        let Some((method, path, host)) = head else {
+            warn!("Rejecting request from {}: malformed or incomplete request head", client_addr);
+            telemetry::record_rejected(RejectionReason::MalformedRequest);
+            let response = render_error_response("400 Bad Request", "Malformed request");
+            if let Err(e) = client_stream.write_all(&response).await {
+                debug!("Failed to send malformed request rejection to {}: {}", client_addr, e);
+            }
+            let _ = client_stream.shutdown().await;
+            return Err(PqSecureError::ProxyError("Malformed or incomplete HTTP request head".to_string()).into());
+        };
+
+        // Clients without a certificate may identify with a JWT-SVID instead
+        let identity = match self.bearer_identity(identity, client_stream.peeked()) {
+            Ok(identity) => identity,
+            Err(e) => {
+                warn!("Rejecting request from {}: {}", client_addr, e);
+                telemetry::record_rejected(RejectionReason::InvalidJwtSvid);
+                let response = render_error_response("401 Unauthorized", "Invalid bearer token");
+                if let Err(e) = client_stream.write_all(&response).await {
+                    debug!("Failed to send bearer token rejection to {}: {}", client_addr, e);
+                }
+                let _ = client_stream.shutdown().await;
+                return Err(e);
+            }
+        };
 
         // Update connection info with identity
         connection_info = connection_info.with_identity(identity.clone());
 
-        // Extract method and path (in a real implementation, this would be parsed from HTTP headers)
-        let (method, path) = self.extract_method_and_path(&client_stream).await
-            .unwrap_or_else(|| ("unknown".to_string(), "/".to_string()));
+        // Everything logged about the request carries its method, path and ID
+        let request_id = request_id(client_stream.peeked());
+        let span = info_span!("request", method = %method, path = %path, request_id = %request_id);
+        async move {
+            // Host-based rules match the Host header, or the SNI when it is missing
+            let host = host.or_else(|| client_stream.server_name().map(str::to_string));
+
+            // Forwarding CONNECT as a plain request would leave the client
+            // waiting for a tunnel the backend never opens
+            if method == "CONNECT" {
+                warn!("Rejecting CONNECT to {} from {}: tunneling is not supported", path, client_addr);
+                telemetry::record_rejected(RejectionReason::UnsupportedMethod);
+                let response =
+                    render_error_response("405 Method Not Allowed", "CONNECT tunneling is not supported by this proxy");
+                if let Err(e) = client_stream.write_all(&response).await {
+                    debug!("Failed to send CONNECT rejection to {}: {}", client_addr, e);
+                }
+                let _ = client_stream.shutdown().await;
+                return Err(PqSecureError::ProxyError("HTTP CONNECT tunneling is not supported".to_string()).into());
+            }
 
-        // Combine method and path for policy check
-        let method_path = format!("{} {}", method, path);
+            // Combine method and path for policy check
+            let method_path = policy_method_for(ProtocolType::Http, Some(&format!("{} {}", method, path)));
         
-        // Update connection info with method
-        connection_info = connection_info.with_method(method_path.clone());
+            // Update connection info with method
+            connection_info = connection_info.with_method(method_path.clone());
+
+            // Get SPIFFE ID for policy check
+            let spiffe_id = &identity.spiffe_id;
+
+            // Check policy
+            let decision =
+                self.base.policy_engine.allow_for_host_detailed(spiffe_id, ProtocolType::Http, &method_path, host.as_deref());
+            let allowed = decision.allowed;
+            telemetry::record_policy_decision(spiffe_id, &method_path, allowed);
+
+            // Tell the client why it was rejected before the connection is closed
+            if !allowed {
+                if let Some(deny_response) = &self.deny_response {
+                    let response = render_deny_response(deny_response, spiffe_id, &method_path, decision.rule, &request_id);
+                    if let Err(e) = client_stream.write_all(&response).await {
+                        debug!("Failed to send deny response to {}: {}", client_addr, e);
+                    }
+                    let _ = client_stream.shutdown().await;
+                }
+            }
+
+            // Later requests on the connection would reach the backend unchecked
+            if allowed {
+                if let Some(len) = head_len(client_stream.peeked()) {
+                    let head = force_connection_close(&client_stream.peeked()[..len], &request_id);
+                    client_stream.replace_peeked(len, &head);
+                }
+            }
+
+            // Use base handler to connect and forward, echoing the request ID
+            let rules = self.response_headers.clone().unwrap_or_default();
+            let rewrite = |backend| ResponseHeaderRewriter::new(backend, rules).with_request_id(request_id);
+            self.base
+                .connect_and_forward_with(client_stream, &connection_info, spiffe_id, &method_path, allowed, rewrite)
+                .await
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Split an HTTP/1.x request line (without its CRLF) into method and target
+fn parse_request_line(line: &[u8]) -> Option<(String, String)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut parts = line.split(' ');
+    let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || method.is_empty() || target.is_empty() || !version.starts_with("HTTP/1.") {
+        return None;
+    }
+
+    Some((method.to_string(), target.to_string()))
+}
+
+/// Split an HTTP/1.x request head into method, path and host
+///
+/// The host is lowercased and stripped of its port. An absolute-form target
+/// (`GET http://host/path`) is reduced to its path, and its authority stands
+/// in for a missing `Host` header. Header lines without their CRLF may still
+/// be arriving and are ignored. A head with more than one `Host` header, or
+/// whose target authority disagrees with `Host`, is rejected, as the backend
+/// might route on a different authority than the one policy matched.
+fn parse_request_head(head: &[u8]) -> Option<(String, String, Option<String>)> {
+    let end = head.windows(2).position(|w| w == b"\r\n")?;
+    let (method, target) = parse_request_line(&head[..end])?;
+
+    let mut rest = &head[end + 2..];
+    let mut host = None;
+    while let Some(len) = rest.windows(2).position(|w| w == b"\r\n") {
+        let line = &rest[..len];
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = std::str::from_utf8(line).ok().and_then(|line| line.split_once(':')) {
+            if name.eq_ignore_ascii_case("host") {
+                if host.is_some() {
+                    return None;
+                }
+                host = Some(strip_port(value.trim()).to_ascii_lowercase());
+            }
+        }
+        rest = &rest[len + 2..];
+    }
+
+    let (path, host) = match split_absolute_form(&target) {
+        Some((authority, _)) if host.as_ref().is_some_and(|host| *host != authority) => return None,
+        Some((authority, path)) => (path, Some(authority)),
+        None => (target, host),
+    };
+    Some((method, path, host))
+}
+
+/// Split an absolute-form request target into its host and path
+///
+/// The host is lowercased and stripped of any user info and port. Returns
+/// `None` for origin-form targets such as `/users`.
+fn split_absolute_form(target: &str) -> Option<(String, String)> {
+    let scheme_len = ["http://", "https://"]
+        .iter()
+        .find(|scheme| target.get(..scheme.len()).is_some_and(|s| s.eq_ignore_ascii_case(scheme)))?
+        .len();
+    let rest = &target[scheme_len..];
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    let path = match &rest[authority_end..] {
+        "" => "/".to_string(),
+        path if path.starts_with('?') => format!("/{}", path),
+        path => path.to_string(),
+    };
+    Some((strip_port(authority).to_ascii_lowercase(), path))
+}
+
+/// Values of every header named `name` in a complete request head
+fn header_values<'a>(head: &'a [u8], name: &str) -> Vec<&'a str> {
+    let Some(len) = head_len(head) else {
+        return Vec::new();
+    };
+    head[..len]
+        .split(|&b| b == b'\n')
+        .skip(1)
+        .filter_map(|line| std::str::from_utf8(line).ok()?.split_once(':'))
+        .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+        .collect()
+}
+
+/// ID for the request: its first `X-Request-Id`, or a new UUID
+///
+/// An inbound ID is kept only if it is at most `MAX_REQUEST_ID_LEN` visible
+/// ASCII characters, so it cannot inject anything into logs or headers.
+fn request_id(head: &[u8]) -> String {
+    match header_values(head, REQUEST_ID_HEADER).first() {
+        Some(id) if id.len() <= MAX_REQUEST_ID_LEN && !id.is_empty() && id.bytes().all(|b| b.is_ascii_graphic()) => {
+            id.to_string()
+        }
+        _ => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+/// Length of the request head, including its terminating blank line, if complete
+fn head_len(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|end| end + 4)
+}
+
+/// Rewrite a complete request head so the backend closes the connection after
+/// answering it
+///
+/// Any `Connection`, `Keep-Alive` or `Proxy-Connection` header is replaced by
+/// `Connection: close`, and any `X-Request-Id` by `request_id`. Request bodies are not parsed, so bytes the client
+/// pipelines after this request are still forwarded without a policy check.
+/// Keeping them from being served relies on the backend closing the
+/// connection after its first response, as RFC 9112 §9.6 requires.
+fn force_connection_close(head: &[u8], request_id: &str) -> Vec<u8> {
+    let mut rewritten = Vec::with_capacity(head.len() + request_id.len() + 35);
+    let mut lines = head[..head.len() - 2].split_inclusive(|&b| b == b'\n');
+    if let Some(request_line) = lines.next() {
+        rewritten.extend_from_slice(request_line);
+    }
+    for line in lines {
+        let name = line.split(|&b| b == b':').next().unwrap_or_default();
+        let replaced = [&b"connection"[..], b"keep-alive", b"proxy-connection", REQUEST_ID_HEADER.as_bytes()]
+            .iter()
+            .any(|header| name.eq_ignore_ascii_case(header));
+        if !replaced {
+            rewritten.extend_from_slice(line);
+        }
+    }
+    rewritten.extend_from_slice(format!("{}: {}\r\n", REQUEST_ID_HEADER, request_id).as_bytes());
+    rewritten.extend_from_slice(b"Connection: close\r\n\r\n");
+    rewritten
+}
+
+/// Remove the port from a `Host` header value, keeping IPv6 brackets
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.find(']').map_or(host, |end| &host[..=end]);
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+/// Fail if a request head exceeds the size or header count limits
+///
+/// `head` is everything read ahead, which may run past the end of the head
+/// or stop before it. A head without its terminating blank line is only too
+/// large once the read-ahead limit is reached.
+fn check_head_limits(head: &[u8], limits: &HttpLimitsConfig) -> Result<()> {
+    let head = match head.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => &head[..end + 2],
+        None if head.len() >= limits.max_total_header_bytes => {
+            return Err(PqSecureError::ProxyError(format!(
+                "Request head exceeds {} bytes",
+                limits.max_total_header_bytes
+            ))
+            .into());
+        }
+        None => head,
+    };
+
+    // Every complete line after the request line is a header field
+    let header_count = head.windows(2).filter(|w| *w == b"\r\n").count().saturating_sub(1);
+    if header_count > limits.max_header_count {
+        return Err(PqSecureError::ProxyError(format!(
+            "Request has more than {} header fields",
+            limits.max_header_count
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Render a plain-text HTTP/1.1 error response that closes the connection
+fn render_error_response(status: &str, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+/// Render a complete HTTP/1.1 response for a policy denial
+///
+/// `rule` is the position of the denying rule, or `None` when the policy's
+/// default action denied the request.
+fn render_deny_response(
+    config: &DenyResponseConfig,
+    spiffe_id: &str,
+    method: &str,
+    rule: Option<usize>,
+    request_id: &str,
+) -> Vec<u8> {
+    let rule = rule.map_or_else(|| "default".to_string(), |rule| rule.to_string());
+    // Substituted first, so a request value containing "{rule}" stays literal
+    let body = config
+        .body
+        .replace("{rule}", &rule)
+        .replace("{spiffe_id}", &escape_for_content_type(&config.content_type, spiffe_id))
+        .replace("{method}", &escape_for_content_type(&config.content_type, method));
+    let reason = http::StatusCode::from_u16(config.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("");
+
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}: {}\r\nConnection: close\r\n\r\n{}",
+        config.status,
+        reason,
+        config.content_type,
+        body.len(),
+        REQUEST_ID_HEADER,
+        request_id,
+        body
+    )
+    .into_bytes()
+}
+
+/// Escape a client-influenced value for the body format of `content_type`
+///
+/// The request target can hold quotes and angle brackets, which would
+/// otherwise let a client break out of a JSON string or inject markup.
+fn escape_for_content_type(content_type: &str, value: &str) -> String {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    if mime.ends_with("json") {
+        let quoted = serde_json::Value::String(value.to_string()).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    } else if mime.ends_with("html") || mime.ends_with("xml") {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MtlsMode;
+    use crate::policy::YamlPolicyEngine;
+    use crate::proxy::handler::ConnectionHandler;
+    use crate::test_support::{backend_config, jwks_for, sign_jwt_svid, CapturedLogs};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Handler serving anonymous clients, allowed only `GET /health`
+    fn anonymous_handler(backend_addr: &str) -> HttpHandler {
+        handler_with_policy(
+            backend_addr,
+            r#"
+            default_action: false
+            rules:
+              - spiffe_id: "anonymous"
+                method: "GET /health"
+                allow: true
+            "#,
+        )
+    }
+
+    /// Handler serving anonymous clients under the given policy
+    fn handler_with_policy(backend_addr: &str, policy: &str) -> HttpHandler {
+        let policy = YamlPolicyEngine::from_yaml(policy).unwrap();
+        HttpHandler::new(
+            backend_config(backend_addr),
+            Arc::new(policy),
+            Arc::new(SpiffeVerifier::new("example.org".to_string()).with_mtls_mode(MtlsMode::Optional)),
+        )
+        .unwrap()
+    }
+
+    /// Send `request` through the handler and return its result and the response
+    async fn send(handler: HttpHandler, request: &[u8]) -> (Result<()>, Vec<u8>) {
+        let (client, mut peer) = tokio::io::duplex(4096);
+        let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap());
+        let handled = tokio::spawn(async move { handler.handle(stream).await });
+
+        peer.write_all(request).await.unwrap();
+        peer.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        peer.read_to_end(&mut response).await.unwrap();
+        (handled.await.unwrap(), response)
+    }
+
+    /// Backend answering one request with an empty 200, returning the head it received
+    async fn recording_backend() -> (String, tokio::task::JoinHandle<String>) {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        let received = tokio::spawn(async move {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 256];
+            while head_len(&request).is_none() {
+                let n = socket.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "client bytes stopped before the end of the head");
+                request.extend_from_slice(&chunk[..n]);
+            }
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (backend_addr, received)
+    }
 
-        // Get SPIFFE ID for policy check
-        let spiffe_id = &identity.spiffe_id;
+    #[test]
+    fn test_parse_request_line() {
+        assert_eq!(
+            parse_request_line(b"GET /api/v1/users?page=2 HTTP/1.1"),
+            Some(("GET".to_string(), "/api/v1/users?page=2".to_string()))
+        );
+        assert_eq!(
+            parse_request_line(b"CONNECT example.org:443 HTTP/1.1"),
+            Some(("CONNECT".to_string(), "example.org:443".to_string()))
+        );
+        assert_eq!(parse_request_line(b"GET /"), None);
+        assert_eq!(parse_request_line(b"GET / HTTP/2.0"), None);
+        assert_eq!(parse_request_line(b"GET  / HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn test_parse_request_head() {
+        assert_eq!(
+            parse_request_head(b"GET /users HTTP/1.1\r\nAccept: */*\r\nhost: API.example.org:8443\r\n\r\n"),
+            Some(("GET".to_string(), "/users".to_string(), Some("api.example.org".to_string())))
+        );
+        assert_eq!(
+            parse_request_head(b"GET / HTTP/1.1\r\nHost: [::1]:8080\r\n\r\n").unwrap().2,
+            Some("[::1]".to_string())
+        );
+
+        // No Host header, or one that has not fully arrived
+        assert_eq!(parse_request_head(b"GET / HTTP/1.1\r\n\r\nHost: late.example.org\r\n").unwrap().2, None);
+        assert_eq!(parse_request_head(b"GET / HTTP/1.1\r\nHost: api.exa").unwrap().2, None);
+        assert_eq!(parse_request_head(b"GET / HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn test_duplicate_host_is_rejected() {
+        assert_eq!(
+            parse_request_head(b"GET / HTTP/1.1\r\nHost: api.example.org\r\nHost: db.example.org\r\n\r\n"),
+            None
+        );
+        assert_eq!(
+            parse_request_head(b"GET / HTTP/1.1\r\nHost: api.example.org\r\nhost: api.example.org\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_absolute_form_authority_must_match_host() {
+        assert_eq!(
+            parse_request_head(b"GET http://internal/admin HTTP/1.1\r\nHost: api.example.org\r\n\r\n"),
+            None
+        );
+
+        // A matching authority is fine, and policy sees the path alone
+        assert_eq!(
+            parse_request_head(b"GET HTTP://API.example.org:80/users?page=2 HTTP/1.1\r\nHost: api.example.org\r\n\r\n"),
+            Some(("GET".to_string(), "/users?page=2".to_string(), Some("api.example.org".to_string())))
+        );
+
+        // Without a Host header the authority is the host
+        assert_eq!(
+            parse_request_head(b"GET https://user@internal:8443 HTTP/1.1\r\n\r\n"),
+            Some(("GET".to_string(), "/".to_string(), Some("internal".to_string())))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_matches_host() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            let mut request = vec![0u8; 128];
+            let _ = socket.read(&mut request).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+        });
+
+        let handler = || {
+            handler_with_policy(
+                &backend_addr,
+                r#"
+                default_action: false
+                rules:
+                  - spiffe_id: "anonymous"
+                    host: "api.example.org"
+                    allow: true
+                "#,
+            )
+        };
+
+        let (result, response) =
+            send(handler(), b"GET /users HTTP/1.1\r\nHost: api.example.org:8443\r\n\r\n").await;
+        assert!(result.is_ok());
+        assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+
+        let (result, _) = send(handler(), b"GET /users HTTP/1.1\r\nHost: db.example.org\r\n\r\n").await;
+        assert!(result.is_err());
+
+        // Neither a second Host nor a target authority can steer the backend elsewhere
+        let (result, response) =
+            send(handler(), b"GET /users HTTP/1.1\r\nHost: api.example.org\r\nHost: db.example.org\r\n\r\n").await;
+        assert!(result.is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+        let (result, response) =
+            send(handler(), b"GET http://db.example.org/users HTTP/1.1\r\nHost: api.example.org\r\n\r\n").await;
+        assert!(result.is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_policy_sees_request_line() {
+        let (backend_addr, received) = recording_backend().await;
+
+        let (result, response) = send(anonymous_handler(&backend_addr), b"GET /health HTTP/1.1\r\n\r\n").await;
+        assert!(result.is_ok());
+        assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+        assert!(received.await.unwrap().starts_with("GET /health HTTP/1.1\r\n"));
+
+        let (result, _) = send(anonymous_handler(&backend_addr), b"GET /admin HTTP/1.1\r\n\r\n").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_client_is_not_matched_by_wildcard() {
+        let policy = YamlPolicyEngine::from_yaml(
+            r#"
+            default_action: false
+            rules:
+              - spiffe_id: "*"
+                allow: true
+            "#,
+        )
+        .unwrap();
+        let handler = HttpHandler::new(
+            backend_config("127.0.0.1:1"),
+            Arc::new(policy),
+            Arc::new(SpiffeVerifier::new("example.org".to_string()).with_mtls_mode(MtlsMode::Required)),
+        )
+        .unwrap();
+
+        // Even with mTLS required, a client from the plaintext window is anonymous
+        let (client, mut peer) = tokio::io::duplex(4096);
+        let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap()).with_plaintext(true);
+        let handled = tokio::spawn(async move { handler.handle(stream).await });
+        peer.write_all(b"GET /health HTTP/1.1\r\n\r\n").await.unwrap();
+        peer.shutdown().await.unwrap();
+
+        let err = handled.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("denied by policy"));
+    }
+
+    /// Handler allowing `GET /api` to the frontend's JWT-SVID, and a key
+    /// trusted to sign them
+    fn jwt_handler(backend_addr: &str) -> (HttpHandler, rcgen::KeyPair) {
+        let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let mut verifier = JwtSvidVerifier::new(vec!["spiffe://example.org/service/backend".to_string()]);
+        verifier.add_bundle("example.org", &jwks_for(&key_pair)).unwrap();
+        let handler = handler_with_policy(
+            backend_addr,
+            r#"
+            default_action: false
+            rules:
+              - spiffe_id: "spiffe://example.org/service/frontend"
+                method: "GET /api"
+                allow: true
+            "#,
+        )
+        .with_jwt_verifier(Some(verifier));
+        (handler, key_pair)
+    }
+
+    #[tokio::test]
+    async fn test_bearer_jwt_svid_identifies_client() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        let backend_task = tokio::spawn(async move {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            let mut request = vec![0u8; 1024];
+            let n = socket.read(&mut request).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+            n
+        });
+
+        let (handler, key_pair) = jwt_handler(&backend_addr);
+        let token = sign_jwt_svid(
+            &key_pair,
+            "spiffe://example.org/service/frontend",
+            "spiffe://example.org/service/backend",
+        );
+        let (client, mut peer) = tokio::io::duplex(4096);
+        let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap());
+        let handled = tokio::spawn(async move { handler.handle(stream).await });
+        peer.write_all(format!("GET /api HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n", token).as_bytes())
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        peer.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+        assert!(backend_task.await.unwrap() > 0);
+        handled.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bearer_token_is_rejected() {
+        let (handler, key_pair) = jwt_handler("127.0.0.1:1");
+        let handler = Arc::new(handler);
+        let wrong_audience = sign_jwt_svid(
+            &key_pair,
+            "spiffe://example.org/service/frontend",
+            "spiffe://example.org/service/other",
+        );
+
+        for authorization in [format!("Bearer {}", wrong_audience), "Basic dXNlcjpwYXNz".to_string()] {
+            let (client, mut peer) = tokio::io::duplex(4096);
+            let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap());
+            let handler = handler.clone();
+            let handled = tokio::spawn(async move { handler.handle(stream).await });
+            peer.write_all(format!("GET /api HTTP/1.1\r\nAuthorization: {}\r\n\r\n", authorization).as_bytes())
+                .await
+                .unwrap();
+
+            let mut response = Vec::new();
+            peer.read_to_end(&mut response).await.unwrap();
+            assert!(response.starts_with(b"HTTP/1.1 401 Unauthorized\r\n"));
+            assert!(handled.await.unwrap().is_err());
+        }
+
+        // Without a token the client stays anonymous and policy denies it
+        let (client, mut peer) = tokio::io::duplex(4096);
+        let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap());
+        let handled = tokio::spawn(async move { handler.handle(stream).await });
+        peer.write_all(b"GET /api HTTP/1.1\r\n\r\n").await.unwrap();
+        let err = handled.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("denied by policy"));
+    }
+
+    #[test]
+    fn test_header_values() {
+        assert!(header_values(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", "authorization").is_empty());
+        assert_eq!(
+            header_values(
+                b"GET / HTTP/1.1\r\nauthorization:  Bearer x \r\nAuthorization: Basic y\r\n\r\n",
+                "authorization"
+            ),
+            vec!["Bearer x", "Basic y"]
+        );
+        // Lines of an unfinished head are not trusted
+        assert!(header_values(b"GET / HTTP/1.1\r\nAuthorization: Bearer x\r\n", "authorization").is_empty());
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(request_id(b"GET / HTTP/1.1\r\nx-request-id: abc-123\r\nX-Request-Id: other\r\n\r\n"), "abc-123");
+
+        // Missing, empty, oversized or non-visible IDs are replaced
+        let long = format!("GET / HTTP/1.1\r\nX-Request-Id: {}\r\n\r\n", "a".repeat(MAX_REQUEST_ID_LEN + 1));
+        for head in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"GET / HTTP/1.1\r\nX-Request-Id:\r\n\r\n",
+            long.as_bytes(),
+            b"GET / HTTP/1.1\r\nX-Request-Id: a b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nX-Request-Id: caf\xc3\xa9\r\n\r\n",
+        ] {
+            assert!(uuid::Uuid::parse_str(&request_id(head)).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inbound_request_id_is_preserved() {
+        let (backend_addr, received) = recording_backend().await;
+        let (result, response) = send(
+            anonymous_handler(&backend_addr),
+            b"GET /health HTTP/1.1\r\nX-Request-Id: abc-123\r\n\r\n",
+        )
+        .await;
+        assert!(result.is_ok());
+
+        assert_eq!(
+            received.await.unwrap(),
+            "GET /health HTTP/1.1\r\nX-Request-Id: abc-123\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nX-Request-Id: abc-123\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_id_generated_when_absent() {
+        let (logs, _guard) = CapturedLogs::install(tracing::Level::INFO);
+        let (backend_addr, received) = recording_backend().await;
+        let (result, response) = send(anonymous_handler(&backend_addr), b"GET /health HTTP/1.1\r\n\r\n").await;
+        assert!(result.is_ok());
+
+        // The backend and the client see the same new ID
+        let received = received.await.unwrap();
+        let id = header_values(received.as_bytes(), REQUEST_ID_HEADER)[0];
+        assert!(uuid::Uuid::parse_str(id).is_ok());
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.contains(&format!("\r\nX-Request-Id: {}\r\n", id)));
+
+        // And the request's logs carry it
+        let output = logs.contents();
+        assert!(output.contains(&format!("request{{method=GET path=/health request_id={}}}", id)));
+    }
+
+    #[tokio::test]
+    async fn test_one_request_per_connection() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        let backend_task = tokio::spawn(async move {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 256];
+            while !request.ends_with(b"GET /admin HTTP/1.1\r\n\r\n") {
+                let n = socket.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "client bytes stopped before the pipelined request");
+                request.extend_from_slice(&chunk[..n]);
+            }
+            // A compliant backend answers once and closes, as Connection: close asks
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        // The client keeps its side open, pipelining a request policy never saw
+        let (client, mut peer) = tokio::io::duplex(4096);
+        let stream = ClientStream::new(client, "127.0.0.1:50000".parse().unwrap());
+        let handler = anonymous_handler(&backend_addr);
+        let handled = tokio::spawn(async move { handler.handle(stream).await });
+        peer.write_all(
+            b"GET /health HTTP/1.1\r\nConnection: keep-alive\r\nX-Request-Id: first\r\n\r\nGET /admin HTTP/1.1\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        let mut response = Vec::new();
+        peer.read_to_end(&mut response).await.unwrap();
+        assert!(handled.await.unwrap().is_ok());
+        assert_eq!(
+            response,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nX-Request-Id: first\r\nConnection: close\r\n\r\n"
+        );
+
+        // Only the first head is rewritten; the pipelined request still reaches
+        // the backend, which must not serve it after Connection: close
+        let forwarded = backend_task.await.unwrap();
+        assert_eq!(
+            forwarded,
+            "GET /health HTTP/1.1\r\nX-Request-Id: first\r\nConnection: close\r\n\r\nGET /admin HTTP/1.1\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_request_is_rejected() {
+        let (result, response) = send(anonymous_handler("127.0.0.1:1"), b"GET /health\r\n\r\n").await;
+        assert!(result.is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+
+        // A head the client never finishes is not evaluated either
+        let (result, response) = send(anonymous_handler("127.0.0.1:1"), b"GET /health HTTP/1.1\r\nHost: a").await;
+        assert!(result.is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn test_force_connection_close() {
+        assert_eq!(
+            force_connection_close(
+                b"GET / HTTP/1.1\r\nHost: a\r\nconnection: Keep-Alive\r\nKeep-Alive: timeout=5\r\nx-request-id: a\r\n\r\n",
+                "a"
+            ),
+            b"GET / HTTP/1.1\r\nHost: a\r\nX-Request-Id: a\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(
+            force_connection_close(b"GET / HTTP/1.1\r\n\r\n", "b"),
+            b"GET / HTTP/1.1\r\nX-Request-Id: b\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_headers_rewritten() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = backend.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 256];
+            while head_len(&request).is_none() {
+                let n = socket.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "client bytes stopped before the end of the head");
+                request.extend_from_slice(&chunk[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nServer: Apache/2.4.1\r\nX-Powered-By: PHP/8.2\r\nContent-Length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        let rules = ResponseHeaderRules::new(
+            &[crate::config::HeaderConfig {
+                name: "Strict-Transport-Security".to_string(),
+                value: "max-age=63072000".to_string(),
+            }],
+            &["server".to_string(), "x-powered-by".to_string()],
+        );
+        let handler = anonymous_handler(&backend_addr).with_response_headers(rules);
+
+        let (result, response) = send(handler, b"GET /health HTTP/1.1\r\nX-Request-Id: req-1\r\n\r\n").await;
+        assert!(result.is_ok());
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nStrict-Transport-Security: max-age=63072000\r\nX-Request-Id: req-1\r\nConnection: close\r\n\r\nok"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_is_rejected() {
+        let (result, response) = send(
+            anonymous_handler("127.0.0.1:1"),
+            b"CONNECT example.org:443 HTTP/1.1\r\nHost: example.org:443\r\n\r\n",
+        )
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("CONNECT tunneling is not supported"));
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(response.ends_with("CONNECT tunneling is not supported by this proxy"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_headers_are_rejected() {
+        let limits = HttpLimitsConfig {
+            max_header_count: 4,
+            max_total_header_bytes: 256,
+        };
+        let handler = || anonymous_handler("127.0.0.1:1").with_limits(limits.clone());
+        let expect_431 = |(result, response): (Result<()>, Vec<u8>)| {
+            assert!(result.is_err());
+            assert!(String::from_utf8(response)
+                .unwrap()
+                .starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        };
+
+        // One huge header, sent without ever ending the head
+        let request = format!("GET /health HTTP/1.1\r\nCookie: {}\r\n", "a".repeat(512));
+        expect_431(send(handler(), request.as_bytes()).await);
+
+        // Too many small headers
+        let headers: String = (0..5).map(|i| format!("X-Header-{}: {}\r\n", i, i)).collect();
+        let request = format!("GET /health HTTP/1.1\r\n{}\r\n", headers);
+        expect_431(send(handler(), request.as_bytes()).await);
+
+        // Within both limits the request goes on to the backend
+        let (result, response) = send(handler(), b"GET /health HTTP/1.1\r\nHost: a\r\n\r\n").await;
+        assert!(result.unwrap_err().to_string().contains("Failed to connect"));
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn test_check_head_limits() {
+        let limits = HttpLimitsConfig {
+            max_header_count: 2,
+            max_total_header_bytes: 64,
+        };
+        assert!(check_head_limits(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\nbody", &limits).is_ok());
+        assert!(check_head_limits(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n", &limits).is_err());
+
+        // An unfinished head is only too large once it fills the read-ahead limit
+        assert!(check_head_limits(b"GET / HTTP/1.1\r\nA: 1", &limits).is_ok());
+        assert!(check_head_limits(&[b'x'; 64], &limits).is_err());
+    }
+
+    #[test]
+    fn test_render_default_deny_response() {
+        let response = render_deny_response(
+            &DenyResponseConfig::default(),
+            "spiffe://example.org/service/web",
+            "GET /admin",
+            None,
+            "req-1",
+        );
+
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            "HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nContent-Length: 23\r\nX-Request-Id: req-1\r\nConnection: close\r\n\r\nAccess denied by policy"
+        );
+    }
+
+    #[test]
+    fn test_render_deny_response_template() {
+        let config = DenyResponseConfig {
+            status: 401,
+            content_type: "application/json".to_string(),
+            body: r#"{"error":"denied","spiffe_id":"{spiffe_id}","method":"{method}","rule":"{rule}"}"#.to_string(),
+        };
+
+        let response = String::from_utf8(render_deny_response(
+            &config,
+            "spiffe://example.org/service/web",
+            "POST /api",
+            Some(2),
+            "req-1",
+        ))
+        .unwrap();
+
+        let expected_body =
+            r#"{"error":"denied","spiffe_id":"spiffe://example.org/service/web","method":"POST /api","rule":"2"}"#;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("Content-Type: application/json\r\n"));
+        assert!(response.contains(&format!("Content-Length: {}\r\n", expected_body.len())));
+        assert!(response.ends_with(expected_body));
+    }
+
+    #[tokio::test]
+    async fn test_deny_response_names_the_rule() {
+        let policy = r#"
+            default_action: false
+            rules:
+              - spiffe_id: "anonymous"
+                method: "GET /health"
+                allow: true
+              - spiffe_id: "anonymous"
+                method: "GET /admin"
+                allow: false
+            "#;
+        let deny_response = DenyResponseConfig {
+            body: "denied by {rule}".to_string(),
+            ..DenyResponseConfig::default()
+        };
+
+        for (request, body) in [
+            (&b"GET /admin HTTP/1.1\r\n\r\n"[..], "denied by 2"),
+            (&b"GET /other HTTP/1.1\r\n\r\n"[..], "denied by default"),
+        ] {
+            let handler = handler_with_policy("127.0.0.1:1", policy).with_deny_response(Some(deny_response.clone()));
+            let (result, response) = send(handler, request).await;
+            assert!(result.is_err());
+            assert!(String::from_utf8(response).unwrap().ends_with(body));
+        }
+    }
 
-        // Check policy
-        let allowed = self.base.policy_engine.allow(spiffe_id, &method_path);
-        telemetry::record_policy_decision(spiffe_id, &method_path, allowed);
+    #[test]
+    fn test_deny_response_escapes_request_values() {
+        let config = DenyResponseConfig {
+            status: 403,
+            content_type: "application/problem+json; charset=utf-8".to_string(),
+            body: r#"{"method":"{method}"}"#.to_string(),
+        };
+        let response = render_deny_response(&config, "anonymous", r#"GET /a","admin":true,"x":"\"#, None, "req-1");
+        let body = String::from_utf8(response).unwrap().split("\r\n\r\n").nth(1).unwrap().to_string();
+        let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed, serde_json::json!({"method": r#"GET /a","admin":true,"x":"\"#}));
 
-        // Use base handler to connect and forward
-        self.base.connect_and_forward(client_stream, &connection_info, spiffe_id, &method_path, allowed).await
+        let config = DenyResponseConfig {
+            status: 403,
+            content_type: "text/html".to_string(),
+            body: "<p>{method}</p>".to_string(),
+        };
+        let response = String::from_utf8(render_deny_response(&config, "anonymous", "GET /<script>", None, "req-1")).unwrap();
+        assert!(response.ends_with("<p>GET /&lt;script&gt;</p>"));
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod grpc;
 pub mod http_tls;
-pub mod raw_tcp;
\ No newline at end of file
+pub mod raw_tcp;
+pub mod response_headers;
\ No newline at end of file
@@ -1,13 +1,12 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::sync::Arc;
-use tokio::net::TcpStream;
 
-use crate::common::{ConnectionInfo, ProtocolType, PqSecureError};
+use crate::common::{ConnectionInfo, ProtocolType};
 use crate::config::BackendConfig;
 use crate::identity::SpiffeVerifier;
-use crate::policy::PolicyEngine;
+use crate::policy::{policy_method_for, PolicyEngine};
+use crate::proxy::client_stream::ClientStream;
 use crate::proxy::handler::{BaseHandler, DefaultConnectionHandler};
-use crate::proxy::pqc_acceptor::get_current_client_cert;
 use crate::telemetry;
 
 /// Handler for raw TCP connections
@@ -34,7 +33,7 @@ impl DefaultConnectionHandler for TcpHandler {
         "TCP"
     }
 
-    async fn can_handle(&self, _stream: &TcpStream) -> bool {
+    async fn can_handle(&self, _stream: &ClientStream) -> bool {
         // TCP handler can handle any connection
         true
     }
@@ -42,33 +41,29 @@ impl DefaultConnectionHandler for TcpHandler {
 
 #[async_trait::async_trait]
 impl crate::proxy::handler::ConnectionHandler for TcpHandler {
-    async fn handle(&self, client_stream: TcpStream) -> Result<()> {
+    async fn handle(&self, client_stream: ClientStream) -> Result<()> {
         // Get client address
-        let client_addr = client_stream.peer_addr()?;
+        let client_addr = client_stream.peer_addr();
 
         // Create connection info
-        let mut connection_info = ConnectionInfo::new(client_addr, ProtocolType::Tcp);
+        let mut connection_info = ConnectionInfo::new(client_addr, ProtocolType::Tcp)
+            .with_id(client_stream.connection_id());
 
-        // Get client certificate from thread-local storage
-        let client_cert = get_current_client_cert()
-            .ok_or_else(|| PqSecureError::AuthenticationError("No client certificate found".to_string()))?;
-
-        // Extract SPIFFE ID from certificate
-        let identity = self.base.extract_spiffe_id(&client_cert)
-            .context("Failed to extract SPIFFE ID from certificate")?;
+        // Identify the client from its certificate, or as anonymous over plaintext
+        let identity = self.base.stream_identity(&client_stream)?;
 
         // Update connection info with identity
         connection_info = connection_info.with_identity(identity.clone());
 
-        // Policy check with generic method for TCP
-        let method = "connect";
+        // Raw TCP has no method of its own
+        let method = policy_method_for(ProtocolType::Tcp, None);
         let spiffe_id = &identity.spiffe_id;
 
         // Check if the connection is allowed by policy
-        let allowed = self.base.policy_engine.allow(spiffe_id, method);
-        telemetry::record_policy_decision(spiffe_id, method, allowed);
+        let allowed = self.base.policy_engine.allow_for_host(spiffe_id, ProtocolType::Tcp, &method, client_stream.server_name());
+        telemetry::record_policy_decision(spiffe_id, &method, allowed);
 
         // Use base handler to connect and forward
-        self.base.connect_and_forward(client_stream, &connection_info, spiffe_id, method, allowed).await
+        self.base.connect_and_forward(client_stream, &connection_info, spiffe_id, &method, allowed).await
     }
 }
\ No newline at end of file
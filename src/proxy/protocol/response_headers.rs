@@ -0,0 +1,318 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::warn;
+
+use crate::config::HeaderConfig;
+use crate::proxy::protocol::http_tls::REQUEST_ID_HEADER;
+
+/// Longest response head buffered for rewriting; longer heads pass unchanged
+const MAX_RESPONSE_HEAD_BYTES: usize = 64 * 1024;
+
+/// Headers added to and removed from HTTP responses
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHeaderRules {
+    /// Headers appended to every rewritten response, replacing any the
+    /// backend sent under the same name
+    add: Vec<(String, String)>,
+
+    /// Names of headers dropped from responses
+    remove: Vec<String>,
+}
+
+impl ResponseHeaderRules {
+    /// Create rules from configuration, or `None` if there is nothing to rewrite
+    pub fn new(add: &[HeaderConfig], remove: &[String]) -> Option<Self> {
+        if add.is_empty() && remove.is_empty() {
+            return None;
+        }
+        Some(Self {
+            add: add.iter().map(|header| (header.name.clone(), header.value.clone())).collect(),
+            remove: remove.to_vec(),
+        })
+    }
+
+    /// Apply the rules to a complete response head, ending with its blank line
+    ///
+    /// The response also gets `Connection: close`, replacing any
+    /// `Connection` or `Keep-Alive` header, as it is the only one the
+    /// connection carries. A `request_id` replaces any `X-Request-Id` the
+    /// backend sent.
+    fn rewrite(&self, head: &[u8], request_id: Option<&str>) -> Vec<u8> {
+        let head = head.strip_suffix(b"\r\n\r\n").unwrap_or(head);
+        let mut lines = head.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+
+        let mut rewritten = Vec::with_capacity(head.len() + 128);
+        rewritten.extend_from_slice(lines.next().unwrap_or_default());
+        rewritten.extend_from_slice(b"\r\n");
+        for line in lines {
+            let name = line.split(|&b| b == b':').next().unwrap_or_default().trim_ascii();
+            let replaced = self
+                .remove
+                .iter()
+                .map(String::as_str)
+                .chain(self.add.iter().map(|(name, _)| name.as_str()))
+                .chain(["Connection", "Keep-Alive"])
+                .chain(request_id.map(|_| REQUEST_ID_HEADER))
+                .any(|dropped| dropped.as_bytes().eq_ignore_ascii_case(name));
+            if !replaced {
+                rewritten.extend_from_slice(line);
+                rewritten.extend_from_slice(b"\r\n");
+            }
+        }
+        for (name, value) in &self.add {
+            rewritten.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        if let Some(request_id) = request_id {
+            rewritten.extend_from_slice(format!("{}: {}\r\n", REQUEST_ID_HEADER, request_id).as_bytes());
+        }
+        rewritten.extend_from_slice(b"Connection: close\r\n\r\n");
+        rewritten
+    }
+}
+
+/// Status code of a response head, from its status line
+fn status_code(head: &[u8]) -> Option<u16> {
+    let line = head.split(|&b| b == b'\r').next()?;
+    let code = line.split(|&b| b == b' ').nth(1)?;
+    std::str::from_utf8(code).ok()?.parse().ok()
+}
+
+/// Backend stream whose first HTTP response head is rewritten as it is read
+///
+/// The HTTP handler sends a single request per connection with
+/// `Connection: close`, so the first final response is the only one, and it
+/// is marked `Connection: close` for the client as well. Interim `1xx`
+/// responses are passed on as they are and the final response after them is
+/// rewritten. A response head that cannot be found, such as after `101
+/// Switching Protocols`, leaves the stream untouched.
+pub struct ResponseHeaderRewriter<B> {
+    /// Backend connection
+    inner: B,
+
+    /// Rules applied to the response head
+    rules: Arc<ResponseHeaderRules>,
+
+    /// Request ID echoed in the response head
+    request_id: Option<String>,
+
+    /// Bytes read while looking for the end of the response head
+    head: Vec<u8>,
+
+    /// Bytes ready to be returned to the reader
+    pending: Vec<u8>,
+
+    /// How many of the pending bytes were returned
+    returned: usize,
+
+    /// Whether the response head was handled and the rest passes through
+    done: bool,
+}
+
+impl<B> ResponseHeaderRewriter<B> {
+    /// Rewrite the first response read from `inner`
+    pub fn new(inner: B, rules: Arc<ResponseHeaderRules>) -> Self {
+        Self {
+            inner,
+            rules,
+            request_id: None,
+            head: Vec::new(),
+            pending: Vec::new(),
+            returned: 0,
+            done: false,
+        }
+    }
+
+    /// Echo the request's ID in the response head
+    pub fn with_request_id(mut self, request_id: String) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
+    /// Queue bytes for the reader and stop rewriting
+    fn pass_through(&mut self, bytes: Vec<u8>) {
+        self.pending = bytes;
+        self.returned = 0;
+        self.done = true;
+    }
+
+    /// Handle a complete head at the start of the buffered bytes, if any
+    fn take_head(&mut self) -> bool {
+        let Some(end) = self.head.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return false;
+        };
+        let rest = self.head.split_off(end + 4);
+        let head = std::mem::replace(&mut self.head, rest);
+
+        match status_code(&head) {
+            // The protocol changes after 101, so nothing more to rewrite
+            Some(101) | None => {
+                let mut bytes = head;
+                bytes.append(&mut self.head);
+                self.pass_through(bytes);
+            }
+            // Interim responses go out as they are; the final one follows
+            Some(code) if (100..200).contains(&code) => {
+                self.pending = head;
+                self.returned = 0;
+            }
+            Some(_) => {
+                let mut bytes = self.rules.rewrite(&head, self.request_id.as_deref());
+                bytes.append(&mut self.head);
+                self.pass_through(bytes);
+            }
+        }
+        true
+    }
+}
+
+impl<B: AsyncRead + Unpin> AsyncRead for ResponseHeaderRewriter<B> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.returned < this.pending.len() {
+                let pending = &this.pending[this.returned..];
+                let n = pending.len().min(buf.remaining());
+                buf.put_slice(&pending[..n]);
+                this.returned += n;
+                if this.returned == this.pending.len() {
+                    this.pending.clear();
+                    this.returned = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            }
+            if this.take_head() {
+                continue;
+            }
+            if this.head.len() > MAX_RESPONSE_HEAD_BYTES {
+                warn!("Response head exceeds {} bytes, forwarding it unchanged", MAX_RESPONSE_HEAD_BYTES);
+                let head = std::mem::take(&mut this.head);
+                this.pass_through(head);
+                continue;
+            }
+
+            let mut chunk = [0u8; 8 * 1024];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                // The backend closed before finishing a head
+                let head = std::mem::take(&mut this.head);
+                this.pass_through(head);
+                if this.pending.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+            this.head.extend_from_slice(read.filled());
+        }
+    }
+}
+
+impl<B: AsyncWrite + Unpin> AsyncWrite for ResponseHeaderRewriter<B> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn rules() -> Arc<ResponseHeaderRules> {
+        let add = vec![
+            HeaderConfig {
+                name: "Strict-Transport-Security".to_string(),
+                value: "max-age=31536000".to_string(),
+            },
+            HeaderConfig {
+                name: "X-Content-Type-Options".to_string(),
+                value: "nosniff".to_string(),
+            },
+        ];
+        let remove = vec!["Server".to_string(), "X-Powered-By".to_string()];
+        Arc::new(ResponseHeaderRules::new(&add, &remove).unwrap())
+    }
+
+    #[test]
+    fn test_rewrite_head() {
+        let rewritten = rules().rewrite(
+            b"HTTP/1.1 200 OK\r\nserver: nginx\r\nContent-Length: 2\r\nX-Powered-By: PHP\r\nx-content-type-options: sniff\r\n\r\n",
+            None,
+        );
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nStrict-Transport-Security: max-age=31536000\r\nX-Content-Type-Options: nosniff\r\nConnection: close\r\n\r\n"
+        );
+
+        assert!(ResponseHeaderRules::new(&[], &[]).is_none());
+    }
+
+    #[test]
+    fn test_rewrite_echoes_request_id() {
+        let rewritten = ResponseHeaderRules::default().rewrite(
+            b"HTTP/1.1 200 OK\r\nx-request-id: from-backend\r\nContent-Length: 0\r\n\r\n",
+            Some("req-1"),
+        );
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nX-Request-Id: req-1\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    /// Read everything the backend sends through a rewriter, sent in `chunks`
+    async fn read_rewritten(chunks: Vec<&'static [u8]>) -> String {
+        let (backend, mut peer) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            for chunk in chunks {
+                peer.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let mut output = Vec::new();
+        ResponseHeaderRewriter::new(backend, rules())
+            .read_to_end(&mut output)
+            .await
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_final_response_is_rewritten() {
+        let output = read_rewritten(vec![
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nSer",
+            b"ver: nginx\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\nContent-Length: 5\r\n\r\nhello",
+        ])
+        .await;
+
+        // The client is told the connection ends with this response
+        assert_eq!(
+            output,
+            "HTTP/1.1 100 Continue\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 5\r\nStrict-Transport-Security: max-age=31536000\r\nX-Content-Type-Options: nosniff\r\nConnection: close\r\n\r\nhello"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_http_response_passes_unchanged() {
+        assert_eq!(read_rewritten(vec![b"not http at all"]).await, "not http at all");
+        assert_eq!(
+            read_rewritten(vec![b"HTTP/1.1 101 Switching Protocols\r\nServer: x\r\n\r\nframes"]).await,
+            "HTTP/1.1 101 Switching Protocols\r\nServer: x\r\n\r\nframes"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
+use tracing::trace;
+
+use crate::proxy::client_stream::ClientStream;
+
+/// Connection preface every HTTP/2 client sends first (RFC 9113, section 3.4)
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Request methods recognised at the start of an HTTP/1.x request
+const HTTP1_METHODS: &[&[u8]] = &[
+    b"GET", b"POST", b"PUT", b"HEAD", b"DELETE", b"OPTIONS", b"PATCH", b"CONNECT", b"TRACE",
+];
+
+/// Fewest bytes needed to tell the protocols apart
+const MIN_SNIFF_BYTES: usize = 3;
+
+/// Protocol recognised from the first bytes a client sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffedProtocol {
+    /// An HTTP/1.x request line
+    Http1,
+    /// The HTTP/2 connection preface (gRPC runs over this)
+    Http2,
+    /// A TLS record header
+    Tls,
+    /// Anything else, or too little data to decide
+    Unknown,
+}
+
+/// Classifies a connection from its first bytes without consuming them
+#[derive(Debug, Clone, Copy)]
+pub struct ProtocolSniffer {
+    /// Largest number of bytes to peek at
+    max_bytes: usize,
+    /// How long to wait for the client to send something
+    timeout: Duration,
+}
+
+impl Default for ProtocolSniffer {
+    fn default() -> Self {
+        Self {
+            max_bytes: HTTP2_PREFACE.len(),
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ProtocolSniffer {
+    /// Create a sniffer peeking at up to `max_bytes` within `timeout`
+    pub fn new(max_bytes: usize, timeout: Duration) -> Self {
+        Self {
+            max_bytes: max_bytes.max(MIN_SNIFF_BYTES),
+            timeout,
+        }
+    }
+
+    /// Read ahead on the stream until its protocol can be told, then classify it
+    ///
+    /// The bytes read stay in the stream for whichever handler takes the
+    /// connection. Clients that send nothing recognisable within the timeout
+    /// are `Unknown`.
+    pub async fn sniff(&self, stream: &mut ClientStream) -> SniffedProtocol {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let protocol = Self::classify(stream.peeked());
+            if protocol != SniffedProtocol::Unknown || stream.peeked().len() >= self.max_bytes {
+                trace!("Sniffed {:?} from {} bytes", protocol, stream.peeked().len());
+                return protocol;
+            }
+
+            match timeout_at(deadline, stream.peek_more(self.max_bytes)).await {
+                Ok(Ok(n)) if n > 0 => {}
+                _ => return protocol,
+            }
+        }
+    }
+
+    /// Classify the first bytes of a connection
+    ///
+    /// A partial HTTP/2 preface or request line is accepted as long as at
+    /// least three bytes are available, since the client may still be
+    /// sending the rest.
+    pub fn classify(bytes: &[u8]) -> SniffedProtocol {
+        if bytes.len() < MIN_SNIFF_BYTES {
+            return SniffedProtocol::Unknown;
+        }
+
+        if is_prefix_or_extension(bytes, HTTP2_PREFACE) {
+            return SniffedProtocol::Http2;
+        }
+
+        // Content type (change_cipher_spec..application_data), then a 3.x version
+        if (0x14..=0x17).contains(&bytes[0]) && bytes[1] == 0x03 && bytes[2] <= 0x04 {
+            return SniffedProtocol::Tls;
+        }
+
+        let is_request_line = HTTP1_METHODS.iter().any(|method| {
+            let mut token = method.to_vec();
+            token.push(b' ');
+            is_prefix_or_extension(bytes, &token)
+        });
+        if is_request_line {
+            return SniffedProtocol::Http1;
+        }
+
+        SniffedProtocol::Unknown
+    }
+}
+
+/// Whether `bytes` starts with `pattern`, or is itself the start of `pattern`
+fn is_prefix_or_extension(bytes: &[u8], pattern: &[u8]) -> bool {
+    let len = bytes.len().min(pattern.len());
+    bytes[..len] == pattern[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_classify_prefaces() {
+        assert_eq!(
+            ProtocolSniffer::classify(b"GET /api/v1/users HTTP/1.1\r\nHost: a\r\n\r\n"),
+            SniffedProtocol::Http1
+        );
+        assert_eq!(ProtocolSniffer::classify(b"OPTIONS * HTTP/1.1\r\n"), SniffedProtocol::Http1);
+        assert_eq!(ProtocolSniffer::classify(b"DEL"), SniffedProtocol::Http1);
+        assert_eq!(ProtocolSniffer::classify(HTTP2_PREFACE), SniffedProtocol::Http2);
+        assert_eq!(ProtocolSniffer::classify(b"PRI * HTTP/2"), SniffedProtocol::Http2);
+
+        // TLS 1.2/1.3 ClientHello and an application data record
+        assert_eq!(ProtocolSniffer::classify(&[0x16, 0x03, 0x01, 0x02, 0x00]), SniffedProtocol::Tls);
+        assert_eq!(ProtocolSniffer::classify(&[0x17, 0x03, 0x03, 0x00, 0x20]), SniffedProtocol::Tls);
+
+        // Lookalikes and short reads
+        assert_eq!(ProtocolSniffer::classify(b"GETTER"), SniffedProtocol::Unknown);
+        assert_eq!(ProtocolSniffer::classify(b"PRIVATE"), SniffedProtocol::Unknown);
+        assert_eq!(ProtocolSniffer::classify(&[0x16, 0x02, 0x01]), SniffedProtocol::Unknown);
+        assert_eq!(ProtocolSniffer::classify(b"\x00\x01binary"), SniffedProtocol::Unknown);
+        assert_eq!(ProtocolSniffer::classify(b"GE"), SniffedProtocol::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_sniff_does_not_consume() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let mut stream = ClientStream::new(server, "127.0.0.1:4000".parse().unwrap());
+        let sniffer = ProtocolSniffer::default();
+
+        // Nothing sent yet
+        assert_eq!(sniffer.sniff(&mut stream).await, SniffedProtocol::Unknown);
+
+        // The preface arrives in pieces
+        client.write_all(&HTTP2_PREFACE[..2]).await.unwrap();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            client.write_all(&HTTP2_PREFACE[2..]).await.unwrap();
+            client.shutdown().await.unwrap();
+        });
+        assert_eq!(sniffer.sniff(&mut stream).await, SniffedProtocol::Http2);
+        writer.await.unwrap();
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, HTTP2_PREFACE);
+    }
+}
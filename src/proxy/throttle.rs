@@ -0,0 +1,247 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{sleep_until, Instant, Sleep};
+
+/// Shortest pause when a bucket is empty, so pacing does not wake for every byte
+const MIN_PACING_DELAY: Duration = Duration::from_millis(10);
+
+/// Token bucket holding up to one second of bytes at a fixed rate
+#[derive(Debug)]
+struct TokenBucket {
+    /// Bytes added per second, also the bucket's capacity
+    rate: u64,
+
+    /// Bytes that may pass right now
+    tokens: u64,
+
+    /// When tokens were last added
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Add the tokens earned since the last refill and return those available
+    fn available(&mut self) -> u64 {
+        let now = Instant::now();
+        let earned = (now - self.refilled_at).as_secs_f64() * self.rate as f64;
+        if earned >= 1.0 {
+            self.tokens = (self.tokens + earned as u64).min(self.rate);
+            self.refilled_at = now;
+        }
+        self.tokens
+    }
+
+    /// When enough tokens will have been earned to be worth waking up for
+    fn next_refill(&self) -> Instant {
+        let wanted = Duration::from_secs_f64(1.0 / self.rate as f64);
+        self.refilled_at + wanted.max(MIN_PACING_DELAY)
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.tokens = self.tokens.saturating_sub(bytes as u64);
+    }
+}
+
+/// One paced direction of a stream
+#[derive(Debug)]
+struct Pacer {
+    bucket: TokenBucket,
+
+    /// Wake-up while waiting for tokens
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Pacer {
+    fn new(rate: u64) -> Self {
+        Self {
+            bucket: TokenBucket::new(rate),
+            delay: None,
+        }
+    }
+
+    /// Wait until some bytes may pass and return how many
+    fn poll_allowance(&mut self, cx: &mut Context<'_>) -> Poll<usize> {
+        loop {
+            let tokens = self.bucket.available();
+            if tokens > 0 {
+                self.delay = None;
+                return Poll::Ready(tokens.min(usize::MAX as u64) as usize);
+            }
+
+            let wake_at = self.bucket.next_refill();
+            let delay = self.delay.get_or_insert_with(|| Box::pin(sleep_until(wake_at)));
+            if delay.deadline() != wake_at {
+                delay.as_mut().reset(wake_at);
+            }
+            ready!(delay.as_mut().poll(cx));
+        }
+    }
+}
+
+/// Stream wrapper capping throughput in each direction and counting bytes
+///
+/// Reads and writes are paced by separate token buckets, each allowing
+/// `max_bytes_per_second` with bursts of up to one second's worth. Without a
+/// limit the wrapper only counts. Anything copying through the stream is
+/// paced, whether a manual copy loop or `copy_bidirectional`.
+#[derive(Debug)]
+pub struct ThrottledStream<S> {
+    inner: S,
+
+    /// Pacing of bytes read from `inner`
+    read_pacer: Option<Pacer>,
+
+    /// Pacing of bytes written to `inner`
+    write_pacer: Option<Pacer>,
+
+    /// Bytes read so far
+    bytes_read: u64,
+
+    /// Bytes written so far
+    bytes_written: u64,
+}
+
+impl<S> ThrottledStream<S> {
+    /// Wrap `inner`, limiting each direction to `max_bytes_per_second` if set
+    pub fn new(inner: S, max_bytes_per_second: Option<u64>) -> Self {
+        let max_bytes_per_second = max_bytes_per_second.filter(|rate| *rate > 0);
+        Self {
+            inner,
+            read_pacer: max_bytes_per_second.map(Pacer::new),
+            write_pacer: max_bytes_per_second.map(Pacer::new),
+            bytes_read: 0,
+            bytes_written: 0,
+        }
+    }
+
+    /// Bytes read from the wrapped stream
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Bytes written to the wrapped stream
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let Some(pacer) = &mut this.read_pacer else {
+            let before = buf.filled().len();
+            ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+            this.bytes_read += (buf.filled().len() - before) as u64;
+            return Poll::Ready(Ok(()));
+        };
+
+        let allowance = ready!(pacer.poll_allowance(cx)).min(buf.remaining());
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(allowance));
+        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut limited))?;
+        let n = limited.filled().len();
+        buf.advance(n);
+
+        pacer.bucket.consume(n);
+        this.bytes_read += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let allowance = match &mut this.write_pacer {
+            Some(pacer) => ready!(pacer.poll_allowance(cx)).min(buf.len()),
+            None => buf.len(),
+        };
+
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..allowance]))?;
+        if let Some(pacer) = &mut this.write_pacer {
+            pacer.bucket.consume(n);
+        }
+        this.bytes_written += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const RATE: u64 = 40_000;
+
+    /// Time to push `len` bytes through a stream limited to `RATE`, in the
+    /// given direction
+    async fn transfer_time(len: usize, through_writes: bool) -> (Duration, u64) {
+        let (local, mut peer) = tokio::io::duplex(64 * 1024);
+        let mut throttled = ThrottledStream::new(local, Some(RATE));
+        let started = std::time::Instant::now();
+
+        if through_writes {
+            let reader = tokio::spawn(async move {
+                let mut received = Vec::new();
+                peer.read_to_end(&mut received).await.unwrap();
+                received.len()
+            });
+            throttled.write_all(&vec![7u8; len]).await.unwrap();
+            throttled.shutdown().await.unwrap();
+            assert_eq!(reader.await.unwrap(), len);
+            (started.elapsed(), throttled.bytes_written())
+        } else {
+            tokio::spawn(async move {
+                peer.write_all(&vec![7u8; len]).await.unwrap();
+                peer.shutdown().await.unwrap();
+            });
+            let mut received = Vec::new();
+            throttled.read_to_end(&mut received).await.unwrap();
+            assert_eq!(received.len(), len);
+            (started.elapsed(), throttled.bytes_read())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throughput_stays_under_cap() {
+        // A full bucket lets one second's worth through at once, the rest is paced
+        for through_writes in [false, true] {
+            let (elapsed, bytes) = transfer_time(3 * RATE as usize, through_writes).await;
+            assert_eq!(bytes, 3 * RATE);
+            assert!(elapsed >= Duration::from_millis(1900), "{:?} was too fast", elapsed);
+            assert!(elapsed < Duration::from_secs(4), "{:?} was too slow", elapsed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_stream_only_counts() {
+        let (local, mut peer) = tokio::io::duplex(1024 * 1024);
+        let mut stream = ThrottledStream::new(local, None);
+        let started = std::time::Instant::now();
+
+        stream.write_all(&[1u8; 500_000]).await.unwrap();
+        peer.write_all(b"reply").await.unwrap();
+        let mut reply = [0u8; 5];
+        stream.read_exact(&mut reply).await.unwrap();
+
+        assert_eq!((stream.bytes_written(), stream.bytes_read()), (500_000, 5));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
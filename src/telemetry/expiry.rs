@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::CertificateDer;
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use x509_parser::prelude::*;
+
+use crate::common::certificate_serial;
+use crate::telemetry;
+
+/// Warns as the proxy's own certificate runs out of lifetime
+///
+/// Each threshold is a percentage of the certificate's total lifetime and
+/// fires at most once per certificate, identified by its serial. When a
+/// check finds several new thresholds crossed at once, for example right
+/// after startup, only the most severe one is reported.
+#[derive(Debug)]
+pub struct ExpiryMonitor {
+    /// Remaining-lifetime percentages to warn at
+    thresholds_percent: Vec<u8>,
+
+    /// Serial of the certificate the fired thresholds belong to
+    serial: Option<String>,
+
+    /// Thresholds already reported for that certificate
+    fired: BTreeSet<u8>,
+}
+
+impl ExpiryMonitor {
+    /// Create a monitor warning at the given remaining-lifetime percentages
+    pub fn new(thresholds_percent: &[u8]) -> Self {
+        Self {
+            thresholds_percent: thresholds_percent.to_vec(),
+            serial: None,
+            fired: BTreeSet::new(),
+        }
+    }
+
+    /// Check a DER certificate at `now`, returning the threshold reported, if any
+    pub fn check(&mut self, cert_der: &[u8], now: SystemTime) -> Result<Option<u8>> {
+        let (_, cert) = X509Certificate::from_der(cert_der).context("Failed to parse X.509 certificate")?;
+        let serial = certificate_serial(cert_der)?;
+        if self.serial.as_deref() != Some(serial.as_str()) {
+            self.serial = Some(serial.clone());
+            self.fired.clear();
+        }
+
+        let now = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        let not_before = cert.validity().not_before.timestamp();
+        let not_after = cert.validity().not_after.timestamp();
+
+        // Compare in integers to avoid rounding at the threshold boundary
+        let lifetime = (not_after - not_before).max(1) as i128;
+        let remaining = not_after - now;
+        let crossed: Vec<u8> = self
+            .thresholds_percent
+            .iter()
+            .copied()
+            .filter(|threshold| (remaining as i128) * 100 < lifetime * *threshold as i128)
+            .filter(|threshold| !self.fired.contains(threshold))
+            .collect();
+
+        let Some(most_severe) = crossed.iter().copied().min() else {
+            return Ok(None);
+        };
+        self.fired.extend(crossed);
+        telemetry::record_identity_expiry_warning(&serial, most_severe, remaining);
+        Ok(Some(most_severe))
+    }
+
+    /// Spawn a task checking the certificate returned by `current_cert` every `interval`
+    ///
+    /// The task stops when `shutdown` is cancelled.
+    pub fn spawn<F>(mut self, interval: Duration, current_cert: F, shutdown: CancellationToken) -> JoinHandle<()>
+    where
+        F: Fn() -> Option<CertificateDer<'static>> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        debug!("Expiry monitor stopped");
+                        return;
+                    }
+                    _ = ticker.tick() => {}
+                }
+
+                if let Some(cert) = current_cert() {
+                    if let Err(e) = self.check(cert.as_ref(), SystemTime::now()) {
+                        debug!("Skipping expiry check: {:#}", e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{date_time_ymd, CertificateParams, KeyPair, SerialNumber};
+
+    /// Certificate valid from 2030-01-01 for 100 days
+    fn generate_cert(serial: u64) -> Vec<u8> {
+        let mut params = CertificateParams::default();
+        params.not_before = date_time_ymd(2030, 1, 1);
+        params.not_after = date_time_ymd(2030, 4, 11);
+        params.serial_number = Some(SerialNumber::from(serial));
+        params.self_signed(&KeyPair::generate().unwrap()).unwrap().der().to_vec()
+    }
+
+    /// Time `days` into the 100-day lifetime
+    fn day(days: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_893_456_000 + days * 24 * 60 * 60)
+    }
+
+    #[test]
+    fn test_each_threshold_fires_once() {
+        let cert = generate_cert(1);
+        let mut monitor = ExpiryMonitor::new(&[50, 20, 5]);
+
+        let fired: Vec<Option<u8>> = [10, 49, 51, 60, 81, 90, 96, 97, 100]
+            .into_iter()
+            .map(|d| monitor.check(&cert, day(d)).unwrap())
+            .collect();
+        assert_eq!(
+            fired,
+            vec![None, None, Some(50), None, Some(20), None, Some(5), None, None]
+        );
+    }
+
+    #[test]
+    fn test_new_certificate_resets_thresholds() {
+        let mut monitor = ExpiryMonitor::new(&[50, 20, 5]);
+
+        // Starting late reports only the most severe crossing
+        assert_eq!(monitor.check(&generate_cert(1), day(85)).unwrap(), Some(20));
+        assert_eq!(monitor.check(&generate_cert(1), day(86)).unwrap(), None);
+
+        // A renewed certificate warns again
+        assert_eq!(monitor.check(&generate_cert(2), day(86)).unwrap(), Some(20));
+    }
+}
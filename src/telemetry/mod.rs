@@ -1,7 +1,15 @@
+mod expiry;
+mod sampler;
+
 use anyhow::Result;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use crate::common::{CloseReason, PqSecureError, RejectionReason};
+
+pub use expiry::ExpiryMonitor;
+pub use sampler::ResourceSampler;
+
 /// Initialize telemetry (logging and metrics)
 pub fn init() -> Result<()> {
     // Get log level from environment variable or default to info
@@ -31,6 +39,29 @@ pub fn record_connection_attempt(source: &str, success: bool) {
     }
 }
 
+/// Record the cryptography negotiated for an accepted TLS connection
+pub fn record_tls_handshake(source: &str, key_exchange: &str, cipher_suite: &str, pqc: bool) {
+    info!(
+        source = %source,
+        key_exchange = %key_exchange,
+        cipher_suite = %cipher_suite,
+        pqc = %pqc,
+        "TLS handshake completed"
+    );
+}
+
+/// Record a client accepted without TLS during the plaintext bootstrap
+/// window, for the `pqsm_plaintext_connections_total` counter
+///
+/// Logged as a warning: operators migrating to mTLS track this to zero.
+pub fn record_plaintext_connection(source: &str) {
+    warn!(
+        source = %source,
+        counter = "pqsm_plaintext_connections_total",
+        "Insecure plaintext connection accepted"
+    );
+}
+
 /// Record a policy decision
 pub fn record_policy_decision(spiffe_id: &str, method: &str, allowed: bool) {
     info!(
@@ -41,6 +72,90 @@ pub fn record_policy_decision(spiffe_id: &str, method: &str, allowed: bool) {
     );
 }
 
+/// Record the end of a connection, labelled for the
+/// `pqsm_connections_closed_total{reason}` counter
+pub fn record_connection_closed(
+    connection_id: &str,
+    source: &str,
+    reason: CloseReason,
+    bytes_received: u64,
+    bytes_sent: u64,
+) {
+    info!(
+        connection_id = %connection_id,
+        source = %source,
+        reason = %reason,
+        bytes_received = %bytes_received,
+        bytes_sent = %bytes_sent,
+        counter = "pqsm_connections_closed_total",
+        "Connection closed"
+    );
+}
+
+/// Record a refused client, labelled for the `pqsm_rejected_total{reason}` counter
+pub fn record_rejected(reason: RejectionReason) {
+    info!(
+        reason = %reason,
+        counter = "pqsm_rejected_total",
+        "Connection rejected"
+    );
+}
+
+/// Record a connection that failed after admission, labelled by the error's
+/// code for the `pqsm_failed_requests_total{error_type}` counter
+pub fn record_failed_request(source: &str, error: &PqSecureError) {
+    info!(
+        source = %source,
+        error_type = %error.code(),
+        counter = "pqsm_failed_requests_total",
+        "Request failed"
+    );
+}
+
+/// Record whether a connection was fully copied to the shadow upstream,
+/// labelled for the `pqsm_mirrored_total{result}` counter
+pub fn record_mirror(target: &str, success: bool) {
+    info!(
+        mirror = %target,
+        result = if success { "success" } else { "failure" },
+        counter = "pqsm_mirrored_total",
+        "Connection mirrored"
+    );
+}
+
+/// Record how many backend addresses are ejected by outlier detection, for
+/// the `pqsm_upstream_ejected_targets` gauge
+pub fn record_ejected_upstreams(ejected: usize) {
+    info!(
+        ejected = %ejected,
+        gauge = "pqsm_upstream_ejected_targets",
+        "Ejected upstreams changed"
+    );
+}
+
+/// Record an attempt to load a rotated identity from its certificate files,
+/// labelled for the `pqsm_identity_reloads_total{result}` counter
+pub fn record_identity_reload(cert_path: &str, success: bool) {
+    info!(
+        cert_path = %cert_path,
+        result = if success { "success" } else { "failure" },
+        counter = "pqsm_identity_reloads_total",
+        "Identity reloaded"
+    );
+}
+
+/// Warn that the proxy's certificate crossed an expiry threshold, labelled
+/// for the `pqsm_identity_expiry_warnings_total{threshold}` counter
+pub fn record_identity_expiry_warning(serial: &str, threshold_percent: u8, remaining_seconds: i64) {
+    warn!(
+        serial = %serial,
+        threshold = %threshold_percent,
+        remaining_seconds = %remaining_seconds,
+        counter = "pqsm_identity_expiry_warnings_total",
+        "Identity certificate nearing expiry"
+    );
+}
+
 /// Record data transfer
 pub fn record_data_transfer(bytes_received: usize, bytes_sent: usize) {
     debug!(
@@ -48,4 +163,26 @@ pub fn record_data_transfer(bytes_received: usize, bytes_sent: usize) {
         bytes_sent = %bytes_sent,
         "Data transfer"
     );
-}
\ No newline at end of file
+}
+
+/// Record the bytes a finished connection moved, for the
+/// `pqsm_connection_bytes` gauge
+pub fn record_connection_bytes(connection_id: &str, bytes_received: u64, bytes_sent: u64) {
+    info!(
+        connection_id = %connection_id,
+        bytes_received = %bytes_received,
+        bytes_sent = %bytes_sent,
+        gauge = "pqsm_connection_bytes",
+        "Connection bytes"
+    );
+}
+
+/// Record the CPU usage of this process as a percentage of one core
+pub fn record_cpu_usage(percent: f64) {
+    debug!(cpu_percent = %format!("{:.2}", percent), "CPU usage");
+}
+
+/// Record the resident memory usage of this process
+pub fn record_memory_usage(rss_bytes: u64) {
+    debug!(rss_bytes = %rss_bytes, "Memory usage");
+}
@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::telemetry;
+
+/// Kernel clock ticks per second used by `/proc/<pid>/stat` (USER_HZ)
+///
+/// The /proc ABI reports CPU times in USER_HZ, which is fixed at 100 on all
+/// mainstream Linux architectures regardless of the kernel's internal HZ.
+const CLOCK_TICKS_PER_SECOND: f64 = 100.0;
+
+/// A single reading of this process's resource usage
+#[derive(Debug, Clone, Copy)]
+struct ResourceReading {
+    /// Cumulative user + system CPU time in seconds
+    cpu_seconds: f64,
+    /// Resident set size in bytes
+    rss_bytes: u64,
+    /// When the reading was taken
+    taken_at: Instant,
+}
+
+/// Background task that periodically samples this process's CPU and memory usage
+pub struct ResourceSampler {
+    /// Time between samples
+    interval: Duration,
+}
+
+impl ResourceSampler {
+    /// Create a new sampler with the given sampling interval
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    /// Spawn the sampler onto the Tokio runtime, stopping when `shutdown` is cancelled
+    pub fn spawn(self, shutdown: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = shutdown.cancelled() => debug!("Resource sampler stopped"),
+                _ = self.run() => {}
+            }
+        })
+    }
+
+    /// Sample resource usage until the task is cancelled
+    async fn run(self) {
+        let mut previous = match read_usage() {
+            Some(reading) => reading,
+            None => {
+                warn!("Resource sampling is not available on this platform, sampler disabled");
+                return;
+            }
+        };
+
+        debug!("Resource sampler started with interval {:?}", self.interval);
+
+        let mut ticker = tokio::time::interval(self.interval);
+        // The first tick completes immediately; skip it so the first sample covers a full interval
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let current = match read_usage() {
+                Some(reading) => reading,
+                None => {
+                    warn!("Failed to read process resource usage, skipping sample");
+                    continue;
+                }
+            };
+
+            if let Some(percent) = cpu_percent(&previous, &current) {
+                telemetry::record_cpu_usage(percent);
+            }
+            telemetry::record_memory_usage(current.rss_bytes);
+
+            previous = current;
+        }
+    }
+}
+
+/// Compute CPU usage as a percentage of one core between two readings
+fn cpu_percent(previous: &ResourceReading, current: &ResourceReading) -> Option<f64> {
+    let elapsed = current.taken_at.duration_since(previous.taken_at).as_secs_f64();
+    if elapsed <= 0.0 {
+        return None;
+    }
+
+    let cpu = (current.cpu_seconds - previous.cpu_seconds).max(0.0);
+    Some(cpu / elapsed * 100.0)
+}
+
+/// Read the current resource usage of this process
+#[cfg(target_os = "linux")]
+fn read_usage() -> Option<ResourceReading> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+
+    Some(ResourceReading {
+        cpu_seconds: parse_cpu_seconds(&stat)?,
+        rss_bytes: parse_rss_bytes(&status)?,
+        taken_at: Instant::now(),
+    })
+}
+
+/// Read the current resource usage of this process
+#[cfg(not(target_os = "linux"))]
+fn read_usage() -> Option<ResourceReading> {
+    None
+}
+
+/// Parse cumulative user + system CPU seconds from `/proc/self/stat`
+fn parse_cpu_seconds(stat: &str) -> Option<f64> {
+    // The command name is wrapped in parentheses and may contain spaces, so
+    // split on the last closing parenthesis before indexing the fields
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<&str> = after_comm.split_whitespace().collect();
+
+    // Fields after the command name start at field 3 (state); utime and stime
+    // are fields 14 and 15
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+
+    Some((utime + stime) as f64 / CLOCK_TICKS_PER_SECOND)
+}
+
+/// Parse the resident set size in bytes from `/proc/self/status`
+fn parse_rss_bytes(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_seconds() {
+        let stat = "1234 (pqsecure mesh) S 1 1234 1234 0 -1 4194560 1000 0 0 0 250 150 0 0 20 0 8 0 100 0 0";
+
+        let cpu = parse_cpu_seconds(stat).unwrap();
+        assert!((cpu - 4.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_parse_rss_bytes() {
+        let status = "Name:\tpqsecure-mesh\nVmPeak:\t  20000 kB\nVmRSS:\t   5120 kB\nThreads:\t8\n";
+
+        assert_eq!(parse_rss_bytes(status), Some(5120 * 1024));
+        assert_eq!(parse_rss_bytes("Name:\tpqsecure-mesh\n"), None);
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        let start = Instant::now();
+        let previous = ResourceReading {
+            cpu_seconds: 1.0,
+            rss_bytes: 0,
+            taken_at: start,
+        };
+        let current = ResourceReading {
+            cpu_seconds: 1.5,
+            rss_bytes: 0,
+            taken_at: start + Duration::from_secs(2),
+        };
+
+        let percent = cpu_percent(&previous, &current).unwrap();
+        assert!((percent - 25.0).abs() < 1e-9);
+        assert!(cpu_percent(&current, &current).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_usage() {
+        let reading = read_usage().unwrap();
+        assert!(reading.rss_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_sampler_stops_on_cancel() {
+        let shutdown = CancellationToken::new();
+        let task = ResourceSampler::new(Duration::from_millis(10)).spawn(shutdown.clone());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(1), task).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Fixtures shared by the unit tests of several modules
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose, SanType,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::subscriber::DefaultGuard;
+
+use crate::config::{BackendConfig, MtlsMode};
+use crate::identity::SpiffeVerifier;
+use crate::policy::YamlPolicyEngine;
+use crate::proxy::handler::BaseHandler;
+
+/// Key ID of the JWT signing key in [`jwks_for`] documents
+pub(crate) const JWT_KEY_ID: &str = "test-key";
+
+/// JWKS containing the public half of a P-256 key
+pub(crate) fn jwks_for(key_pair: &KeyPair) -> Vec<u8> {
+    // Uncompressed point: 0x04 || x || y
+    let point = key_pair.public_key_raw();
+    serde_json::json!({
+        "keys": [{
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": JWT_KEY_ID,
+            "use": "jwt-svid",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        }]
+    })
+    .to_string()
+    .into_bytes()
+}
+
+/// JWT-SVID for `subject` and `audience`, valid for five minutes
+pub(crate) fn sign_jwt_svid(key_pair: &KeyPair, subject: &str, audience: &str) -> String {
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(JWT_KEY_ID.to_string());
+    header.typ = Some("JWT".to_string());
+    let exp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 300;
+    let claims = serde_json::json!({ "sub": subject, "aud": [audience], "exp": exp });
+
+    let key = EncodingKey::from_ec_pem(key_pair.serialize_pem().as_bytes()).unwrap();
+    encode(&header, &claims, &key).unwrap()
+}
+
+/// Certificate parameters carrying `spiffe_id` as their URI SAN
+pub(crate) fn spiffe_params(spiffe_id: &str) -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params
+        .subject_alt_names
+        .push(SanType::URI(rcgen::Ia5String::try_from(spiffe_id).unwrap()));
+    params
+}
+
+/// Self-signed certificate for `mesh.example.org`, without a SPIFFE ID
+pub(crate) fn self_signed_cert() -> (Certificate, KeyPair) {
+    let key_pair = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(vec!["mesh.example.org".to_string()])
+        .unwrap()
+        .self_signed(&key_pair)
+        .unwrap();
+    (cert, key_pair)
+}
+
+/// Self-signed certificate carrying `spiffe_id`
+pub(crate) fn self_signed_svid(spiffe_id: &str) -> (Certificate, KeyPair) {
+    let key_pair = KeyPair::generate().unwrap();
+    let cert = spiffe_params(spiffe_id).self_signed(&key_pair).unwrap();
+    (cert, key_pair)
+}
+
+/// DER of a self-signed certificate carrying `spiffe_id`
+pub(crate) fn self_signed_svid_der(spiffe_id: &str) -> CertificateDer<'static> {
+    self_signed_svid(spiffe_id).0.der().clone()
+}
+
+/// Throwaway root CA named `name`
+pub(crate) fn test_ca_named(name: &str) -> (Certificate, KeyPair) {
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    params.distinguished_name.push(DnType::CommonName, name);
+    let key_pair = KeyPair::generate().unwrap();
+    (params.self_signed(&key_pair).unwrap(), key_pair)
+}
+
+/// Throwaway root CA
+pub(crate) fn test_ca() -> (Certificate, KeyPair) {
+    test_ca_named("Test Root")
+}
+
+/// X.509-SVID for `spiffe_id` issued by `ca`, usable by clients and servers
+pub(crate) fn issue_svid(spiffe_id: &str, ca: &(Certificate, KeyPair)) -> (Certificate, KeyPair) {
+    let mut params = spiffe_params(spiffe_id);
+    params.is_ca = IsCa::ExplicitNoCa;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth, ExtendedKeyUsagePurpose::ClientAuth];
+    let key_pair = KeyPair::generate().unwrap();
+    (params.signed_by(&key_pair, &ca.0, &ca.1).unwrap(), key_pair)
+}
+
+/// Leaf-only chain and private key of an SVID for `spiffe_id` issued by `ca`
+pub(crate) fn issue_identity(
+    spiffe_id: &str,
+    ca: &(Certificate, KeyPair),
+) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+    let (cert, key_pair) = issue_svid(spiffe_id, ca);
+    (vec![cert.der().clone()], private_key_der(&key_pair))
+}
+
+/// Chain and private key of a self-signed certificate carrying `spiffe_id`
+pub(crate) fn self_signed_identity(spiffe_id: &str) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+    let (cert, key_pair) = self_signed_svid(spiffe_id);
+    (vec![cert.der().clone()], private_key_der(&key_pair))
+}
+
+/// PKCS#8 private key of a generated key pair
+pub(crate) fn private_key_der(key_pair: &KeyPair) -> PrivateKeyDer<'static> {
+    PrivateKeyDer::Pkcs8(key_pair.serialize_der().into())
+}
+
+/// Backend configuration for `address` with a 5 second timeout
+pub(crate) fn backend_config(address: &str) -> BackendConfig {
+    serde_yaml::from_str(&format!("address: \"{}\"\ntimeout_seconds: 5", address)).unwrap()
+}
+
+/// Base handler denying everything and forwarding to an unreachable backend
+pub(crate) fn deny_all_handler(mtls_mode: MtlsMode) -> BaseHandler {
+    let policy = YamlPolicyEngine::from_yaml("default_action: false\nrules: []").unwrap();
+    BaseHandler::new(
+        backend_config("127.0.0.1:1"),
+        Arc::new(policy),
+        Arc::new(SpiffeVerifier::new("example.org".to_string()).with_mtls_mode(mtls_mode)),
+    )
+    .unwrap()
+}
+
+/// Log sink shared between a test and the subscriber it installs
+#[derive(Clone, Default)]
+pub(crate) struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    /// Capture everything logged on this thread at `level` or above until the
+    /// guard is dropped
+    pub(crate) fn install(level: tracing::Level) -> (Self, DefaultGuard) {
+        let logs = Self::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(logs.clone())
+            .with_max_level(level)
+            .with_ansi(false)
+            .finish();
+        (logs, tracing::subscriber::set_default(subscriber))
+    }
+
+    /// Everything logged so far
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for CapturedLogs {
+    type Writer = CapturedLogs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}